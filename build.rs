@@ -17,8 +17,5 @@ fn main() {
 }
 
 fn debug_tesselator_flag_on() -> bool {
-    match env::var("CARGO_FEATURE_DEBUG_TESSELATOR") {
-        Ok(_) => true,
-        Err(_) => false
-    }
+    env::var("CARGO_FEATURE_DEBUG_TESSELATOR").is_ok()
 }
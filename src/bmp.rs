@@ -0,0 +1,118 @@
+/*
+ * Cairus - a reimplementation of the cairo graphics library in Rust
+ *
+ * Copyright © 2017 CairusOrg
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it either under the terms of the GNU Lesser General Public
+ * License version 2.1 as published by the Free Software Foundation
+ * (the "LGPL") or, at your option, under the terms of the Mozilla
+ * Public License Version 2.0 (the "MPL"). If you do not alter this
+ * notice, a recipient may use your version of this file under either
+ * the MPL or the LGPL.
+ *
+ * You should have received a copy of the LGPL along with this library
+ * in the file LICENSE-LGPL-2_1; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Suite 500, Boston, MA 02110-1335, USA
+ * You should have received a copy of the MPL along with this library
+ * in the file LICENSE-MPL-2_0
+ *
+ * The contents of this file are subject to the Mozilla Public License
+ * Version 2.0 (the "License"); you may not use this file except in
+ * compliance with the License. You may obtain a copy of the License at
+ * http://www.mozilla.org/MPL/
+ *
+ * This software is distributed on an "AS IS" basis, WITHOUT WARRANTY
+ * OF ANY KIND, either express or implied. See the LGPL or the MPL for
+ * the specific language governing rights and limitations.
+ *
+ * The Original Code is the cairus graphics library.
+ *
+ */
+
+//! Writes ImageSurfaces as BMP images, for tools that read nothing newer.
+//!
+//! Images are written with the plain 40 byte BITMAPINFOHEADER and 24 bits a pixel, which every
+//! reader of the format understands.  That leaves no room for alpha, so the colors are written
+//! pre-multiplied, as if the surface was shown over black.
+
+use std::io::Write;
+use status::Status;
+use surfaces::ImageSurface;
+use types::{ChannelOrder, AlphaMode};
+
+// Bytes in the file header and the BITMAPINFOHEADER before the pixels
+const HEADERS_SIZE: usize = 14 + 40;
+
+// Pixels a meter, which is 72 pixels an inch
+const RESOLUTION: u32 = 2835;
+
+/// Writes `surface` to `writer` as a 24 bit BMP image.  Returns Status::InvalidSize for a
+/// surface too large for the format and Status::WriteError if `writer` fails.
+pub fn write_bmp<W: Write>(surface: &ImageSurface, mut writer: W) -> Result<(), Status> {
+    // Rows are padded to whole 32 bit words
+    let stride = (surface.width * 3).div_ceil(4) * 4;
+    let file_size = stride.checked_mul(surface.height)
+        .and_then(|size| size.checked_add(HEADERS_SIZE))
+        .filter(|&size| size <= i32::MAX as usize)
+        .ok_or(Status::InvalidSize)?;
+
+    let mut bytes = Vec::with_capacity(file_size);
+    bytes.extend_from_slice(b"BM");
+    bytes.extend_from_slice(&(file_size as u32).to_le_bytes());
+    bytes.extend_from_slice(&[0; 4]);
+    bytes.extend_from_slice(&(HEADERS_SIZE as u32).to_le_bytes());
+
+    bytes.extend_from_slice(&40u32.to_le_bytes());
+    bytes.extend_from_slice(&(surface.width as i32).to_le_bytes());
+    // A positive height means the rows are stored bottom to top
+    bytes.extend_from_slice(&(surface.height as i32).to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&24u16.to_le_bytes());
+    // No compression
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend_from_slice(&((file_size - HEADERS_SIZE) as u32).to_le_bytes());
+    bytes.extend_from_slice(&RESOLUTION.to_le_bytes());
+    bytes.extend_from_slice(&RESOLUTION.to_le_bytes());
+    // No palette
+    bytes.extend_from_slice(&[0; 8]);
+
+    for y in (0..surface.height).rev() {
+        let start = bytes.len();
+        for x in 0..surface.width {
+            let pixel = surface.get(x, y).unwrap();
+            let color = pixel.to_bytes(ChannelOrder::Bgra, AlphaMode::Premultiplied);
+            bytes.extend_from_slice(&color[..3]);
+        }
+        bytes.resize(start + stride, 0);
+    }
+    writer.write_all(&bytes).map_err(|_| Status::WriteError)
+}
+
+#[cfg(test)]
+mod tests {
+    use bmp::write_bmp;
+    use surfaces::{ImageSurface, Format};
+    use types::Rgba;
+
+    #[test]
+    fn bmp_layout() {
+        let mut surface = ImageSurface::create(Format::ARGB32, 2, 2);
+        *surface.get_mut(0, 0).unwrap() = Rgba::new(1., 0., 0., 1.);
+        *surface.get_mut(1, 1).unwrap() = Rgba::new(0., 0., 1., 0.6);
+        let mut bytes = Vec::new();
+        write_bmp(&surface, &mut bytes).unwrap();
+
+        assert_eq!(bytes.len(), 70);
+        assert_eq!(&bytes[..2], b"BM");
+        assert_eq!(&bytes[2..6], &70u32.to_le_bytes());
+        assert_eq!(&bytes[10..14], &54u32.to_le_bytes());
+        assert_eq!(&bytes[18..26], &[2, 0, 0, 0, 2, 0, 0, 0]);
+        assert_eq!(&bytes[28..30], &24u16.to_le_bytes());
+        assert_eq!(&bytes[34..38], &16u32.to_le_bytes());
+
+        // The bottom row comes first, in blue, green, red order and padded to 8 bytes
+        assert_eq!(&bytes[54..62], &[0, 0, 0, 153, 0, 0, 0, 0]);
+        assert_eq!(&bytes[62..70], &[0, 0, 255, 0, 0, 0, 0, 0]);
+    }
+}
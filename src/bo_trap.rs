@@ -45,119 +45,53 @@ Sweep line is a horizontal line going from top (minimum y) to bottom (maximum y)
 LineSegment defined in common_geometry.rs contains 2 points
 edge is a line + top, bot, dir
     dir is a direction and should come from whatever initially 'drew' the lines
-        in a pinch, we could generate dir from a sequence of line segments assuming
-        each segment's first point is the previous segment's end point.
         dir should be +1 for a segment that is being drawn in the positive y direction,
         -1 for a segment being drawn in the negative y direction, and 0 for horizontal lines
         (horizontal lines don't actually matter since we will never cross them with a
         horizontal ray)
     For example: A clockwise drawn square would have a right side with a +1 dir,
         and a left side with a -1 dir.
-SL_edge has edge + *prev, *next, *colinear, deferred_trap (top, *right)
+SL_edge has edge + deferred_trap (top, right)
 
-1. build event queue (EQ) (BST?)
-    add event for each endpoint of lines in edge list.
+1. build event queue (EQ)
+    add event for each endpoint of the (non-horizontal) edges.
         min(y of points) is START, max is END
-    event is a point and associated edge or two and an enum event type
-    sort events by point.y first, then by edge (top bottom, left right)
-
-2. initialize sweep line list (SLL)
-    SLL starts empty. Contains SL_edges. Is doubly linked list
-    SL has *head, y, *current_SL_edge
-    ? what about multiple lines intersecting at the same point?
-
-while EQ not empty:
-    Pop event off EQ.
-    Set SL.y = event.y
-    Process event:
-        case: event.type = start
-            insert event.edge into SLL (build SL_edge)
-                building SL_edge:
-                    SL_edge->edge = event.edge
-                    if SL_edge->next != null start new trap:
-                        SL_edge.deferred_trap->right = SL_edge->next.edge
-                        SL_edge.deferred_trap.top = SL.y
-                    if SL_edge->prev.deferred_trap.right != null (edge to left has
-                                                deferred trap)
-                        add_to_traps(SL_edge->prev, SL.y)
-                    SL_edge->prev.deferred_trap.right = SL_edge
-                    SL_edge->prev.deferred_trap.top = SL.y
-            check if SL_edge.prev intersects with SL_edge
-                add intersection to EQ
-            check if SL_edge.next intersects with SL_edge
-                add intersection to EQ (future? current?)
-
-        case: event.type = end
-            if SL_edge->prev intersects with SL_edge->next
-                add intersection to EQ if it isn't already there
-            if SL_edge.deferred_trap->right != null
-                add_to_traps(SL_edge, SL.y)
-            if SL_edge->prev.deferred_trap->right != null (should never be null
-                                prob just check SL_edge->prev != null)
-                add_to_traps(SL_edge->prev, SL.y)
-                SL_edge->prev.deferred_trap->right = SL_edge.deferred_trap->right
-                SL_edge->prev.deferred_trap.top = SL.y
-            remove SL_edge from SLL:
-                SL_edge->prev = SL_edge->next
-                SL_edge->next = SL_edge->prev
-
-        case: event.type = intersection
-            if SL_edgeL.deferred_trap->right != null (should be SL_edgeR.edge)
-                add_to_traps(SL_edgeL, SL.y)
-            SL_edgeL.deferred_trap->right = SL_edgeR.deferred_trap->right
-            SL_edgeL.deferred_trap.top = SL.y
-            if SL_edgeR.deferred_trap->right != null
-                add_to_traps(SL_edgeR, SL.y)
-            SL_edgeR.deferred_trap->right = SL_edgeL->edge
-            SL_edgeR.deferred_trap.top = SL.y
-            if SL_edgeL->prev.deferred_trap->right != null (should be SL_edgeL.edge)
-                add_to_traps(SL_edgeL->prev, SL.y)
-            SL_edgeL->prev.deferred_trap->right = SL_edgeR->edge
-            SL_edgeL->prev.deferred_trap.top = SL.y
-            swap SL_edgeL and SL_edgeR:
-                SL_edgeL->prev->next = SL_edgeR (if L->prev == null, SL->head = R)
-                SL_edgeR->prev = SL_edgeL->prev
-                SL_edgeL->next = SL_edgeR->next
-                SL_edgeL->prev = SL_edgeR
-                SL_edgeR->next->prev = SL_edgeL (if R->next != null)
-                SL_edgeR->next = SL_edgeL
-            check if SL_edgeR.prev intersects with SL_edgeR
-                add intersection to EQ
-            check if SL_edgeL.next intersects with SL_edgeL
-                add intersection to EQ
-
-In case of multiple lines crossing at same intersection point we have a couple problems:
-    1. if order of event insertion is wrong, we may end up with non-adjacent edges in SLL being
-        swapped
-    2. we end up in an infinite loop adding the same intersections to the event queue over and over
-does slope of lines help with this? investigate cairo code...
-
+    add an INTERSECTION event wherever two edges cross inside of both of them
+    sort events by point.y first, then by x, then by event type
+
+2. walk the distinct y values of the EQ from top to bottom.  Between two neighbouring y
+   values no edge starts, ends, or crosses another, so the edges spanning that band have a
+   fixed left to right order.  For every band:
+    build the sweep line list (SLL) of edges spanning the band, sorted by x at the middle of
+    the band
+    for every pair of neighbouring SL_edges (left, right):
+        in_out = sum of dir for SL_edges to the right of left
+        if in_out & mask != 0 the space between left and right is inside the shape, where
+        mask is -1 (0xFFFFFFFF) for the winding rule and 1 for the even-odd rule
+            if left.deferred_trap->right was already right, the trap simply keeps growing
+            otherwise a new deferred trap starts: left.deferred_trap = (band top, right)
+    every deferred trap of the previous SLL that did not continue is finished:
+        add_to_traps(SL_edge, band top)
+
+3. finish the deferred traps left in the SLL at the last y value.
+
+Processing whole bands, rather than updating the SLL one event at a time, means edges that
+start, end, and cross at the same point never have to be untangled in the right order.
 */
 /*
-add_to_traps(SL_edge edge, float bot, int mask, traps *traps)
-    //mask is 0xFFFFFFFF if using winding rule, 0x1 if using even/odd rule
+add_to_traps(SL_edge edge, LineSegment right, float bot, traps *traps)
     //only output traps with positive area
     if edge.deferred_trap.top >= bot
         return
-    //count edge directions for ray right to infinity
-    in_out = 0
-    pos = edge.deferred_trap->right (or pos = edge->next? should be same, no?)
-    while (pos != null)
-        in_out += pos.dir
-        pos = pos.deferred_trap->right (or pos = pos->next? should be same, no?)
-    //in_out & mask is zero means do not fill (0 or even)
-    if in_out & mask != 0
-        LineSegment left, right
-        left = edge->LineSegment
-        right = edge.deferred_trap->right->LineSegment
-        traps_push(left, right, edge.deferred_trap.top, bot)
+    LineSegment left
+    left = edge->LineSegment
+    traps_push(left, right, edge.deferred_trap.top, bot)
 */
 use common_geometry::{Edge, Point, LineSegment};
 use std::cmp::Ordering;
 use std::clone::Clone;
 use trapezoid_rasterizer::Trapezoid;
-extern crate linked_list;
-use self::linked_list::{LinkedList, Cursor};
+use context::FillRule;
 
 
 /// ## EventType
@@ -172,7 +106,7 @@ pub enum EventType {
 
 impl PartialOrd for EventType {
     fn partial_cmp(&self, other: &EventType) -> Option<Ordering> {
-        Some(self.cmp(&other))
+        Some(self.cmp(other))
     }
 }
 
@@ -219,7 +153,7 @@ pub struct Event {
 
 impl PartialOrd for Event {
     fn partial_cmp(&self, other: &Event) -> Option<Ordering> {
-        Some(self.cmp(&other))
+        Some(self.cmp(other))
     }
 }
 
@@ -246,7 +180,7 @@ impl Ord for Event {
 }
 
 impl PartialEq for Event {
-    fn eq(&self, other:&Event) -> bool {
+    fn eq(&self, _other:&Event) -> bool {
         true
     }
 }
@@ -295,430 +229,213 @@ fn event_list_from_edges(edges: Vec<Edge>) -> Vec<Event> {
     events
 }
 
-/// Defines a SweepLineEdge for our SweepLineList
+/// Adds an Intersection event for every pair of edges that cross strictly inside of both edges.
+///
+/// Crossings at an endpoint need no event of their own, the endpoint's Start or End event
+/// already splits the sweep there.
+fn add_intersection_events(edges: &[Edge], events: &mut Vec<Event>) {
+    for (idx, first) in edges.iter().enumerate() {
+        for second in edges[idx + 1..].iter() {
+            // Edges that don't share any y values can't cross
+            if first.bottom <= second.top || second.bottom <= first.top {
+                continue;
+            }
+
+            if let Some(point) = crossing_point(&first.line, &second.line) {
+                let top = first.top.max(second.top);
+                let bottom = first.bottom.min(second.bottom);
+                if point.y > top && point.y < bottom {
+                    events.push(Event::new_intersection(*first, *second, &point));
+                }
+            }
+        }
+    }
+}
+
+/// Returns the point where two line segments cross, or None if they are parallel or don't
+/// reach each other.
+fn crossing_point(first: &LineSegment, second: &LineSegment) -> Option<Point> {
+    let first_delta = first.point2 - first.point1;
+    let second_delta = second.point2 - second.point1;
+    let denominator = first_delta.x * second_delta.y - first_delta.y * second_delta.x;
+    if denominator == 0. {
+        return None;
+    }
+
+    let offset = second.point1 - first.point1;
+    let t = (offset.x * second_delta.y - offset.y * second_delta.x) / denominator;
+    let u = (offset.x * first_delta.y - offset.y * first_delta.x) / denominator;
+    if !(0. ..=1.).contains(&t) || !(0. ..=1.).contains(&u) {
+        return None;
+    }
+
+    Some(Point::new(first.point1.x + t * first_delta.x, first.point1.y + t * first_delta.y))
+}
+
+/// Defines a SweepLineEdge for our sweep line list
 ///
 /// The SweepLineEdges will be used to create trapezoids.
-/// Top will be set by our SweepLine to mark the top of our trapezoid.
-/// Left will be set based on the leftmost point of our line to determine where in our SweepLineList
-///     we need to insert our SweepLineEdge. This is used for sorting our SweepLineList and is updated
-///     when it intersects another line.
-/// Line is our current line.
-/// Note: We may need to add a Right (right: Option<Box<LineSegment>>) to track the right side of
-///     our trapezoid but for now we will let the SweepLineList determine this based on if there is a
-///     SweepLineEdge after the current SweepLineEdge in our SweepLineList.
+/// Left is the x value of the edge in the middle of the current band, and is what the sweep
+///     line list is sorted by.
+/// Edge is the edge this SweepLineEdge follows, index is its position in the list passed to
+///     sweep() so that identical edges can be told apart.
+/// Trap_top and trap_right make up the deferred trapezoid to the right of this edge: the
+///     trapezoid began at trap_top, and is bounded on the right by the edge at index trap_right.
+///     trap_right is None when the space to the right of this edge isn't filled.
 #[derive(Debug, Copy, Clone)]
 pub struct SweepLineEdge {
     trap_top: f32,
+    trap_right: Option<usize>,
     left: f32,
+    index: usize,
     edge: Edge,
 }
 
 impl SweepLineEdge {
-    fn new(trap_top: f32, left: f32, edge: Edge) -> SweepLineEdge {
+    fn new(left: f32, index: usize, edge: Edge) -> SweepLineEdge {
         SweepLineEdge {
-            trap_top: trap_top,
+            trap_top: 0.,
+            trap_right: None,
             left: left,
+            index: index,
             edge: edge,
         }
     }
 }
 
-/// Creates trapezoids out of the passed in edges.
-pub fn sweep(edges: Vec<Edge>) -> Vec<Trapezoid> {
-    // Create the empty sweep Line Linked List
-    let mut sl_list: LinkedList<SweepLineEdge> = LinkedList::new();
-    // Create a cursor to move over the list
-    let mut cursor = sl_list.cursor();
-    // Create the list of events
-    let mut events = event_list_from_edges(edges);
-    // Create empty traps list for eventual return
-    let mut traps: Vec<Trapezoid> = Vec::new();
-    // Keep looping until the Event List is empty
-    while !events.is_empty() {
-        // Get the current event
-        let event = events.remove(0);
-
-        // Set the sweep line to the events y value
-        let sweep_line = event.point.y;
-
-        // Process Event
-        // START CASE
-        if event.event_type == EventType::Start{
-            println!("Starting START case for point: ({},{})", event.point.x, event.point.y);
-            println!("Sweep Line is: {}", sweep_line );
-            // find the left most point of the edge_left line
-            let left = event.edge_left.line.min_x_point().x;
-            // create a new node and add it to the list
-            let sl_edge = SweepLineEdge::new(sweep_line, left, event.edge_left);
-            // Set the cursor back to the beginning
-            cursor.reset();
-            if cursor.peek_next().is_none() {
-                cursor.insert(sl_edge);
-            } else {
-                while find_line_place(event.point, event.edge_left, *cursor.peek_next().unwrap()) == Comparator::Less {
-                    cursor.next();
-                    if cursor.peek_next().is_none() {
-                        break;
-                    }
-                }
-                // **** ADD TRAPEZOID *****
-                // If before we add our new sl_edge there is a previous and next we need to make a
-                // new Trapezoid and set the prev top
-                if cursor.peek_prev().is_some() && cursor.peek_next().is_some() {
-                    // passing -1 for mask as winding rule default 0xFFFFFFFF
-                    add_to_traps(&mut cursor, sweep_line, -1 , &mut traps);
-                    cursor.peek_prev().unwrap().trap_top = sweep_line;
-                }
-
-                // Add the new edge to our sweep line list
-                cursor.insert(sl_edge);
+/// Creates trapezoids out of the passed in edges, covering the area inside of them according to
+/// `fill_rule`.
+pub fn sweep(edges: Vec<Edge>, fill_rule: FillRule) -> Vec<Trapezoid> {
+    // The winding rule fills any nonzero sum of directions, so its mask keeps every bit
+    // (0xFFFFFFFF).  The even-odd rule only fills odd sums, so its mask keeps the lowest bit.
+    let mask = match fill_rule {
+        FillRule::Winding => -1,
+        FillRule::EvenOdd => 1,
+    };
 
-                // **** CHECK FOR INTERSECTIONS ****
-                // Check to see if the new edge intersects with the previous or next
-                // if it does after the current sweep line then we add it to our event list.
-                // Check if there is an intersection with the left line, if yes check for interaction
-
-                // Move the cursor to just before our newly inserted sweep line edge
-                cursor.prev();
-                // If it has a previous before our new event there way be an intersection
-                if cursor.peek_prev().is_some() {
-                    check_for_intersection(sweep_line, &mut cursor, &mut events);
-                }
-                // Move the cursor to after the newly added sweep line edge
-                cursor.next();
-                // If there is a sweep line edge after the new one, check for intersections
-                if cursor.peek_next().is_some() {
-                    check_for_intersection(sweep_line, &mut cursor, &mut events);
-                }
+    // Horizontal edges never bound a trapezoid
+    let edges: Vec<Edge> = edges.into_iter().filter(|edge| edge.top < edge.bottom).collect();
 
+    // Create the list of events
+    let mut events = event_list_from_edges(edges.clone());
+    add_intersection_events(&edges, &mut events);
+    events.sort();
+    let mut sweep_lines: Vec<f32> = events.iter().map(|event| event.point.y).collect();
+    sweep_lines.dedup();
+
+    // Edges in the order they reach the sweep line
+    let mut pending: Vec<usize> = (0..edges.len()).collect();
+    pending.sort_by(|&a, &b| edges[a].top.partial_cmp(&edges[b].top).unwrap_or(Ordering::Equal));
+    let mut pending = pending.into_iter().peekable();
+
+    let mut active: Vec<usize> = Vec::new();
+    // The deferred trap to the right of each edge, as (right edge index, trap top)
+    let mut deferred: Vec<Option<(usize, f32)>> = vec![None; edges.len()];
+    let mut sl_list: Vec<SweepLineEdge> = Vec::new();
+    let mut traps: Vec<Trapezoid> = Vec::new();
+    for band in sweep_lines.windows(2) {
+        let (top, bottom) = (band[0], band[1]);
+
+        // Update the edges spanning this band
+        active.retain(|&idx| edges[idx].bottom > top);
+        while let Some(&idx) = pending.peek() {
+            if edges[idx].top > top {
+                break;
             }
-            println!("Finished START Case");
-         //   println!("current x, y value: {} {}",cursor.next().unwrap().edge.line.current_x_for_y(sweep_line), sweep_line );
+            active.push(idx);
+            pending.next();
         }
 
-        // END CASE
-        else if event.event_type == EventType::End {
-            println!("Starting END case for point: ({},{})", event.point.x, event.point.y);
-            println!("Sweep Line is: {}", sweep_line );
-
-            // REMOVE FROM SL_LIST
-            // if our event line is equal to our cursor_left line then see if our lines are equal, if yes remove
-            // if no then we need to see which direction to move...
-            // if our event line is greater then our cursor left line then we need to move right and repeat
-            // if our event line is less then our cursor left line then we need to move left
-
-            // Move the cursor to before the sweep line edge we wish to delete
-            move_cursor_to_line(event.point, event.edge_left, &mut cursor);
-
-            let line = cursor.peek_next().unwrap().edge.line.clone();
-            println!("Cursor Next point is: ({},{})", line.current_x_for_y(sweep_line), sweep_line);
-
-            // **** CREATE TRAPEZOIDS *****
-            // we will be at the point of removal here, so we need to see about building trapezoids
-            // before and after this point before we remove it. We will want to update the TOP of the
-            // node before if we create a trapezoid
-            if cursor.peek_prev().is_some() {
-                // passing -1 for mask as winding rule default 0xFFFFFFFF
-                println!("Calling add_to_traps for trap before current cursor");
-                add_to_traps(&mut cursor, sweep_line, -1 , &mut traps);
-                cursor.peek_prev().unwrap().trap_top = sweep_line;
-            }
-            if cursor.peek_next().is_some() {
-                cursor.next();
-                if cursor.peek_next().is_some() {
-                    println!("Calling add_to_traps for trap after current cursor");
-                    let line_before = cursor.peek_prev().unwrap().edge.line.clone();
-                    let line_after = cursor.peek_next().unwrap().edge.line.clone();
-                    println!("   Line Previous point is: ({},{})", line_before.current_x_for_y(sweep_line), sweep_line);
-                    println!("   Line Next point is: ({},{})", line_after.current_x_for_y(sweep_line), sweep_line);
-                    // passing -1 for mask as winding rule default 0xFFFFFFFF
-                    add_to_traps(&mut cursor, sweep_line, -1, &mut traps);
-                    cursor.peek_prev().unwrap().trap_top = sweep_line;
-                }
-                cursor.prev();
-            }
-            cursor.remove();
-
-            // ****** CHECK FOR INTERSECTIONS ****
-            // After we remove it we will want to see if there is any intersections with the lines
-            // before and after the cursor. If yes, and it happens after our current y we add it to
-            // our event list.
-            if cursor.peek_prev().is_some() && cursor.peek_next().is_some() {
-                check_for_intersection(sweep_line, &mut cursor, &mut events);
+        // Build this band's sweep line list, ordered by x in the middle of the band
+        let middle = (top + bottom) / 2.;
+        let mut next_sl_list: Vec<SweepLineEdge> = active.iter()
+            .map(|&idx| SweepLineEdge::new(edges[idx].line.current_x_for_y(middle), idx, edges[idx]))
+            .collect();
+        next_sl_list.sort_by(|a, b| {
+            a.left.partial_cmp(&b.left).unwrap_or(Ordering::Equal).then(a.index.cmp(&b.index))
+        });
+
+        // Continue or start the deferred traps between neighbouring edges.  A trap carries on
+        // from the band above when the same two edges still bound it.
+        let mut in_out = next_sl_list.iter().fold(0, |sum, sl_edge| sum + sl_edge.edge.direction);
+        for position in 0..next_sl_list.len().saturating_sub(1) {
+            in_out -= next_sl_list[position].edge.direction;
+            if in_out & mask == 0 {
+                continue;
             }
 
-            println!("Finished END Case");
+            let right = next_sl_list[position + 1].index;
+            let sl_edge = &mut next_sl_list[position];
+            sl_edge.trap_right = Some(right);
+            sl_edge.trap_top = match deferred[sl_edge.index] {
+                Some((previous_right, previous_top)) if previous_right == right => previous_top,
+                _ => top,
+            };
         }
 
-
-        // INTERSECT CASE
-            else if event.event_type == EventType::Intersection {
-                println!("Starting INTERSECT case for point: ({},{})", event.point.x, event.point.y);
-                println!("Sweep Line is: {}", sweep_line);
-
-                // move the cursor between the two edges
-                // *** Issue: How do i access the element inside of a BOX? ***
-                move_cursor_to_line(event.point, *event.edge_right.get(0).unwrap(), &mut cursor );
-                let line_before = cursor.peek_prev().unwrap().edge.line.clone();
-                let line_after = cursor.peek_next().unwrap().edge.line.clone();
-                println!("   Line Previous point is: ({},{})", line_before.current_x_for_y(sweep_line), sweep_line);
-                println!("   Line Next point is: ({},{})", line_after.current_x_for_y(sweep_line), sweep_line);
-                // check for traps before
-                println!("Starting trap checks");
-                cursor.prev();
-                if cursor.peek_prev().is_some() {
-                    add_to_traps(&mut cursor, sweep_line, -1 , &mut traps);
-                    cursor.peek_prev().unwrap().trap_top = sweep_line;
-                }
-                // check for traps between
-                cursor.next();
-                add_to_traps(&mut cursor, sweep_line, -1 , &mut traps);
-                cursor.peek_prev().unwrap().trap_top = sweep_line;
-
-                // check for traps after
-                cursor.next();
-                if cursor.next().is_some() {
-                    add_to_traps(&mut cursor, sweep_line, -1 , &mut traps);
-                    cursor.peek_prev().unwrap().trap_top = sweep_line;
-                }
-                println!("Ending trap checks");
-
-                // move the cursor back between our edges
-                cursor.prev();
-
-                if  cursor.peek_prev().is_none() || cursor.peek_next().is_none() {
-                    println! ("**** ERROR WHAT HAPPENED TO THE CURSOR ****");
-                    move_cursor_to_line(event.point, *event.edge_right.get(0).unwrap(), &mut cursor );
-                }
-                let line_before = cursor.peek_prev().unwrap().edge.line.clone();
-                let line_after = cursor.peek_next().unwrap().edge.line.clone();
-                println!("   Line Previous point is: ({},{})", line_before.current_x_for_y(sweep_line), sweep_line);
-                println!("   Line Next point is: ({},{})", line_after.current_x_for_y(sweep_line), sweep_line);
-
-                // swap
-                let swap_sl_edge = cursor.remove().unwrap();
-                cursor.prev();
-                cursor.insert(swap_sl_edge);
-
-                // check for intersections before set
-                cursor.prev();
-                if cursor.peek_prev().is_some() {
-                    check_for_intersection(sweep_line, &mut cursor, &mut events);
-                }
-                cursor.next();
-
-                // check for intersections after set
-                cursor.next();
-                if cursor.peek_next().is_some() {
-                    check_for_intersection(sweep_line, &mut cursor, &mut events);
+        // Finish the deferred traps that did not carry on into this band
+        for sl_edge in sl_list.iter() {
+            deferred[sl_edge.index] = None;
+        }
+        for sl_edge in next_sl_list.iter() {
+            if let Some(right) = sl_edge.trap_right {
+                deferred[sl_edge.index] = Some((right, sl_edge.trap_top));
+            }
+        }
+        for sl_edge in sl_list.iter() {
+            if let Some(right) = sl_edge.trap_right {
+                if deferred[sl_edge.index] != Some((right, sl_edge.trap_top)) {
+                    add_to_traps(sl_edge, &edges[right].line, top, &mut traps);
                 }
-
-                println!("Finished INTERSECT Case");
             }
-
-
-        // print the Sweep Line List
-        cursor.reset();
-        println!("***Printing Sweep Line List***");
-        let mut index = 0;
-        while cursor.peek_next().is_some(){
-            let line = cursor.peek_next().unwrap().edge.line.clone();
-            let top = cursor.peek_next().unwrap().trap_top;
-            println!("     Index {}:  x:{}  Top:({},{}) Slope:{}", index, line.current_x_for_y(sweep_line), line.current_x_for_y(top), top, line.slope()) ;
-            index = index + 1;
-            cursor.next();
         }
-        println!("********");
+        sl_list = next_sl_list;
+    }
 
-        println!("EVENT COMPLETE at sweep: {}", sweep_line);
-        println!("")
+    if let Some(&last) = sweep_lines.last() {
+        finish_traps(&sl_list, &edges, last, &mut traps);
     }
     // Return the list of trapezoids
     traps
 }
 
-/// Checks to see if we should add the intersection to the event list
-/// Expects the cursor to be between the two lines that we want to check for intersection
-pub fn check_for_intersection(sweep_line: f32, cursor: &mut Cursor<SweepLineEdge>, events: &mut Vec<Event>)  {
-    // Verifies there is a previous and next before we check for intersections
-    if cursor.peek_prev().is_none() || cursor.peek_next().is_none() {
-        return;
-    }
-    println!("Starting Intersection Checks");
-    let next_line = &cursor.peek_next().unwrap().edge.line.clone();
-    let result = cursor.peek_prev().unwrap().edge.line.intersection(next_line);
-    // Add the event if it exists
-    if result.is_some() {
-        let point = result.unwrap();
-        // if the event has already happened, do not add it
-        if point.y <= sweep_line {
-            println!("Ending Intersection Checks: No Intersection");
-            return;
-        }
-        // if the intersection happens at the end of either line, do not add it
-        if point == cursor.peek_prev().unwrap().edge.line.max_y_point() {
-            println!("Ending Intersection Checks: No Intersection");
-            return;
+/// Adds the deferred trap of every SweepLineEdge in `sl_list` that still has one, ending at
+/// `bottom`.
+fn finish_traps(sl_list: &[SweepLineEdge], edges: &[Edge], bottom: f32, traps: &mut Vec<Trapezoid>) {
+    for sl_edge in sl_list.iter() {
+        if let Some(right) = sl_edge.trap_right {
+            add_to_traps(sl_edge, &edges[right].line, bottom, traps);
         }
-        if point == cursor.peek_next().unwrap().edge.line.max_y_point() {
-            println!("Ending Intersection Checks: No Intersection");
-            return;
-        }
-        // add the intersection
-        println!("Adding intersect to events");
-        events.push(Event::new_intersection(cursor.peek_prev().unwrap().edge, cursor.peek_next().unwrap().edge, &point));
-        events.sort();
-        println!("Ending Intersection Checks: Intersect Added");
     }
 }
 
-#[derive(Eq, PartialEq, Debug)]
-pub enum Comparator {
-    Greater,
-    Less,
-    Equal,
-    Empty,
-}
-
-/// Searches the sweep line list for a line matching the one if the edge
-/// point: the current event point
-/// edge: the edge we are trying to find a match to
-/// cursor: will be set to the position before the edge that is equal
-pub fn move_cursor_to_line(point: Point, edge:Edge, cursor: &mut Cursor<SweepLineEdge> ) {
-    println!("Starting move_cursor to line");
-    // If we are at the end of the list move one position back so we have something to compare
-//    if cursor.peek_next().is_none() {
-//        cursor.prev();
-//    }
-//    let mut result = Comparator::Empty;
-//    while result != Comparator::Equal {
-//        result = find_line_place(point, edge, *cursor.peek_next().unwrap());
-//
-//        if result == Comparator::Equal {
-//            break;
-//        } else if result == Comparator::Greater {
-//            cursor.prev();
-//        } else if result == Comparator::Less {
-//            cursor.next();
-//        } else {
-//            break;
-//        }
-//    }
-    // Temp code until we handle all of the edge cases involving horizontal lines and intersections
-    cursor.reset();
-    let mut result = find_line_place(point, edge, *cursor.peek_next().unwrap());
-    while result != Comparator::Equal {
-        cursor.next();
-        result = find_line_place(point, edge, *cursor.peek_next().unwrap());
-    }
-
-    println!("Ending move_cursor to point");
-}
-
-/// Compares a line to the next one in the list
-/// Returns Equal if line and next_sl_edge.line are equal
-/// Returns Greater if Next current x is greater then events, if points are equal compares slopes
-/// Returns Less if Next current x is less then events, if points are equal compares slopes
-pub fn find_line_place(point: Point, edge: Edge, next_sl_edge : SweepLineEdge) -> Comparator {
-    let next_line = next_sl_edge.edge.line;
-    if edge.line == next_line {
-        return Comparator::Equal;
-    }
-    // Get the point on the next line for the current y value we are at since that is how the
-    // linked list is sorted.
-    let next_x = next_sl_edge.edge.line.current_x_for_y(point.y);
-    // if the point is the same as the next point or lines intersect and we need to look at the
-    // slope to determine the sorting order. We already know they have the same y value so we just
-    // look at the x values
-    if point.x == next_x {
-        // compare the slopes of the lines
-        if edge.line.slope() < next_line.slope() {
-            return Comparator::Greater;
-        }
-        else {
-            return Comparator::Less;
-        }
-        // if the point is not on the nextLine we just need to see if it comes before or after
-    } else if point.x < next_x {
-        return Comparator::Greater;
-    } else {
-        return Comparator::Less;
-    }
-
-}
-
-/*
-add_to_traps(SL_edge edge, float bot, int mask, traps *traps)
-    //mask is 0xFFFFFFFF if using winding rule, 0x1 if using even/odd rule
-    //only output traps with positive area
-    if edge.deferred_trap.top >= bot
-        return
-    //count edge directions for ray right to infinity
-    in_out = 0
-    pos = edge.deferred_trap->right (or pos = edge->next? should be same, no?)
-    while (pos != null)
-        in_out += pos.dir
-        pos = pos.deferred_trap->right (or pos = pos->next? should be same, no?)
-    //in_out & mask is zero means do not fill (0 or even)
-    if in_out & mask != 0
-        LineSegment left, right
-        left = edge->LineSegment
-        right = edge.deferred_trap->right->LineSegment
-        traps_push(left, right, edge.deferred_trap.top, bot)
-*/
-fn add_to_traps(cursor: &mut Cursor<SweepLineEdge>, bottom: f32, mask: i32, traps: &mut Vec<Trapezoid>) {
-    println!("Starting add_to_traps");
-    if cursor.peek_prev().is_none() || cursor.peek_next().is_none() {
-        println!("Error: add_to_traps called when it shouldnt have");
-    }
-    // We unwrap because it should be considered a bug if this gets called when the value is
-    // incorrect
-    let sl_edge = *cursor.peek_prev().unwrap();
-
+/// Adds the trapezoid between `sl_edge` and `right`, running from the top of `sl_edge`'s
+/// deferred trap down to `bottom`.  Only trapezoids with positive area are added.
+fn add_to_traps(sl_edge: &SweepLineEdge, right: &LineSegment, bottom: f32, traps: &mut Vec<Trapezoid>) {
     if sl_edge.trap_top >= bottom {
         return;
     }
 
-    let right = *cursor.peek_next().unwrap();
-    let mut in_out = 0;
-    let mut count = 0;
-    println!("   Starting cursor count loop");
-    while let Some(edge) = cursor.next() {
-        count += 1;
-        in_out += edge.edge.direction;
-    }
-    println!("   Ending cursor count loop");
-
-    in_out &= mask;
-
-    // Add a trapezoid if in_out isn't zero
-    if in_out != 0 {
-        let left = sl_edge.edge.line;
-        let right = right.edge.line;
-        let top_y = sl_edge.trap_top;
-        let trap = bo_trap_from_lines(&left, &right, top_y, bottom);
-        traps.push(trap)
-    }
-    //rewind cursor to starting position (+1 because loop advances past end)
-    cursor.seek_backward(count+1);
-    println!("Ending add_to_traps");
+    let left = sl_edge.edge.line;
+    let top_y = sl_edge.trap_top;
+    let trap = bo_trap_from_lines(&left, right, top_y, bottom);
+    traps.push(trap)
 }
 
 fn bo_trap_from_lines(left: &LineSegment,
                       right: &LineSegment,
                       top: f32,
                       bottom: f32) -> Trapezoid {
-    println!("Starting Create Trap");
-    println!("left line: {:?}", left);
-    println!("right line: {:?}", right);
-    println!("top: {} bottom: {}", top, bottom);
     let top_left = Point::new(left.current_x_for_y(top),top);
     let top_right = Point::new(right.current_x_for_y(top),top);
     let bottom_left = Point::new(left.current_x_for_y(bottom),bottom);
     let bottom_right = Point::new(right.current_x_for_y(bottom),bottom);
 
-    println!("Ending Create Trap");
-    Trapezoid::from_points(top_left, top_right, bottom_left, bottom_right)
+    // The bases are always horizontal, building from them directly avoids having to find them
+    // among the points (which is ambiguous when a base has no length).
+    let top_base = LineSegment::from_points(top_left, top_right);
+    let bottom_base = LineSegment::from_points(bottom_left, bottom_right);
+    Trapezoid::from_bases(top_base, bottom_base)
 }
 
 #[cfg(test)]
@@ -816,14 +533,12 @@ mod tests {
     #[test]
     fn event_sorting_points() {
         // Verify that the events are sorted by points
-        let mut event_list = vec![
-            create_start_event(0., 2., 9., 9., 1),
+        let mut event_list = [create_start_event(0., 2., 9., 9., 1),
             create_start_event(0., 1., 9., 9., 1),
-            create_start_event(0., 3., 9., 9., 1),
-        ];
+            create_start_event(0., 3., 9., 9., 1)];
 
         event_list.sort();
-        assert_eq!(event_list.get(0).unwrap().point.y, 1.);
+        assert_eq!(event_list.first().unwrap().point.y, 1.);
         assert_eq!(event_list.get(1).unwrap().point.y, 2.);
         assert_eq!(event_list.get(2).unwrap().point.y, 3.);
     }
@@ -831,16 +546,14 @@ mod tests {
     #[test]
     fn event_sorting_type() {
         // Verify that the End event precedes the Start event for equal points
-        let mut event_list = vec![
-        create_start_event(0., 2., 9., 9., 1),
+        let mut event_list = [create_start_event(0., 2., 9., 9., 1),
         create_start_event(0., 1., 9., 9., 1),
         create_start_event(0., 3., 9., 9., 1),
-        create_end_event(0., 1., 9., 9., 1)
-        ];
+        create_end_event(0., 1., 9., 9., 1)];
 
         event_list.sort();
-        assert_eq!(event_list.get(0).unwrap().point.y, 1.);
-        assert_eq!(event_list.get(0).unwrap().event_type, EventType::End );
+        assert_eq!(event_list.first().unwrap().point.y, 1.);
+        assert_eq!(event_list.first().unwrap().event_type, EventType::End );
         assert_eq!(event_list.get(1).unwrap().point.y, 1.);
         assert_eq!(event_list.get(1).unwrap().event_type, EventType::Start );
     }
@@ -848,14 +561,12 @@ mod tests {
     #[test]
     fn event_sorting_intersection() {
         // Verify that intersection is between start end end event for equal points
-        let mut event_list = vec![
-        create_start_event(0., 2., 9., 9., 1),
+        let mut event_list = [create_start_event(0., 2., 9., 9., 1),
         create_start_event(0., 1., 9., 9., 1),
         create_intersection_event(0., 0., 0., 0., 1),
         create_start_event(0., 3., 9., 9., 1),
         create_end_event(0., 1., 9., 9., 1),
-        create_intersection_event(0., 1., 9., 9., 1)
-        ];
+        create_intersection_event(0., 1., 9., 9., 1)];
 
         event_list.sort();
         assert_eq!(event_list.get(1).unwrap().point.y, 1.);
@@ -889,7 +600,7 @@ mod tests {
         ];
 
         let event_list = event_list_from_edges(edges);
-        assert_eq!(event_list.get(0).unwrap().point, Point::new(0., 0.));
+        assert_eq!(event_list.first().unwrap().point, Point::new(0., 0.));
         assert_eq!(event_list.get(1).unwrap().point, Point::new(0., 1.));
         assert_eq!(event_list.get(2).unwrap().point, Point::new(1., 2.));
         assert_eq!(event_list.get(3).unwrap().point, Point::new(3., 4.));
@@ -907,7 +618,7 @@ mod tests {
         ];
 
         let event_list = event_list_from_edges(edges);
-        assert_eq!(event_list.get(0).unwrap().event_type, EventType::Start);
+        assert_eq!(event_list.first().unwrap().event_type, EventType::Start);
         assert_eq!(event_list.get(1).unwrap().event_type, EventType::Start);
         assert_eq!(event_list.get(2).unwrap().event_type, EventType::Start);
         assert_eq!(event_list.get(3).unwrap().event_type, EventType::End);
@@ -923,8 +634,8 @@ mod tests {
         ];
 
         let event_list = event_list_from_edges(edges);
-        assert_eq!(event_list.get(0).unwrap().point.x, 1.);
-        assert_eq!(event_list.get(0).unwrap().event_type, EventType::Start);
+        assert_eq!(event_list.first().unwrap().point.x, 1.);
+        assert_eq!(event_list.first().unwrap().event_type, EventType::Start);
         assert_eq!(event_list.get(1).unwrap().point.x, 3.);
         assert_eq!(event_list.get(1).unwrap().event_type, EventType::End);
     }
@@ -947,7 +658,7 @@ mod tests {
         create_edge(0., 1., 6., 6., 1),
         ];
 
-        let traps = sweep(edges);
+        let traps = sweep(edges, FillRule::Winding);
         assert_eq!(traps.len(), 2);
     }
 
@@ -958,7 +669,7 @@ mod tests {
         create_edge(0., 0., 1., 4., 1),
         ];
 
-        let traps = sweep(edges);
+        let traps = sweep(edges, FillRule::Winding);
         assert_eq!(traps.len(), 0);
     }
 
@@ -970,7 +681,7 @@ mod tests {
         create_edge(2., 0., 3., 4., 1),
         ];
 
-        let traps = sweep(edges);
+        let traps = sweep(edges, FillRule::Winding);
         assert_eq!(traps.len(), 1);
     }
 
@@ -983,7 +694,7 @@ mod tests {
         create_edge(4., 0., 5., 4., 1),
         ];
 
-        let traps = sweep(edges);
+        let traps = sweep(edges, FillRule::Winding);
         assert_eq!(traps.len(), 2);
     }
 
@@ -997,7 +708,7 @@ mod tests {
         create_edge(6., 0., 7., 4., -1),
         ];
 
-        let traps = sweep(edges);
+        let traps = sweep(edges, FillRule::Winding);
         assert_eq!(traps.len(), 2);
     }
 
@@ -1009,13 +720,17 @@ mod tests {
         create_edge(2., 0., 0., 4., 1),
         ];
 
-        let traps = sweep(edges);
+        let traps = sweep(edges, FillRule::Winding);
         assert_eq!(traps.len(), 2);
     }
 
     #[test]
     fn sweep_test_intersect_four() {
-        // Expected to make 2 traps with the 2 lines that cross
+        // The crossings at y = 2, 3 and 4 split the sweep into bands 0-2, 2-3, 3-4 and 4-6,
+        // which hold 1, 2, 2 and 1 filled regions.  Neighbouring regions are bounded by
+        // different edges, so none of them merge and the lines make 6 traps.  The linked list
+        // sweep this replaced returned 5, but three of those had no area and the other two
+        // were boxes that didn't follow the edges.
         let edges = vec![
         create_edge(0., 0., 4., 4., -1),
         create_edge(0., 2., 4., 6., -1),
@@ -1023,8 +738,8 @@ mod tests {
         create_edge(0., 6., 4., 2., 1),
         ];
 
-        let traps = sweep(edges);
-        assert_eq!(traps.len(), 5);
+        let traps = sweep(edges, FillRule::Winding);
+        assert_eq!(traps.len(), 6);
     }
 
     #[test]
@@ -1034,7 +749,7 @@ mod tests {
         create_edge(1., 0., 1., 2., 0),
         ];
 
-        let traps = sweep(edges);
+        let traps = sweep(edges, FillRule::Winding);
         assert_eq!(traps.len(), 0);
     }
 
@@ -1046,7 +761,7 @@ mod tests {
         create_edge(2., 1., 2., 3., 1),
         ];
 
-        let traps = sweep(edges);
+        let traps = sweep(edges, FillRule::Winding);
         assert_eq!(traps.len(), 1);
     }
 
@@ -1057,7 +772,7 @@ mod tests {
         create_edge(1., 0., 3., 0., 0),
         ];
 
-        let traps = sweep(edges);
+        let traps = sweep(edges, FillRule::Winding);
         assert_eq!(traps.len(), 0);
     }
 
@@ -1077,10 +792,10 @@ mod tests {
         create_edge(p3.x, p3.y, p1.x, p1.y, -1),
         ];
 
-        let traps = sweep(edges);
+        let traps = sweep(edges, FillRule::Winding);
         assert_eq!(traps.len(), 1);
-        assert!(traps.get(0).unwrap().contains_point(&Point{x:1.,y:1.}));
-        assert!(!traps.get(0).unwrap().contains_point(&Point{x:3.,y:1.}));
+        assert!(traps.first().unwrap().contains_point(&Point{x:1.,y:1.}));
+        assert!(!traps.first().unwrap().contains_point(&Point{x:3.,y:1.}));
     }
 
     #[test]
@@ -1107,10 +822,10 @@ mod tests {
         create_edge(p7.x, p7.y, p5.x, p5.y, -1),
         ];
 
-        let traps = sweep(edges);
+        let traps = sweep(edges, FillRule::Winding);
         assert_eq!(traps.len(), 2);
-        assert!(traps.get(0).unwrap().contains_point(&Point{x:1.,y:1.}));
-        assert!(!traps.get(0).unwrap().contains_point(&Point{x:3.,y:1.}));
+        assert!(traps.first().unwrap().contains_point(&Point{x:1.,y:1.}));
+        assert!(!traps.first().unwrap().contains_point(&Point{x:3.,y:1.}));
         assert!(traps.get(1).unwrap().contains_point(&Point{x:5.,y:1.}));
         assert!(!traps.get(1).unwrap().contains_point(&Point{x:3.,y:1.}));
     }
@@ -1126,7 +841,7 @@ mod tests {
         create_edge(1., 3., 0., 0., -1),
         ];
 
-        let traps = sweep(edges);
+        let traps = sweep(edges, FillRule::Winding);
         assert_eq!(traps.len(), 1);
     }
 
@@ -1140,76 +855,128 @@ mod tests {
         create_edge(0., 2., 2., 0., 1),
         ];
 
-        let traps = sweep(edges);
+        let traps = sweep(edges, FillRule::Winding);
         assert_eq!(traps.len(), 2);
     }
 
+    // Creates the closed polygon's edges, in drawing order.
+    fn polygon_edges(points: &[(f32, f32)]) -> Vec<Edge> {
+        let mut edges = Vec::new();
+        for idx in 0..points.len() {
+            let (x1, y1) = points[idx];
+            let (x2, y2) = points[(idx + 1) % points.len()];
+            edges.push(Edge::from_points(Point::new(x1, y1), Point::new(x2, y2)));
+        }
+        edges
+    }
+
+    fn traps_contain(traps: &[Trapezoid], x: f32, y: f32) -> bool {
+        traps.iter().any(|trap| trap.contains_point(&Point::new(x, y)))
+    }
+
+    #[test]
+    fn sweep_test_concave() {
+        // A U shape, the notch between the arms must stay empty
+        let edges = polygon_edges(&[(0., 0.), (1., 0.), (1., 2.), (2., 2.),
+                                    (2., 0.), (3., 0.), (3., 3.), (0., 3.)]);
+
+        let traps = sweep(edges, FillRule::Winding);
+        assert!(traps_contain(&traps, 0.5, 1.));
+        assert!(traps_contain(&traps, 2.5, 1.));
+        assert!(traps_contain(&traps, 1.5, 2.5));
+        assert!(!traps_contain(&traps, 1.5, 1.));
+    }
+
+    #[test]
+    fn sweep_test_star() {
+        // A self-intersecting five pointed star, the winding rule fills the middle
+        let edges = polygon_edges(&[(5., 0.), (8., 9.), (0., 3.5), (10., 3.5), (2., 9.)]);
+
+        let traps = sweep(edges, FillRule::Winding);
+        assert!(traps_contain(&traps, 5., 5.));
+        assert!(traps_contain(&traps, 5., 1.));
+        assert!(traps_contain(&traps, 1.5, 4.));
+        assert!(!traps_contain(&traps, 5., 8.5));
+        assert!(!traps_contain(&traps, 1., 1.));
+    }
+
+    #[test]
+    fn sweep_test_star_even_odd() {
+        // Under the even-odd rule the middle of the star is crossed twice, and stays empty
+        let edges = polygon_edges(&[(5., 0.), (8., 9.), (0., 3.5), (10., 3.5), (2., 9.)]);
+
+        let traps = sweep(edges, FillRule::EvenOdd);
+        assert!(!traps_contain(&traps, 5., 5.));
+        assert!(traps_contain(&traps, 5., 1.));
+        assert!(traps_contain(&traps, 1.5, 4.));
+        assert!(!traps_contain(&traps, 1., 1.));
+    }
+
+    #[test]
+    fn sweep_test_donut_same_direction() {
+        // Both squares drawn the same way: the winding rule fills the hole, even-odd doesn't
+        let mut edges = polygon_edges(&[(0., 0.), (4., 0.), (4., 4.), (0., 4.)]);
+        edges.extend(polygon_edges(&[(1., 1.), (3., 1.), (3., 3.), (1., 3.)]));
+
+        let traps = sweep(edges.clone(), FillRule::Winding);
+        assert!(traps_contain(&traps, 2., 2.));
+        let traps = sweep(edges, FillRule::EvenOdd);
+        assert!(!traps_contain(&traps, 2., 2.));
+        assert!(traps_contain(&traps, 0.5, 2.));
+    }
+
+    #[test]
+    fn sweep_test_donut() {
+        // An inner square drawn against the outer one leaves a hole
+        let mut edges = polygon_edges(&[(0., 0.), (4., 0.), (4., 4.), (0., 4.)]);
+        edges.extend(polygon_edges(&[(1., 1.), (1., 3.), (3., 3.), (3., 1.)]));
+
+        let traps = sweep(edges, FillRule::Winding);
+        assert!(traps_contain(&traps, 0.5, 2.));
+        assert!(traps_contain(&traps, 2., 0.5));
+        assert!(traps_contain(&traps, 3.5, 2.));
+        assert!(!traps_contain(&traps, 2., 2.));
+    }
+
     // Tests that add_to_traps doesn't change the traps vector if the SweepLineEdge's top
     // is greater than the `bottom` arg passed in.
     #[test]
     fn add_to_traps_edge_top_gt_bottom() {
         // Setup
-        let edge = SweepLineEdge {
-            trap_top: 1.,
-            left: 0.,
-            edge: Edge {
-                line: LineSegment::new(0., 0., 0., 0.),
-                top: 0.,
-                bottom: 0.,
-                direction: 0
-            }
-        };
+        let mut edge = SweepLineEdge::new(0., 0, Edge {
+            line: LineSegment::new(0., 0., 0., 0.),
+            top: 0.,
+            bottom: 0.,
+            direction: 0
+        });
+        edge.trap_top = 1.;
+        let right = LineSegment::new(0., 0., 0., 0.);
 
         // bottom is less than edge.top!
         let bottom = 0.;
-        let mask = 1;
         let mut traps: Vec<Trapezoid> = Vec::new();
-        let mut sl_list: LinkedList<SweepLineEdge> = LinkedList::new();
-        sl_list.push_front(edge);
-        let mut cursor = sl_list.cursor();
-        cursor.next();
         // Call
-        add_to_traps(&mut cursor, bottom, mask, &mut traps);
+        add_to_traps(&edge, &right, bottom, &mut traps);
         assert_eq!(traps.len(), 0);
     }
 
     #[test]
     fn add_to_traps_edge_top_lt_bottom() {
 
-        let edge1 = SweepLineEdge {
-            trap_top: 1.,
-            left: 0.,
-            edge: Edge {
-                line: LineSegment::new(1., 1., 3., 8.),
-                top: 1.,
-                bottom: 0.,
-                direction: 1
-            }
-        };
-
-        let edge2 = SweepLineEdge {
-            trap_top: 1.,
-            left: 0.,
-            edge: Edge {
-                line: LineSegment::new(5., 1., 1., 8.),
-                top: 1.,
-                bottom: 0.,
-                direction: -1
-            }
-        };
-
-        let mut sl_list: LinkedList<SweepLineEdge> = LinkedList::new();
-        sl_list.push_front(edge1);
-        sl_list.push_back(edge2);
+        let mut edge1 = SweepLineEdge::new(0., 0, Edge {
+            line: LineSegment::new(1., 1., 3., 8.),
+            top: 1.,
+            bottom: 0.,
+            direction: 1
+        });
+        edge1.trap_top = 1.;
+        let right = LineSegment::new(5., 1., 1., 8.);
 
         let bottom = 20.;
-        let mask = 1;
         let mut traps: Vec<Trapezoid> = Vec::new();
 
-        let mut cursor = sl_list.cursor();
-        cursor.next();
         // Call
-        add_to_traps(&mut cursor, bottom, mask, &mut traps);
-        assert!(traps.len() > 0);
+        add_to_traps(&edge1, &right, bottom, &mut traps);
+        assert!(!traps.is_empty());
     }
 }
@@ -203,7 +203,7 @@ impl LineSegment {
             //if b1 == b2 {
                 //colinear lines
             //}
-            return None;
+            None
         }
         else {
             let intersection_x = (b2 - b1) / (m1 - m2);
@@ -321,6 +321,26 @@ pub struct Edge {
     pub direction: i32,
 }
 
+impl Edge {
+    /// Returns the Edge drawn from `from` to `to`, with its top, bottom, and direction filled in.
+    pub fn from_points(from: Point, to: Point) -> Edge {
+        let direction = if to.y > from.y {
+            1
+        } else if to.y < from.y {
+            -1
+        } else {
+            0
+        };
+
+        Edge {
+            line: LineSegment::from_points(from, to),
+            top: from.y.min(to.y),
+            bottom: from.y.max(to.y),
+            direction: direction,
+        }
+    }
+}
+
 impl Clone for Edge {
     fn clone(&self) -> Edge { *self }
 }
@@ -378,7 +398,7 @@ impl PartialEq for Vector {
 
 #[cfg(test)]
 mod tests {
-    use super::{LineSegment, Point, Vector};
+    use super::{Edge, LineSegment, Point, Vector};
     use std::f32;
     use types::{Pixel, IntoPixels};
 
@@ -572,6 +592,19 @@ mod tests {
         assert_eq!(line.current_x_for_y(2.),4.);
     }
 
+    // Tests that Edge::from_points() orders top and bottom, and sets the drawing direction.
+    #[test]
+    fn edge_from_points() {
+        let down = Edge::from_points(Point::new(0., 0.), Point::new(1., 2.));
+        assert_eq!((down.top, down.bottom, down.direction), (0., 2., 1));
+
+        let up = Edge::from_points(Point::new(1., 2.), Point::new(0., 0.));
+        assert_eq!((up.top, up.bottom, up.direction), (0., 2., -1));
+
+        let horizontal = Edge::from_points(Point::new(0., 1.), Point::new(3., 1.));
+        assert_eq!((horizontal.top, horizontal.bottom, horizontal.direction), (1., 1., 0));
+    }
+
     // Tests Vector::new()
     #[test]
     fn vector_new() {
@@ -678,7 +711,7 @@ mod tests {
           let b = Point{x: 0., y: 10.};
           let line = LineSegment{point1: a, point2: b};
           let coordinates = line.into_pixels();
-          assert!(coordinates.len() != 0);
+          assert!(!coordinates.is_empty());
           for (idx, coordinate) in coordinates.iter().enumerate() {
               let expected_coordinate = Pixel::new(0, idx as i32 + 1);
               assert_eq!(*coordinate, expected_coordinate);
@@ -692,7 +725,7 @@ mod tests {
           let b = Point{x: 10., y: 0.};
           let line = LineSegment{point1: a, point2: b};
           let coordinates = line.into_pixels();
-          assert!(coordinates.len() != 0);
+          assert!(!coordinates.is_empty());
           for (idx, coordinate) in coordinates.iter().enumerate() {
               let expected_coordinate = Pixel::new(idx as i32 + 1, 0);
               assert_eq!(*coordinate, expected_coordinate);
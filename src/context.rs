@@ -36,38 +36,343 @@
  *
  */
 
-use surfaces::ImageSurface;
+use surfaces::{ImageSurface, SvgSurface, PdfSurface};
 use types::Rgba;
 use operators::Operator;
 use operators::fetch_operator;
+use common_geometry::{Point, Edge};
+use path::Path;
+use status::Status;
+use bo_trap::sweep;
+use trapezoid_rasterizer::mask_from_trapezoids;
+use stroker::{StrokeStyle, stroke_polygons, polygon_edges};
+use matrix::Matrix;
+use patterns::{Pattern, PatternType};
+use pdf;
+use svg;
+use surfaces::Format;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::borrow::Cow;
+
+// The smallest tolerance set_tolerance accepts, as CAIRO_TOLERANCE_MINIMUM.
+const TOLERANCE_MINIMUM: f32 = 1. / 256.;
+
+/// Specifies which areas of a path are inside it when filling, analogous to cairo_fill_rule_t.
+///
+/// Both rules count how many times a ray from a point out to infinity crosses the path.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FillRule {
+    /// Crossings where the path runs one way count +1 and the other way -1.  The point is
+    /// inside if the total is not zero.
+    Winding,
+    /// The point is inside if the ray crosses the path an odd number of times.
+    EvenOdd,
+}
+
+/// Specifies how the ends of open sub-paths are drawn when stroking, analogous to
+/// cairo_line_cap_t.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LineCap {
+    /// The line stops exactly at its end point.
+    Butt,
+    /// A half circle centered on the end point is added.
+    Round,
+    /// The line is extended past its end point by half the line width.
+    Square,
+}
+
+/// Specifies how the corners between segments are drawn when stroking, analogous to
+/// cairo_line_join_t.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LineJoin {
+    /// The outer edges are extended until they meet, unless that is farther than the miter
+    /// limit allows, in which case the join is beveled.
+    Miter,
+    /// A circle centered on the corner is added.
+    Round,
+    /// The corner is cut off halfway through the line width.
+    Bevel,
+}
+
+/// The surface a context draws on.
+pub enum Target<'a, 'd: 'a> {
+    /// Drawing is rasterized into the pixels of an image surface.
+    Image(&'a mut ImageSurface<'d>),
+    /// Drawing is recorded as vector graphics on an SVG surface.
+    Svg(&'a mut SvgSurface),
+    /// Drawing is recorded as vector graphics on the current page of a PDF surface, and
+    /// rasterized into its image of the page for the operators PDF has nothing like.
+    Pdf(&'a mut PdfSurface<'d>),
+}
+
+impl<'a, 'd> From<&'a mut ImageSurface<'d>> for Target<'a, 'd> {
+    fn from(surface: &'a mut ImageSurface<'d>) -> Target<'a, 'd> {
+        Target::Image(surface)
+    }
+}
+
+impl<'a, 'd> From<&'a mut SvgSurface> for Target<'a, 'd> {
+    fn from(surface: &'a mut SvgSurface) -> Target<'a, 'd> {
+        Target::Svg(surface)
+    }
+}
+
+impl<'a, 'd> From<&'a mut PdfSurface<'d>> for Target<'a, 'd> {
+    fn from(surface: &'a mut PdfSurface<'d>) -> Target<'a, 'd> {
+        Target::Pdf(surface)
+    }
+}
+
+// The region drawing is restricted to.  Coverage is shared through an Rc, so that `save` only
+// copies a pointer; a new clip always builds a new coverage rather than changing a shared one.
+#[derive(Clone)]
+enum Clip {
+    // The coverage of every pixel of an image target
+    Coverage(Rc<Vec<f32>>),
+    // The id of a clip path recorded on an SVG target, and its area for when the drawing is
+    // rasterized
+    Svg(String, Rc<ClipArea>),
+    // The index of a clip path recorded on a PDF target, and its area for when the page is
+    // rasterized
+    Pdf(usize, Rc<ClipArea>),
+}
+
+impl Clip {
+    // Returns how much of every pixel of a `width` by `height` image the clip covers.
+    fn coverage(&self, width: usize, height: usize) -> Rc<Vec<f32>> {
+        match *self {
+            Clip::Coverage(ref coverage) => coverage.clone(),
+            Clip::Svg(_, ref area) | Clip::Pdf(_, ref area) => area.coverage(width, height),
+        }
+    }
+
+    fn svg_id(&self) -> Option<&str> {
+        match *self {
+            Clip::Svg(ref id, _) => Some(id),
+            _ => None,
+        }
+    }
+
+    fn pdf_index(&self) -> Option<usize> {
+        match *self {
+            Clip::Pdf(index, _) => Some(index),
+            _ => None,
+        }
+    }
+
+    fn area(&self) -> Option<Rc<ClipArea>> {
+        match *self {
+            Clip::Svg(_, ref area) | Clip::Pdf(_, ref area) => Some(area.clone()),
+            Clip::Coverage(_) => None,
+        }
+    }
+}
+
+// The area of a clip on a vector target, within the area of its parent clip if there is one.
+// It is only rasterized if drawing on the target has to be.
+struct ClipArea {
+    edges: Vec<Edge>,
+    fill_rule: FillRule,
+    parent: Option<Rc<ClipArea>>,
+    // The coverage, once it has been rasterized
+    coverage: RefCell<Option<Rc<Vec<f32>>>>,
+}
+
+impl ClipArea {
+    fn new(edges: Vec<Edge>, fill_rule: FillRule, parent: Option<Rc<ClipArea>>) -> ClipArea {
+        ClipArea {
+            edges: edges,
+            fill_rule: fill_rule,
+            parent: parent,
+            coverage: RefCell::new(None),
+        }
+    }
+
+    // Returns how much of every pixel of a `width` by `height` image the area covers.  Vector
+    // targets always rasterize at the same size, so the first coverage is kept.
+    fn coverage(&self, width: usize, height: usize) -> Rc<Vec<f32>> {
+        if let Some(ref coverage) = *self.coverage.borrow() {
+            return coverage.clone();
+        }
+        let mut coverage = coverage_from_edges(self.edges.clone(), self.fill_rule, width, height);
+        if let Some(ref parent) = self.parent {
+            let parent = parent.coverage(width, height);
+            for (coverage, parent) in coverage.iter_mut().zip(parent.iter()) {
+                *coverage *= *parent;
+            }
+        }
+        let coverage = Rc::new(coverage);
+        *self.coverage.borrow_mut() = Some(coverage.clone());
+        coverage
+    }
+}
+
+// What a drawing operation draws with the current path.
+#[allow(clippy::large_enum_variant)]
+enum Drawing<'p> {
+    // The area inside the path
+    Fill,
+    // The stroke along the path
+    Stroke,
+    // Everywhere, faded to an alpha
+    Paint(f32),
+    // Everywhere, through the alpha of a pattern seen through a matrix mapping device space to
+    // it
+    Mask(Cow<'p, Pattern>, Matrix),
+}
+
+impl<'p> Drawing<'p> {
+    // Returns the drawing with a copy of the mask pattern it borrows, if any.
+    fn into_owned(self) -> Drawing<'static> {
+        match self {
+            Drawing::Fill => Drawing::Fill,
+            Drawing::Stroke => Drawing::Stroke,
+            Drawing::Paint(alpha) => Drawing::Paint(alpha),
+            Drawing::Mask(pattern, matrix) => {
+                Drawing::Mask(Cow::Owned(pattern.into_owned()), matrix)
+            },
+        }
+    }
+}
+
+// A snapshot of the drawing state, pushed by `Context::save` and popped by `Context::restore`.
+// Analogous to cairo_gstate_t.
+#[derive(Clone)]
+struct GState {
+    source: Rc<Pattern>,
+    source_ctm: Matrix,
+    operator: Operator,
+    tolerance: f32,
+    fill_rule: FillRule,
+    stroke_style: StrokeStyle,
+    ctm: Matrix,
+    clip: Option<Clip>,
+}
+
+// A drawing operation on a vector target, kept so that it can be rasterized later.
+struct Recorded {
+    state: GState,
+    path: Path,
+    drawing: Drawing<'static>,
+}
+
+// The drawing on the current page of a vector target, for rasterizing what the format can't
+// draw.  Drawing is only recorded until an operator that has to be rasterized is used.  Then the
+// recording is rasterized into an image one pixel per point, which from then on is drawn on too.
+pub(crate) struct Fallback {
+    width: usize,
+    height: usize,
+    recorded: Vec<Recorded>,
+    image: Option<ImageSurface<'static>>,
+}
+
+impl Fallback {
+    // Returns an empty recording of a `width` by `height` pixel page.
+    pub(crate) fn new(width: usize, height: usize) -> Fallback {
+        Fallback {
+            width: width,
+            height: height,
+            recorded: Vec::new(),
+            image: None,
+        }
+    }
+
+    // Returns the image of the page, if it has been rasterized.
+    pub(crate) fn image(&self) -> Option<&ImageSurface<'static>> {
+        self.image.as_ref()
+    }
+
+    // Keeps up with `drawing` of `path` drawn with `state`.  When `rasterized`, the page is
+    // rasterized if it isn't yet, and the pixels the drawing could change are returned.
+    fn draw(&mut self, state: GState, path: &Path, drawing: Drawing, rasterized: bool)
+            -> Option<Vec<bool>> {
+        if self.image.is_none() {
+            if !rasterized {
+                self.recorded.push(Recorded {
+                    state: state,
+                    path: path.clone(),
+                    drawing: drawing.into_owned(),
+                });
+                return None;
+            }
+            let mut image = ImageSurface::create(Format::ARGB32, self.width, self.height);
+            for recorded in self.recorded.drain(..) {
+                recorded.state.rasterize(&recorded.path, &recorded.drawing, &mut image, false);
+            }
+            self.image = Some(image);
+        }
+        let image = self.image.as_mut().expect("the page was just rasterized");
+        state.rasterize(path, &drawing, image, rasterized)
+    }
+}
 
 /// Struct defined for context
-pub struct Context<'a>{
-    pub rgba: Rgba,
-    target: &'a mut ImageSurface,
+pub struct Context<'a, 'd: 'a>{
+    source: Rc<Pattern>,
+    // The CTM when the source was set, which fixes the source in that user space
+    source_ctm: Matrix,
+    target: Target<'a, 'd>,
     operator: Operator,
+    path: Path,
+    tolerance: f32,
+    fill_rule: FillRule,
+    stroke_style: StrokeStyle,
+    // The current transformation matrix, mapping user space to device space
+    ctm: Matrix,
+    // The clip region, or None when nothing is clipped
+    clip: Option<Clip>,
+    saved: Vec<GState>,
 }
 
 /// Implementation of methods for context
-impl<'a> Context<'a> {
+impl<'a, 'd> Context<'a, 'd> {
     //Creates a new cairo context with rgba values set to zeroes with passed ImageSurface as target surface
     //When new context is created a target surface needs to be passed in.
-    pub fn create(target: &'a mut ImageSurface )-> Context {
+    //An SvgSurface can be passed in too, which records the drawing instead.
+    pub fn create<T: Into<Target<'a, 'd>>>(target: T)-> Context<'a, 'd> {
         Context{
-            rgba: Rgba::new(0., 0., 0., 0.),
-            target: target,
-            operator: Operator::Over
+            source: Rc::new(Pattern::create_rgba(0., 0., 0., 0.)),
+            source_ctm: Matrix::init_identity(),
+            target: target.into(),
+            operator: Operator::Over,
+            path: Path::new(),
+            tolerance: 0.1,
+            fill_rule: FillRule::Winding,
+            stroke_style: StrokeStyle::new(),
+            ctm: Matrix::init_identity(),
+            clip: None,
+            saved: Vec::new(),
         }
     }
 
     /// Sets Rgba values of source to used defined values
     /// This function changes the Rgba values of the source
     pub fn set_source_rgba(&mut self, red: f32, green: f32, blue: f32, alpha: f32){
-        self.rgba.red = red * alpha;
-        self.rgba.green = green * alpha;
-        self.rgba.blue = blue * alpha;
-        self.rgba.alpha = alpha;
-        self.rgba.correct();
+        self.set_source(&Pattern::create_rgba(red, green, blue, alpha));
+    }
+
+    /// Sets the source to a copy of `source`, which everything is drawn with from now on.
+    ///
+    /// The source is fixed in the user space in effect now, so later changes to the current
+    /// transformation matrix do not move it.
+    pub fn set_source(&mut self, source: &Pattern) {
+        self.source = Rc::new(source.clone());
+        self.source_ctm = self.ctm;
+    }
+
+    /// Sets the source to a copy of `surface`, with its top left corner at (x, y) in user space.
+    /// Outside of the surface the source is transparent.
+    pub fn set_source_surface(&mut self, surface: &ImageSurface, x: f32, y: f32) {
+        let mut source = Pattern::create_for_surface(surface);
+        source.set_matrix(Matrix::init_translate(-x, -y))
+            .expect("a translation is always invertible");
+        self.set_source(&source);
+    }
+
+    /// Returns the current source.
+    pub fn get_source(&self) -> &Pattern {
+        &self.source
     }
 
     ///Set Operator function
@@ -83,7 +388,7 @@ impl<'a> Context<'a> {
     ///
     ///# Usage
     ///set_operator(&context, op_enum);
-    fn set_operator(&mut self, operator: Operator){
+    pub fn set_operator(&mut self, operator: Operator){
         self.operator = operator;
     }
 
@@ -96,39 +401,694 @@ impl<'a> Context<'a> {
     ///
     /// # Usage
     /// let op_enum = get_operator();
-    fn get_operator(&self)-> &Operator{
+    pub fn get_operator(&self)-> &Operator{
         &self.operator
     }
 
-    /// Paints this context's Rgba on the destination surface with the over operator.
+    /// Begins a new sub-path.  After this call the current point will be (x, y).
+    pub fn move_to(&mut self, x: f32, y: f32) {
+        let point = self.device_point(x, y);
+        self.path.move_to(point);
+    }
+
+    /// Adds a line to the path from the current point to (x, y).  After this call the current
+    /// point will be (x, y).
+    ///
+    /// If there is no current point, this behaves like `move_to(x, y)`.
+    pub fn line_to(&mut self, x: f32, y: f32) {
+        let point = self.device_point(x, y);
+        self.path.line_to(point);
+    }
+
+    /// Adds a cubic Bézier spline to the path from the current point to (x3, y3), using (x1, y1)
+    /// and (x2, y2) as control points.  After this call the current point will be (x3, y3).
+    ///
+    /// If there is no current point, the spline starts at (x1, y1).
+    pub fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x3: f32, y3: f32) {
+        let control1 = self.device_point(x1, y1);
+        let control2 = self.device_point(x2, y2);
+        let end = self.device_point(x3, y3);
+        self.path.curve_to(control1, control2, end);
+    }
+
+    /// Begins a new sub-path at an offset of (dx, dy) from the current point.
+    ///
+    /// Returns `Err(Status::NoCurrentPoint)` if there is no current point.
+    pub fn rel_move_to(&mut self, dx: f32, dy: f32) -> Result<(), Status> {
+        let (x, y) = self.get_current_point().ok_or(Status::NoCurrentPoint)?;
+        self.move_to(x + dx, y + dy);
+        Ok(())
+    }
+
+    /// Adds a line to the path from the current point to a point offset by (dx, dy).
+    ///
+    /// Returns `Err(Status::NoCurrentPoint)` if there is no current point.
+    pub fn rel_line_to(&mut self, dx: f32, dy: f32) -> Result<(), Status> {
+        let (x, y) = self.get_current_point().ok_or(Status::NoCurrentPoint)?;
+        self.line_to(x + dx, y + dy);
+        Ok(())
+    }
+
+    /// Relative-coordinate version of `curve_to`.  All offsets are relative to the current point.
+    ///
+    /// Returns `Err(Status::NoCurrentPoint)` if there is no current point.
+    pub fn rel_curve_to(&mut self, dx1: f32, dy1: f32, dx2: f32, dy2: f32, dx3: f32, dy3: f32)
+        -> Result<(), Status> {
+        let (x, y) = self.get_current_point().ok_or(Status::NoCurrentPoint)?;
+        self.curve_to(x + dx1, y + dy1, x + dx2, y + dy2, x + dx3, y + dy3);
+        Ok(())
+    }
+
+    /// Adds a line from the current point to the beginning of the current sub-path, and closes
+    /// the sub-path.  After this call the current point will be at the start of the sub-path.
+    ///
+    /// Does nothing if there is no current point.
+    pub fn close_path(&mut self) {
+        self.path.close_path();
+    }
+
+    /// Clears the current path and the current point.
+    pub fn new_path(&mut self) {
+        self.path.clear();
+    }
+
+    /// Begins a new sub-path without a current point.  Unlike `move_to`, the start of the new
+    /// sub-path is decided by whatever segment gets added next.
+    pub fn new_sub_path(&mut self) {
+        self.path.new_sub_path();
+    }
+
+    /// Returns true if the current path has a current point.
+    pub fn has_current_point(&self) -> bool {
+        self.path.current_point().is_some()
+    }
+
+    /// Returns the current point of the current path in user space, or `None` if there isn't
+    /// one.
+    pub fn get_current_point(&self) -> Option<(f32, f32)> {
+        self.path.current_point().map(|point| self.device_to_user(point.x, point.y))
+    }
+
+    /// Moves the origin of user space by (tx, ty), in user space units.
+    ///
+    /// Returns `Err(Status::InvalidMatrix)`, leaving user space unchanged, if either offset isn't
+    /// finite.
+    pub fn translate(&mut self, tx: f32, ty: f32) -> Result<(), Status> {
+        self.transform(&Matrix::init_translate(tx, ty))
+    }
+
+    /// Scales user space by `sx` along its x axis and `sy` along its y axis.
+    ///
+    /// Returns `Err(Status::InvalidMatrix)`, leaving user space unchanged, if either factor is
+    /// zero.
+    pub fn scale(&mut self, sx: f32, sy: f32) -> Result<(), Status> {
+        self.transform(&Matrix::init_scale(sx, sy))
+    }
+
+    /// Rotates user space by `radians`.  Positive angles rotate the positive x axis toward the
+    /// positive y axis.
+    ///
+    /// Returns `Err(Status::InvalidMatrix)`, leaving user space unchanged, if `radians` isn't
+    /// finite.
+    pub fn rotate(&mut self, radians: f32) -> Result<(), Status> {
+        self.transform(&Matrix::init_rotate(radians))
+    }
+
+    /// Applies `matrix` to user space, before the current transformation.
+    ///
+    /// Returns `Err(Status::InvalidMatrix)`, leaving user space unchanged, if the result can't
+    /// be inverted.
+    pub fn transform(&mut self, matrix: &Matrix) -> Result<(), Status> {
+        self.set_matrix(&matrix.multiply(&self.ctm))
+    }
+
+    /// Replaces the current transformation matrix, which maps user space to device space.
+    ///
+    /// Returns `Err(Status::InvalidMatrix)`, leaving it unchanged, if `matrix` can't be inverted.
+    pub fn set_matrix(&mut self, matrix: &Matrix) -> Result<(), Status> {
+        matrix.invert()?;
+        self.ctm = *matrix;
+        Ok(())
+    }
+
+    /// Returns the current transformation matrix.
+    pub fn get_matrix(&self) -> Matrix {
+        self.ctm
+    }
+
+    /// Resets the current transformation matrix, making user space the same as device space.
+    pub fn identity_matrix(&mut self) {
+        self.ctm = Matrix::init_identity();
+    }
+
+    /// Returns the device space position of the user space point (x, y).
+    pub fn user_to_device(&self, x: f32, y: f32) -> (f32, f32) {
+        self.ctm.transform_point(x, y)
+    }
+
+    /// Returns the user space position of the device space point (x, y).
+    pub fn device_to_user(&self, x: f32, y: f32) -> (f32, f32) {
+        self.ctm_inverse().transform_point(x, y)
+    }
+
+    // The current path is kept in device space, so points are transformed as they're added.
+    fn device_point(&self, x: f32, y: f32) -> Point {
+        let (x, y) = self.user_to_device(x, y);
+        Point::new(x, y)
+    }
+
+    // The matrix mapping device space back to user space.  set_matrix never lets a matrix that
+    // can't be inverted become the CTM.
+    fn ctm_inverse(&self) -> Matrix {
+        self.ctm.invert().expect("the current transformation matrix is always invertible")
+    }
+
+    /// Sets the tolerance used when converting curves into line segments, in pixels.  Smaller
+    /// values give smoother curves at the cost of speed.  The default is 0.1.
+    ///
+    /// Like cairo, values below 1/256 are raised to it, since a tolerance of zero would split
+    /// every curve as finely as possible.  NaN is ignored, leaving the tolerance unchanged.
+    pub fn set_tolerance(&mut self, tolerance: f32) {
+        if tolerance.is_nan() {
+            return;
+        }
+        self.tolerance = tolerance.max(TOLERANCE_MINIMUM);
+    }
+
+    /// Returns the tolerance used when converting curves into line segments.
+    pub fn get_tolerance(&self) -> f32 {
+        self.tolerance
+    }
+
+    /// Sets the rule `fill` uses to decide which areas are inside the path.  The default is
+    /// `FillRule::Winding`.
+    pub fn set_fill_rule(&mut self, fill_rule: FillRule) {
+        self.fill_rule = fill_rule;
+    }
+
+    /// Returns the rule `fill` uses to decide which areas are inside the path.
+    pub fn get_fill_rule(&self) -> FillRule {
+        self.fill_rule
+    }
+
+    /// Sets the width of the lines drawn by `stroke`.  The default is 2.0.
+    pub fn set_line_width(&mut self, width: f32) {
+        self.stroke_style.line_width = width;
+    }
+
+    /// Returns the width of the lines drawn by `stroke`.
+    pub fn get_line_width(&self) -> f32 {
+        self.stroke_style.line_width
+    }
+
+    /// Sets how `stroke` draws the ends of open sub-paths.  The default is `LineCap::Butt`.
+    pub fn set_line_cap(&mut self, line_cap: LineCap) {
+        self.stroke_style.line_cap = line_cap;
+    }
+
+    /// Returns how `stroke` draws the ends of open sub-paths.
+    pub fn get_line_cap(&self) -> LineCap {
+        self.stroke_style.line_cap
+    }
+
+    /// Sets how `stroke` draws the corners between segments.  The default is `LineJoin::Miter`.
+    pub fn set_line_join(&mut self, line_join: LineJoin) {
+        self.stroke_style.line_join = line_join;
+    }
+
+    /// Returns how `stroke` draws the corners between segments.
+    pub fn get_line_join(&self) -> LineJoin {
+        self.stroke_style.line_join
+    }
+
+    /// Sets the limit on the ratio of a miter join's length to the line width.  Corners that
+    /// would exceed it are beveled instead.  The default is 10.0, which bevels corners sharper
+    /// than about 11 degrees.
+    pub fn set_miter_limit(&mut self, limit: f32) {
+        self.stroke_style.miter_limit = limit;
+    }
+
+    /// Returns the limit on the ratio of a miter join's length to the line width.
+    pub fn get_miter_limit(&self) -> f32 {
+        self.stroke_style.miter_limit
+    }
+
+    /// Sets the dash pattern used by `stroke`.  `dashes` alternates between the lengths of the
+    /// "on" and "off" parts of the line, and `offset` is how far into the pattern the stroke of
+    /// each sub-path starts.  An empty `dashes` turns dashing off.
+    ///
+    /// Like cairo, a pattern with an odd number of lengths is repeated, so `[3.]` draws 3 units on
+    /// and 3 units off.  "On" dashes of zero length are drawn as dots with round or square caps.
+    ///
+    /// Returns `Err(Status::InvalidDash)`, leaving the dash pattern unchanged, if any length is
+    /// negative or every length is zero, or if a length or the offset isn't finite.
+    pub fn set_dash(&mut self, dashes: &[f32], offset: f32) -> Result<(), Status> {
+        if dashes.iter().any(|&dash| !dash.is_finite() || dash < 0.) || !offset.is_finite() ||
+           (!dashes.is_empty() && dashes.iter().all(|&dash| dash == 0.)) {
+            return Err(Status::InvalidDash);
+        }
+
+        self.stroke_style.dashes = dashes.to_vec();
+        self.stroke_style.dash_offset = offset;
+        Ok(())
+    }
+
+    /// Returns the dash pattern and offset used by `stroke`.  The pattern is empty when dashing
+    /// is off.
+    pub fn get_dash(&self) -> (&[f32], f32) {
+        (&self.stroke_style.dashes, self.stroke_style.dash_offset)
+    }
+
+    /// Pushes a copy of the drawing state onto a stack, so that it can be brought back by
+    /// `restore`.  The source, operator, tolerance, fill rule, line style, transformation matrix
+    /// and clip are saved.  The current path is not part of the drawing state.
+    pub fn save(&mut self) {
+        let gstate = self.gstate();
+        self.saved.push(gstate);
+    }
+
+    /// Brings back the drawing state saved by the matching call to `save`.
+    ///
+    /// Returns `Err(Status::InvalidRestore)` if there is no saved state left to restore.
+    pub fn restore(&mut self) -> Result<(), Status> {
+        let gstate = self.saved.pop().ok_or(Status::InvalidRestore)?;
+        self.source = gstate.source;
+        self.source_ctm = gstate.source_ctm;
+        self.operator = gstate.operator;
+        self.tolerance = gstate.tolerance;
+        self.fill_rule = gstate.fill_rule;
+        self.stroke_style = gstate.stroke_style;
+        self.ctm = gstate.ctm;
+        self.clip = gstate.clip;
+        Ok(())
+    }
+
+    /// Restricts drawing to the area the current path would fill, intersected with the current
+    /// clip, then clears the path.
+    pub fn clip(&mut self) {
+        self.clip_preserve();
+        self.path.clear();
+    }
+
+    /// Restricts drawing like `clip`, but keeps the path for further drawing.
+    pub fn clip_preserve(&mut self) {
+        let fill_rule = self.fill_rule;
+        self.clip = match self.target {
+            Target::Image(ref image) => {
+                let (width, height) = (image.width, image.height);
+                let edges = self.path.fill_edges(self.tolerance);
+                let mut coverage = coverage_from_edges(edges, fill_rule, width, height);
+                if let Some(ref clip) = self.clip {
+                    let clip = clip.coverage(width, height);
+                    for (coverage, clip) in coverage.iter_mut().zip(clip.iter()) {
+                        *coverage *= *clip;
+                    }
+                }
+                Some(Clip::Coverage(Rc::new(coverage)))
+            },
+            Target::Svg(ref mut svg) => {
+                let parent = self.clip.as_ref().and_then(Clip::svg_id);
+                let id = svg.clip(&self.path, fill_rule, parent);
+                let edges = self.path.fill_edges(self.tolerance);
+                let parent = self.clip.as_ref().and_then(Clip::area);
+                Some(Clip::Svg(id, Rc::new(ClipArea::new(edges, fill_rule, parent))))
+            },
+            Target::Pdf(ref mut pdf) => {
+                let parent = self.clip.as_ref().and_then(Clip::pdf_index);
+                let index = pdf.clip(&self.path, fill_rule, parent);
+                let edges = self.path.fill_edges(self.tolerance);
+                let parent = self.clip.as_ref().and_then(Clip::area);
+                Some(Clip::Pdf(index, Rc::new(ClipArea::new(edges, fill_rule, parent))))
+            },
+        };
+    }
+
+    /// Ends the current page, so that drawing continues on a new, empty page.  Only PDF
+    /// surfaces have more than one page, so this does nothing on other targets.
     ///
-    /// This is a completely naive, and frankly useless implementation.  It is a place holder for
-    /// the real paint function to later be implemented.  It operates on every 'pixel' of the
-    /// destination surface.
+    /// Returns the error of `PdfSurface::show_page` if the page can't be written.
+    pub fn show_page(&mut self) -> Result<(), Status> {
+        match self.target {
+            Target::Pdf(ref mut pdf) => pdf.show_page(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Removes the clip, so that drawing can reach the whole target again.
+    pub fn reset_clip(&mut self) {
+        self.clip = None;
+    }
+
+    /// Paints this context's Rgba on every pixel of the destination surface inside the clip,
+    /// using the context's operator.
     pub fn paint(&mut self) {
-        let op = Operator::Over;
-        let operator = fetch_operator(&op);
-        for mut pixel in self.target.iter_mut() {
-            operator(&self.rgba, pixel);
+        self.paint_with_alpha(1.);
+    }
+
+    /// Paints like `paint`, but with the source faded to `alpha`, which is clamped between 0
+    /// and 1.
+    pub fn paint_with_alpha(&mut self, alpha: f32) {
+        self.draw(Drawing::Paint(alpha.clamp(0., 1.)));
+    }
+
+    /// Paints this context's Rgba using the alpha of `pattern` as a mask, so the source shows
+    /// where the pattern is opaque and not where it is transparent.
+    pub fn mask(&mut self, pattern: &Pattern) {
+        let device_to_mask = self.ctm_inverse().multiply(&pattern.get_matrix());
+        self.draw(Drawing::Mask(Cow::Borrowed(pattern), device_to_mask));
+    }
+
+    /// Paints this context's Rgba using the alpha of `surface` as a mask, with the surface's top
+    /// left corner placed at (x, y) in user space.  Outside of the surface nothing is painted.
+    pub fn mask_surface(&mut self, surface: &ImageSurface, x: f32, y: f32) {
+        let mut pattern = Pattern::create_for_surface(surface);
+        pattern.set_matrix(Matrix::init_translate(-x, -y))
+            .expect("a translation is always invertible");
+        self.mask(&pattern);
+    }
+
+    /// Fills the current path with this context's Rgba using the context's operator, then
+    /// clears the path.  Each sub-path is implicitly closed before filling.
+    pub fn fill(&mut self) {
+        self.fill_preserve();
+        self.path.clear();
+    }
+
+    /// Fills the current path like `fill`, but keeps the path for further drawing.
+    ///
+    /// The path is flattened into edges, swept into trapezoids, and the trapezoids are
+    /// rasterized into a coverage mask that the source is composited through.
+    pub fn fill_preserve(&mut self) {
+        self.draw(Drawing::Fill);
+    }
+
+    /// Strokes the current path with this context's Rgba using the context's operator and line
+    /// style, then clears the path.
+    pub fn stroke(&mut self) {
+        self.stroke_preserve();
+        self.path.clear();
+    }
+
+    /// Strokes the current path like `stroke`, but keeps the path for further drawing.
+    ///
+    /// The stroke's outline is built from the flattened path and then filled the same way
+    /// `fill` fills a path.  The line width, dashes and caps are measured in user space, so a
+    /// scaled or skewed user space stretches the stroke too.
+    pub fn stroke_preserve(&mut self) {
+        self.draw(Drawing::Stroke);
+    }
+
+    // Returns the drawing state, as `save` keeps it.
+    fn gstate(&self) -> GState {
+        GState {
+            source: self.source.clone(),
+            source_ctm: self.source_ctm,
+            operator: self.operator,
+            tolerance: self.tolerance,
+            fill_rule: self.fill_rule,
+            stroke_style: self.stroke_style.clone(),
+            ctm: self.ctm,
+            clip: self.clip.clone(),
+        }
+    }
+
+    // Draws `drawing` with the current path and drawing state.  Image targets rasterize it, and
+    // vector targets record it, along with their fallback for what they can't draw.
+    fn draw(&mut self, drawing: Drawing) {
+        let state = self.gstate();
+        let device_to_source = device_to_source(&self.source, &self.source_ctm);
+        match self.target {
+            Target::Image(ref mut image) => {
+                state.rasterize(&self.path, &drawing, image, false);
+            },
+            Target::Svg(ref mut svg) => {
+                let operator = self.operator;
+                let clip = self.clip.as_ref().and_then(Clip::svg_id);
+                match drawing {
+                    Drawing::Fill => {
+                        svg.fill(&self.path, self.fill_rule, &self.source, &device_to_source,
+                                 operator, clip)
+                    },
+                    Drawing::Stroke => {
+                        svg.stroke(&self.path, &self.ctm, &self.stroke_style, &self.source,
+                                   &device_to_source, operator, clip)
+                    },
+                    Drawing::Paint(alpha) => {
+                        svg.paint(&self.source, &device_to_source, alpha, operator, clip)
+                    },
+                    Drawing::Mask(ref pattern, ref device_to_mask) => {
+                        svg.mask(&self.source, &device_to_source, pattern, device_to_mask,
+                                 operator, clip)
+                    },
+                }
+                let rasterized = svg::is_rasterized(operator);
+                let area = svg.fallback_mut().draw(state, &self.path, drawing, rasterized);
+                if let Some(area) = area {
+                    svg.replace_area(&area);
+                }
+            },
+            Target::Pdf(ref mut pdf) => {
+                let operator = self.operator;
+                let clip = self.clip.as_ref().and_then(Clip::pdf_index);
+                match drawing {
+                    Drawing::Fill => {
+                        pdf.fill(&self.path, self.fill_rule, &self.source, &device_to_source,
+                                 operator, clip)
+                    },
+                    Drawing::Stroke => {
+                        pdf.stroke(&self.path, &self.ctm, &self.stroke_style, &self.source,
+                                   &device_to_source, operator, clip)
+                    },
+                    Drawing::Paint(alpha) => {
+                        pdf.paint(&self.source, &device_to_source, alpha, operator, clip)
+                    },
+                    Drawing::Mask(ref pattern, ref device_to_mask) => {
+                        pdf.mask(&self.source, &device_to_source, pattern, device_to_mask,
+                                 operator, clip)
+                    },
+                }
+                let rasterized = pdf::is_rasterized(operator);
+                let path = &self.path;
+                let area = pdf.fallback_mut()
+                    .and_then(|fallback| fallback.draw(state, path, drawing, rasterized));
+                if let Some(area) = area {
+                    pdf.replace_area(&area);
+                }
+            },
+        }
+    }
+}
+
+impl GState {
+    // Rasterizes `drawing` of `path` onto `image` with this state.  If `area` is set, returns
+    // which pixels of the image the drawing could change, unless it draws nothing.
+    fn rasterize(&self, path: &Path, drawing: &Drawing, image: &mut ImageSurface, area: bool)
+                 -> Option<Vec<bool>> {
+        let (width, height) = (image.width, image.height);
+        let mask = match *drawing {
+            Drawing::Fill => {
+                let edges = path.fill_edges(self.tolerance);
+                Some(self.edges_mask(edges, self.fill_rule, 1., width, height)?)
+            },
+            Drawing::Stroke => Some(self.stroke_mask(path, width, height)?),
+            Drawing::Paint(alpha) if alpha >= 1. => None,
+            Drawing::Paint(alpha) => Some(vec![alpha; width * height]),
+            Drawing::Mask(ref pattern, ref device_to_mask) => {
+                let alphas = PatternColors::new(pattern, device_to_mask, width, height);
+                Some((0..width * height).map(|idx| alphas.get(idx).alpha).collect())
+            },
+        };
+        self.composite(image, mask.as_deref(), area)
+    }
+
+    // Returns the mask stroking `path` with the line style, or None if nothing would be drawn.
+    fn stroke_mask(&self, path: &Path, width: usize, height: usize) -> Option<Vec<f32>> {
+        // Stroke in user space, where the line style applies, then map the outline back
+        let inverse = self.ctm.invert()
+            .expect("the current transformation matrix is always invertible");
+        let mut polylines = path.flatten(self.tolerance);
+        for polyline in polylines.iter_mut() {
+            transform_points(&inverse, &mut polyline.points);
+        }
+
+        // A user space tolerance that stays within the device space one after transforming
+        let (xx, yx) = self.ctm.transform_distance(1., 0.);
+        let (xy, yy) = self.ctm.transform_distance(0., 1.);
+        let expansion = (xx * xx + yx * yx).sqrt().max((xy * xy + yy * yy).sqrt());
+        let tolerance = self.tolerance / expansion;
+
+        // Like cairo, dashes too fine to tell apart are drawn as a solid line, faded by how much
+        // of it they would cover, rather than as a huge number of tiny pieces
+        let mut style = self.stroke_style.clone();
+        let mut alpha = 1.;
+        if !style.dashes.is_empty() && style.dash_period() < tolerance {
+            alpha = style.dash_coverage();
+            style.dashes.clear();
+        }
+
+        let mut polygons = stroke_polygons(&polylines, &style, tolerance);
+        for polygon in polygons.iter_mut() {
+            transform_points(&self.ctm, polygon);
+        }
+        let edges = polygon_edges(&polygons);
+        self.edges_mask(edges, FillRule::Winding, alpha, width, height)
+    }
+
+    // Returns the mask of the area `edges` enclose under `fill_rule`, faded to `alpha`, or None
+    // if nothing would be drawn.
+    fn edges_mask(&self, edges: Vec<Edge>, fill_rule: FillRule, alpha: f32, width: usize,
+                  height: usize) -> Option<Vec<f32>> {
+        // Nothing to draw, unless an unbounded operator still has to clear the rest of the clip
+        if edges.is_empty() && self.operator.is_bounded() {
+            return None;
+        }
+
+        let mut coverage = coverage_from_edges(edges, fill_rule, width, height);
+        if alpha < 1. {
+            for coverage in coverage.iter_mut() {
+                *coverage *= alpha;
+            }
+        }
+        Some(coverage)
+    }
+
+    // Composites the source onto `image` with the operator, through `mask` (or everywhere when
+    // there is no mask) and the clip.  If `area` is set, returns which pixels could change.
+    //
+    // Bounded operators leave pixels outside the mask alone, and make partly covered pixels a mix
+    // of their old value and the operator's result.  Unbounded operators are applied to every
+    // pixel in the clip with the source faded by the mask, like cairo, so that e.g. `In` clears
+    // whatever lies outside the shape.
+    fn composite(&self, image: &mut ImageSurface, mask: Option<&[f32]>, area: bool)
+                 -> Option<Vec<bool>> {
+        let bounded = self.operator.is_bounded();
+        let operator = fetch_operator(&self.operator);
+        let device_to_source = device_to_source(&self.source, &self.source_ctm);
+        let colors = PatternColors::new(&self.source, &device_to_source, image.width,
+                                        image.height);
+        let clip = self.clip.as_ref().map(|clip| clip.coverage(image.width, image.height));
+        let mut area = if area { Some(vec![false; image.width * image.height]) } else { None };
+        image.update_pixels(|idx, pixel| {
+            let mask_coverage = mask.map_or(1., |mask| mask[idx]);
+            let clip_coverage = clip.as_ref().map_or(1., |clip| clip[idx]);
+            let rgba = colors.get(idx);
+            let (source, coverage) = if bounded {
+                (rgba, mask_coverage * clip_coverage)
+            } else {
+                (scale_rgba(&rgba, mask_coverage), clip_coverage)
+            };
+            if coverage <= 0. {
+                return;
+            }
+            if let Some(ref mut area) = area {
+                area[idx] = true;
+            }
+            if coverage >= 1. {
+                operator(&source, pixel);
+                return;
+            }
+
+            let mut result = *pixel;
+            operator(&source, &mut result);
+            pixel.red += (result.red - pixel.red) * coverage;
+            pixel.green += (result.green - pixel.green) * coverage;
+            pixel.blue += (result.blue - pixel.blue) * coverage;
+            pixel.alpha += (result.alpha - pixel.alpha) * coverage;
+        });
+        area
+    }
+}
+
+// The colors of a pattern at the centers of target pixels.  Mesh patterns are drawn a patch at
+// a time, so they are rendered for the whole target up front; other patterns are sampled pixel by
+// pixel, so that a solid color or a small fill doesn't need a color for every target pixel.
+enum PatternColors<'p> {
+    Rendered(Vec<Rgba>),
+    Sampled(&'p Pattern, Matrix, usize),
+}
+
+impl<'p> PatternColors<'p> {
+    fn new(pattern: &'p Pattern, device_to_pattern: &Matrix, width: usize, height: usize)
+           -> PatternColors<'p> {
+        match pattern.get_type() {
+            PatternType::Mesh => {
+                PatternColors::Rendered(pattern.render(device_to_pattern, width, height))
+            },
+            _ => PatternColors::Sampled(pattern, *device_to_pattern, width),
+        }
+    }
+
+    // Returns the color at the target pixel with index `idx`, counting row by row.
+    fn get(&self, idx: usize) -> Rgba {
+        match *self {
+            PatternColors::Rendered(ref colors) => colors[idx],
+            PatternColors::Sampled(pattern, ref device_to_pattern, width) => {
+                pattern.sample_pixel(device_to_pattern, idx % width, idx / width)
+            },
         }
     }
 }
 
+// Returns how much of every pixel of a `width` by `height` image the area inside `edges` covers.
+fn coverage_from_edges(edges: Vec<Edge>, fill_rule: FillRule, width: usize, height: usize)
+                       -> Vec<f32> {
+    if edges.is_empty() {
+        return vec![0.; width * height];
+    }
+
+    let trapezoids = sweep(edges, fill_rule);
+    mask_from_trapezoids(&trapezoids, width, height)
+}
+
+// Returns the matrix mapping device space to the space of `source`, which was set when the
+// transformation matrix was `source_ctm`.
+fn device_to_source(source: &Pattern, source_ctm: &Matrix) -> Matrix {
+    source_ctm.invert()
+        .expect("the current transformation matrix is always invertible")
+        .multiply(&source.get_matrix())
+}
+
+// Returns `rgba` with every channel multiplied by `factor`, which fades a pre-multiplied color.
+fn scale_rgba(rgba: &Rgba, factor: f32) -> Rgba {
+    Rgba {
+        red: rgba.red * factor,
+        green: rgba.green * factor,
+        blue: rgba.blue * factor,
+        alpha: rgba.alpha * factor,
+    }
+}
+
+// Transforms every point of `points` by `matrix`.
+fn transform_points(matrix: &Matrix, points: &mut [Point]) {
+    for point in points.iter_mut() {
+        let (x, y) = matrix.transform_point(point.x, point.y);
+        *point = Point::new(x, y);
+    }
+}
+
 /// # References
 /// [Cairo Operators]: https://www.cairographics.org/operators/
 
 #[cfg(test)]
 mod tests{
 
-    use surfaces::ImageSurface;
+    use surfaces::{ImageSurface, Format};
     use types::Rgba;
     use operators::Operator;
-    use super::Context;
+    use status::Status;
+    use common_geometry::Point;
+    use path::PathSegment;
+    use super::{Context, Target, Clip, FillRule, LineCap, LineJoin};
+    use std::rc::Rc;
+    use operators::fetch_operator;
+    use patterns::{Pattern, Filter, Extend};
+    use matrix::Matrix;
+    use std::f32::consts::PI;
 
     #[test]
     fn test_get_default_operator(){
         // Setup
-        let mut surface = ImageSurface::create(255, 255);
+        let mut surface = ImageSurface::create(Format::RGBA128F, 255, 255);
         let context = Context::create( &mut surface );
 
         // Call
@@ -141,7 +1101,7 @@ mod tests{
     #[test]
     fn test_set_operator(){
         // Setup
-        let mut surface = ImageSurface::create(255, 255);
+        let mut surface = ImageSurface::create(Format::RGBA128F, 255, 255);
         let mut context = Context::create( &mut surface );
 
         // Call
@@ -157,7 +1117,7 @@ mod tests{
     #[test]
     fn test_paint() {
         // Setup
-        let mut target = ImageSurface::create(100, 100);
+        let mut target = ImageSurface::create(Format::RGBA128F, 100, 100);
 
         // Call
         {
@@ -171,45 +1131,1266 @@ mod tests{
         // Test
         let expected = Rgba::new(0., 1., 0., 1.);
         for pixel in target.iter() {
-            assert_eq!(*pixel, expected);
+            assert_eq!(pixel, expected);
         }
     }
 
+    // Returns the color of the solid source of `context`.
+    fn source_rgba(context: &Context) -> Rgba {
+        context.get_source().sample(0., 0.)
+    }
+
     #[test]
     fn test_set_rgba_happy(){
-        let mut surface = ImageSurface::create(100, 100);
+        let mut surface = ImageSurface::create(Format::RGBA128F, 100, 100);
         let mut context = Context::create(&mut surface);
         context.set_source_rgba(0.1, 0.2, 0.3, 1.);
-        assert_eq!(context.rgba.red, 0.1);
-        assert_eq!(context.rgba.green, 0.2);
-        assert_eq!(context.rgba.blue, 0.3);
-        assert_eq!(context.rgba.alpha, 1.);
+        assert_eq!(source_rgba(&context).red, 0.1);
+        assert_eq!(source_rgba(&context).green, 0.2);
+        assert_eq!(source_rgba(&context).blue, 0.3);
+        assert_eq!(source_rgba(&context).alpha, 1.);
 
         // Test Rbga premultiply
         context.set_source_rgba(0.2, 0.4, 0.6, 0.5);
-        assert_eq!(context.rgba.red, 0.1);
-        assert_eq!(context.rgba.green, 0.2);
-        assert_eq!(context.rgba.blue, 0.3);
-        assert_eq!(context.rgba.alpha, 0.5);
+        assert_eq!(source_rgba(&context).red, 0.1);
+        assert_eq!(source_rgba(&context).green, 0.2);
+        assert_eq!(source_rgba(&context).blue, 0.3);
+        assert_eq!(source_rgba(&context).alpha, 0.5);
     }
 
     #[test]
     fn test_set_rgba_out_of_bounds_values(){
-        let mut surface = ImageSurface::create(100, 100);
+        let mut surface = ImageSurface::create(Format::RGBA128F, 100, 100);
         let mut context = Context::create(&mut surface);
 
         // Test negative alpha value pre-multiplting to zero
         context.set_source_rgba(1., 1., 1., -10.);
-        assert_eq!(context.rgba.red, 0.);
-        assert_eq!(context.rgba.green, 0.);
-        assert_eq!(context.rgba.blue, 0.);
-        assert_eq!(context.rgba.alpha, 0.);
+        assert_eq!(source_rgba(&context).red, 0.);
+        assert_eq!(source_rgba(&context).green, 0.);
+        assert_eq!(source_rgba(&context).blue, 0.);
+        assert_eq!(source_rgba(&context).alpha, 0.);
 
         // Test bound to range [0,1]
         context.set_source_rgba(-22.,22.,-22.,9.);
-        assert_eq!(context.rgba.red, 0.);
-        assert_eq!(context.rgba.green, 1.);
-        assert_eq!(context.rgba.blue, 0.);
-        assert_eq!(context.rgba.alpha, 1.);
+        assert_eq!(source_rgba(&context).red, 0.);
+        assert_eq!(source_rgba(&context).green, 1.);
+        assert_eq!(source_rgba(&context).blue, 0.);
+        assert_eq!(source_rgba(&context).alpha, 1.);
+    }
+
+    #[test]
+    fn test_path_move_to_line_to() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 100, 100);
+        let mut context = Context::create(&mut surface);
+        assert!(!context.has_current_point());
+
+        context.move_to(10., 10.);
+        context.line_to(20., 10.);
+        assert_eq!(context.get_current_point(), Some((20., 10.)));
+        assert_eq!(*context.path.segments(), vec![
+            PathSegment::MoveTo(Point::new(10., 10.)),
+            PathSegment::LineTo(Point::new(20., 10.)),
+        ]);
+    }
+
+    #[test]
+    fn test_path_relative_segments() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 100, 100);
+        let mut context = Context::create(&mut surface);
+        context.move_to(10., 10.);
+        context.rel_line_to(5., 0.).unwrap();
+        context.rel_move_to(0., 5.).unwrap();
+        context.rel_curve_to(1., 1., 2., 2., 3., 0.).unwrap();
+        assert_eq!(*context.path.segments(), vec![
+            PathSegment::MoveTo(Point::new(10., 10.)),
+            PathSegment::LineTo(Point::new(15., 10.)),
+            PathSegment::MoveTo(Point::new(15., 15.)),
+            PathSegment::CurveTo(Point::new(16., 16.), Point::new(17., 17.),
+                                 Point::new(18., 15.)),
+        ]);
+        assert_eq!(context.get_current_point(), Some((18., 15.)));
+    }
+
+    #[test]
+    fn test_path_relative_without_current_point() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 100, 100);
+        let mut context = Context::create(&mut surface);
+        assert_eq!(context.rel_move_to(1., 1.), Err(Status::NoCurrentPoint));
+        assert_eq!(context.rel_line_to(1., 1.), Err(Status::NoCurrentPoint));
+        assert_eq!(context.rel_curve_to(1., 1., 2., 2., 3., 3.), Err(Status::NoCurrentPoint));
+        assert!(context.path.segments().is_empty());
+    }
+
+    #[test]
+    fn test_path_close_and_new_path() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 100, 100);
+        let mut context = Context::create(&mut surface);
+        context.move_to(0., 0.);
+        context.line_to(10., 0.);
+        context.line_to(10., 10.);
+        context.close_path();
+        assert_eq!(context.get_current_point(), Some((0., 0.)));
+        assert_eq!(context.path.segments().last(), Some(&PathSegment::ClosePath));
+
+        context.new_sub_path();
+        assert!(!context.has_current_point());
+
+        context.new_path();
+        assert!(context.path.segments().is_empty());
+        assert!(!context.has_current_point());
+    }
+
+    #[test]
+    fn test_fill_square() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 20, 20);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.move_to(5., 5.);
+            context.line_to(15., 5.);
+            context.line_to(15., 15.);
+            context.line_to(5., 15.);
+            context.fill();
+            assert!(!context.has_current_point());
+        }
+
+        let red = Rgba::new(1., 0., 0., 1.);
+        let clear = Rgba::new(0., 0., 0., 0.);
+        assert_eq!(surface.get(5, 5).unwrap(), red);
+        assert_eq!(surface.get(10, 10).unwrap(), red);
+        assert_eq!(surface.get(14, 14).unwrap(), red);
+        assert_eq!(surface.get(4, 10).unwrap(), clear);
+        assert_eq!(surface.get(15, 10).unwrap(), clear);
+        assert_eq!(surface.get(10, 15).unwrap(), clear);
+    }
+
+    #[test]
+    fn test_fill_partial_coverage_is_antialiased() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 10, 10);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(0., 0., 1., 1.);
+            context.move_to(2.5, 2.);
+            context.line_to(6., 2.);
+            context.line_to(6., 6.);
+            context.line_to(2.5, 6.);
+            context.fill();
+        }
+
+        let edge = surface.get(2, 4).unwrap();
+        assert!(edge.alpha > 0.4 && edge.alpha < 0.6);
+        assert_eq!(edge.blue, edge.alpha);
+    }
+
+    #[test]
+    fn test_fill_concave() {
+        // A U shape, the notch between the arms stays empty
+        let mut surface = ImageSurface::create(Format::RGBA128F, 30, 30);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(0., 1., 0., 1.);
+            context.move_to(0., 0.);
+            context.line_to(10., 0.);
+            context.line_to(10., 20.);
+            context.line_to(20., 20.);
+            context.line_to(20., 0.);
+            context.line_to(30., 0.);
+            context.line_to(30., 30.);
+            context.line_to(0., 30.);
+            context.close_path();
+            context.fill();
+        }
+
+        assert_eq!(surface.get(5, 10).unwrap().alpha, 1.);
+        assert_eq!(surface.get(25, 10).unwrap().alpha, 1.);
+        assert_eq!(surface.get(15, 25).unwrap().alpha, 1.);
+        assert_eq!(surface.get(15, 10).unwrap().alpha, 0.);
+    }
+
+    #[test]
+    fn test_fill_star() {
+        // A self-intersecting star, the middle is filled by the winding rule
+        let mut surface = ImageSurface::create(Format::RGBA128F, 100, 100);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 1., 1., 1.);
+            star(&mut context);
+            context.fill();
+        }
+
+        assert_eq!(surface.get(50, 50).unwrap().alpha, 1.);
+        assert_eq!(surface.get(50, 10).unwrap().alpha, 1.);
+        assert_eq!(surface.get(10, 38).unwrap().alpha, 1.);
+        assert_eq!(surface.get(50, 85).unwrap().alpha, 0.);
+        assert_eq!(surface.get(5, 5).unwrap().alpha, 0.);
+    }
+
+    #[test]
+    fn test_fill_curve() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 40, 40);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.move_to(0., 20.);
+            context.curve_to(0., 0., 40., 0., 40., 20.);
+            context.fill();
+        }
+
+        // The bulge of the curve is filled, but not its corners
+        assert_eq!(surface.get(20, 15).unwrap().alpha, 1.);
+        assert_eq!(surface.get(20, 19).unwrap().alpha, 1.);
+        assert_eq!(surface.get(1, 1).unwrap().alpha, 0.);
+        assert_eq!(surface.get(20, 25).unwrap().alpha, 0.);
+    }
+
+    #[test]
+    fn test_fill_preserve_keeps_path() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 10, 10);
+        let mut context = Context::create(&mut surface);
+        context.move_to(1., 1.);
+        context.line_to(5., 1.);
+        context.line_to(5., 5.);
+        context.fill_preserve();
+        assert_eq!(context.path.segments().len(), 3);
+        assert_eq!(context.get_current_point(), Some((5., 5.)));
+    }
+
+    #[test]
+    fn test_line_style_defaults() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 10, 10);
+        let mut context = Context::create(&mut surface);
+        assert_eq!(context.get_line_width(), 2.);
+        assert_eq!(context.get_line_cap(), LineCap::Butt);
+        assert_eq!(context.get_line_join(), LineJoin::Miter);
+        assert_eq!(context.get_miter_limit(), 10.);
+
+        context.set_line_width(4.);
+        context.set_line_cap(LineCap::Round);
+        context.set_line_join(LineJoin::Bevel);
+        context.set_miter_limit(2.);
+        assert_eq!(context.get_line_width(), 4.);
+        assert_eq!(context.get_line_cap(), LineCap::Round);
+        assert_eq!(context.get_line_join(), LineJoin::Bevel);
+        assert_eq!(context.get_miter_limit(), 2.);
+    }
+
+    #[test]
+    fn test_stroke_line() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 20, 20);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.set_line_width(4.);
+            context.move_to(5., 10.);
+            context.line_to(15., 10.);
+            context.stroke();
+            assert!(!context.has_current_point());
+        }
+
+        let red = Rgba::new(1., 0., 0., 1.);
+        assert_eq!(surface.get(5, 8).unwrap(), red);
+        assert_eq!(surface.get(10, 11).unwrap(), red);
+        assert_eq!(surface.get(14, 9).unwrap(), red);
+        // Outside the line's width, and past its butt caps
+        assert_eq!(surface.get(10, 7).unwrap().alpha, 0.);
+        assert_eq!(surface.get(10, 12).unwrap().alpha, 0.);
+        assert_eq!(surface.get(4, 10).unwrap().alpha, 0.);
+        assert_eq!(surface.get(15, 10).unwrap().alpha, 0.);
+    }
+
+    #[test]
+    fn test_stroke_square_caps_extend_line() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 20, 20);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.set_line_width(4.);
+            context.set_line_cap(LineCap::Square);
+            context.move_to(5., 10.);
+            context.line_to(15., 10.);
+            context.stroke();
+        }
+
+        assert_eq!(surface.get(3, 10).unwrap().alpha, 1.);
+        assert_eq!(surface.get(16, 10).unwrap().alpha, 1.);
+        assert_eq!(surface.get(2, 10).unwrap().alpha, 0.);
+        assert_eq!(surface.get(17, 10).unwrap().alpha, 0.);
+    }
+
+    #[test]
+    fn test_stroke_closed_rectangle() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 30, 30);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(0., 0., 1., 1.);
+            context.set_line_width(2.);
+            context.move_to(5., 5.);
+            context.line_to(25., 5.);
+            context.line_to(25., 25.);
+            context.line_to(5., 25.);
+            context.close_path();
+            context.stroke();
+        }
+
+        // The mitered corners are filled, the inside of the rectangle is not
+        assert_eq!(surface.get(4, 4).unwrap().alpha, 1.);
+        assert_eq!(surface.get(25, 25).unwrap().alpha, 1.);
+        assert_eq!(surface.get(15, 4).unwrap().alpha, 1.);
+        assert_eq!(surface.get(15, 15).unwrap().alpha, 0.);
+        assert_eq!(surface.get(3, 3).unwrap().alpha, 0.);
+    }
+
+    #[test]
+    fn test_stroke_bevel_join_cuts_corner() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 30, 30);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(0., 0., 1., 1.);
+            context.set_line_width(6.);
+            context.set_line_join(LineJoin::Bevel);
+            context.move_to(5., 10.);
+            context.line_to(20., 10.);
+            context.line_to(20., 25.);
+            context.stroke();
+        }
+
+        // With a miter join this pixel would be covered
+        assert_eq!(surface.get(22, 7).unwrap().alpha, 0.);
+        assert_eq!(surface.get(20, 9).unwrap().alpha, 1.);
+    }
+
+    #[test]
+    fn test_stroke_preserve_keeps_path() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 10, 10);
+        let mut context = Context::create(&mut surface);
+        context.move_to(1., 1.);
+        context.line_to(5., 1.);
+        context.stroke_preserve();
+        assert_eq!(context.path.segments().len(), 2);
+    }
+
+    #[test]
+    fn test_set_dash() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 10, 10);
+        let mut context = Context::create(&mut surface);
+        assert_eq!(context.get_dash(), (&[][..], 0.));
+
+        context.set_dash(&[4., 2.], 1.).unwrap();
+        assert_eq!(context.get_dash(), (&[4., 2.][..], 1.));
+
+        assert_eq!(context.set_dash(&[4., -2.], 0.), Err(Status::InvalidDash));
+        assert_eq!(context.set_dash(&[0., 0.], 0.), Err(Status::InvalidDash));
+        assert_eq!(context.set_dash(&[4., f32::NAN], 0.), Err(Status::InvalidDash));
+        assert_eq!(context.set_dash(&[f32::INFINITY, 2.], 0.), Err(Status::InvalidDash));
+        assert_eq!(context.set_dash(&[4., 2.], f32::NAN), Err(Status::InvalidDash));
+        assert_eq!(context.set_dash(&[4., 2.], f32::INFINITY), Err(Status::InvalidDash));
+        assert_eq!(context.get_dash(), (&[4., 2.][..], 1.));
+
+        context.set_dash(&[], 0.).unwrap();
+        assert_eq!(context.get_dash(), (&[][..], 0.));
+    }
+
+    #[test]
+    fn test_stroke_dashed_line() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 30, 10);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.set_line_width(4.);
+            context.set_dash(&[4., 6.], 0.).unwrap();
+            context.move_to(0., 5.);
+            context.line_to(30., 5.);
+            context.stroke();
+        }
+
+        for &x in [0, 3, 10, 13, 20, 23].iter() {
+            assert_eq!(surface.get(x, 5).unwrap().alpha, 1.);
+        }
+        for &x in [4, 9, 14, 19, 24, 29].iter() {
+            assert_eq!(surface.get(x, 5).unwrap().alpha, 0.);
+        }
+    }
+
+    #[test]
+    fn test_stroke_fine_dashes_fade_solid_line() {
+        // Dashes this fine used to be split into millions of pieces
+        let mut surface = ImageSurface::create(Format::RGBA128F, 30, 10);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.set_line_width(4.);
+            context.set_dash(&[1e-6, 3e-6], 0.).unwrap();
+            context.move_to(1., 5.);
+            context.line_to(29., 5.);
+            context.stroke();
+        }
+
+        for x in 1..29 {
+            assert_eq!(surface.get(x, 5).unwrap().alpha, 0.25);
+        }
+        assert_eq!(surface.get(0, 5).unwrap().alpha, 0.);
+    }
+
+    #[test]
+    fn test_stroke_dashed_round_dots() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 30, 10);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.set_line_width(4.);
+            context.set_line_cap(LineCap::Round);
+            context.set_dash(&[0., 10.], 0.).unwrap();
+            context.move_to(5., 5.);
+            context.line_to(30., 5.);
+            context.stroke();
+        }
+
+        for &x in [4, 5, 14, 15, 24, 25].iter() {
+            assert_eq!(surface.get(x, 5).unwrap().alpha, 1.);
+        }
+        for &x in [0, 9, 10, 19, 20, 29].iter() {
+            assert_eq!(surface.get(x, 5).unwrap().alpha, 0.);
+        }
+    }
+
+    // Draws a five pointed star whose middle is enclosed twice.
+    fn star(context: &mut Context) {
+        context.move_to(50., 0.);
+        context.line_to(80., 90.);
+        context.line_to(0., 35.);
+        context.line_to(100., 35.);
+        context.line_to(20., 90.);
+        context.close_path();
+    }
+
+    #[test]
+    fn test_fill_rule() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 10, 10);
+        let mut context = Context::create(&mut surface);
+        assert_eq!(context.get_fill_rule(), FillRule::Winding);
+        context.set_fill_rule(FillRule::EvenOdd);
+        assert_eq!(context.get_fill_rule(), FillRule::EvenOdd);
+    }
+
+    #[test]
+    fn test_fill_star_even_odd() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 100, 100);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 1., 1., 1.);
+            context.set_fill_rule(FillRule::EvenOdd);
+            star(&mut context);
+            context.fill();
+        }
+
+        assert_eq!(surface.get(50, 50).unwrap().alpha, 0.);
+        assert_eq!(surface.get(50, 10).unwrap().alpha, 1.);
+        assert_eq!(surface.get(10, 38).unwrap().alpha, 1.);
+    }
+
+    #[test]
+    fn test_fill_donut() {
+        // Inner contour drawn against the outer one: a hole under both rules
+        for &fill_rule in [FillRule::Winding, FillRule::EvenOdd].iter() {
+            let mut surface = ImageSurface::create(Format::RGBA128F, 40, 40);
+            {
+                let mut context = Context::create(&mut surface);
+                context.set_source_rgba(1., 1., 1., 1.);
+                context.set_fill_rule(fill_rule);
+                context.move_to(0., 0.);
+                context.line_to(40., 0.);
+                context.line_to(40., 40.);
+                context.line_to(0., 40.);
+                context.close_path();
+                context.move_to(10., 10.);
+                context.line_to(10., 30.);
+                context.line_to(30., 30.);
+                context.line_to(30., 10.);
+                context.close_path();
+                context.fill();
+            }
+
+            assert_eq!(surface.get(5, 20).unwrap().alpha, 1.);
+            assert_eq!(surface.get(20, 35).unwrap().alpha, 1.);
+            assert_eq!(surface.get(20, 20).unwrap().alpha, 0.);
+        }
+    }
+
+    #[test]
+    fn test_matrix_calls() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 10, 10);
+        let mut context = Context::create(&mut surface);
+        assert_eq!(context.get_matrix(), Matrix::init_identity());
+
+        context.translate(10., 20.).unwrap();
+        context.scale(2., 3.).unwrap();
+        assert_eq!(context.user_to_device(1., 1.), (12., 23.));
+        assert_eq!(context.device_to_user(12., 23.), (1., 1.));
+
+        context.transform(&Matrix::init_translate(1., 0.)).unwrap();
+        assert_eq!(context.user_to_device(0., 0.), (12., 20.));
+
+        context.identity_matrix();
+        context.rotate(PI).unwrap();
+        let (x, y) = context.user_to_device(1., 0.);
+        assert!((x + 1.).abs() < 1e-6 && y.abs() < 1e-6);
+
+        context.set_matrix(&Matrix::init_scale(4., 4.)).unwrap();
+        assert_eq!(context.get_matrix(), Matrix::init_scale(4., 4.));
+    }
+
+    #[test]
+    fn test_invalid_matrix_is_rejected() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 10, 10);
+        let mut context = Context::create(&mut surface);
+        context.translate(1., 1.).unwrap();
+        assert_eq!(context.scale(0., 1.), Err(Status::InvalidMatrix));
+        assert_eq!(context.set_matrix(&Matrix::new(1., 1., 1., 1., 0., 0.)),
+                   Err(Status::InvalidMatrix));
+        assert_eq!(context.get_matrix(), Matrix::init_translate(1., 1.));
+    }
+
+    #[test]
+    fn test_non_finite_translate_and_rotate_are_rejected() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 10, 10);
+        {
+            let mut context = Context::create(&mut surface);
+            context.move_to(1., 2.);
+            assert_eq!(context.translate(f32::INFINITY, 0.), Err(Status::InvalidMatrix));
+            assert_eq!(context.get_matrix(), Matrix::init_identity());
+            assert_eq!(context.get_current_point(), Some((1., 2.)));
+
+            assert_eq!(context.rotate(f32::NAN), Err(Status::InvalidMatrix));
+            assert_eq!(context.get_matrix(), Matrix::init_identity());
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.set_line_width(2.);
+            context.line_to(8., 2.);
+            context.stroke();
+        }
+        assert_eq!(surface.get(4, 2).unwrap().red, 1.);
+    }
+
+    #[test]
+    fn test_path_is_transformed() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 10, 10);
+        let mut context = Context::create(&mut surface);
+        context.translate(5., 5.).unwrap();
+        context.scale(2., 2.).unwrap();
+        context.move_to(1., 0.);
+        context.rel_line_to(1., 1.).unwrap();
+        assert_eq!(*context.path.segments(), vec![
+            PathSegment::MoveTo(Point::new(7., 5.)),
+            PathSegment::LineTo(Point::new(9., 7.)),
+        ]);
+        // The current point is reported in user space
+        assert_eq!(context.get_current_point(), Some((2., 1.)));
+    }
+
+    #[test]
+    fn test_fill_translated_and_scaled() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 20, 20);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.translate(10., 10.).unwrap();
+            context.scale(2., 2.).unwrap();
+            context.move_to(0., 0.);
+            context.line_to(4., 0.);
+            context.line_to(4., 4.);
+            context.line_to(0., 4.);
+            context.fill();
+        }
+
+        assert_eq!(surface.get(10, 10).unwrap().alpha, 1.);
+        assert_eq!(surface.get(17, 17).unwrap().alpha, 1.);
+        assert_eq!(surface.get(9, 12).unwrap().alpha, 0.);
+        assert_eq!(surface.get(18, 12).unwrap().alpha, 0.);
+    }
+
+    #[test]
+    fn test_stroke_width_is_in_user_space() {
+        // A 2 unit line in a user space stretched 3 times vertically is 6 pixels tall
+        let mut surface = ImageSurface::create(Format::RGBA128F, 20, 20);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.scale(1., 3.).unwrap();
+            context.move_to(2., 3.);
+            context.line_to(18., 3.);
+            context.stroke();
+        }
+
+        assert_eq!(surface.get(10, 6).unwrap().alpha, 1.);
+        assert_eq!(surface.get(10, 11).unwrap().alpha, 1.);
+        assert_eq!(surface.get(10, 5).unwrap().alpha, 0.);
+        assert_eq!(surface.get(10, 12).unwrap().alpha, 0.);
+    }
+
+    #[test]
+    fn test_set_tolerance_clamps_to_minimum() {
+        let mut surface = ImageSurface::create(Format::ARGB32, 10, 10);
+        let mut context = Context::create(&mut surface);
+        context.set_tolerance(0.);
+        assert_eq!(context.get_tolerance(), 1. / 256.);
+        context.set_tolerance(-1.);
+        assert_eq!(context.get_tolerance(), 1. / 256.);
+    }
+
+    #[test]
+    fn test_set_tolerance_ignores_nan() {
+        let mut surface = ImageSurface::create(Format::ARGB32, 10, 10);
+        let mut context = Context::create(&mut surface);
+        context.set_tolerance(0.5);
+        context.set_tolerance(f32::NAN);
+        assert_eq!(context.get_tolerance(), 0.5);
+    }
+
+    #[test]
+    fn test_zero_tolerance_curve_strokes() {
+        // A zero tolerance used to split the curve to the depth limit
+        let mut surface = ImageSurface::create(Format::ARGB32, 20, 20);
+        let mut context = Context::create(&mut surface);
+        context.set_source_rgba(1., 0., 0., 1.);
+        context.set_tolerance(0.);
+        context.move_to(2., 2.);
+        context.curve_to(18., 2., 2., 18., 18., 18.);
+        context.stroke();
+        assert!(surface.get(10, 10).unwrap().alpha > 0.);
+    }
+
+    #[test]
+    fn test_save_restore() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 10, 10);
+        let mut context = Context::create(&mut surface);
+        context.set_source_rgba(1., 0., 0., 1.);
+        context.set_line_width(3.);
+        context.set_tolerance(0.5);
+        context.save();
+
+        context.set_source_rgba(0., 1., 0., 1.);
+        context.set_operator(Operator::Source);
+        context.set_line_width(5.);
+        context.set_line_cap(LineCap::Round);
+        context.set_dash(&[1.], 0.).unwrap();
+        context.set_fill_rule(FillRule::EvenOdd);
+        context.set_tolerance(1.);
+        context.translate(5., 5.).unwrap();
+        context.move_to(1., 1.);
+        context.restore().unwrap();
+
+        assert_eq!(source_rgba(&context), Rgba::new(1., 0., 0., 1.));
+        assert_eq!(context.get_operator(), &Operator::Over);
+        assert_eq!(context.get_line_width(), 3.);
+        assert_eq!(context.get_line_cap(), LineCap::Butt);
+        assert_eq!(context.get_dash(), (&[][..], 0.));
+        assert_eq!(context.get_fill_rule(), FillRule::Winding);
+        assert_eq!(context.get_tolerance(), 0.5);
+        assert_eq!(context.get_matrix(), Matrix::init_identity());
+        // The path isn't part of the saved state
+        assert_eq!(context.get_current_point(), Some((6., 6.)));
+    }
+
+    #[test]
+    fn test_nested_save_restore() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 10, 10);
+        let mut context = Context::create(&mut surface);
+        context.save();
+        context.set_line_width(1.);
+        context.save();
+        context.set_line_width(4.);
+        context.restore().unwrap();
+        assert_eq!(context.get_line_width(), 1.);
+        context.restore().unwrap();
+        assert_eq!(context.get_line_width(), 2.);
+    }
+
+    #[test]
+    fn test_unbalanced_restore() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 10, 10);
+        let mut context = Context::create(&mut surface);
+        assert_eq!(context.restore(), Err(Status::InvalidRestore));
+        context.save();
+        assert_eq!(context.restore(), Ok(()));
+        assert_eq!(context.restore(), Err(Status::InvalidRestore));
+    }
+
+    // Adds the rectangle from (x, y) to (x + width, y + height) to the path.
+    fn rectangle(context: &mut Context, x: f32, y: f32, width: f32, height: f32) {
+        context.move_to(x, y);
+        context.line_to(x + width, y);
+        context.line_to(x + width, y + height);
+        context.line_to(x, y + height);
+        context.close_path();
+    }
+
+    #[test]
+    fn test_clip_limits_paint_and_fill() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 20, 20);
+        {
+            let mut context = Context::create(&mut surface);
+            rectangle(&mut context, 0., 0., 10., 20.);
+            context.clip();
+            assert!(!context.has_current_point());
+
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.paint();
+            context.set_source_rgba(0., 0., 1., 1.);
+            rectangle(&mut context, 5., 5., 10., 10.);
+            context.fill();
+        }
+
+        assert_eq!(surface.get(2, 2).unwrap(), Rgba::new(1., 0., 0., 1.));
+        assert_eq!(surface.get(7, 7).unwrap(), Rgba::new(0., 0., 1., 1.));
+        assert_eq!(surface.get(12, 2).unwrap().alpha, 0.);
+        assert_eq!(surface.get(12, 7).unwrap().alpha, 0.);
+    }
+
+    #[test]
+    fn test_clip_intersects_and_restores() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 20, 20);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 1., 1., 1.);
+            context.save();
+            rectangle(&mut context, 0., 0., 10., 10.);
+            context.clip();
+            rectangle(&mut context, 5., 5., 10., 10.);
+            context.clip();
+            context.paint();
+            context.restore().unwrap();
+
+            // The clip is gone after restore
+            rectangle(&mut context, 15., 15., 5., 5.);
+            context.fill();
+        }
+
+        assert_eq!(surface.get(7, 7).unwrap().alpha, 1.);
+        assert_eq!(surface.get(2, 2).unwrap().alpha, 0.);
+        assert_eq!(surface.get(12, 12).unwrap().alpha, 0.);
+        assert_eq!(surface.get(17, 17).unwrap().alpha, 1.);
+    }
+
+    #[test]
+    fn test_save_shares_clip_coverage() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 20, 20);
+        let mut context = Context::create(&mut surface);
+        rectangle(&mut context, 0., 0., 10., 10.);
+        context.clip();
+        context.save();
+        context.save();
+
+        let coverage = |clip: &Option<Clip>| match *clip {
+            Some(Clip::Coverage(ref coverage)) => coverage.clone(),
+            _ => panic!("expected a coverage clip"),
+        };
+        assert!(Rc::ptr_eq(&coverage(&context.clip), &coverage(&context.saved[0].clip)));
+        assert!(Rc::ptr_eq(&coverage(&context.clip), &coverage(&context.saved[1].clip)));
+    }
+
+    #[test]
+    fn test_reset_clip() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 10, 10);
+        {
+            let mut context = Context::create(&mut surface);
+            rectangle(&mut context, 0., 0., 2., 2.);
+            context.clip();
+            context.reset_clip();
+            context.set_source_rgba(1., 1., 1., 1.);
+            context.paint();
+        }
+
+        assert!(surface.iter().all(|pixel| pixel.alpha == 1.));
+    }
+
+    #[test]
+    fn test_fill_bounded_operator_leaves_outside_alone() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 20, 20);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(0., 1., 0., 1.);
+            context.paint();
+            context.set_operator(Operator::Clear);
+            rectangle(&mut context, 5., 5., 10., 10.);
+            context.fill();
+        }
+
+        assert_eq!(surface.get(10, 10).unwrap().alpha, 0.);
+        assert_eq!(surface.get(2, 2).unwrap(), Rgba::new(0., 1., 0., 1.));
+    }
+
+    #[test]
+    fn test_fill_unbounded_operator_clears_outside() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 20, 20);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(0., 1., 0., 1.);
+            context.paint();
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.set_operator(Operator::In);
+            rectangle(&mut context, 5., 5., 10., 10.);
+            context.fill();
+        }
+
+        assert_eq!(surface.get(10, 10).unwrap(), Rgba::new(1., 0., 0., 1.));
+        assert_eq!(surface.get(2, 2).unwrap().alpha, 0.);
+        assert_eq!(surface.get(17, 17).unwrap().alpha, 0.);
+    }
+
+    #[test]
+    fn test_unbounded_operator_respects_clip() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 20, 20);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(0., 1., 0., 1.);
+            context.paint();
+            rectangle(&mut context, 0., 0., 10., 20.);
+            context.clip();
+            context.set_operator(Operator::DestIn);
+            rectangle(&mut context, 5., 5., 10., 10.);
+            context.fill();
+        }
+
+        // DestIn keeps the destination inside the shape, clears the rest of the clip, and
+        // doesn't reach past the clip
+        assert_eq!(surface.get(7, 7).unwrap(), Rgba::new(0., 1., 0., 1.));
+        assert_eq!(surface.get(2, 2).unwrap().alpha, 0.);
+        assert_eq!(surface.get(12, 2).unwrap(), Rgba::new(0., 1., 0., 1.));
+    }
+
+    #[test]
+    fn test_unbounded_operator_with_empty_path_clears_clip() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 20, 20);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.paint();
+            context.set_operator(Operator::In);
+            context.fill();
+        }
+        assert_eq!(surface.get(2, 2).unwrap(), Rgba::new(0., 0., 0., 0.));
+
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.paint();
+            rectangle(&mut context, 0., 0., 10., 20.);
+            context.clip();
+            context.set_operator(Operator::DestIn);
+            context.stroke();
+        }
+
+        assert_eq!(surface.get(2, 2).unwrap().alpha, 0.);
+        assert_eq!(surface.get(12, 2).unwrap(), Rgba::new(1., 0., 0., 1.));
+    }
+
+    // Every operator Cairus implements.
+    fn all_operators() -> Vec<Operator> {
+        vec![
+            Operator::Clear, Operator::Source, Operator::Over, Operator::In, Operator::Out,
+            Operator::Atop, Operator::Dest, Operator::DestOver, Operator::DestIn,
+            Operator::DestOut, Operator::DestAtop, Operator::Xor, Operator::Add,
+            Operator::Saturate, Operator::Multiply, Operator::Screen, Operator::Overlay,
+            Operator::Darken, Operator::Lighten, Operator::ColorDodge, Operator::ColorBurn,
+            Operator::HardLight, Operator::SoftLight, Operator::Difference, Operator::Exclusion,
+            Operator::HSLHue, Operator::HSLSaturation, Operator::HSLColor,
+            Operator::HSLLuminosity,
+        ]
+    }
+
+    // The destination every operator test starts from, and the source drawn onto it.
+    fn operator_test_colors() -> (Rgba, Rgba) {
+        (Rgba::new(0.2, 0.6, 1., 0.75), Rgba::new(0.9, 0.3, 0.1, 0.5))
+    }
+
+    #[test]
+    fn test_paint_with_each_operator() {
+        let (destination, source) = operator_test_colors();
+        for operator in all_operators() {
+            let mut surface = ImageSurface::create(Format::RGBA128F, 4, 4);
+            {
+                let mut context = Context::create(&mut surface);
+                context.set_source_rgba(0.2, 0.6, 1., 0.75);
+                context.paint();
+                context.set_source_rgba(0.9, 0.3, 0.1, 0.5);
+                context.set_operator(operator);
+                context.paint();
+            }
+
+            let mut expected = destination;
+            fetch_operator(&operator)(&source, &mut expected);
+            assert!(surface.iter().all(|pixel| pixel == expected), "{:?}", operator);
+        }
+    }
+
+    #[test]
+    fn test_fill_and_stroke_with_each_operator() {
+        let (destination, source) = operator_test_colors();
+        let transparent = Rgba::new(0., 0., 0., 0.);
+        for operator in all_operators() {
+            let mut expected_inside = destination;
+            fetch_operator(&operator)(&source, &mut expected_inside);
+            let mut expected_outside = destination;
+            if !operator.is_bounded() {
+                fetch_operator(&operator)(&transparent, &mut expected_outside);
+            }
+
+            for &stroke in [false, true].iter() {
+                let mut surface = ImageSurface::create(Format::RGBA128F, 20, 20);
+                {
+                    let mut context = Context::create(&mut surface);
+                    context.set_source_rgba(0.2, 0.6, 1., 0.75);
+                    context.paint();
+                    context.set_source_rgba(0.9, 0.3, 0.1, 0.5);
+                    context.set_operator(operator);
+                    if stroke {
+                        context.set_line_width(10.);
+                        context.move_to(5., 10.);
+                        context.line_to(15., 10.);
+                        context.stroke();
+                    } else {
+                        rectangle(&mut context, 5., 5., 10., 10.);
+                        context.fill();
+                    }
+                }
+
+                assert_eq!(surface.get(10, 10).unwrap(), expected_inside, "{:?}", operator);
+                assert_eq!(surface.get(2, 2).unwrap(), expected_outside, "{:?}", operator);
+            }
+        }
+    }
+
+    #[test]
+    fn test_paint_with_alpha() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 4, 4);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.paint_with_alpha(0.5);
+        }
+        assert!(surface.iter().all(|pixel| pixel == Rgba::new(1., 0., 0., 0.5)));
+
+        let mut surface = ImageSurface::create(Format::RGBA128F, 4, 4);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.paint_with_alpha(2.);
+        }
+        assert!(surface.iter().all(|pixel| pixel == Rgba::new(1., 0., 0., 1.)));
+    }
+
+    #[test]
+    fn test_mask_with_solid_pattern() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 4, 4);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(0., 0., 1., 1.);
+            context.mask(&Pattern::create_rgba(0., 0., 0., 0.25));
+        }
+        assert!(surface.iter().all(|pixel| pixel == Rgba::new(0., 0., 1., 0.25)));
+    }
+
+    #[test]
+    fn test_mask_surface() {
+        // A mask that fades from transparent to opaque from left to right
+        let mut mask = ImageSurface::create(Format::RGBA128F, 4, 1);
+        for x in 0..4 {
+            *mask.get_mut(x, 0).unwrap() = Rgba::new(0., 0., 0., x as f32 / 3.);
+        }
+
+        let mut surface = ImageSurface::create(Format::RGBA128F, 10, 10);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 1., 1., 1.);
+            context.mask_surface(&mask, 2., 5.);
+        }
+
+        assert_eq!(surface.get(2, 5).unwrap().alpha, 0.);
+        assert!((surface.get(3, 5).unwrap().alpha - 1. / 3.).abs() < 1e-6);
+        assert_eq!(surface.get(5, 5).unwrap().alpha, 1.);
+        // Outside the mask surface
+        assert_eq!(surface.get(6, 5).unwrap().alpha, 0.);
+        assert_eq!(surface.get(5, 4).unwrap().alpha, 0.);
+        assert_eq!(surface.get(5, 6).unwrap().alpha, 0.);
+    }
+
+    #[test]
+    fn test_mask_surface_from_trapezoids() {
+        // A rasterized mask modulates the source like the shape it came from
+        let mut mask = ImageSurface::create(Format::RGBA128F, 10, 10);
+        {
+            let mut context = Context::create(&mut mask);
+            context.set_source_rgba(0., 0., 0., 1.);
+            rectangle(&mut context, 2., 2., 4., 4.);
+            context.fill();
+        }
+
+        let mut surface = ImageSurface::create(Format::RGBA128F, 20, 20);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(0., 1., 0., 1.);
+            context.scale(2., 2.).unwrap();
+            context.mask_surface(&mask, 0., 0.);
+        }
+
+        // The enlarged mask is interpolated, so its edges are soft
+        assert_eq!(surface.get(5, 5).unwrap(), Rgba::new(0., 1., 0., 1.));
+        assert_eq!(surface.get(10, 10).unwrap(), Rgba::new(0., 1., 0., 1.));
+        assert_eq!(surface.get(2, 2).unwrap().alpha, 0.);
+        assert_eq!(surface.get(13, 13).unwrap().alpha, 0.);
+    }
+
+    #[test]
+    fn test_mask_unbounded_operator_clears_outside() {
+        let mut mask = ImageSurface::create(Format::RGBA128F, 2, 2);
+        *mask.get_mut(0, 0).unwrap() = Rgba::new(0., 0., 0., 1.);
+
+        let mut surface = ImageSurface::create(Format::RGBA128F, 2, 2);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 1., 1., 1.);
+            context.paint();
+            context.set_operator(Operator::In);
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.mask_surface(&mask, 0., 0.);
+        }
+
+        assert_eq!(surface.get(0, 0).unwrap(), Rgba::new(1., 0., 0., 1.));
+        assert_eq!(surface.get(1, 1).unwrap().alpha, 0.);
+    }
+
+    // Returns a 2x2 surface with a red, green, blue and white pixel.
+    fn four_colors() -> ImageSurface<'static> {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 2, 2);
+        *surface.get_mut(0, 0).unwrap() = Rgba::new(1., 0., 0., 1.);
+        *surface.get_mut(1, 0).unwrap() = Rgba::new(0., 1., 0., 1.);
+        *surface.get_mut(0, 1).unwrap() = Rgba::new(0., 0., 1., 1.);
+        *surface.get_mut(1, 1).unwrap() = Rgba::new(1., 1., 1., 1.);
+        surface
+    }
+
+    #[test]
+    fn test_set_source_surface() {
+        let source = four_colors();
+        let mut surface = ImageSurface::create(Format::RGBA128F, 5, 5);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_surface(&source, 2., 1.);
+            context.paint();
+        }
+
+        assert_eq!(surface.get(2, 1).unwrap(), Rgba::new(1., 0., 0., 1.));
+        assert_eq!(surface.get(3, 1).unwrap(), Rgba::new(0., 1., 0., 1.));
+        assert_eq!(surface.get(2, 2).unwrap(), Rgba::new(0., 0., 1., 1.));
+        assert_eq!(surface.get(3, 2).unwrap(), Rgba::new(1., 1., 1., 1.));
+        // Outside of the source surface nothing is painted
+        assert_eq!(surface.get(1, 1).unwrap().alpha, 0.);
+        assert_eq!(surface.get(4, 2).unwrap().alpha, 0.);
+        assert_eq!(surface.get(2, 3).unwrap().alpha, 0.);
+    }
+
+    #[test]
+    fn test_fill_with_surface_source() {
+        let source = four_colors();
+        let mut surface = ImageSurface::create(Format::RGBA128F, 4, 4);
+        {
+            let mut context = Context::create(&mut surface);
+            context.scale(2., 2.).unwrap();
+            let mut pattern = Pattern::create_for_surface(&source);
+            pattern.set_filter(Filter::Nearest);
+            context.set_source(&pattern);
+            rectangle(&mut context, 1., 0., 1., 2.);
+            context.fill();
+        }
+
+        // Only the right half of the scaled up source is filled
+        assert_eq!(surface.get(1, 1).unwrap().alpha, 0.);
+        assert_eq!(surface.get(2, 0).unwrap(), Rgba::new(0., 1., 0., 1.));
+        assert_eq!(surface.get(3, 1).unwrap(), Rgba::new(0., 1., 0., 1.));
+        assert_eq!(surface.get(3, 3).unwrap(), Rgba::new(1., 1., 1., 1.));
+    }
+
+    #[test]
+    fn test_source_is_fixed_in_user_space() {
+        let source = four_colors();
+        let mut surface = ImageSurface::create(Format::RGBA128F, 4, 4);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_surface(&source, 0., 0.);
+            // Moving user space afterwards moves the path but not the source
+            context.translate(2., 2.).unwrap();
+            rectangle(&mut context, -2., -2., 2., 2.);
+            context.fill();
+        }
+
+        assert_eq!(surface.get(0, 0).unwrap(), Rgba::new(1., 0., 0., 1.));
+        assert_eq!(surface.get(1, 1).unwrap(), Rgba::new(1., 1., 1., 1.));
+    }
+
+    #[test]
+    fn test_restore_source() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 2, 2);
+        let mut context = Context::create(&mut surface);
+        context.set_source_rgba(1., 0., 0., 1.);
+        context.save();
+        context.set_source_surface(&four_colors(), 0., 0.);
+        context.restore().unwrap();
+        assert_eq!(source_rgba(&context), Rgba::new(1., 0., 0., 1.));
+        assert_eq!(context.get_source().sample(1.5, 1.5), Rgba::new(1., 0., 0., 1.));
+    }
+
+    #[test]
+    fn test_paint_linear_gradient() {
+        let mut gradient = Pattern::create_linear(0., 0., 4., 0.);
+        gradient.add_color_stop_rgb(0., 0., 0., 0.).unwrap();
+        gradient.add_color_stop_rgb(1., 1., 1., 1.).unwrap();
+
+        let mut surface = ImageSurface::create(Format::RGBA128F, 4, 2);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source(&gradient);
+            context.paint();
+        }
+
+        // Every pixel takes the color at its center
+        for x in 0..4 {
+            let gray = (x as f32 + 0.5) / 4.;
+            assert_eq!(surface.get(x, 1).unwrap(), Rgba::new(gray, gray, gray, 1.));
+        }
+    }
+
+    #[test]
+    fn test_paint_mesh() {
+        let mut mesh = Pattern::create_mesh();
+        mesh.begin_patch().unwrap();
+        mesh.move_to(1., 1.).unwrap();
+        mesh.line_to(3., 1.).unwrap();
+        mesh.line_to(3., 3.).unwrap();
+        mesh.line_to(1., 3.).unwrap();
+        for corner in 0..4 {
+            mesh.set_corner_color_rgb(corner, 0., 1., 0.).unwrap();
+        }
+        mesh.end_patch().unwrap();
+
+        let mut surface = ImageSurface::create(Format::RGBA128F, 8, 8);
+        {
+            let mut context = Context::create(&mut surface);
+            context.scale(2., 2.).unwrap();
+            context.set_source(&mesh);
+            context.paint();
+        }
+
+        assert!((surface.get(2, 2).unwrap().green - 1.).abs() < 1e-5);
+        assert!((surface.get(5, 5).unwrap().green - 1.).abs() < 1e-5);
+        assert_eq!(surface.get(1, 1).unwrap().alpha, 0.);
+        assert_eq!(surface.get(6, 6).unwrap().alpha, 0.);
+    }
+
+    #[test]
+    fn test_scaled_down_source_is_averaged() {
+        // A checkerboard drawn at a quarter of its size turns an even gray
+        let mut checkerboard = ImageSurface::create(Format::RGBA128F, 16, 16);
+        for y in 0..16 {
+            for x in (0..16).filter(|x| (x + y) % 2 == 0) {
+                *checkerboard.get_mut(x, y).unwrap() = Rgba::new(1., 1., 1., 1.);
+            }
+        }
+
+        let mut surface = ImageSurface::create(Format::RGBA128F, 4, 4);
+        {
+            let mut context = Context::create(&mut surface);
+            context.scale(0.25, 0.25).unwrap();
+            context.set_source_surface(&checkerboard, 0., 0.);
+            context.paint();
+        }
+
+        for pixel in surface.iter() {
+            assert!((pixel.alpha - 0.5).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_tiled_source() {
+        let tile = four_colors();
+        let mut pattern = Pattern::create_for_surface(&tile);
+        pattern.set_extend(Extend::Repeat);
+        pattern.set_filter(Filter::Nearest);
+        // Tiles twice the size of the surface, starting at (1, 1)
+        let matrix = Matrix::init_scale(0.5, 0.5).multiply(&Matrix::init_translate(-0.5, -0.5));
+        pattern.set_matrix(matrix).unwrap();
+
+        let mut surface = ImageSurface::create(Format::RGBA128F, 9, 9);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source(&pattern);
+            context.paint();
+        }
+
+        let red = Rgba::new(1., 0., 0., 1.);
+        let white = Rgba::new(1., 1., 1., 1.);
+        for &(x, y) in &[(1, 1), (2, 2), (5, 5), (6, 1), (1, 6)] {
+            assert_eq!(surface.get(x, y).unwrap(), red);
+        }
+        for &(x, y) in &[(0, 0), (3, 3), (4, 4), (7, 8), (8, 3)] {
+            assert_eq!(surface.get(x, y).unwrap(), white);
+        }
+        assert_eq!(surface.get(3, 1).unwrap(), Rgba::new(0., 1., 0., 1.));
+    }
+
+    #[test]
+    fn test_composite_into_packed_formats() {
+        // Faint red, painted twice into 8 bit ARGB
+        let mut surface = ImageSurface::create(Format::ARGB32, 4, 4);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 0., 0., 0.2);
+            context.paint();
+            if let Target::Image(ref surface) = context.target {
+                assert_eq!(surface.get(3, 3).unwrap(), Rgba::new(1., 0., 0., 51. / 255.));
+            }
+            context.paint();
+        }
+        // 0.36 is rounded to the nearest 8 bit value
+        assert_eq!(surface.get(3, 3).unwrap(), Rgba::new(1., 0., 0., 92. / 255.));
+
+        // An alpha-only mask keeps just the coverage of a fill
+        let mut mask = ImageSurface::create(Format::A8, 8, 8);
+        {
+            let mut context = Context::create(&mut mask);
+            context.set_source_rgba(1., 0., 0., 1.);
+            rectangle(&mut context, 2., 2., 4., 4.);
+            context.fill();
+        }
+        assert_eq!(mask.get(3, 3).unwrap(), Rgba::new(0., 0., 0., 1.));
+        assert_eq!(mask.get(1, 3).unwrap().alpha, 0.);
+
+        // Opaque formats stay opaque
+        let mut surface = ImageSurface::create(Format::RGB16_565, 2, 2);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(0., 0., 1., 0.5);
+            context.paint();
+        }
+        assert_eq!(surface.get(0, 0).unwrap(), Rgba::new(0., 0., 16. / 31., 1.));
+    }
+
+    #[test]
+    fn test_draw_into_external_data() {
+        // A 4x2 ARGB32 buffer with 8 bytes of padding after each row
+        let mut data = vec![0; 48];
+        {
+            let mut surface = ImageSurface::create_for_data(&mut data, Format::ARGB32, 4, 2, 24)
+                .unwrap();
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(0., 1., 0., 1.);
+            rectangle(&mut context, 1., 0., 2., 2.);
+            context.fill();
+        }
+        let green = 0xff00ff00u32.to_ne_bytes();
+        for row in data.chunks(24) {
+            assert_eq!(&row[0..4], &[0; 4]);
+            assert_eq!(&row[4..8], &green);
+            assert_eq!(&row[8..12], &green);
+            assert_eq!(&row[12..24], &[0; 12]);
+        }
     }
 }
@@ -72,7 +72,7 @@ macro_rules! debug_render {
     ($lines:expr, $color:expr) => {
         {
             use $crate::types::Rgba;
-            use surfaces::ImageSurface;
+            use surfaces::{ImageSurface, Format};
             use debug_utils::get_target_dir;
             use types::{Pixel, IntoPixels};
             use std::env;
@@ -105,13 +105,13 @@ macro_rules! debug_render {
             max_x = max_x + 20;
             max_y = max_y + 20;
 
-            let mut surface = ImageSurface::create(max_x as usize, max_y as usize);
+            let mut surface = ImageSurface::create(Format::ARGB32, max_x as usize, max_y as usize);
 
             // Actually color in the pixels
             for line in $lines.iter() {
                 for pixel in line.into_pixels() {
                     match surface.get_mut(pixel.x as usize, pixel.y as usize) {
-                        Some(pixel) => {
+                        Some(mut pixel) => {
                             pixel.red = color.red;
                             pixel.blue = color.blue;
                             pixel.green = color.green;
@@ -155,7 +155,7 @@ macro_rules! debug_render {
             let filename = filename + &count.to_string();
             let extension = ".png";
             path.push(filename + &extension.to_string());
-            surface.to_file(path.as_path());
+            surface.to_file(path.as_path()).unwrap();
             path
         }
     }
@@ -166,7 +166,7 @@ macro_rules! debug_render_traps {
     ($traps:expr, $color:expr) => {
         {
             use $crate::types::Rgba;
-            use surfaces::ImageSurface;
+            use surfaces::{ImageSurface, Format};
             use debug_utils::get_target_dir;
             use types::IntoPixels;
             use trapezoid_rasterizer::mask_from_trapezoids;
@@ -192,11 +192,9 @@ macro_rules! debug_render_traps {
             max_x = max_x + 20;
             max_y = max_y + 20;
 
-            let mut destination = ImageSurface::create(max_x as usize, max_y as usize);
-            let mut mask = mask_from_trapezoids(&$traps, max_x as usize, max_y as usize);
-            let mut source = ImageSurface::create(max_x as usize, max_y as usize);
-
-
+            let mut destination = ImageSurface::create(Format::ARGB32, max_x as usize,
+                                                       max_y as usize);
+            let mask = mask_from_trapezoids(&$traps, max_x as usize, max_y as usize);
 
             let color =
                 match $color.as_ref() {
@@ -206,31 +204,11 @@ macro_rules! debug_render_traps {
                     "black" | _ => Rgba{red: 0., green: 0., blue: 0., alpha: 1.}
                 };
 
-            for pixel in source.iter_mut() {
-                pixel.red = color.red;
-                pixel.green = color.green;
-                pixel.blue = color.blue;
-                pixel.alpha = color.alpha;
-            }
-
-
-            for (idx, mut mask_pixel) in mask.iter_mut().enumerate() {
-                match source.get_with_index(idx) {
-                    Some(src_pixel) => {
-                        operator_in(&src_pixel, &mut mask_pixel);
-                    },
-                    None => {}
-                }
-            }
-
-            for (idx, mask_pixel) in mask.iter().enumerate() {
-                match destination.get_mut_with_index(idx) {
-                    Some(mut dest_pixel) => {
-                        operator_over(&mask_pixel, &mut dest_pixel);
-                    },
-                    None => {}
-                }
-            }
+            destination.update_pixels(|idx, dest_pixel| {
+                let mut mask_pixel = Rgba{red: 0., green: 0., blue: 0., alpha: mask[idx]};
+                operator_in(&color, &mut mask_pixel);
+                operator_over(&mask_pixel, dest_pixel);
+            });
 
             // Push folders onto path
             let mut path = get_target_dir();
@@ -264,7 +242,7 @@ macro_rules! debug_render_traps {
             let filename = filename + &count.to_string();
             let extension = ".png";
             path.push(filename + &extension.to_string());
-            destination.to_file(path.as_path());
+            destination.to_file(path.as_path()).unwrap();
             path
         }
     }
@@ -385,7 +363,7 @@ mod tests {
         path.push("images");
         path.push("debug_utils_246.png"); // Must be line number of debug_render! call
         // Cleanup
-        assert_eq!(path.exists(), false);
+        assert!(!path.exists());
     }
 
     // Tests that an image is output when the debug-tesselator feature flag is set
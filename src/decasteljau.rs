@@ -37,6 +37,7 @@
 use common_geometry::Point;
 
 ///SplineKnots for bezier curves
+#[derive(Copy, Clone, Debug)]
 pub struct SplineKnots{
     pub a: Point,
     pub b: Point,
@@ -47,7 +48,7 @@ pub struct SplineKnots{
 ///Implements SplineKnots methods
 impl SplineKnots{
     ///Creates a new SplineKnots with user defined points
-    fn create(a: &Point, b: &Point, c: &Point, d: &Point)->SplineKnots{
+    pub fn create(a: &Point, b: &Point, c: &Point, d: &Point)->SplineKnots{
         SplineKnots{
             a:Point::new(a.x, a.y),
             b:Point::new(b.x, b.y),
@@ -55,6 +56,23 @@ impl SplineKnots{
             d:Point::new(d.x, d.y),
         }
     }
+
+    ///Returns the point at `t` along the spline, from `a` at 0 to `d` at 1, found by repeatedly
+    ///interpolating between the knots.
+    pub fn point_at(&self, t: f32) -> Point {
+        let ab = lerp(&self.a, &self.b, t);
+        let bc = lerp(&self.b, &self.c, t);
+        let cd = lerp(&self.c, &self.d, t);
+        lerp(&lerp(&ab, &bc, t), &lerp(&bc, &cd, t), t)
+    }
+}
+
+///Returns the point `t` of the way from `a` to `b`
+fn lerp(a: &Point, b: &Point, t: f32) -> Point {
+    Point{
+        x: a.x + (b.x - a.x) * t,
+        y: a.y + (b.y - a.y) * t,
+    }
 }
 
 ///This function takes two end points which are interpolated providing the intermediate point
@@ -107,12 +125,65 @@ impl DeCasteljauPoints {
     }
 }
 
+// Deepest that `decompose` will subdivide a spline.  2^16 lines is far more than any visible
+// curve needs, this only guards against degenerate (NaN or huge) coordinates.
+const MAX_DECOMPOSE_DEPTH: u32 = 16;
+
+///Flattens the spline into straight lines that stay within `tolerance` of the true curve.
+///
+///Returns the end points of the lines in order.  The first knot (`knots.a`) is not included,
+///since it is the end of whatever came before the spline.  The last point is always `knots.d`.
+pub fn decompose(knots: &SplineKnots, tolerance: f32) -> Vec<Point> {
+    let mut knots = SplineKnots::create(&knots.a, &knots.b, &knots.c, &knots.d);
+    let mut points = Vec::new();
+    decompose_into(&mut knots, tolerance * tolerance, MAX_DECOMPOSE_DEPTH, &mut points);
+    points
+}
+
+///Splits the spline in half with de Casteljau's algorithm until each half is flat enough to be
+///drawn as a single line.
+fn decompose_into(s1: &mut SplineKnots, tolerance_squared: f32, depth: u32,
+                  points: &mut Vec<Point>) {
+    if depth == 0 || error_squared(s1) <= tolerance_squared {
+        points.push(s1.d);
+        return;
+    }
+
+    let mut s2 = SplineKnots::create(&Point::origin(), &Point::origin(),
+                                     &Point::origin(), &Point::origin());
+    DeCasteljauPoints::create().create_spline(s1, &mut s2);
+    decompose_into(s1, tolerance_squared, depth - 1, points);
+    decompose_into(&mut s2, tolerance_squared, depth - 1, points);
+}
+
+///Returns the squared distance of the furthest control point from the line a-d.  The spline is
+///contained in the hull of its knots, so this bounds how far the line strays from the curve.
+fn error_squared(knots: &SplineKnots) -> f32 {
+    distance_squared_to_segment(&knots.b, &knots.a, &knots.d)
+        .max(distance_squared_to_segment(&knots.c, &knots.a, &knots.d))
+}
+
+///Returns the squared distance from `point` to the line segment from `start` to `end`.
+fn distance_squared_to_segment(point: &Point, start: &Point, end: &Point) -> f32 {
+    let (dx, dy) = (end.x - start.x, end.y - start.y);
+    let (px, py) = (point.x - start.x, point.y - start.y);
+    let length_squared = dx * dx + dy * dy;
+    if length_squared == 0. {
+        return px * px + py * py;
+    }
+
+    let t = ((px * dx + py * dy) / length_squared).clamp(0., 1.);
+    let (ex, ey) = (px - t * dx, py - t * dy);
+    ex * ex + ey * ey
+}
+
 #[cfg(test)]
 mod tests{
     use::common_geometry::Point;
     use::decasteljau::SplineKnots;
     use::decasteljau::DeCasteljauPoints;
     use::decasteljau::lerp_half;
+    use::decasteljau::decompose;
 
 
     #[test]
@@ -557,7 +628,46 @@ mod tests{
         assert_eq!(s1.d.x, d1.fin.x);
         assert_eq!(s1.d.y, d1.fin.y);
     }
-}
 
+    #[test]
+    fn test_decompose_straight_spline(){
+        //A spline whose control points lie on the line a-d is already flat, so it decomposes
+        //into a single line ending at d
 
+        //Setup
+        let s1 = SplineKnots::create(&Point::new(0., 0.), &Point::new(1., 1.),
+                                     &Point::new(2., 2.), &Point::new(3., 3.));
+        //Call
+        let points = decompose(&s1, 0.1);
+        //Test
+        assert_eq!(points, vec![Point::new(3., 3.)]);
+    }
+
+    #[test]
+    fn test_decompose_curve_within_tolerance(){
+        //Every point of the decomposed curve should be within the tolerance of the true curve,
+        //and the curve should end at d
 
+        //Setup
+        let s1 = SplineKnots::create(&Point::new(0., 0.), &Point::new(0., 50.),
+                                     &Point::new(100., 50.), &Point::new(100., 0.));
+        let tolerance = 0.1;
+        //Call
+        let points = decompose(&s1, tolerance);
+        //Test
+        assert!(points.len() > 8);
+        assert_eq!(*points.last().unwrap(), Point::new(100., 0.));
+        //The curve's highest point is at t = 0.5, (50, 37.5)
+        let top = points.iter().fold(0., |max: f32, point| max.max(point.y));
+        assert!((top - 37.5).abs() < tolerance);
+    }
+
+    #[test]
+    fn test_point_at(){
+        let knots = SplineKnots::create(&Point::new(0., 0.), &Point::new(0., 4.),
+                                        &Point::new(4., 4.), &Point::new(4., 0.));
+        assert_eq!(knots.point_at(0.), Point::new(0., 0.));
+        assert_eq!(knots.point_at(0.5), Point::new(2., 3.));
+        assert_eq!(knots.point_at(1.), Point::new(4., 0.));
+    }
+}
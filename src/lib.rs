@@ -41,9 +41,14 @@
 //! to utilize and preserve the Cairo drawing model while providing the benefits of a native Rust
 //! implementation.
 
+// Explicit `field: field` initialization, the `into_*` naming of borrowing conversions and doc
+// comment section headings set apart from the item below are house style throughout Cairus.
+#![allow(clippy::redundant_field_names, clippy::wrong_self_convention,
+         clippy::empty_line_after_doc_comments)]
+
 /// When we get down to the level of pixels, they are blended together by operations
 /// defined in the operators module.
-#[allow(dead_code)]
+#[allow(dead_code, unused_macros)]
 #[macro_use]
 mod debug_utils;
 
@@ -52,10 +57,26 @@ pub mod operators;
 
 #[allow(dead_code)]
 mod types;
+pub use types::{Rgba, ChannelOrder, AlphaMode};
 
 #[allow(dead_code)]
 pub mod surfaces;
 
+#[allow(dead_code)]
+mod netpbm;
+
+#[allow(dead_code)]
+mod bmp;
+
+#[allow(dead_code)]
+mod tga;
+
+#[allow(dead_code)]
+mod svg;
+
+#[allow(dead_code)]
+mod pdf;
+
 #[allow(dead_code)]
 mod decasteljau;
 
@@ -70,3 +91,20 @@ mod common_geometry;
 
 #[allow(dead_code)]
 mod bo_trap;
+
+#[allow(dead_code)]
+mod path;
+
+#[allow(dead_code)]
+mod stroker;
+
+#[allow(dead_code)]
+pub mod matrix;
+
+#[allow(dead_code)]
+pub mod patterns;
+#[allow(dead_code)]
+mod mesh;
+
+#[allow(dead_code)]
+pub mod status;
@@ -0,0 +1,246 @@
+/*
+ * Cairus - a reimplementation of the cairo graphics library in Rust
+ *
+ * Copyright © 2017 CairusOrg
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it either under the terms of the GNU Lesser General Public
+ * License version 2.1 as published by the Free Software Foundation
+ * (the "LGPL") or, at your option, under the terms of the Mozilla
+ * Public License Version 2.0 (the "MPL"). If you do not alter this
+ * notice, a recipient may use your version of this file under either
+ * the MPL or the LGPL.
+ *
+ * You should have received a copy of the LGPL along with this library
+ * in the file LICENSE-LGPL-2_1; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Suite 500, Boston, MA 02110-1335, USA
+ * You should have received a copy of the MPL along with this library
+ * in the file LICENSE-MPL-2_0
+ *
+ * The contents of this file are subject to the Mozilla Public License
+ * Version 2.0 (the "License"); you may not use this file except in
+ * compliance with the License. You may obtain a copy of the License at
+ * http://www.mozilla.org/MPL/
+ *
+ * This software is distributed on an "AS IS" basis, WITHOUT WARRANTY
+ * OF ANY KIND, either express or implied. See the LGPL or the MPL for
+ * the specific language governing rights and limitations.
+ *
+ * The Original Code is the cairus graphics library.
+ *
+ */
+
+//! This module defines the affine transformations used to map between coordinate spaces.
+//!
+//! A `Matrix` maps a point (x, y) to
+//!
+//! ```text
+//! x_new = xx * x + xy * y + x0
+//! y_new = yx * x + yy * y + y0
+//! ```
+//!
+//! This is analogous to cairo_matrix_t, and the members have the same names and meaning.
+
+use status::Status;
+
+/// An affine transformation, analogous to cairo_matrix_t.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Matrix {
+    pub xx: f32,
+    pub yx: f32,
+    pub xy: f32,
+    pub yy: f32,
+    pub x0: f32,
+    pub y0: f32,
+}
+
+impl Matrix {
+    /// Returns the matrix with the given members.
+    pub fn new(xx: f32, yx: f32, xy: f32, yy: f32, x0: f32, y0: f32) -> Matrix {
+        Matrix {
+            xx: xx,
+            yx: yx,
+            xy: xy,
+            yy: yy,
+            x0: x0,
+            y0: y0,
+        }
+    }
+
+    /// Returns the identity transformation, which leaves every point where it is.
+    pub fn init_identity() -> Matrix {
+        Matrix::new(1., 0., 0., 1., 0., 0.)
+    }
+
+    /// Returns a transformation that moves every point by (tx, ty).
+    pub fn init_translate(tx: f32, ty: f32) -> Matrix {
+        Matrix::new(1., 0., 0., 1., tx, ty)
+    }
+
+    /// Returns a transformation that scales x by `sx` and y by `sy`.
+    pub fn init_scale(sx: f32, sy: f32) -> Matrix {
+        Matrix::new(sx, 0., 0., sy, 0., 0.)
+    }
+
+    /// Returns a transformation that rotates by `radians`.  With the y axis pointing down, as it
+    /// does on a surface, positive angles rotate from the positive x axis toward the positive y
+    /// axis.
+    pub fn init_rotate(radians: f32) -> Matrix {
+        let (sin, cos) = radians.sin_cos();
+        Matrix::new(cos, sin, -sin, cos, 0., 0.)
+    }
+
+    /// Applies a translation by (tx, ty) before this transformation.
+    pub fn translate(&mut self, tx: f32, ty: f32) {
+        *self = Matrix::init_translate(tx, ty).multiply(self);
+    }
+
+    /// Applies a scale by (sx, sy) before this transformation.
+    pub fn scale(&mut self, sx: f32, sy: f32) {
+        *self = Matrix::init_scale(sx, sy).multiply(self);
+    }
+
+    /// Applies a rotation by `radians` before this transformation.
+    pub fn rotate(&mut self, radians: f32) {
+        *self = Matrix::init_rotate(radians).multiply(self);
+    }
+
+    /// Returns the transformation that applies this one first and then `other`.
+    pub fn multiply(&self, other: &Matrix) -> Matrix {
+        Matrix {
+            xx: self.xx * other.xx + self.yx * other.xy,
+            yx: self.xx * other.yx + self.yx * other.yy,
+            xy: self.xy * other.xx + self.yy * other.xy,
+            yy: self.xy * other.yx + self.yy * other.yy,
+            x0: self.x0 * other.xx + self.y0 * other.xy + other.x0,
+            y0: self.x0 * other.yx + self.y0 * other.yy + other.y0,
+        }
+    }
+
+    /// Returns the transformation that undoes this one.
+    ///
+    /// Returns `Err(Status::InvalidMatrix)` if this matrix squashes the plane onto a line or a
+    /// point, or has members that aren't finite, since it can't be undone.
+    pub fn invert(&self) -> Result<Matrix, Status> {
+        let determinant = self.determinant();
+        if determinant == 0. || !determinant.is_finite() || !self.x0.is_finite() ||
+           !self.y0.is_finite() {
+            return Err(Status::InvalidMatrix);
+        }
+
+        let xx = self.yy / determinant;
+        let yx = -self.yx / determinant;
+        let xy = -self.xy / determinant;
+        let yy = self.xx / determinant;
+        Ok(Matrix {
+            xx: xx,
+            yx: yx,
+            xy: xy,
+            yy: yy,
+            x0: -(xx * self.x0 + xy * self.y0),
+            y0: -(yx * self.x0 + yy * self.y0),
+        })
+    }
+
+    /// Returns the point (x, y) transformed by this matrix.
+    pub fn transform_point(&self, x: f32, y: f32) -> (f32, f32) {
+        let (dx, dy) = self.transform_distance(x, y);
+        (dx + self.x0, dy + self.y0)
+    }
+
+    /// Returns the distance vector (dx, dy) transformed by this matrix.  Unlike a point, a
+    /// distance isn't affected by the translation part of the matrix.
+    pub fn transform_distance(&self, dx: f32, dy: f32) -> (f32, f32) {
+        (self.xx * dx + self.xy * dy, self.yx * dx + self.yy * dy)
+    }
+
+    // The factor this matrix scales areas by.
+    fn determinant(&self) -> f32 {
+        self.xx * self.yy - self.yx * self.xy
+    }
+}
+
+impl Default for Matrix {
+    fn default() -> Matrix {
+        Matrix::init_identity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Matrix;
+    use status::Status;
+    use std::f32::consts::PI;
+
+    fn assert_close(actual: (f32, f32), expected: (f32, f32)) {
+        assert!((actual.0 - expected.0).abs() < 1e-5 && (actual.1 - expected.1).abs() < 1e-5,
+                "{:?} is not close to {:?}", actual, expected);
+    }
+
+    #[test]
+    fn identity_leaves_points() {
+        let matrix = Matrix::init_identity();
+        assert_eq!(matrix.transform_point(3., -4.), (3., -4.));
+        assert_eq!(matrix, Matrix::default());
+    }
+
+    #[test]
+    fn translate_moves_points_not_distances() {
+        let matrix = Matrix::init_translate(10., 20.);
+        assert_eq!(matrix.transform_point(1., 2.), (11., 22.));
+        assert_eq!(matrix.transform_distance(1., 2.), (1., 2.));
+    }
+
+    #[test]
+    fn scale() {
+        let matrix = Matrix::init_scale(2., 3.);
+        assert_eq!(matrix.transform_point(1., 1.), (2., 3.));
+        assert_eq!(matrix.transform_distance(-1., 2.), (-2., 6.));
+    }
+
+    #[test]
+    fn rotate_turns_x_toward_y() {
+        let matrix = Matrix::init_rotate(PI / 2.);
+        assert_close(matrix.transform_point(1., 0.), (0., 1.));
+        assert_close(matrix.transform_point(0., 1.), (-1., 0.));
+    }
+
+    #[test]
+    fn multiply_applies_self_first() {
+        let translate = Matrix::init_translate(1., 0.);
+        let scale = Matrix::init_scale(2., 2.);
+        assert_eq!(translate.multiply(&scale).transform_point(1., 1.), (4., 2.));
+        assert_eq!(scale.multiply(&translate).transform_point(1., 1.), (3., 2.));
+    }
+
+    #[test]
+    fn in_place_operations_apply_before_matrix() {
+        // Like cairo_matrix_translate, the new transformation happens first
+        let mut matrix = Matrix::init_scale(2., 2.);
+        matrix.translate(1., 0.);
+        assert_eq!(matrix.transform_point(0., 0.), (2., 0.));
+
+        let mut matrix = Matrix::init_translate(5., 0.);
+        matrix.rotate(PI);
+        matrix.scale(2., 1.);
+        assert_close(matrix.transform_point(1., 0.), (3., 0.));
+    }
+
+    #[test]
+    fn invert_undoes_matrix() {
+        let mut matrix = Matrix::init_translate(3., -2.);
+        matrix.rotate(0.5);
+        matrix.scale(2., 0.5);
+        let inverse = matrix.invert().unwrap();
+        let (x, y) = matrix.transform_point(7., 11.);
+        assert_close(inverse.transform_point(x, y), (7., 11.));
+        assert_close(matrix.multiply(&inverse).transform_point(-4., 9.), (-4., 9.));
+    }
+
+    #[test]
+    fn invert_singular_matrix_fails() {
+        assert_eq!(Matrix::init_scale(0., 1.).invert(), Err(Status::InvalidMatrix));
+        assert_eq!(Matrix::new(1., 2., 2., 4., 0., 0.).invert(), Err(Status::InvalidMatrix));
+        assert_eq!(Matrix::init_translate(f32::NAN, 0.).invert(), Err(Status::InvalidMatrix));
+    }
+}
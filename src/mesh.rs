@@ -0,0 +1,298 @@
+/*
+ * Cairus - a reimplementation of the cairo graphics library in Rust
+ *
+ * Copyright © 2017 CairusOrg
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it either under the terms of the GNU Lesser General Public
+ * License version 2.1 as published by the Free Software Foundation
+ * (the "LGPL") or, at your option, under the terms of the Mozilla
+ * Public License Version 2.0 (the "MPL"). If you do not alter this
+ * notice, a recipient may use your version of this file under either
+ * the MPL or the LGPL.
+ *
+ * You should have received a copy of the LGPL along with this library
+ * in the file LICENSE-LGPL-2_1; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Suite 500, Boston, MA 02110-1335, USA
+ * You should have received a copy of the MPL along with this library
+ * in the file LICENSE-MPL-2_0
+ *
+ * The contents of this file are subject to the Mozilla Public License
+ * Version 2.0 (the "License"); you may not use this file except in
+ * compliance with the License. You may obtain a copy of the License at
+ * http://www.mozilla.org/MPL/
+ *
+ * This software is distributed on an "AS IS" basis, WITHOUT WARRANTY
+ * OF ANY KIND, either express or implied. See the LGPL or the MPL for
+ * the specific language governing rights and limitations.
+ *
+ * The Original Code is the cairus graphics library.
+ *
+ */
+
+//! This module defines the patches of mesh patterns and draws them.
+//!
+//! A patch is a tensor-product Bézier surface: a 4x4 grid of control points `points[i][j]` maps
+//! the unit square of parameters (u, v) onto pattern space, with u following i and v following
+//! j.  Its four corners are
+//!
+//! ```text
+//! corner 0: points[0][0]      corner 1: points[0][3]
+//! corner 3: points[3][0]      corner 2: points[3][3]
+//! ```
+//!
+//! and the sides run from corner 0 to 1, 1 to 2, 2 to 3 and 3 back to 0, like in cairo.  Each
+//! corner has a color, and the color of the point at (u, v) is interpolated bilinearly between
+//! them.
+//!
+//! A patch is drawn by cutting its parameter square into cells small enough to be flat, and
+//! filling each cell with two triangles whose colors are interpolated across every pixel.
+
+use common_geometry::Point;
+use decasteljau::SplineKnots;
+use matrix::Matrix;
+use types::Rgba;
+
+// Most cells a patch is cut into along u or along v
+const MAX_STEPS: usize = 512;
+
+// Length in device pixels that the side of a cell should not exceed.  Colors are interpolated
+// linearly across the triangles of a cell rather than bilinearly, which is only close enough
+// when cells are small.
+const CELL_SIZE: f32 = 1.;
+
+// The interior control points of a patch, as (i, j) indices into its points
+const CONTROL_POINTS: [(usize, usize); 4] = [(1, 1), (1, 2), (2, 2), (2, 1)];
+
+/// A patch of a mesh pattern.
+#[derive(Copy, Clone, Debug)]
+pub struct Patch {
+    points: [[Point; 4]; 4],
+    colors: [Rgba; 4],
+}
+
+impl Patch {
+    /// Returns the patch bounded by `sides`, which run around it from corner 0.
+    ///
+    /// Interior control points that are `None` are placed so that the patch is a Coons patch,
+    /// whose shape depends only on its sides.
+    pub fn create(sides: &[SplineKnots; 4], control_points: &[Option<Point>; 4],
+                  colors: &[Rgba; 4]) -> Patch {
+        let mut points = [[Point::origin(); 4]; 4];
+        for k in 0..4 {
+            let side = [sides[0].a, sides[0].b, sides[0].c, sides[0].d];
+            points[0][k] = side[k];
+            let side = [sides[1].a, sides[1].b, sides[1].c, sides[1].d];
+            points[k][3] = side[k];
+            let side = [sides[2].a, sides[2].b, sides[2].c, sides[2].d];
+            points[3][3 - k] = side[k];
+            let side = [sides[3].a, sides[3].b, sides[3].c, sides[3].d];
+            points[3 - k][0] = side[k];
+        }
+
+        for (index, &(i, j)) in CONTROL_POINTS.iter().enumerate() {
+            points[i][j] = match control_points[index] {
+                Some(point) => point,
+                None => coons_control_point(&points, i, j),
+            };
+        }
+
+        Patch {
+            points: points,
+            colors: *colors,
+        }
+    }
+
+    /// Returns the point of pattern space at parameters (u, v).
+    pub fn point_at(&self, u: f32, v: f32) -> Point {
+        point_at(&self.points, u, v)
+    }
+
+    /// Returns the pre-multiplied color at parameters (u, v).
+    pub fn color_at(&self, u: f32, v: f32) -> Rgba {
+        let top = lerp_rgba(&self.colors[0], &self.colors[1], v);
+        let bottom = lerp_rgba(&self.colors[3], &self.colors[2], v);
+        lerp_rgba(&top, &bottom, u)
+    }
+}
+
+// Returns where the interior control point (i, j) of a Coons patch goes, which only depends on
+// the points of the sides.  This is cairo's formula, written for the control point next to
+// corner 0 and mirrored onto the others.
+fn coons_control_point(points: &[[Point; 4]; 4], i: usize, j: usize) -> Point {
+    // Flipping bit 1 of an index moves to the nearest side and bit 2 to the far side, so p(1, 1)
+    // is the nearest corner and p(2, 2) the opposite one.
+    let p = |a: usize, b: usize| points[i ^ a][j ^ b];
+    let combine = |f: &dyn Fn(&Point) -> f32| {
+        (-4. * f(&p(1, 1)) +
+         6. * (f(&p(1, 0)) + f(&p(0, 1))) -
+         2. * (f(&p(1, 2)) + f(&p(2, 1))) +
+         3. * (f(&p(2, 0)) + f(&p(0, 2))) -
+         f(&p(2, 2))) / 9.
+    };
+    Point::new(combine(&|point| point.x), combine(&|point| point.y))
+}
+
+// Returns the point of the tensor-product surface with control points `points` at (u, v).
+fn point_at(points: &[[Point; 4]; 4], u: f32, v: f32) -> Point {
+    let mut column = [Point::origin(); 4];
+    for (i, row) in points.iter().enumerate() {
+        column[i] = SplineKnots::create(&row[0], &row[1], &row[2], &row[3]).point_at(v);
+    }
+    SplineKnots::create(&column[0], &column[1], &column[2], &column[3]).point_at(u)
+}
+
+// Returns the color `t` of the way from `a` to `b`.
+fn lerp_rgba(a: &Rgba, b: &Rgba, t: f32) -> Rgba {
+    Rgba {
+        red: a.red + (b.red - a.red) * t,
+        green: a.green + (b.green - a.green) * t,
+        blue: a.blue + (b.blue - a.blue) * t,
+        alpha: a.alpha + (b.alpha - a.alpha) * t,
+    }
+}
+
+/// Draws `patch` into `pixels`, a row-major grid of `width` by `height` device pixels, replacing
+/// the color of every pixel whose center it covers.  `pattern_to_device` places the patch.
+///
+/// Where a patch folds over itself, the part with larger parameters ends up on top.
+pub fn draw_patch(patch: &Patch, pattern_to_device: &Matrix, width: usize, height: usize,
+                  pixels: &mut [Rgba]) {
+    // Bézier surfaces keep their shape under affine transformations, so the control points can
+    // be moved into device space directly.
+    let mut points = patch.points;
+    for row in points.iter_mut() {
+        for point in row.iter_mut() {
+            let (x, y) = pattern_to_device.transform_point(point.x, point.y);
+            *point = Point::new(x, y);
+        }
+    }
+
+    let u_steps = steps(|k, l| points[l][k]);
+    let v_steps = steps(|k, l| points[k][l]);
+
+    // Positions and colors of the corners of the cells
+    let mut grid = Vec::with_capacity((u_steps + 1) * (v_steps + 1));
+    for step_u in 0..u_steps + 1 {
+        let u = step_u as f32 / u_steps as f32;
+        for step_v in 0..v_steps + 1 {
+            let v = step_v as f32 / v_steps as f32;
+            grid.push((point_at(&points, u, v), patch.color_at(u, v)));
+        }
+    }
+
+    let vertex = |step_u: usize, step_v: usize| grid[step_u * (v_steps + 1) + step_v];
+    for step_u in 0..u_steps {
+        for step_v in 0..v_steps {
+            let (a, b) = (vertex(step_u, step_v), vertex(step_u + 1, step_v));
+            let (c, d) = (vertex(step_u + 1, step_v + 1), vertex(step_u, step_v + 1));
+            draw_triangle(&[a, b, c], width, height, pixels);
+            draw_triangle(&[a, c, d], width, height, pixels);
+        }
+    }
+}
+
+// Returns how many cells to cut a patch into along one parameter, from the longest of the four
+// control polygons running that way.  `point(k, l)` is the l-th point of the k-th polygon.
+fn steps<F>(point: F) -> usize where F: Fn(usize, usize) -> Point {
+    let mut longest: f32 = 0.;
+    for k in 0..4 {
+        let mut length = 0.;
+        for l in 0..3 {
+            let (start, end) = (point(k, l), point(k, l + 1));
+            length += ((end.x - start.x).powi(2) + (end.y - start.y).powi(2)).sqrt();
+        }
+        longest = longest.max(length);
+    }
+
+    let steps = (longest / CELL_SIZE).ceil();
+    if steps.is_nan() || steps < 1. {
+        1
+    } else {
+        (steps as usize).min(MAX_STEPS)
+    }
+}
+
+// Fills the pixels whose centers lie in the triangle with corners `vertices`, interpolating the
+// colors of its corners across it.
+fn draw_triangle(vertices: &[(Point, Rgba); 3], width: usize, height: usize,
+                 pixels: &mut [Rgba]) {
+    let (a, b, c) = (vertices[0].0, vertices[1].0, vertices[2].0);
+    let area = cross(&a, &b, &c);
+    if area == 0. || area.is_nan() {
+        return;
+    }
+
+    // Pixels whose centers may be inside
+    let min_x = a.x.min(b.x).min(c.x) - 0.5;
+    let max_x = a.x.max(b.x).max(c.x) - 0.5;
+    let min_y = a.y.min(b.y).min(c.y) - 0.5;
+    let max_y = a.y.max(b.y).max(c.y) - 0.5;
+    if max_x < 0. || max_y < 0. || min_x >= width as f32 || min_y >= height as f32 {
+        return;
+    }
+    let (first_column, last_column) = (min_x.ceil().max(0.) as usize,
+                                       (max_x.floor() as usize).min(width - 1));
+    let (first_row, last_row) = (min_y.ceil().max(0.) as usize,
+                                 (max_y.floor() as usize).min(height - 1));
+
+    // Centers exactly on an edge belong to both triangles sharing it, so a small margin stops
+    // rounding from leaving gaps between cells.
+    let margin = -1e-4;
+    for row in first_row..last_row + 1 {
+        for column in first_column..last_column + 1 {
+            let center = Point::new(column as f32 + 0.5, row as f32 + 0.5);
+            let weight_a = cross(&center, &b, &c) / area;
+            let weight_b = cross(&a, &center, &c) / area;
+            let weight_c = 1. - weight_a - weight_b;
+            if weight_a < margin || weight_b < margin || weight_c < margin {
+                continue;
+            }
+
+            let (color_a, color_b, color_c) = (vertices[0].1, vertices[1].1, vertices[2].1);
+            pixels[row * width + column] = Rgba {
+                red: color_a.red * weight_a + color_b.red * weight_b + color_c.red * weight_c,
+                green: color_a.green * weight_a + color_b.green * weight_b +
+                       color_c.green * weight_c,
+                blue: color_a.blue * weight_a + color_b.blue * weight_b + color_c.blue * weight_c,
+                alpha: color_a.alpha * weight_a + color_b.alpha * weight_b +
+                       color_c.alpha * weight_c,
+            };
+        }
+    }
+}
+
+// Returns twice the signed area of the triangle a, b, c.
+fn cross(a: &Point, b: &Point, c: &Point) -> f32 {
+    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Patch;
+    use common_geometry::Point;
+    use decasteljau::SplineKnots;
+    use types::Rgba;
+
+    // Returns the straight side from a to b.
+    fn line(a: Point, b: Point) -> SplineKnots {
+        SplineKnots::create(&a, &Point::new(a.x + (b.x - a.x) / 3., a.y + (b.y - a.y) / 3.),
+                            &Point::new(a.x + (b.x - a.x) * 2. / 3., a.y + (b.y - a.y) * 2. / 3.),
+                            &b)
+    }
+
+    #[test]
+    fn coons_patch_of_a_square_is_flat() {
+        let corners = [Point::new(0., 0.), Point::new(3., 0.), Point::new(3., 3.),
+                       Point::new(0., 3.)];
+        let sides = [line(corners[0], corners[1]), line(corners[1], corners[2]),
+                     line(corners[2], corners[3]), line(corners[3], corners[0])];
+        let patch = Patch::create(&sides, &[None; 4], &[Rgba::new(0., 0., 0., 0.); 4]);
+
+        for &(u, v) in &[(0., 0.), (0.25, 0.5), (0.5, 0.5), (1., 0.75)] {
+            let point = patch.point_at(u, v);
+            assert!((point.x - 3. * v).abs() < 1e-5 && (point.y - 3. * u).abs() < 1e-5,
+                    "{:?} at ({}, {})", point, u, v);
+        }
+    }
+}
@@ -0,0 +1,355 @@
+/*
+ * Cairus - a reimplementation of the cairo graphics library in Rust
+ *
+ * Copyright © 2017 CairusOrg
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it either under the terms of the GNU Lesser General Public
+ * License version 2.1 as published by the Free Software Foundation
+ * (the "LGPL") or, at your option, under the terms of the Mozilla
+ * Public License Version 2.0 (the "MPL"). If you do not alter this
+ * notice, a recipient may use your version of this file under either
+ * the MPL or the LGPL.
+ *
+ * You should have received a copy of the LGPL along with this library
+ * in the file LICENSE-LGPL-2_1; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Suite 500, Boston, MA 02110-1335, USA
+ * You should have received a copy of the MPL along with this library
+ * in the file LICENSE-MPL-2_0
+ *
+ * The contents of this file are subject to the Mozilla Public License
+ * Version 2.0 (the "License"); you may not use this file except in
+ * compliance with the License. You may obtain a copy of the License at
+ * http://www.mozilla.org/MPL/
+ *
+ * This software is distributed on an "AS IS" basis, WITHOUT WARRANTY
+ * OF ANY KIND, either express or implied. See the LGPL or the MPL for
+ * the specific language governing rights and limitations.
+ *
+ * The Original Code is the cairus graphics library.
+ *
+ */
+
+//! Reads and writes ImageSurfaces as Netpbm images, which are simple enough to need no
+//! dependencies.
+//!
+//! Surfaces are written as PAM (P7) images with straight alpha, PPM (P6) images of their
+//! pre-multiplied colors, as if shown over black, or for Format::A8 surfaces as PGM (P5) images
+//! of their alpha.  Every channel is written as one byte.
+//!
+//! PAM, PPM and PGM images with up to 16 bits a channel can be read back.  Gray images become
+//! Format::A8 surfaces, so that a PGM written from a mask reads back as one, color images become
+//! Format::RGB24 surfaces, and images with alpha become Format::ARGB32 surfaces.
+
+use std::io::{Read, Write};
+use status::Status;
+use surfaces::{ImageSurface, Format};
+use types::{Rgba, ChannelOrder, AlphaMode};
+
+/// Writes `surface` to `writer` as a PAM image with an RGB_ALPHA tuple type.
+pub fn write_pam<W: Write>(surface: &ImageSurface, mut writer: W) -> Result<(), Status> {
+    let header = format!("P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\n\
+                          ENDHDR\n", surface.width, surface.height);
+    let mut bytes = header.into_bytes();
+    for pixel in surface.iter() {
+        bytes.extend_from_slice(&pixel.to_bytes(ChannelOrder::Rgba, AlphaMode::Straight));
+    }
+    writer.write_all(&bytes).map_err(|_| Status::WriteError)
+}
+
+/// Writes the pre-multiplied colors of `surface` to `writer` as a PPM image.
+pub fn write_ppm<W: Write>(surface: &ImageSurface, mut writer: W) -> Result<(), Status> {
+    let header = format!("P6\n{} {}\n255\n", surface.width, surface.height);
+    let mut bytes = header.into_bytes();
+    for pixel in surface.iter() {
+        let color = pixel.to_bytes(ChannelOrder::Rgba, AlphaMode::Premultiplied);
+        bytes.extend_from_slice(&color[..3]);
+    }
+    writer.write_all(&bytes).map_err(|_| Status::WriteError)
+}
+
+/// Writes the alpha of `surface` to `writer` as a PGM image.  Returns Status::InvalidFormat
+/// unless `surface` is Format::A8.
+pub fn write_pgm<W: Write>(surface: &ImageSurface, mut writer: W) -> Result<(), Status> {
+    if surface.get_format() != Format::A8 {
+        return Err(Status::InvalidFormat);
+    }
+    let header = format!("P5\n{} {}\n255\n", surface.width, surface.height);
+    let mut bytes = header.into_bytes();
+    for pixel in surface.iter() {
+        bytes.push(pixel.to_bytes(ChannelOrder::Rgba, AlphaMode::Premultiplied)[3]);
+    }
+    writer.write_all(&bytes).map_err(|_| Status::WriteError)
+}
+
+/// Reads a PAM, PPM or PGM image from `reader` into a new surface.
+///
+/// Returns Status::ReadError if `reader` fails or the image is cut short or malformed, and
+/// Status::InvalidFormat for other Netpbm images, such as the plain text ones.
+pub fn read<R: Read>(mut reader: R) -> Result<ImageSurface<'static>, Status> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).map_err(|_| Status::ReadError)?;
+    let mut header = Header { bytes: &bytes, position: 0 };
+
+    let image = match header.token()? {
+        "P5" => header.image(Tuple::Gray)?,
+        "P6" => header.image(Tuple::Rgb)?,
+        "P7" => header.pam()?,
+        "P1" | "P2" | "P3" | "P4" => return Err(Status::InvalidFormat),
+        _ => return Err(Status::ReadError),
+    };
+    if image.width == 0 || image.height == 0 || image.maxval == 0 || image.maxval > 65535 {
+        return Err(Status::ReadError);
+    }
+
+    let sample_size = if image.maxval > 255 { 2 } else { 1 };
+    let pixel_size = image.tuple.depth() * sample_size;
+    let length = image.width.checked_mul(image.height)
+        .and_then(|pixels| pixels.checked_mul(pixel_size))
+        .ok_or(Status::ReadError)?;
+    let raster = &bytes[header.position..];
+    if raster.len() < length {
+        return Err(Status::ReadError);
+    }
+
+    let format = match image.tuple {
+        Tuple::Gray => Format::A8,
+        Tuple::Rgb => Format::RGB24,
+        Tuple::GrayAlpha | Tuple::RgbAlpha => Format::ARGB32,
+    };
+    let mut surface = ImageSurface::create(format, image.width, image.height);
+    let maxval = image.maxval as f32;
+    let mut samples = raster[..length].chunks(sample_size).map(|sample| {
+        let value = sample.iter().fold(0, |value, &byte| value << 8 | byte as u32);
+        (value as usize).min(image.maxval) as f32 / maxval
+    });
+    surface.update_pixels(|_, pixel| {
+        let mut sample = || samples.next().unwrap();
+        *pixel = match image.tuple {
+            Tuple::Gray => Rgba::new(0., 0., 0., sample()),
+            Tuple::Rgb => Rgba::new(sample(), sample(), sample(), 1.),
+            Tuple::GrayAlpha => {
+                let gray = sample();
+                Rgba::new(gray, gray, gray, sample())
+            },
+            Tuple::RgbAlpha => Rgba::new(sample(), sample(), sample(), sample()),
+        };
+    });
+    Ok(surface)
+}
+
+// The channels of each pixel of an image.
+#[derive(Copy, Clone)]
+enum Tuple {
+    Gray,
+    GrayAlpha,
+    Rgb,
+    RgbAlpha,
+}
+
+impl Tuple {
+    fn depth(&self) -> usize {
+        match *self {
+            Tuple::Gray => 1,
+            Tuple::GrayAlpha => 2,
+            Tuple::Rgb => 3,
+            Tuple::RgbAlpha => 4,
+        }
+    }
+}
+
+// What the header of an image says about its raster.
+struct Image {
+    width: usize,
+    height: usize,
+    maxval: usize,
+    tuple: Tuple,
+}
+
+// Reads the header of an image, which is made of whitespace separated tokens and comments that
+// run from a # to the end of the line.  Leaves `position` at the start of the raster.
+struct Header<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Header<'a> {
+    // Returns the next token.
+    fn token(&mut self) -> Result<&'a str, Status> {
+        let bytes = self.bytes;
+        loop {
+            match bytes.get(self.position) {
+                Some(&b'#') => {
+                    while bytes.get(self.position).is_some_and(|&byte| byte != b'\n') {
+                        self.position += 1;
+                    }
+                },
+                Some(byte) if byte.is_ascii_whitespace() => self.position += 1,
+                Some(_) => break,
+                None => return Err(Status::ReadError),
+            }
+        }
+        let start = self.position;
+        while bytes.get(self.position).is_some_and(|byte| !byte.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+        ::std::str::from_utf8(&bytes[start..self.position]).map_err(|_| Status::ReadError)
+    }
+
+    // Returns the next token as a number.
+    fn number(&mut self) -> Result<usize, Status> {
+        self.token()?.parse().map_err(|_| Status::ReadError)
+    }
+
+    // Skips the single whitespace character that ends a header.
+    fn end(&mut self) -> Result<(), Status> {
+        match self.bytes.get(self.position) {
+            Some(byte) if byte.is_ascii_whitespace() => {
+                self.position += 1;
+                Ok(())
+            },
+            _ => Err(Status::ReadError),
+        }
+    }
+
+    // Reads the rest of a PPM or PGM header.
+    fn image(&mut self, tuple: Tuple) -> Result<Image, Status> {
+        let width = self.number()?;
+        let height = self.number()?;
+        let maxval = self.number()?;
+        self.end()?;
+        Ok(Image {
+            width: width,
+            height: height,
+            maxval: maxval,
+            tuple: tuple,
+        })
+    }
+
+    // Reads the rest of a PAM header, which is made of lines of a keyword and its value.
+    fn pam(&mut self) -> Result<Image, Status> {
+        let (mut width, mut height, mut depth, mut maxval) = (None, None, None, None);
+        let mut tuple_type = None;
+        loop {
+            match self.token()? {
+                "WIDTH" => width = Some(self.number()?),
+                "HEIGHT" => height = Some(self.number()?),
+                "DEPTH" => depth = Some(self.number()?),
+                "MAXVAL" => maxval = Some(self.number()?),
+                "TUPLTYPE" => tuple_type = Some(self.token()?),
+                "ENDHDR" => break,
+                _ => return Err(Status::ReadError),
+            }
+        }
+        self.end()?;
+        let tuple = match (tuple_type, depth) {
+            (Some("GRAYSCALE"), Some(1)) => Tuple::Gray,
+            (Some("GRAYSCALE_ALPHA"), Some(2)) => Tuple::GrayAlpha,
+            (Some("RGB"), Some(3)) => Tuple::Rgb,
+            (Some("RGB_ALPHA"), Some(4)) => Tuple::RgbAlpha,
+            _ => return Err(Status::InvalidFormat),
+        };
+        match (width, height, maxval) {
+            (Some(width), Some(height), Some(maxval)) => {
+                Ok(Image {
+                    width: width,
+                    height: height,
+                    maxval: maxval,
+                    tuple: tuple,
+                })
+            },
+            _ => Err(Status::ReadError),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use netpbm::{read, write_pam, write_ppm, write_pgm};
+    use status::Status;
+    use surfaces::{ImageSurface, Format};
+    use types::Rgba;
+
+    // A 2x1 surface of half transparent red and transparent black.
+    fn translucent() -> ImageSurface<'static> {
+        let mut surface = ImageSurface::create(Format::ARGB32, 2, 1);
+        *surface.get_mut(0, 0).unwrap() = Rgba::new(1., 0., 0., 0.6);
+        surface
+    }
+
+    #[test]
+    fn pam_has_straight_alpha() {
+        let mut bytes = Vec::new();
+        write_pam(&translucent(), &mut bytes).unwrap();
+        let header = b"P7\nWIDTH 2\nHEIGHT 1\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n";
+        assert_eq!(&bytes[..header.len()], &header[..]);
+        assert_eq!(&bytes[header.len()..], &[255, 0, 0, 153, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn ppm_has_premultiplied_colors() {
+        let mut bytes = Vec::new();
+        write_ppm(&translucent(), &mut bytes).unwrap();
+        assert_eq!(bytes, b"P6\n2 1\n255\n\x99\0\0\0\0\0".to_vec());
+    }
+
+    #[test]
+    fn pgm_has_alpha() {
+        let mut mask = ImageSurface::create(Format::A8, 1, 2);
+        *mask.get_mut(0, 1).unwrap() = Rgba::new(0., 0., 0., 0.2);
+        let mut bytes = Vec::new();
+        write_pgm(&mask, &mut bytes).unwrap();
+        assert_eq!(bytes, b"P5\n1 2\n255\n\0\x33".to_vec());
+
+        assert_eq!(write_pgm(&translucent(), Vec::new()), Err(Status::InvalidFormat));
+    }
+
+    #[test]
+    fn round_trips() {
+        let surface = translucent();
+        let mut bytes = Vec::new();
+        write_pam(&surface, &mut bytes).unwrap();
+        let result = read(&bytes[..]).unwrap();
+        assert_eq!(result.get_format(), Format::ARGB32);
+        assert_eq!(result.iter().collect::<Vec<_>>(), surface.iter().collect::<Vec<_>>());
+
+        let mut bytes = Vec::new();
+        write_ppm(&surface, &mut bytes).unwrap();
+        let result = read(&bytes[..]).unwrap();
+        assert_eq!(result.get_format(), Format::RGB24);
+        assert_eq!(result.get(0, 0).unwrap(), Rgba::new(0.6, 0., 0., 1.));
+        assert_eq!(result.get(1, 0).unwrap(), Rgba::new(0., 0., 0., 1.));
+
+        let mut mask = ImageSurface::create(Format::A8, 3, 1);
+        *mask.get_mut(1, 0).unwrap() = Rgba::new(0., 0., 0., 0.2);
+        let mut bytes = Vec::new();
+        write_pgm(&mask, &mut bytes).unwrap();
+        let result = read(&bytes[..]).unwrap();
+        assert_eq!(result.get_format(), Format::A8);
+        assert_eq!(result.iter().collect::<Vec<_>>(), mask.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn read_comments_and_16_bit() {
+        let bytes = b"P6 # a comment\n1 # another\n1\n65535\n\xff\xff\x80\x00\x00\x00";
+        let surface = read(&bytes[..]).unwrap();
+        assert_eq!(surface.get(0, 0).unwrap(), Rgba::new(1., 128. / 255., 0., 1.));
+
+        let bytes = b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 2\nMAXVAL 15\nTUPLTYPE GRAYSCALE_ALPHA\n\
+                      ENDHDR\n\x0f\x05";
+        let surface = read(&bytes[..]).unwrap();
+        assert_eq!(surface.get_format(), Format::ARGB32);
+        assert_eq!(surface.get(0, 0).unwrap(), Rgba::new(1., 1., 1., 1. / 3.));
+    }
+
+    #[test]
+    fn read_errors() {
+        assert_eq!(read(&b"P3\n1 1\n255\n0 0 0\n"[..]).err(), Some(Status::InvalidFormat));
+        assert_eq!(read(&b"P6\n2 1\n255\n\0\0\0"[..]).err(), Some(Status::ReadError));
+        assert_eq!(read(&b"P5\n0 1\n255\n"[..]).err(), Some(Status::ReadError));
+        assert_eq!(read(&b"P5\n1 1\n"[..]).err(), Some(Status::ReadError));
+        assert_eq!(read(&b"GIF89a"[..]).err(), Some(Status::ReadError));
+        let bytes = b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 1\nMAXVAL 255\nTUPLTYPE CMYK\nENDHDR\n\0";
+        assert_eq!(read(&bytes[..]).err(), Some(Status::InvalidFormat));
+    }
+}
@@ -45,12 +45,36 @@
 //! this page).
 //!
 //! # Supported Operators:
-//! * Over - Cairus's default operator.  Blends a source onto a destination, similar to overlapping
-//!          two semi-transparent slides.  If the source is opaque, the over operation will make
-//!          the destination opaque as well.
+//! * Clear - Clears the destination, leaving it transparent.
 //! * Source - Overwrites the destination with the source. Result color & alpha is equal to source.
+//! * Over - Cairus's default operator.  Blends a source onto a destination, similar to overlapping
+//!   two semi-transparent slides.  If the source is opaque, the over operation will make
+//!   the destination opaque as well.
 //! * In - The destination object is removed and the source object is only drawn where the
-//! destination was.
+//!   destination was.
+//! * Out - The destination object is removed and the source object is only drawn where the
+//!   destination wasn't.
+//! * Atop - The source object is only drawn where the destination was, on top of it.
+//! * Dest - The destination is left as it is.
+//! * DestOver, DestIn, DestOut, DestAtop - Like Over, In, Out and Atop, with the roles of the
+//!   source and destination swapped.
+//! * Xor - Each object is only shown where the other one isn't.
+//! * Add - The source and destination are added together.
+//! * Saturate - Like Over, but the source is faded by however much room the destination leaves.
+//! * Multiply, Screen, Overlay, Darken, Lighten, ColorDodge, ColorBurn, HardLight, SoftLight,
+//!   Difference, Exclusion, HSLHue, HSLSaturation, HSLColor and HSLLuminosity - The blend modes.
+//!   These draw like Over, but blend the colors where the source and destination overlap.
+//!
+//! All operators work on pre-multiplied Rgba values.
+//!
+//! # Bounded and Unbounded Operators
+//! Most operators leave the destination alone where a source made transparent by the shape's
+//! coverage is drawn, so drawing a shape with them only changes pixels inside the shape.  These
+//! are bounded.  In, Out, DestIn
+//! and DestAtop change the destination even where the source is transparent (they clear it, or
+//! keep only part of it), so a context has to apply them outside of the shape too.  These are
+//! unbounded; see `Operator::is_bounded`.
+//!
 //! Descriptions/formulas for Cairo operators:
 //! [Cairo Operators](https://www.cairographics.org/operators/)
 
@@ -65,7 +89,7 @@ use types::Rgba;
 // to any context via `fetch_operator`.
 
 /// The supported image compositing operators in Cairus.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Operator {
     /// Cairus's default operator.  Draws source layer on top of destination layer.
     Over,
@@ -73,40 +97,63 @@ pub enum Operator {
     In,
     ///Source will be the next operator to implement. It replaces the destination later.
     Source,
-
-
-
-    //Remaining operators enumerated for later implementation
-    //options pulled from Cairo Graphics Library
-    //reference: https://www.cairographics.org/manual/cairo-cairo-t.html#CAIRO-OPERATOR-OVER:CAPS
-/*
+    /// Clears the destination layer.
     Clear,
+    /// Draw source layer where there was no destination layer.
     Out,
+    /// Draw source layer on top of destination layer, only where there was destination layer.
     Atop,
+    /// Leaves the destination layer alone.
     Dest,
+    /// Draw destination layer on top of source layer.
     DestOver,
+    /// Keep destination layer only where there was source layer.
     DestIn,
+    /// Keep destination layer only where there was no source layer.
     DestOut,
+    /// Keep destination layer on top of source layer, only where there was source layer.
     DestAtop,
+    /// Show each layer only where the other layer isn't.
     Xor,
+    /// Add the source and destination layers together.
     Add,
+    /// Like Over, but the source is faded by however much room the destination leaves.
     Saturate,
+
+    // The blend modes below draw the source over the destination like Over, except where both
+    // layers overlap, where the color is a blend of the two.
+    // reference: https://www.cairographics.org/manual/cairo-cairo-t.html#CAIRO-OPERATOR-OVER:CAPS
+
+    /// The colors are multiplied, which can only make them darker.
     Multiply,
+    /// The complements of the colors are multiplied, which can only make them lighter.
     Screen,
+    /// Multiplies or screens, depending on the destination color.
     Overlay,
+    /// The darker of each color channel is kept.
     Darken,
+    /// The lighter of each color channel is kept.
     Lighten,
+    /// Brightens the destination to reflect the source.
     ColorDodge,
+    /// Darkens the destination to reflect the source.
     ColorBurn,
+    /// Multiplies or screens, depending on the source color.
     HardLight,
+    /// Darkens or lightens, depending on the source color.
     SoftLight,
+    /// The darker color is subtracted from the lighter one.
     Difference,
+    /// Like Difference, but with lower contrast.
     Exclusion,
+    /// The hue of the source with the saturation and luminosity of the destination.
     HSLHue,
+    /// The saturation of the source with the hue and luminosity of the destination.
     HSLSaturation,
+    /// The hue and saturation of the source with the luminosity of the destination.
     HSLColor,
+    /// The luminosity of the source with the hue and saturation of the destination.
     HSLLuminosity,
-*/
 }
 
 /// Returns an image compositing function that corresponds to an Operator enum.
@@ -131,6 +178,44 @@ pub fn fetch_operator(op: &Operator) -> fn(&Rgba, &mut Rgba) {
         Operator::Over      => operator_over,
         Operator::In        => operator_in,
         Operator::Source    => operator_source,
+        Operator::Clear     => operator_clear,
+        Operator::Out       => operator_out,
+        Operator::Atop      => operator_atop,
+        Operator::Dest      => operator_dest,
+        Operator::DestOver  => operator_dest_over,
+        Operator::DestIn    => operator_dest_in,
+        Operator::DestOut   => operator_dest_out,
+        Operator::DestAtop  => operator_dest_atop,
+        Operator::Xor       => operator_xor,
+        Operator::Add       => operator_add,
+        Operator::Saturate  => operator_saturate,
+        Operator::Multiply      => operator_multiply,
+        Operator::Screen        => operator_screen,
+        Operator::Overlay       => operator_overlay,
+        Operator::Darken        => operator_darken,
+        Operator::Lighten       => operator_lighten,
+        Operator::ColorDodge    => operator_color_dodge,
+        Operator::ColorBurn     => operator_color_burn,
+        Operator::HardLight     => operator_hard_light,
+        Operator::SoftLight     => operator_soft_light,
+        Operator::Difference    => operator_difference,
+        Operator::Exclusion     => operator_exclusion,
+        Operator::HSLHue        => operator_hsl_hue,
+        Operator::HSLSaturation => operator_hsl_saturation,
+        Operator::HSLColor      => operator_hsl_color,
+        Operator::HSLLuminosity => operator_hsl_luminosity,
+    }
+}
+
+impl Operator {
+    /// Returns true if this operator leaves the destination unchanged outside of the shape being
+    /// drawn, analogous to cairo's _cairo_operator_bounded_by_mask.
+    ///
+    /// A context only needs to apply bounded operators inside the shape it draws.  Unbounded
+    /// operators also affect the destination outside of the shape, as if a transparent source
+    /// were drawn there.
+    pub fn is_bounded(&self) -> bool {
+        !matches!(*self, Operator::In | Operator::Out | Operator::DestIn | Operator::DestAtop)
     }
 }
 
@@ -169,13 +254,312 @@ pub fn operator_source(source: &Rgba, destination: &mut Rgba) {
 ///Note: The transparency of the first object is still taken in to account.
 ///The effect of the IN operator depends on the interpretation of the source.
 ///This operator is unbounded.
-///This function currently assumes post-multiplied alpha values, the alpha value
-///must be factored out
 pub fn operator_in(source: &Rgba, destination: &mut Rgba) {
-    destination.alpha = source.alpha * destination.alpha;
-    destination.red = source.red;
-    destination.green = source.green;
-    destination.blue = source.blue;
+    let factor = destination.alpha;
+    combine(source, factor, destination, 0.);
+}
+
+/// Clear operator.  The destination becomes transparent, whatever the source is.
+pub fn operator_clear(_source: &Rgba, destination: &mut Rgba) {
+    *destination = Rgba::new(0., 0., 0., 0.);
+}
+
+/// Out operator.  The destination object is removed and the source object is only drawn where
+/// the destination wasn't.  This operator is unbounded.
+pub fn operator_out(source: &Rgba, destination: &mut Rgba) {
+    let factor = 1. - destination.alpha;
+    combine(source, factor, destination, 0.);
+}
+
+/// Atop operator.  The source object is drawn on top of the destination, but only where the
+/// destination was.  The result's alpha is the destination's.
+pub fn operator_atop(source: &Rgba, destination: &mut Rgba) {
+    let (source_factor, destination_factor) = (destination.alpha, 1. - source.alpha);
+    combine(source, source_factor, destination, destination_factor);
+}
+
+/// Dest operator.  The destination is left as it is.
+pub fn operator_dest(_source: &Rgba, _destination: &mut Rgba) {
+}
+
+/// Dest over operator.  The destination object is drawn over the source object.
+pub fn operator_dest_over(source: &Rgba, destination: &mut Rgba) {
+    let factor = 1. - destination.alpha;
+    combine(source, factor, destination, 1.);
+}
+
+/// Dest in operator.  The destination object is only kept where the source was.  This operator
+/// is unbounded.
+pub fn operator_dest_in(source: &Rgba, destination: &mut Rgba) {
+    let factor = source.alpha;
+    combine(source, 0., destination, factor);
+}
+
+/// Dest out operator.  The destination object is only kept where the source wasn't.
+pub fn operator_dest_out(source: &Rgba, destination: &mut Rgba) {
+    let factor = 1. - source.alpha;
+    combine(source, 0., destination, factor);
+}
+
+/// Dest atop operator.  The destination object is drawn on top of the source, but only where the
+/// source was.  The result's alpha is the source's.  This operator is unbounded.
+pub fn operator_dest_atop(source: &Rgba, destination: &mut Rgba) {
+    let (source_factor, destination_factor) = (1. - destination.alpha, source.alpha);
+    combine(source, source_factor, destination, destination_factor);
+}
+
+/// Xor operator.  Each object is only drawn where the other one wasn't.
+pub fn operator_xor(source: &Rgba, destination: &mut Rgba) {
+    let (source_factor, destination_factor) = (1. - destination.alpha, 1. - source.alpha);
+    combine(source, source_factor, destination, destination_factor);
+}
+
+/// Add operator.  The source and destination are added together, clamped at 1.
+pub fn operator_add(source: &Rgba, destination: &mut Rgba) {
+    combine(source, 1., destination, 1.);
+    destination.correct();
+}
+
+/// Saturate operator.  Like over, except that the source is faded to fit into however much
+/// transparency the destination has left, so an opaque destination is never changed.
+pub fn operator_saturate(source: &Rgba, destination: &mut Rgba) {
+    let factor = if source.alpha > 0. {
+        ((1. - destination.alpha) / source.alpha).min(1.)
+    } else {
+        1.
+    };
+    combine(source, factor, destination, 1.);
+    destination.correct();
+}
+
+// Sets `destination` to `source` * `source_factor` + `destination` * `destination_factor`,
+// channel by channel.  Every Porter Duff operator has this form.
+fn combine(source: &Rgba, source_factor: f32, destination: &mut Rgba, destination_factor: f32) {
+    destination.red = source.red * source_factor + destination.red * destination_factor;
+    destination.green = source.green * source_factor + destination.green * destination_factor;
+    destination.blue = source.blue * source_factor + destination.blue * destination_factor;
+    destination.alpha = source.alpha * source_factor + destination.alpha * destination_factor;
+}
+
+/// # Blend Mode Formulas
+/// The blend modes follow the PDF specification (section 11.3.5), as cairo does.  Where both
+/// layers are present the result is a blend B of their colors, elsewhere it is whichever layer is
+/// there:
+///
+/// alpha = alpha_s + alpha_d - alpha_s * alpha_d
+/// color = (1 - alpha_d) * color_s + (1 - alpha_s) * color_d + alpha_s * alpha_d * B(s, d)
+///
+/// where color_s and color_d are pre-multiplied, and B works on colors with the alpha factored
+/// out (s and d).
+
+/// Multiply blend mode, B(s, d) = s * d.
+pub fn operator_multiply(source: &Rgba, destination: &mut Rgba) {
+    blend_separable(source, destination, |s, d| s * d);
+}
+
+/// Screen blend mode, B(s, d) = s + d - s * d.
+pub fn operator_screen(source: &Rgba, destination: &mut Rgba) {
+    blend_separable(source, destination, screen);
+}
+
+/// Overlay blend mode, hard light with the layers swapped.
+pub fn operator_overlay(source: &Rgba, destination: &mut Rgba) {
+    blend_separable(source, destination, |s, d| hard_light(d, s));
+}
+
+/// Darken blend mode, B(s, d) = min(s, d).
+pub fn operator_darken(source: &Rgba, destination: &mut Rgba) {
+    blend_separable(source, destination, |s, d| s.min(d));
+}
+
+/// Lighten blend mode, B(s, d) = max(s, d).
+pub fn operator_lighten(source: &Rgba, destination: &mut Rgba) {
+    blend_separable(source, destination, |s, d| s.max(d));
+}
+
+/// Color dodge blend mode, B(s, d) = min(1, d / (1 - s)).
+pub fn operator_color_dodge(source: &Rgba, destination: &mut Rgba) {
+    blend_separable(source, destination, |s, d| {
+        if d <= 0. {
+            0.
+        } else if s >= 1. {
+            1.
+        } else {
+            (d / (1. - s)).min(1.)
+        }
+    });
+}
+
+/// Color burn blend mode, B(s, d) = 1 - min(1, (1 - d) / s).
+pub fn operator_color_burn(source: &Rgba, destination: &mut Rgba) {
+    blend_separable(source, destination, |s, d| {
+        if d >= 1. {
+            1.
+        } else if s <= 0. {
+            0.
+        } else {
+            1. - ((1. - d) / s).min(1.)
+        }
+    });
+}
+
+/// Hard light blend mode, multiplies for dark sources and screens for light ones.
+pub fn operator_hard_light(source: &Rgba, destination: &mut Rgba) {
+    blend_separable(source, destination, hard_light);
+}
+
+/// Soft light blend mode, darkens for dark sources and lightens for light ones.
+pub fn operator_soft_light(source: &Rgba, destination: &mut Rgba) {
+    blend_separable(source, destination, |s, d| {
+        if s <= 0.5 {
+            d - (1. - 2. * s) * d * (1. - d)
+        } else {
+            let lightened = if d <= 0.25 {
+                ((16. * d - 12.) * d + 4.) * d
+            } else {
+                d.sqrt()
+            };
+            d + (2. * s - 1.) * (lightened - d)
+        }
+    });
+}
+
+/// Difference blend mode, B(s, d) = |s - d|.
+pub fn operator_difference(source: &Rgba, destination: &mut Rgba) {
+    blend_separable(source, destination, |s, d| (s - d).abs());
+}
+
+/// Exclusion blend mode, B(s, d) = s + d - 2 * s * d.
+pub fn operator_exclusion(source: &Rgba, destination: &mut Rgba) {
+    blend_separable(source, destination, |s, d| s + d - 2. * s * d);
+}
+
+/// Hue blend mode, the hue of the source with the saturation and luminosity of the destination.
+pub fn operator_hsl_hue(source: &Rgba, destination: &mut Rgba) {
+    blend_non_separable(source, destination, |s, d| {
+        set_luminosity(set_saturation(s, saturation(d)), luminosity(d))
+    });
+}
+
+/// Saturation blend mode, the saturation of the source with the hue and luminosity of the
+/// destination.
+pub fn operator_hsl_saturation(source: &Rgba, destination: &mut Rgba) {
+    blend_non_separable(source, destination, |s, d| {
+        set_luminosity(set_saturation(d, saturation(s)), luminosity(d))
+    });
+}
+
+/// Color blend mode, the hue and saturation of the source with the luminosity of the
+/// destination.
+pub fn operator_hsl_color(source: &Rgba, destination: &mut Rgba) {
+    blend_non_separable(source, destination, |s, d| set_luminosity(s, luminosity(d)));
+}
+
+/// Luminosity blend mode, the luminosity of the source with the hue and saturation of the
+/// destination.
+pub fn operator_hsl_luminosity(source: &Rgba, destination: &mut Rgba) {
+    blend_non_separable(source, destination, |s, d| set_luminosity(d, luminosity(s)));
+}
+
+fn screen(s: f32, d: f32) -> f32 {
+    s + d - s * d
+}
+
+fn hard_light(s: f32, d: f32) -> f32 {
+    if s <= 0.5 {
+        d * 2. * s
+    } else {
+        screen(d, 2. * s - 1.)
+    }
+}
+
+// Blends `source` onto `destination` with a blend function applied to each color channel on its
+// own.
+fn blend_separable<F: Fn(f32, f32) -> f32>(source: &Rgba, destination: &mut Rgba, blend: F) {
+    let s = unpremultiply(source);
+    let d = unpremultiply(destination);
+    let blended = [blend(s[0], d[0]), blend(s[1], d[1]), blend(s[2], d[2])];
+    composite_blend(source, destination, blended);
+}
+
+// Blends `source` onto `destination` with a blend function of all three color channels at once.
+fn blend_non_separable<F: Fn([f32; 3], [f32; 3]) -> [f32; 3]>(source: &Rgba, destination: &mut Rgba,
+                                                            blend: F) {
+    let blended = blend(unpremultiply(source), unpremultiply(destination));
+    composite_blend(source, destination, blended);
+}
+
+// Sets `destination` to the blend mode result, given the blended color where both layers
+// overlap.  See "Blend Mode Formulas" above.
+fn composite_blend(source: &Rgba, destination: &mut Rgba, blended: [f32; 3]) {
+    let both = source.alpha * destination.alpha;
+    let only_source = 1. - destination.alpha;
+    let only_destination = 1. - source.alpha;
+    destination.red = only_source * source.red + only_destination * destination.red +
+                      both * blended[0];
+    destination.green = only_source * source.green + only_destination * destination.green +
+                        both * blended[1];
+    destination.blue = only_source * source.blue + only_destination * destination.blue +
+                       both * blended[2];
+    destination.alpha = source.alpha + destination.alpha - both;
+}
+
+// The color channels of `rgba` with its alpha factored out.  Transparent colors are black.
+fn unpremultiply(rgba: &Rgba) -> [f32; 3] {
+    if rgba.alpha <= 0. {
+        return [0., 0., 0.];
+    }
+    [rgba.red / rgba.alpha, rgba.green / rgba.alpha, rgba.blue / rgba.alpha]
+}
+
+// The non-separable blend mode helpers from the PDF specification.
+
+fn luminosity(color: [f32; 3]) -> f32 {
+    0.3 * color[0] + 0.59 * color[1] + 0.11 * color[2]
+}
+
+fn saturation(color: [f32; 3]) -> f32 {
+    color[0].max(color[1]).max(color[2]) - color[0].min(color[1]).min(color[2])
+}
+
+// Brings every channel of `color` back between 0 and 1, keeping its luminosity.
+fn clip_color(color: [f32; 3]) -> [f32; 3] {
+    let lum = luminosity(color);
+    let min = color[0].min(color[1]).min(color[2]);
+    let max = color[0].max(color[1]).max(color[2]);
+    let mut clipped = color;
+    if min < 0. {
+        for channel in clipped.iter_mut() {
+            *channel = lum + (*channel - lum) * lum / (lum - min);
+        }
+    }
+    if max > 1. {
+        for channel in clipped.iter_mut() {
+            *channel = lum + (*channel - lum) * (1. - lum) / (max - lum);
+        }
+    }
+    clipped
+}
+
+fn set_luminosity(color: [f32; 3], lum: f32) -> [f32; 3] {
+    let delta = lum - luminosity(color);
+    clip_color([color[0] + delta, color[1] + delta, color[2] + delta])
+}
+
+fn set_saturation(color: [f32; 3], sat: f32) -> [f32; 3] {
+    let min = color[0].min(color[1]).min(color[2]);
+    let max = color[0].max(color[1]).max(color[2]);
+    if max <= min {
+        return [0., 0., 0.];
+    }
+
+    // The smallest channel becomes 0, the largest becomes `sat`, and the middle one keeps its
+    // place between them
+    let mut result = [0.; 3];
+    for (channel, value) in result.iter_mut().zip(color.iter()) {
+        *channel = (value - min) * sat / (max - min);
+    }
+    result
 }
 
 /// # References
@@ -190,6 +574,34 @@ mod tests {
     use super::operator_in;
     use super::operator_source;
     use super::fetch_operator;
+    use super::{
+        operator_clear,
+        operator_out,
+        operator_atop,
+        operator_dest,
+        operator_dest_over,
+        operator_dest_in,
+        operator_dest_out,
+        operator_dest_atop,
+        operator_xor,
+        operator_add,
+        operator_saturate,
+        operator_multiply,
+        operator_screen,
+        operator_overlay,
+        operator_darken,
+        operator_lighten,
+        operator_color_dodge,
+        operator_color_burn,
+        operator_hard_light,
+        operator_soft_light,
+        operator_difference,
+        operator_exclusion,
+        operator_hsl_hue,
+        operator_hsl_saturation,
+        operator_hsl_color,
+        operator_hsl_luminosity,
+    };
     use types::Rgba;
 
     #[test]
@@ -253,10 +665,11 @@ mod tests {
         };
         let mut destination = Rgba::new(0., 1., 0., 0.5);
         fetch_operator(&Operator::In)(&source, &mut destination);
+        // Pre-multiplied, every channel of the source is scaled by the destination's alpha
         let test_rgba = Rgba{
-            red:0.5,
-            green:0.5,
-            blue:0.5,
+            red:0.25,
+            green:0.25,
+            blue:0.25,
             alpha:0.25
         };
         assert_eq!(destination, test_rgba);
@@ -268,9 +681,9 @@ mod tests {
         let mut destination = Rgba::new(1., 1., 1., 0.5);
         operator_in(&source, &mut destination);
         let test_rgba =  Rgba{
-            red:0.5,
-            green:0.5,
-            blue:0.5,
+            red:0.25,
+            green:0.25,
+            blue:0.25,
             alpha:0.5
         };
         assert_eq!(destination, test_rgba);
@@ -306,9 +719,9 @@ mod tests {
         let mut destination = Rgba::new(1.0, 1.0, 1.0, 0.0);
         operator_in(&source, &mut destination);
         let test_rgba = Rgba{
-            red:0.5,
-            green:0.5,
-            blue:0.5,
+            red:0.0,
+            green:0.0,
+            blue:0.0,
             alpha:0.0
         };
         assert_eq!(destination, test_rgba);
@@ -332,7 +745,7 @@ mod tests {
     #[test]
     fn test_rgba_into_bytes_all_half() {
         let color = Rgba::new(0.5, 0.5, 0.5, 0.5);
-        let expected = vec![127, 127, 127, 127];
+        let expected = vec![128, 128, 128, 128];
         assert_eq!(color.into_bytes(), expected);
     }
 
@@ -365,4 +778,226 @@ mod tests {
         // default floating point decimal place rounding.
         assert_eq!(destination, expected);
     }
+
+    // The source and destination used by the Porter Duff tests below: a half transparent red
+    // source and a destination that is green where it is 3/4 opaque.
+    fn porter_duff(operator: fn(&Rgba, &mut Rgba)) -> Rgba {
+        let source = Rgba::new(1., 0., 0., 0.5);
+        let mut destination = Rgba::new(0., 1., 0., 0.75);
+        operator(&source, &mut destination);
+        destination
+    }
+
+    #[test]
+    fn test_clear_operator() {
+        assert_eq!(porter_duff(operator_clear), Rgba::new(0., 0., 0., 0.));
+    }
+
+    #[test]
+    fn test_out_operator() {
+        // source * (1 - 0.75)
+        assert_eq!(porter_duff(operator_out), Rgba{red: 0.125, green: 0., blue: 0., alpha: 0.125});
+    }
+
+    #[test]
+    fn test_atop_operator() {
+        // source * 0.75 + destination * 0.5
+        assert_eq!(porter_duff(operator_atop),
+                   Rgba{red: 0.375, green: 0.375, blue: 0., alpha: 0.75});
+    }
+
+    #[test]
+    fn test_dest_operator() {
+        assert_eq!(porter_duff(operator_dest), Rgba::new(0., 1., 0., 0.75));
+    }
+
+    #[test]
+    fn test_dest_over_operator() {
+        // source * (1 - 0.75) + destination
+        assert_eq!(porter_duff(operator_dest_over),
+                   Rgba{red: 0.125, green: 0.75, blue: 0., alpha: 0.875});
+    }
+
+    #[test]
+    fn test_dest_in_operator() {
+        // destination * 0.5
+        assert_eq!(porter_duff(operator_dest_in),
+                   Rgba{red: 0., green: 0.375, blue: 0., alpha: 0.375});
+    }
+
+    #[test]
+    fn test_dest_out_operator() {
+        // destination * (1 - 0.5)
+        assert_eq!(porter_duff(operator_dest_out),
+                   Rgba{red: 0., green: 0.375, blue: 0., alpha: 0.375});
+    }
+
+    #[test]
+    fn test_dest_atop_operator() {
+        // source * (1 - 0.75) + destination * 0.5
+        assert_eq!(porter_duff(operator_dest_atop),
+                   Rgba{red: 0.125, green: 0.375, blue: 0., alpha: 0.5});
+    }
+
+    #[test]
+    fn test_xor_operator() {
+        // source * (1 - 0.75) + destination * (1 - 0.5)
+        assert_eq!(porter_duff(operator_xor),
+                   Rgba{red: 0.125, green: 0.375, blue: 0., alpha: 0.5});
+    }
+
+    #[test]
+    fn test_add_operator() {
+        assert_eq!(porter_duff(operator_add), Rgba{red: 0.5, green: 0.75, blue: 0., alpha: 1.});
+
+        // Channels are clamped at 1
+        let source = Rgba::new(1., 1., 1., 1.);
+        let mut destination = Rgba::new(1., 1., 1., 1.);
+        operator_add(&source, &mut destination);
+        assert_eq!(destination, Rgba::new(1., 1., 1., 1.));
+    }
+
+    #[test]
+    fn test_saturate_operator() {
+        // Only 1/4 of the destination is left, so the source is scaled by 0.25 / 0.5
+        assert_eq!(porter_duff(operator_saturate),
+                   Rgba{red: 0.25, green: 0.75, blue: 0., alpha: 1.});
+
+        // An opaque destination is unchanged
+        let source = Rgba::new(1., 0., 0., 1.);
+        let mut destination = Rgba::new(0., 0., 1., 1.);
+        operator_saturate(&source, &mut destination);
+        assert_eq!(destination, Rgba::new(0., 0., 1., 1.));
+    }
+
+    #[test]
+    fn test_fetch_operator_all() {
+        let operators = vec![
+            (Operator::Clear, operator_clear as fn(&Rgba, &mut Rgba)),
+            (Operator::Out, operator_out),
+            (Operator::Atop, operator_atop),
+            (Operator::Dest, operator_dest),
+            (Operator::DestOver, operator_dest_over),
+            (Operator::DestIn, operator_dest_in),
+            (Operator::DestOut, operator_dest_out),
+            (Operator::DestAtop, operator_dest_atop),
+            (Operator::Xor, operator_xor),
+            (Operator::Add, operator_add),
+            (Operator::Saturate, operator_saturate),
+        ];
+        for (operator, function) in operators {
+            assert_eq!(porter_duff(fetch_operator(&operator)), porter_duff(function));
+        }
+    }
+
+    #[test]
+    fn test_bounded_operators() {
+        assert!(Operator::Over.is_bounded());
+        assert!(Operator::Source.is_bounded());
+        assert!(Operator::Clear.is_bounded());
+        assert!(Operator::DestOut.is_bounded());
+        assert!(!Operator::In.is_bounded());
+        assert!(!Operator::Out.is_bounded());
+        assert!(!Operator::DestIn.is_bounded());
+        assert!(!Operator::DestAtop.is_bounded());
+    }
+
+    // Blends opaque `source` onto opaque `destination`, where the result is just the blend.
+    fn blend_opaque(operator: fn(&Rgba, &mut Rgba), source: (f32, f32, f32),
+                    destination: (f32, f32, f32)) -> Rgba {
+        let source = Rgba::new(source.0, source.1, source.2, 1.);
+        let mut destination = Rgba::new(destination.0, destination.1, destination.2, 1.);
+        operator(&source, &mut destination);
+        destination
+    }
+
+    fn assert_rgba_close(actual: Rgba, expected: Rgba) {
+        let close = (actual.red - expected.red).abs() < 1e-5 &&
+                    (actual.green - expected.green).abs() < 1e-5 &&
+                    (actual.blue - expected.blue).abs() < 1e-5 &&
+                    (actual.alpha - expected.alpha).abs() < 1e-5;
+        assert!(close, "{:?} is not close to {:?}", actual, expected);
+    }
+
+    #[test]
+    fn test_separable_blend_modes() {
+        let source = (0.25, 0.5, 1.);
+        let destination = (0.5, 0.5, 0.25);
+        let cases = vec![
+            (operator_multiply as fn(&Rgba, &mut Rgba), (0.125, 0.25, 0.25)),
+            (operator_screen, (0.625, 0.75, 1.)),
+            (operator_overlay, (0.25, 0.5, 0.5)),
+            (operator_darken, (0.25, 0.5, 0.25)),
+            (operator_lighten, (0.5, 0.5, 1.)),
+            (operator_color_dodge, (0.6666667, 1., 1.)),
+            (operator_color_burn, (0., 0., 0.25)),
+            (operator_hard_light, (0.25, 0.5, 1.)),
+            (operator_soft_light, (0.375, 0.5, 0.5)),
+            (operator_difference, (0.25, 0., 0.75)),
+            (operator_exclusion, (0.5, 0.5, 0.75)),
+        ];
+        for (operator, expected) in cases {
+            assert_rgba_close(blend_opaque(operator, source, destination),
+                              Rgba::new(expected.0, expected.1, expected.2, 1.));
+        }
+    }
+
+    #[test]
+    fn test_blend_mode_semi_transparent() {
+        // A quarter of the pixel is only source, a quarter blended and a quarter only
+        // destination, so red is 0.25 * 1 + 0.25 * (1 * 0.5) + 0.25 * 0.5
+        let source = Rgba::new(1., 0., 0., 0.5);
+        let mut destination = Rgba::new(0.5, 1., 0., 0.5);
+        operator_multiply(&source, &mut destination);
+        assert_rgba_close(destination, Rgba{red: 0.5, green: 0.25, blue: 0., alpha: 0.75});
+
+        // A transparent source leaves the destination as it is
+        let source = Rgba::new(0., 0., 0., 0.);
+        let mut destination = Rgba::new(0.5, 1., 0., 0.5);
+        operator_screen(&source, &mut destination);
+        assert_rgba_close(destination, Rgba::new(0.5, 1., 0., 0.5));
+    }
+
+    #[test]
+    fn test_hsl_blend_modes() {
+        let red = (1., 0., 0.);
+        let gray = (0.5, 0.5, 0.5);
+
+        // Gray has no hue or saturation to give
+        assert_rgba_close(blend_opaque(operator_hsl_hue, gray, red), Rgba::new(0.3, 0.3, 0.3, 1.));
+        assert_rgba_close(blend_opaque(operator_hsl_saturation, gray, red),
+                          Rgba::new(0.3, 0.3, 0.3, 1.));
+        // Red on gray keeps red's hue and saturation at gray's luminosity
+        assert_rgba_close(blend_opaque(operator_hsl_color, red, gray),
+                          Rgba::new(1., 0.28571427, 0.28571427, 1.));
+        assert_rgba_close(blend_opaque(operator_hsl_luminosity, gray, red),
+                          Rgba::new(1., 0.28571427, 0.28571427, 1.));
+        assert_rgba_close(blend_opaque(operator_hsl_luminosity, red, gray),
+                          Rgba::new(0.3, 0.3, 0.3, 1.));
+    }
+
+    #[test]
+    fn test_fetch_blend_modes() {
+        let operators = vec![
+            (Operator::Multiply, operator_multiply as fn(&Rgba, &mut Rgba)),
+            (Operator::Screen, operator_screen),
+            (Operator::Overlay, operator_overlay),
+            (Operator::Darken, operator_darken),
+            (Operator::Lighten, operator_lighten),
+            (Operator::ColorDodge, operator_color_dodge),
+            (Operator::ColorBurn, operator_color_burn),
+            (Operator::HardLight, operator_hard_light),
+            (Operator::SoftLight, operator_soft_light),
+            (Operator::Difference, operator_difference),
+            (Operator::Exclusion, operator_exclusion),
+            (Operator::HSLHue, operator_hsl_hue),
+            (Operator::HSLSaturation, operator_hsl_saturation),
+            (Operator::HSLColor, operator_hsl_color),
+            (Operator::HSLLuminosity, operator_hsl_luminosity),
+        ];
+        for (operator, function) in operators {
+            assert!(operator.is_bounded());
+            assert_eq!(porter_duff(fetch_operator(&operator)), porter_duff(function));
+        }
+    }
 }
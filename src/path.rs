@@ -0,0 +1,406 @@
+/*
+ * Cairus - a reimplementation of the cairo graphics library in Rust
+ *
+ * Copyright © 2017 CairusOrg
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it either under the terms of the GNU Lesser General Public
+ * License version 2.1 as published by the Free Software Foundation
+ * (the "LGPL") or, at your option, under the terms of the Mozilla
+ * Public License Version 2.0 (the "MPL"). If you do not alter this
+ * notice, a recipient may use your version of this file under either
+ * the MPL or the LGPL.
+ *
+ * You should have received a copy of the LGPL along with this library
+ * in the file LICENSE-LGPL-2_1; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Suite 500, Boston, MA 02110-1335, USA
+ * You should have received a copy of the MPL along with this library
+ * in the file LICENSE-MPL-2_0
+ *
+ * The contents of this file are subject to the Mozilla Public License
+ * Version 2.0 (the "License"); you may not use this file except in
+ * compliance with the License. You may obtain a copy of the License at
+ * http://www.mozilla.org/MPL/
+ *
+ * This software is distributed on an "AS IS" basis, WITHOUT WARRANTY
+ * OF ANY KIND, either express or implied. See the LGPL or the MPL for
+ * the specific language governing rights and limitations.
+ *
+ * The Original Code is the cairus graphics library.
+ *
+ */
+
+//! This module defines the current path of a context.
+//!
+//! A path is a list of segments built up by calls such as `Context::move_to` and
+//! `Context::line_to`.  Nothing is drawn while a path is being described; drawing operations
+//! (fill, stroke) later consume the segments.  This is analogous to cairo_path_fixed_t.
+//!
+//! # Current point semantics
+//! These follow cairo:
+//! * `line_to` and `curve_to` without a current point behave as if `move_to` had been called
+//!   with their first point.
+//! * `close_path` draws a line back to the start of the sub-path, and leaves the current point
+//!   there.  The next drawing call starts a new sub-path from that point.
+//! * `new_sub_path` clears the current point without starting a new sub-path.
+
+use common_geometry::{Point, Edge};
+use decasteljau::{SplineKnots, decompose};
+
+/// A single instruction of a path, analogous to cairo_path_data_type_t.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PathSegment {
+    MoveTo(Point),
+    LineTo(Point),
+    CurveTo(Point, Point, Point),
+    ClosePath,
+}
+
+/// A sub-path that has been flattened into a series of connected straight lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polyline {
+    pub points: Vec<Point>,
+    // True if the sub-path was ended with `close_path`.
+    pub closed: bool,
+}
+
+/// A list of path segments plus the state needed to keep appending to it.
+#[derive(Debug, Clone)]
+pub struct Path {
+    segments: Vec<PathSegment>,
+    current_point: Option<Point>,
+    // Where the last sub-path began, this is the point `close_path` returns to.
+    subpath_start: Point,
+    // Set after `close_path`, the next segment needs a `MoveTo` inserted before it.
+    needs_move_to: bool,
+}
+
+impl Path {
+    /// Returns an empty path with no current point.
+    pub fn new() -> Path {
+        Path {
+            segments: Vec::new(),
+            current_point: None,
+            subpath_start: Point::origin(),
+            needs_move_to: false,
+        }
+    }
+
+    /// Returns the segments of this path in the order they were added.
+    pub fn segments(&self) -> &Vec<PathSegment> {
+        &self.segments
+    }
+
+    /// Returns the current point, or `None` if there isn't one.
+    pub fn current_point(&self) -> Option<Point> {
+        self.current_point
+    }
+
+    /// Removes every segment and clears the current point.
+    pub fn clear(&mut self) {
+        self.segments.clear();
+        self.current_point = None;
+        self.needs_move_to = false;
+    }
+
+    /// Begins a new sub-path at `point`.
+    ///
+    /// Consecutive `move_to` calls collapse into one, only the last point is kept.
+    pub fn move_to(&mut self, point: Point) {
+        if let Some(&PathSegment::MoveTo(_)) = self.segments.last() {
+            self.segments.pop();
+        }
+        self.segments.push(PathSegment::MoveTo(point));
+        self.current_point = Some(point);
+        self.subpath_start = point;
+        self.needs_move_to = false;
+    }
+
+    /// Adds a line from the current point to `point`.
+    pub fn line_to(&mut self, point: Point) {
+        if !self.ensure_sub_path(point) {
+            return;
+        }
+        self.segments.push(PathSegment::LineTo(point));
+        self.current_point = Some(point);
+    }
+
+    /// Adds a cubic Bézier spline from the current point to `end`, using `control1` and
+    /// `control2` as its control points.
+    pub fn curve_to(&mut self, control1: Point, control2: Point, end: Point) {
+        self.ensure_sub_path(control1);
+        self.segments.push(PathSegment::CurveTo(control1, control2, end));
+        self.current_point = Some(end);
+    }
+
+    /// Adds a line back to the start of the current sub-path and marks it closed.
+    ///
+    /// Does nothing if there is no current point.
+    pub fn close_path(&mut self) {
+        if self.current_point.is_none() {
+            return;
+        }
+
+        if self.needs_move_to {
+            // The sub-path was already closed, there is nothing new to close.
+            return;
+        }
+
+        self.segments.push(PathSegment::ClosePath);
+        self.current_point = Some(self.subpath_start);
+        self.needs_move_to = true;
+    }
+
+    /// Clears the current point so that the next segment starts a new sub-path.
+    pub fn new_sub_path(&mut self) {
+        self.current_point = None;
+        self.needs_move_to = false;
+    }
+
+    /// Returns every sub-path of this path flattened into straight lines.  Curves are replaced by
+    /// lines that stay within `tolerance` of the true curve.
+    pub fn flatten(&self, tolerance: f32) -> Vec<Polyline> {
+        let mut polylines = Vec::new();
+        let mut current: Option<Polyline> = None;
+        for segment in self.segments.iter() {
+            match *segment {
+                PathSegment::MoveTo(point) => {
+                    if let Some(polyline) = current.take() {
+                        polylines.push(polyline);
+                    }
+                    current = Some(Polyline { points: vec![point], closed: false });
+                },
+                PathSegment::LineTo(point) => {
+                    if let Some(ref mut polyline) = current {
+                        polyline.points.push(point);
+                    }
+                },
+                PathSegment::CurveTo(control1, control2, end) => {
+                    if let Some(ref mut polyline) = current {
+                        let start = *polyline.points.last().unwrap();
+                        let knots = SplineKnots::create(&start, &control1, &control2, &end);
+                        polyline.points.extend(decompose(&knots, tolerance));
+                    }
+                },
+                PathSegment::ClosePath => {
+                    if let Some(mut polyline) = current.take() {
+                        polyline.closed = true;
+                        polylines.push(polyline);
+                    }
+                },
+            }
+        }
+
+        if let Some(polyline) = current {
+            polylines.push(polyline);
+        }
+        polylines
+    }
+
+    /// Returns the directed edges outlining the area this path encloses.  Every sub-path is
+    /// implicitly closed, as it is when cairo fills a path.
+    pub fn fill_edges(&self, tolerance: f32) -> Vec<Edge> {
+        let mut edges = Vec::new();
+        for polyline in self.flatten(tolerance) {
+            let points = &polyline.points;
+            for (idx, &from) in points.iter().enumerate() {
+                let to = points[(idx + 1) % points.len()];
+                if from != to {
+                    edges.push(Edge::from_points(from, to));
+                }
+            }
+        }
+        edges
+    }
+
+    // Makes sure a sub-path is open before a segment gets appended.  Returns false if `point`
+    // started the sub-path itself, meaning there is no segment left to add for a line.
+    fn ensure_sub_path(&mut self, point: Point) -> bool {
+        match self.current_point {
+            None => {
+                self.move_to(point);
+                false
+            },
+            Some(current) => {
+                if self.needs_move_to {
+                    self.move_to(current);
+                }
+                true
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Path, PathSegment, Polyline};
+    use common_geometry::{Point, Edge};
+
+    // Tests that a new path is empty and has no current point.
+    #[test]
+    fn path_new_is_empty() {
+        let path = Path::new();
+        assert!(path.segments().is_empty());
+        assert_eq!(path.current_point(), None);
+    }
+
+    // Tests that move_to followed by line_to records both segments.
+    #[test]
+    fn path_move_to_line_to() {
+        let mut path = Path::new();
+        path.move_to(Point::new(1., 2.));
+        path.line_to(Point::new(3., 4.));
+        assert_eq!(*path.segments(), vec![
+            PathSegment::MoveTo(Point::new(1., 2.)),
+            PathSegment::LineTo(Point::new(3., 4.)),
+        ]);
+        assert_eq!(path.current_point(), Some(Point::new(3., 4.)));
+    }
+
+    // Tests that line_to without a current point behaves like move_to.
+    #[test]
+    fn path_line_to_without_current_point() {
+        let mut path = Path::new();
+        path.line_to(Point::new(5., 5.));
+        assert_eq!(*path.segments(), vec![PathSegment::MoveTo(Point::new(5., 5.))]);
+        assert_eq!(path.current_point(), Some(Point::new(5., 5.)));
+    }
+
+    // Tests that curve_to without a current point moves to the first control point.
+    #[test]
+    fn path_curve_to_without_current_point() {
+        let mut path = Path::new();
+        path.curve_to(Point::new(1., 1.), Point::new(2., 2.), Point::new(3., 1.));
+        assert_eq!(*path.segments(), vec![
+            PathSegment::MoveTo(Point::new(1., 1.)),
+            PathSegment::CurveTo(Point::new(1., 1.), Point::new(2., 2.), Point::new(3., 1.)),
+        ]);
+        assert_eq!(path.current_point(), Some(Point::new(3., 1.)));
+    }
+
+    // Tests that consecutive move_to calls collapse into the last one.
+    #[test]
+    fn path_consecutive_move_to() {
+        let mut path = Path::new();
+        path.move_to(Point::new(1., 1.));
+        path.move_to(Point::new(2., 2.));
+        assert_eq!(*path.segments(), vec![PathSegment::MoveTo(Point::new(2., 2.))]);
+    }
+
+    // Tests that close_path returns the current point to the start of the sub-path, and that
+    // drawing afterwards begins a new sub-path from there.
+    #[test]
+    fn path_close_path() {
+        let mut path = Path::new();
+        path.move_to(Point::new(0., 0.));
+        path.line_to(Point::new(10., 0.));
+        path.line_to(Point::new(10., 10.));
+        path.close_path();
+        assert_eq!(path.current_point(), Some(Point::new(0., 0.)));
+
+        path.line_to(Point::new(0., 10.));
+        assert_eq!(*path.segments(), vec![
+            PathSegment::MoveTo(Point::new(0., 0.)),
+            PathSegment::LineTo(Point::new(10., 0.)),
+            PathSegment::LineTo(Point::new(10., 10.)),
+            PathSegment::ClosePath,
+            PathSegment::MoveTo(Point::new(0., 0.)),
+            PathSegment::LineTo(Point::new(0., 10.)),
+        ]);
+    }
+
+    // Tests that close_path is ignored when there is no current point.
+    #[test]
+    fn path_close_path_without_current_point() {
+        let mut path = Path::new();
+        path.close_path();
+        assert!(path.segments().is_empty());
+    }
+
+    // Tests that new_sub_path clears the current point without adding a segment.
+    #[test]
+    fn path_new_sub_path() {
+        let mut path = Path::new();
+        path.move_to(Point::new(0., 0.));
+        path.line_to(Point::new(1., 1.));
+        path.new_sub_path();
+        assert_eq!(path.current_point(), None);
+        assert_eq!(path.segments().len(), 2);
+
+        path.line_to(Point::new(4., 4.));
+        assert_eq!(path.segments()[2], PathSegment::MoveTo(Point::new(4., 4.)));
+    }
+
+    // Tests that clear removes every segment.
+    #[test]
+    fn path_clear() {
+        let mut path = Path::new();
+        path.move_to(Point::new(0., 0.));
+        path.line_to(Point::new(1., 1.));
+        path.clear();
+        assert!(path.segments().is_empty());
+        assert_eq!(path.current_point(), None);
+    }
+
+    // Tests that flatten splits the path into one polyline per sub-path.
+    #[test]
+    fn path_flatten_sub_paths() {
+        let mut path = Path::new();
+        path.move_to(Point::new(0., 0.));
+        path.line_to(Point::new(1., 0.));
+        path.line_to(Point::new(1., 1.));
+        path.close_path();
+        path.move_to(Point::new(5., 5.));
+        path.line_to(Point::new(6., 5.));
+
+        assert_eq!(path.flatten(0.1), vec![
+            Polyline {
+                points: vec![Point::new(0., 0.), Point::new(1., 0.), Point::new(1., 1.)],
+                closed: true,
+            },
+            Polyline {
+                points: vec![Point::new(5., 5.), Point::new(6., 5.)],
+                closed: false,
+            },
+        ]);
+    }
+
+    // Tests that flatten replaces curves with lines ending at the curve's end point.
+    #[test]
+    fn path_flatten_curve() {
+        let mut path = Path::new();
+        path.move_to(Point::new(0., 0.));
+        path.curve_to(Point::new(0., 10.), Point::new(10., 10.), Point::new(10., 0.));
+
+        let polylines = path.flatten(0.1);
+        assert_eq!(polylines.len(), 1);
+        assert!(polylines[0].points.len() > 2);
+        assert_eq!(polylines[0].points[0], Point::new(0., 0.));
+        assert_eq!(*polylines[0].points.last().unwrap(), Point::new(10., 0.));
+    }
+
+    // Tests that fill_edges closes every sub-path, even ones that were left open.
+    #[test]
+    fn path_fill_edges_closes_sub_paths() {
+        let mut path = Path::new();
+        path.move_to(Point::new(0., 0.));
+        path.line_to(Point::new(4., 0.));
+        path.line_to(Point::new(4., 4.));
+
+        let edges = path.fill_edges(0.1);
+        assert_eq!(edges.len(), 3);
+        let closing = edges[2];
+        assert_eq!(closing.line.point1, Point::new(4., 4.));
+        assert_eq!(closing.line.point2, Point::new(0., 0.));
+        assert_eq!(closing.direction, -1);
+    }
+
+    // Tests that a lone move_to contributes no edges.
+    #[test]
+    fn path_fill_edges_ignores_degenerate_sub_paths() {
+        let mut path = Path::new();
+        path.move_to(Point::new(3., 3.));
+        let edges: Vec<Edge> = path.fill_edges(0.1);
+        assert!(edges.is_empty());
+    }
+}
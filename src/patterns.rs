@@ -0,0 +1,1477 @@
+/*
+ * Cairus - a reimplementation of the cairo graphics library in Rust
+ *
+ * Copyright © 2017 CairusOrg
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it either under the terms of the GNU Lesser General Public
+ * License version 2.1 as published by the Free Software Foundation
+ * (the "LGPL") or, at your option, under the terms of the Mozilla
+ * Public License Version 2.0 (the "MPL"). If you do not alter this
+ * notice, a recipient may use your version of this file under either
+ * the MPL or the LGPL.
+ *
+ * You should have received a copy of the LGPL along with this library
+ * in the file LICENSE-LGPL-2_1; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Suite 500, Boston, MA 02110-1335, USA
+ * You should have received a copy of the MPL along with this library
+ * in the file LICENSE-MPL-2_0
+ *
+ * The contents of this file are subject to the Mozilla Public License
+ * Version 2.0 (the "License"); you may not use this file except in
+ * compliance with the License. You may obtain a copy of the License at
+ * http://www.mozilla.org/MPL/
+ *
+ * This software is distributed on an "AS IS" basis, WITHOUT WARRANTY
+ * OF ANY KIND, either express or implied. See the LGPL or the MPL for
+ * the specific language governing rights and limitations.
+ *
+ * The Original Code is the cairus graphics library.
+ *
+ */
+
+//! This module defines patterns, the "paint" a context draws with or masks through.
+//!
+//! A pattern gives a color to every point of its own coordinate space, pattern space.  The
+//! pattern's matrix maps user space to pattern space, so a context finds the color of a device
+//! pixel by taking its center into user space (through the inverse of the CTM) and then into
+//! pattern space.  This is analogous to cairo_pattern_t.
+
+use std::f32;
+use types::Rgba;
+use surfaces::ImageSurface;
+use matrix::Matrix;
+use status::Status;
+use common_geometry::Point;
+use decasteljau::SplineKnots;
+use mesh::{Patch, draw_patch};
+
+/// The source of color for drawing, or of alpha for masking.
+#[derive(Clone)]
+pub struct Pattern {
+    kind: PatternKind,
+    matrix: Matrix,
+    extend: Extend,
+    filter: Filter,
+}
+
+/// Specifies how a surface pattern is resampled when it is drawn transformed, analogous to
+/// cairo_filter_t.
+///
+/// Every filter takes colors from the pixels around a point of the surface.  The filters that
+/// shrink well widen that area to all the surface pixels one device pixel covers, so that
+/// shrinking a surface averages its pixels instead of skipping some of them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Filter {
+    /// A fast filter, the same as `Nearest`.
+    Fast,
+    /// A filter of good quality and speed, the default.  It is the same as `Bilinear` when a
+    /// surface is drawn at its size or larger, and averages the covered surface pixels (a box
+    /// filter) when it is drawn smaller.
+    Good,
+    /// The filter of the best quality.  It interpolates with a Lanczos kernel three pixels
+    /// wide on each side, which keeps edges sharper than `Gaussian`, widened to all the covered
+    /// surface pixels when a surface is drawn smaller.
+    Best,
+    /// The color of the pixel the point is in.
+    Nearest,
+    /// Linear interpolation between the four pixels whose centers are nearest.  Shrinking a
+    /// surface by more than half skips pixels.
+    Bilinear,
+    /// A weighted average of the nearby pixels, with weights falling off along a Gaussian curve
+    /// that widens when a surface is drawn smaller.
+    Gaussian,
+}
+
+/// Specifies what a pattern shows outside of its natural area, analogous to cairo_extend_t.
+///
+/// For a gradient the natural area is between its start (offset 0) and its end (offset 1).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Extend {
+    /// Nothing is shown outside of the area.  This is the default for surface patterns.
+    None,
+    /// The pattern repeats itself.
+    Repeat,
+    /// The pattern repeats itself, mirrored every other time.
+    Reflect,
+    /// The edge of the area continues outwards.  This is the default for gradients.
+    Pad,
+}
+
+/// The kinds of pattern, analogous to cairo_pattern_type_t.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PatternType {
+    /// A single color, from `create_rgb` or `create_rgba`.
+    Solid,
+    /// An image, from `create_for_surface`.
+    Surface,
+    /// A linear gradient, from `create_linear`.
+    Linear,
+    /// A radial gradient, from `create_radial`.
+    Radial,
+    /// A mesh of patches, from `create_mesh`.
+    Mesh,
+}
+
+#[derive(Clone)]
+enum PatternKind {
+    Solid(Rgba),
+    Surface(ImageSurface<'static>),
+    Linear(LinearGradient),
+    Radial(RadialGradient),
+    Mesh(Mesh),
+}
+
+// A color along a gradient.  `offset` runs from 0 at the start of the gradient to 1 at its end.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct ColorStop {
+    offset: f32,
+    rgba: Rgba,
+}
+
+// A gradient along the line from (x0, y0) to (x1, y1).  Every point takes the color at its
+// projection onto the line.
+#[derive(Clone)]
+struct LinearGradient {
+    x0: f32,
+    y0: f32,
+    x1: f32,
+    y1: f32,
+    stops: Vec<ColorStop>,
+}
+
+// A gradient between the circle of radius r0 around (cx0, cy0) and the circle of radius r1
+// around (cx1, cy1).
+#[derive(Clone)]
+struct RadialGradient {
+    cx0: f32,
+    cy0: f32,
+    r0: f32,
+    cx1: f32,
+    cy1: f32,
+    r1: f32,
+    stops: Vec<ColorStop>,
+}
+
+// The patches of a mesh pattern, in the order they are drawn.
+#[derive(Clone)]
+struct Mesh {
+    patches: Vec<Patch>,
+    // The patch between begin_patch and end_patch
+    current: Option<PatchDefinition>,
+}
+
+// A patch of a mesh pattern that is still being defined.
+#[derive(Clone)]
+struct PatchDefinition {
+    // The first point of the patch, set by the first move_to, line_to or curve_to
+    start: Option<Point>,
+    current_point: Option<Point>,
+    sides: Vec<SplineKnots>,
+    control_points: [Option<Point>; 4],
+    colors: [Rgba; 4],
+}
+
+impl PatchDefinition {
+    fn new() -> PatchDefinition {
+        PatchDefinition {
+            start: None,
+            current_point: None,
+            sides: Vec::new(),
+            control_points: [None; 4],
+            colors: [Rgba::new(0., 0., 0., 0.); 4],
+        }
+    }
+
+    fn move_to(&mut self, point: Point) -> Result<(), Status> {
+        if self.current_point.is_some() {
+            return Err(Status::InvalidMeshConstruction);
+        }
+        self.start = Some(point);
+        self.current_point = Some(point);
+        Ok(())
+    }
+
+    fn curve_to(&mut self, b: Point, c: Point, d: Point) -> Result<(), Status> {
+        if self.sides.len() == 4 {
+            return Err(Status::InvalidMeshConstruction);
+        }
+        let a = match self.current_point {
+            Some(point) => point,
+            None => {
+                self.move_to(b)?;
+                b
+            },
+        };
+        self.sides.push(SplineKnots::create(&a, &b, &c, &d));
+        self.current_point = Some(d);
+        Ok(())
+    }
+
+    fn line_to(&mut self, point: Point) -> Result<(), Status> {
+        let a = match self.current_point {
+            Some(point) => point,
+            None => return self.move_to(point),
+        };
+        // A straight side is a curve with its control points a third of the way from each end
+        let b = Point::new(a.x + (point.x - a.x) / 3., a.y + (point.y - a.y) / 3.);
+        let c = Point::new(a.x + (point.x - a.x) * 2. / 3., a.y + (point.y - a.y) * 2. / 3.);
+        self.curve_to(b, c, point)
+    }
+
+    // Closes the patch with straight sides back to its start and returns it.
+    fn finish(mut self) -> Result<Patch, Status> {
+        let start = self.start.ok_or(Status::InvalidMeshConstruction)?;
+        while self.sides.len() < 4 {
+            self.line_to(start)?;
+        }
+        let sides = [self.sides[0], self.sides[1], self.sides[2], self.sides[3]];
+        Ok(Patch::create(&sides, &self.control_points, &self.colors))
+    }
+}
+
+impl LinearGradient {
+    // Returns how far along the gradient (x, y) is, 0 at (x0, y0) and 1 at (x1, y1).
+    fn offset(&self, x: f32, y: f32) -> f32 {
+        let (dx, dy) = (self.x1 - self.x0, self.y1 - self.y0);
+        let length_squared = dx * dx + dy * dy;
+        if length_squared == 0. {
+            return 0.;
+        }
+        ((x - self.x0) * dx + (y - self.y0) * dy) / length_squared
+    }
+}
+
+impl RadialGradient {
+    // Returns how far between the two circles (x, y) is, or None if no circle of the gradient
+    // passes through it.
+    //
+    // The gradient is the family of circles whose centers and radii are interpolated from the
+    // start circle at offset 0 to the end circle at offset 1, and extrapolated beyond them.  A
+    // point can lie on two of those circles, and like cairo the circle with the larger offset is
+    // painted on top.  Circles with a negative radius do not exist, and when the gradient does
+    // not extend only offsets between 0 and 1 count.
+    fn offset(&self, x: f32, y: f32, extend: Extend) -> Option<f32> {
+        // Solve |p - c(t)| = r(t) for t, with c(t) = c0 + t * (c1 - c0) and r(t) = r0 + t * dr,
+        // which is the quadratic a * t^2 - 2 * b * t + c = 0.
+        let (cdx, cdy) = (self.cx1 - self.cx0, self.cy1 - self.cy0);
+        let (pdx, pdy) = (x - self.cx0, y - self.cy0);
+        let dr = self.r1 - self.r0;
+        let a = cdx * cdx + cdy * cdy - dr * dr;
+        let b = pdx * cdx + pdy * cdy + self.r0 * dr;
+        let c = pdx * pdx + pdy * pdy - self.r0 * self.r0;
+
+        let valid = |t: f32| {
+            self.r0 + t * dr >= 0. && (extend != Extend::None || (0. ..=1.).contains(&t))
+        };
+
+        if a.abs() < 1e-6 {
+            // The quadratic degenerates into the linear equation -2 * b * t + c = 0
+            if b == 0. {
+                return None;
+            }
+            let t = c / (2. * b);
+            return if valid(t) { Some(t) } else { None };
+        }
+
+        let discriminant = b * b - a * c;
+        if discriminant < 0. {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        let (t1, t2) = ((b + root) / a, (b - root) / a);
+        let (larger, smaller) = if t1 > t2 { (t1, t2) } else { (t2, t1) };
+        if valid(larger) {
+            Some(larger)
+        } else if valid(smaller) {
+            Some(smaller)
+        } else {
+            None
+        }
+    }
+}
+
+impl Pattern {
+    /// Returns an opaque pattern of a single color.
+    pub fn create_rgb(red: f32, green: f32, blue: f32) -> Pattern {
+        Pattern::create_rgba(red, green, blue, 1.)
+    }
+
+    /// Returns a pattern of a single color.  The channels are clamped between 0 and 1.
+    pub fn create_rgba(red: f32, green: f32, blue: f32, alpha: f32) -> Pattern {
+        let mut rgba = Rgba::new(red, green, blue, alpha);
+        rgba.correct();
+        Pattern::from_kind(PatternKind::Solid(rgba))
+    }
+
+    /// Returns a pattern showing a copy of `surface`, with its top left corner at the origin of
+    /// pattern space and one pixel per unit.  Outside of the surface the pattern is transparent,
+    /// unless its extend is changed.
+    pub fn create_for_surface(surface: &ImageSurface) -> Pattern {
+        Pattern::from_kind(PatternKind::Surface(surface.copy()))
+    }
+
+    /// Returns a linear gradient from (x0, y0) to (x1, y1) in pattern space, without color stops.
+    pub fn create_linear(x0: f32, y0: f32, x1: f32, y1: f32) -> Pattern {
+        Pattern::from_kind(PatternKind::Linear(LinearGradient {
+            x0: x0,
+            y0: y0,
+            x1: x1,
+            y1: y1,
+            stops: Vec::new(),
+        }))
+    }
+
+    /// Returns a radial gradient from the circle of radius r0 around (cx0, cy0) to the circle of
+    /// radius r1 around (cx1, cy1) in pattern space, without color stops.
+    ///
+    /// The colors are painted on the circles in between, so when the start circle lies inside
+    /// the end circle but off its center it acts as a focal point.
+    pub fn create_radial(cx0: f32, cy0: f32, r0: f32, cx1: f32, cy1: f32, r1: f32) -> Pattern {
+        Pattern::from_kind(PatternKind::Radial(RadialGradient {
+            cx0: cx0,
+            cy0: cy0,
+            r0: r0,
+            cx1: cx1,
+            cy1: cy1,
+            r1: r1,
+            stops: Vec::new(),
+        }))
+    }
+
+    /// Returns a mesh pattern without patches.  Patches are added between `begin_patch` and
+    /// `end_patch`, and outside of them the pattern is transparent.
+    pub fn create_mesh() -> Pattern {
+        Pattern::from_kind(PatternKind::Mesh(Mesh {
+            patches: Vec::new(),
+            current: None,
+        }))
+    }
+
+    fn from_kind(kind: PatternKind) -> Pattern {
+        let extend = match kind {
+            PatternKind::Surface(_) => Extend::None,
+            _ => Extend::Pad,
+        };
+        Pattern {
+            kind: kind,
+            matrix: Matrix::init_identity(),
+            extend: extend,
+            filter: Filter::Good,
+        }
+    }
+
+    /// Adds an opaque color stop to a gradient.  See `add_color_stop_rgba`.
+    pub fn add_color_stop_rgb(&mut self, offset: f32, red: f32, green: f32, blue: f32)
+                              -> Result<(), Status> {
+        self.add_color_stop_rgba(offset, red, green, blue, 1.)
+    }
+
+    /// Adds a color stop to a gradient, at `offset` between 0 (its start) and 1 (its end).
+    ///
+    /// The offset and channels are clamped between 0 and 1.  Stops are kept in order of offset,
+    /// and a stop added at the same offset as earlier stops goes after them, which makes a sharp
+    /// transition.  Returns `Err(Status::PatternTypeMismatch)` if this is not a gradient.
+    pub fn add_color_stop_rgba(&mut self, offset: f32, red: f32, green: f32, blue: f32,
+                               alpha: f32) -> Result<(), Status> {
+        let stops = match self.kind {
+            PatternKind::Linear(ref mut gradient) => &mut gradient.stops,
+            PatternKind::Radial(ref mut gradient) => &mut gradient.stops,
+            _ => return Err(Status::PatternTypeMismatch),
+        };
+
+        let offset = offset.clamp(0., 1.);
+        let mut rgba = Rgba::new(red, green, blue, alpha);
+        rgba.correct();
+        let index = stops.iter().position(|stop| stop.offset > offset).unwrap_or(stops.len());
+        stops.insert(index, ColorStop { offset: offset, rgba: rgba });
+        Ok(())
+    }
+
+    // Returns the mesh of a mesh pattern.
+    fn mesh_mut(&mut self) -> Result<&mut Mesh, Status> {
+        match self.kind {
+            PatternKind::Mesh(ref mut mesh) => Ok(mesh),
+            _ => Err(Status::PatternTypeMismatch),
+        }
+    }
+
+    // Returns the patch a mesh pattern is in the middle of defining.
+    fn current_patch(&mut self) -> Result<&mut PatchDefinition, Status> {
+        self.mesh_mut()?.current.as_mut().ok_or(Status::InvalidMeshConstruction)
+    }
+
+    /// Starts a new patch of a mesh pattern.  Its sides are given with `move_to`, `line_to` and
+    /// `curve_to`, and it is added to the pattern by `end_patch`.
+    ///
+    /// Returns `Err(Status::InvalidMeshConstruction)` if a patch was already started, or
+    /// `Err(Status::PatternTypeMismatch)` if this is not a mesh pattern.
+    pub fn begin_patch(&mut self) -> Result<(), Status> {
+        let mesh = self.mesh_mut()?;
+        if mesh.current.is_some() {
+            return Err(Status::InvalidMeshConstruction);
+        }
+        mesh.current = Some(PatchDefinition::new());
+        Ok(())
+    }
+
+    /// Adds the current patch to the mesh.  If it has fewer than four sides, straight sides back
+    /// to its first point are added.
+    ///
+    /// Returns `Err(Status::InvalidMeshConstruction)` if no patch was started or it has no
+    /// points.
+    pub fn end_patch(&mut self) -> Result<(), Status> {
+        let mesh = self.mesh_mut()?;
+        let patch = mesh.current.take().ok_or(Status::InvalidMeshConstruction)?.finish()?;
+        mesh.patches.push(patch);
+        Ok(())
+    }
+
+    /// Sets the first point of the current patch, corner 0.  This must come before its sides.
+    pub fn move_to(&mut self, x: f32, y: f32) -> Result<(), Status> {
+        self.current_patch()?.move_to(Point::new(x, y))
+    }
+
+    /// Adds a straight side to the current patch, from the current point to (x, y), which
+    /// becomes the next corner.  Without a current point this acts like `move_to`.
+    ///
+    /// Returns `Err(Status::InvalidMeshConstruction)` if the patch already has four sides.
+    pub fn line_to(&mut self, x: f32, y: f32) -> Result<(), Status> {
+        self.current_patch()?.line_to(Point::new(x, y))
+    }
+
+    /// Adds a cubic Bézier side to the current patch, from the current point to (x3, y3) with
+    /// control points (x1, y1) and (x2, y2).  Without a current point, (x1, y1) is moved to
+    /// first.
+    ///
+    /// Returns `Err(Status::InvalidMeshConstruction)` if the patch already has four sides.
+    pub fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x3: f32, y3: f32)
+                    -> Result<(), Status> {
+        self.current_patch()?.curve_to(Point::new(x1, y1), Point::new(x2, y2),
+                                       Point::new(x3, y3))
+    }
+
+    /// Sets interior control point `point_num` (0 to 3) of the current patch, the one nearest
+    /// corner `point_num`.  Control points that are not set are placed so that the patch is a
+    /// Coons patch.
+    ///
+    /// Returns `Err(Status::InvalidIndex)` if `point_num` is greater than 3.
+    pub fn set_control_point(&mut self, point_num: usize, x: f32, y: f32)
+                             -> Result<(), Status> {
+        if point_num > 3 {
+            return Err(Status::InvalidIndex);
+        }
+        self.current_patch()?.control_points[point_num] = Some(Point::new(x, y));
+        Ok(())
+    }
+
+    /// Sets the opaque color of corner `corner_num` (0 to 3) of the current patch.  See
+    /// `set_corner_color_rgba`.
+    pub fn set_corner_color_rgb(&mut self, corner_num: usize, red: f32, green: f32, blue: f32)
+                                -> Result<(), Status> {
+        self.set_corner_color_rgba(corner_num, red, green, blue, 1.)
+    }
+
+    /// Sets the color of corner `corner_num` (0 to 3) of the current patch.  Corners are
+    /// transparent until their color is set, and the channels are clamped between 0 and 1.
+    ///
+    /// Returns `Err(Status::InvalidIndex)` if `corner_num` is greater than 3.
+    pub fn set_corner_color_rgba(&mut self, corner_num: usize, red: f32, green: f32, blue: f32,
+                                 alpha: f32) -> Result<(), Status> {
+        if corner_num > 3 {
+            return Err(Status::InvalidIndex);
+        }
+        let mut rgba = Rgba::new(red, green, blue, alpha);
+        rgba.correct();
+        self.current_patch()?.colors[corner_num] = rgba;
+        Ok(())
+    }
+
+    /// Returns the kind of pattern this is.
+    pub fn get_type(&self) -> PatternType {
+        match self.kind {
+            PatternKind::Solid(_) => PatternType::Solid,
+            PatternKind::Surface(_) => PatternType::Surface,
+            PatternKind::Linear(_) => PatternType::Linear,
+            PatternKind::Radial(_) => PatternType::Radial,
+            PatternKind::Mesh(_) => PatternType::Mesh,
+        }
+    }
+
+    /// Returns the red, green, blue and alpha of a solid pattern, with the colors not
+    /// pre-multiplied.  Returns `Err(Status::PatternTypeMismatch)` for other patterns.
+    pub fn get_rgba(&self) -> Result<(f32, f32, f32, f32), Status> {
+        match self.kind {
+            PatternKind::Solid(ref rgba) => Ok(straight(rgba)),
+            _ => Err(Status::PatternTypeMismatch),
+        }
+    }
+
+    /// Returns the surface a surface pattern shows.  Returns `Err(Status::PatternTypeMismatch)`
+    /// for other patterns.
+    pub fn get_surface(&self) -> Result<&ImageSurface<'static>, Status> {
+        match self.kind {
+            PatternKind::Surface(ref surface) => Ok(surface),
+            _ => Err(Status::PatternTypeMismatch),
+        }
+    }
+
+    /// Returns the number of color stops of a gradient.  Returns
+    /// `Err(Status::PatternTypeMismatch)` for other patterns.
+    pub fn get_color_stop_count(&self) -> Result<usize, Status> {
+        self.color_stops().map(|stops| stops.len())
+    }
+
+    /// Returns the offset, red, green, blue and alpha of color stop `index` of a gradient, in
+    /// order of offset and with the colors not pre-multiplied.
+    ///
+    /// Returns `Err(Status::InvalidIndex)` if there is no such stop, or
+    /// `Err(Status::PatternTypeMismatch)` if this is not a gradient.
+    pub fn get_color_stop_rgba(&self, index: usize)
+                               -> Result<(f32, f32, f32, f32, f32), Status> {
+        let stop = self.color_stops()?.get(index).ok_or(Status::InvalidIndex)?;
+        let (red, green, blue, alpha) = straight(&stop.rgba);
+        Ok((stop.offset, red, green, blue, alpha))
+    }
+
+    // Returns the color stops of a gradient.
+    fn color_stops(&self) -> Result<&[ColorStop], Status> {
+        match self.kind {
+            PatternKind::Linear(ref gradient) => Ok(&gradient.stops),
+            PatternKind::Radial(ref gradient) => Ok(&gradient.stops),
+            _ => Err(Status::PatternTypeMismatch),
+        }
+    }
+
+    /// Returns the start (x0, y0) and end (x1, y1) of a linear gradient.  Returns
+    /// `Err(Status::PatternTypeMismatch)` for other patterns.
+    pub fn get_linear_points(&self) -> Result<(f32, f32, f32, f32), Status> {
+        match self.kind {
+            PatternKind::Linear(ref gradient) => {
+                Ok((gradient.x0, gradient.y0, gradient.x1, gradient.y1))
+            },
+            _ => Err(Status::PatternTypeMismatch),
+        }
+    }
+
+    /// Returns the start circle (cx0, cy0, r0) and end circle (cx1, cy1, r1) of a radial
+    /// gradient.  Returns `Err(Status::PatternTypeMismatch)` for other patterns.
+    pub fn get_radial_circles(&self) -> Result<(f32, f32, f32, f32, f32, f32), Status> {
+        match self.kind {
+            PatternKind::Radial(ref gradient) => {
+                Ok((gradient.cx0, gradient.cy0, gradient.r0, gradient.cx1, gradient.cy1,
+                    gradient.r1))
+            },
+            _ => Err(Status::PatternTypeMismatch),
+        }
+    }
+
+    /// Returns how many patches a mesh pattern has, not counting one still being defined.
+    pub fn get_patch_count(&self) -> Result<usize, Status> {
+        match self.kind {
+            PatternKind::Mesh(ref mesh) => Ok(mesh.patches.len()),
+            _ => Err(Status::PatternTypeMismatch),
+        }
+    }
+
+    /// Sets how a surface pattern is resampled.  Other patterns are not affected.
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+    }
+
+    /// Returns how a surface pattern is resampled.
+    pub fn get_filter(&self) -> Filter {
+        self.filter
+    }
+
+    /// Sets what this pattern shows outside of its natural area.
+    pub fn set_extend(&mut self, extend: Extend) {
+        self.extend = extend;
+    }
+
+    /// Returns what this pattern shows outside of its natural area.
+    pub fn get_extend(&self) -> Extend {
+        self.extend
+    }
+
+    /// Sets the matrix mapping user space to this pattern's space.  For example, to draw a
+    /// surface pattern twice as large, scale this matrix by a half.
+    ///
+    /// Returns `Err(Status::InvalidMatrix)` and leaves the matrix unchanged if `matrix` is not
+    /// invertible.
+    pub fn set_matrix(&mut self, matrix: Matrix) -> Result<(), Status> {
+        matrix.invert()?;
+        self.matrix = matrix;
+        Ok(())
+    }
+
+    /// Returns the matrix mapping user space to this pattern's space.
+    pub fn get_matrix(&self) -> Matrix {
+        self.matrix
+    }
+
+    /// Returns the pre-multiplied colors of this pattern at the centers of a `width` by `height`
+    /// grid of device pixels, row by row.  `device_to_pattern` maps device space to pattern
+    /// space.
+    pub fn render(&self, device_to_pattern: &Matrix, width: usize, height: usize) -> Vec<Rgba> {
+        let mut pixels = Vec::with_capacity(width * height);
+        if let PatternKind::Mesh(ref mesh) = self.kind {
+            // Patches are drawn forwards, so they need the opposite transformation
+            pixels.resize(width * height, Rgba::new(0., 0., 0., 0.));
+            if let Ok(pattern_to_device) = device_to_pattern.invert() {
+                for patch in &mesh.patches {
+                    draw_patch(patch, &pattern_to_device, width, height, &mut pixels);
+                }
+            }
+            return pixels;
+        }
+
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(self.sample_pixel(device_to_pattern, x, y));
+            }
+        }
+        pixels
+    }
+
+    /// Returns the pre-multiplied color of this pattern at the center of the device pixel in
+    /// column `x` and row `y`, the same as `render` gives for it.
+    ///
+    /// This needs no memory for the other pixels, but a mesh pattern is drawn whole for every
+    /// call, so `render` is much faster for meshes.
+    pub fn sample_pixel(&self, device_to_pattern: &Matrix, x: usize, y: usize) -> Rgba {
+        if let PatternKind::Mesh(_) = self.kind {
+            let to_pixel = Matrix::init_translate(x as f32, y as f32);
+            return self.render(&to_pixel.multiply(device_to_pattern), 1, 1)[0];
+        }
+
+        // The width and height in pattern space of the box around a device pixel
+        let footprint = (device_to_pattern.xx.abs() + device_to_pattern.xy.abs(),
+                         device_to_pattern.yx.abs() + device_to_pattern.yy.abs());
+        let (pattern_x, pattern_y) =
+            device_to_pattern.transform_point(x as f32 + 0.5, y as f32 + 0.5);
+        self.sample_footprint(pattern_x, pattern_y, footprint)
+    }
+
+    /// Returns the pre-multiplied color of this pattern at (x, y) in pattern space.  Surface
+    /// patterns are resampled as if drawn at their own size.
+    pub fn sample(&self, x: f32, y: f32) -> Rgba {
+        self.sample_footprint(x, y, (1., 1.))
+    }
+
+    // Returns the color of this pattern at (x, y), as seen by a device pixel that covers
+    // `footprint` (a width and height) of pattern space.
+    fn sample_footprint(&self, x: f32, y: f32, footprint: (f32, f32)) -> Rgba {
+        match self.kind {
+            PatternKind::Solid(rgba) => rgba,
+            PatternKind::Surface(ref surface) => {
+                sample_surface(surface, x, y, self.filter, self.extend, footprint)
+            },
+            PatternKind::Linear(ref gradient) => {
+                gradient_color(&gradient.stops, gradient.offset(x, y), self.extend)
+            },
+            PatternKind::Radial(ref gradient) => {
+                match gradient.offset(x, y, self.extend) {
+                    Some(offset) => gradient_color(&gradient.stops, offset, self.extend),
+                    None => Rgba::new(0., 0., 0., 0.),
+                }
+            },
+            PatternKind::Mesh(_) => {
+                // Render the single pixel whose center is (x, y)
+                self.render(&Matrix::init_translate(x - 0.5, y - 0.5), 1, 1)[0]
+            },
+        }
+    }
+}
+
+// Returns the channels of `rgba` with the colors divided back out of the alpha.
+fn straight(rgba: &Rgba) -> (f32, f32, f32, f32) {
+    if rgba.alpha <= 0. {
+        return (0., 0., 0., 0.);
+    }
+    (rgba.red / rgba.alpha, rgba.green / rgba.alpha, rgba.blue / rgba.alpha, rgba.alpha)
+}
+
+// Returns the color of `surface` around (x, y), resampled with `filter` for a device pixel that
+// covers `footprint` of it.  Pixels outside of the surface are found with `extend`.
+fn sample_surface(surface: &ImageSurface, x: f32, y: f32, filter: Filter, extend: Extend,
+                  footprint: (f32, f32)) -> Rgba {
+    let (width, height) = footprint;
+    match filter {
+        Filter::Fast | Filter::Nearest => surface_pixel(surface, x.floor(), y.floor(), extend),
+        Filter::Bilinear => {
+            weighted_pixels(surface, extend, &box_weights(x, 1.), &box_weights(y, 1.))
+        },
+        Filter::Good => {
+            weighted_pixels(surface, extend, &box_weights(x, width.max(1.)),
+                            &box_weights(y, height.max(1.)))
+        },
+        Filter::Best => {
+            // The negative lobes of the kernel can overshoot, so the result is brought back to
+            // a valid pre-multiplied color
+            let sum = weighted_pixels(surface, extend, &lanczos_weights(x, width.max(1.)),
+                                      &lanczos_weights(y, height.max(1.)));
+            let alpha = sum.alpha.clamp(0., 1.);
+            Rgba {
+                red: sum.red.clamp(0., alpha),
+                green: sum.green.clamp(0., alpha),
+                blue: sum.blue.clamp(0., alpha),
+                alpha: alpha,
+            }
+        },
+        Filter::Gaussian => {
+            weighted_pixels(surface, extend, &gaussian_weights(x, width.max(1.)),
+                            &gaussian_weights(y, height.max(1.)))
+        },
+    }
+}
+
+// Returns the pixel of `surface` in `column` and `row`.  Outside of the surface `extend` picks
+// the pixel to use, or makes it transparent.
+fn surface_pixel(surface: &ImageSurface, column: f32, row: f32, extend: Extend) -> Rgba {
+    let column = extend_index(column, surface.width as f32, extend);
+    let row = extend_index(row, surface.height as f32, extend);
+    match (column, row) {
+        (Some(column), Some(row)) => surface.get(column, row).unwrap(),
+        _ => Rgba::new(0., 0., 0., 0.),
+    }
+}
+
+// Returns the index of the pixel to use for pixel `index` of a row or column `size` long, or
+// None if there is no such pixel.
+fn extend_index(index: f32, size: f32, extend: Extend) -> Option<usize> {
+    if size == 0. || !index.is_finite() {
+        return None;
+    }
+    let index = match extend {
+        Extend::None => index,
+        Extend::Repeat => index.rem_euclid(size),
+        Extend::Reflect => {
+            // Every other copy is mirrored, so the pattern repeats every two copies
+            let index = index.rem_euclid(2. * size);
+            if index >= size { 2. * size - 1. - index } else { index }
+        },
+        Extend::Pad => index.clamp(0., size - 1.),
+    };
+    if index < 0. || index >= size {
+        return None;
+    }
+    Some(index as usize)
+}
+
+// Returns the sum of the pixels of `surface` weighted by the product of their column's weight
+// in `columns` and their row's weight in `rows`.
+fn weighted_pixels(surface: &ImageSurface, extend: Extend, columns: &[(f32, f32)],
+                   rows: &[(f32, f32)]) -> Rgba {
+    let mut sum = Rgba::new(0., 0., 0., 0.);
+    for &(row, row_weight) in rows {
+        for &(column, column_weight) in columns {
+            let pixel = surface_pixel(surface, column, row, extend);
+            let weight = row_weight * column_weight;
+            sum.red += pixel.red * weight;
+            sum.green += pixel.green * weight;
+            sum.blue += pixel.blue * weight;
+            sum.alpha += pixel.alpha * weight;
+        }
+    }
+    sum
+}
+
+// Most pixels a filter takes along one direction.  This bounds the work of shrinking a surface
+// enormously, where averaging fewer pixels is unnoticeable.
+const MAX_FILTER_TAPS: f32 = 256.;
+
+// Returns the weights of the pixels along one direction (by index) for a box `size` wide
+// centered on `center`.  Each pixel weighs how much of the box it covers, so for a box one pixel
+// wide this is linear interpolation between the two nearest pixel centers.
+fn box_weights(center: f32, size: f32) -> Vec<(f32, f32)> {
+    let size = size.min(MAX_FILTER_TAPS);
+    let (start, end) = (center - size / 2., center + size / 2.);
+    if !start.is_finite() || !end.is_finite() {
+        return Vec::new();
+    }
+
+    let mut weights = Vec::new();
+    let mut index = start.floor();
+    while index < end {
+        let overlap = end.min(index + 1.) - start.max(index);
+        if overlap > 0. {
+            weights.push((index, overlap / size));
+        }
+        index += 1.;
+    }
+    weights
+}
+
+// Returns the weights of the pixels along one direction (by index) for a Gaussian centered on
+// `center`, whose standard deviation is half of `size`.  Pixels further than three standard
+// deviations away are left out.
+fn gaussian_weights(center: f32, size: f32) -> Vec<(f32, f32)> {
+    let sigma = size.min(MAX_FILTER_TAPS / 6.) / 2.;
+    let radius = 3. * sigma;
+    if !center.is_finite() {
+        return Vec::new();
+    }
+
+    let mut weights = Vec::new();
+    let mut total = 0.;
+    let mut index = (center - radius - 0.5).ceil();
+    while index + 0.5 <= center + radius {
+        let distance = index + 0.5 - center;
+        let weight = (-distance * distance / (2. * sigma * sigma)).exp();
+        weights.push((index, weight));
+        total += weight;
+        index += 1.;
+    }
+    for weight in weights.iter_mut() {
+        weight.1 /= total;
+    }
+    weights
+}
+
+// Returns the weights of the pixels along one direction (by index) for a Lanczos-3 kernel
+// centered on `center` and stretched to `size` pixels per lobe, so that shrinking averages every
+// covered pixel.  Pixels further than three lobes away are left out.
+fn lanczos_weights(center: f32, size: f32) -> Vec<(f32, f32)> {
+    let scale = size.min(MAX_FILTER_TAPS / 6.);
+    let radius = 3. * scale;
+    if !center.is_finite() {
+        return Vec::new();
+    }
+
+    let mut weights = Vec::new();
+    let mut total = 0.;
+    let mut index = (center - radius - 0.5).ceil();
+    while index + 0.5 <= center + radius {
+        let distance = (index + 0.5 - center) / scale;
+        let weight = sinc(distance) * sinc(distance / 3.);
+        weights.push((index, weight));
+        total += weight;
+        index += 1.;
+    }
+    for weight in weights.iter_mut() {
+        weight.1 /= total;
+    }
+    weights
+}
+
+// The normalized sinc function, sin(pi x) / (pi x).
+fn sinc(x: f32) -> f32 {
+    if x == 0. {
+        return 1.;
+    }
+    let x = f32::consts::PI * x;
+    x.sin() / x
+}
+
+// Returns the color of a gradient with sorted `stops` at `offset`, which `extend` brings back
+// between 0 and 1 first.
+fn gradient_color(stops: &[ColorStop], offset: f32, extend: Extend) -> Rgba {
+    let offset = match extend {
+        Extend::None => {
+            if !(0. ..=1.).contains(&offset) {
+                return Rgba::new(0., 0., 0., 0.);
+            }
+            offset
+        },
+        Extend::Repeat => offset - offset.floor(),
+        Extend::Reflect => {
+            let offset = offset.abs() % 2.;
+            if offset > 1. { 2. - offset } else { offset }
+        },
+        Extend::Pad => offset.clamp(0., 1.),
+    };
+    color_at_offset(stops, offset)
+}
+
+// Returns the color of a gradient with sorted `stops` at `offset`.  Between two stops the
+// pre-multiplied colors are interpolated linearly, and beyond the first and last stops their
+// colors continue.  A gradient without stops is transparent, and so is an offset that isn't a
+// number, which overflowing or NaN gradient geometry gives.
+fn color_at_offset(stops: &[ColorStop], offset: f32) -> Rgba {
+    let (first, last) = match (stops.first(), stops.last()) {
+        (Some(first), Some(last)) if !offset.is_nan() => (first, last),
+        _ => return Rgba::new(0., 0., 0., 0.),
+    };
+    if offset <= first.offset {
+        return first.rgba;
+    }
+    if offset >= last.offset {
+        return last.rgba;
+    }
+
+    // The pair of stops around offset
+    let end = stops.iter().position(|stop| stop.offset > offset).unwrap();
+    let (start, end) = (&stops[end - 1], &stops[end]);
+    let t = (offset - start.offset) / (end.offset - start.offset);
+    Rgba {
+        red: start.rgba.red + (end.rgba.red - start.rgba.red) * t,
+        green: start.rgba.green + (end.rgba.green - start.rgba.green) * t,
+        blue: start.rgba.blue + (end.rgba.blue - start.rgba.blue) * t,
+        alpha: start.rgba.alpha + (end.rgba.alpha - start.rgba.alpha) * t,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pattern, PatternType, Extend, Filter};
+    use status::Status;
+    use matrix::Matrix;
+    use types::Rgba;
+    use surfaces::{ImageSurface, Format};
+
+    #[test]
+    fn solid_pattern_is_premultiplied_everywhere() {
+        let pattern = Pattern::create_rgba(1., 0.5, 0., 0.5);
+        assert_eq!(pattern.sample(-100., 3.), Rgba::new(1., 0.5, 0., 0.5));
+        assert_eq!(pattern.sample(7., 1e6), Rgba::new(1., 0.5, 0., 0.5));
+        assert_eq!(Pattern::create_rgb(2., 0., 0.).sample(0., 0.), Rgba::new(1., 0., 0., 1.));
+    }
+
+    #[test]
+    fn pattern_getters() {
+        let solid = Pattern::create_rgba(1., 0.5, 0., 0.5);
+        assert_eq!(solid.get_type(), PatternType::Solid);
+        assert_eq!(solid.get_rgba(), Ok((1., 0.5, 0., 0.5)));
+        assert_eq!(solid.get_color_stop_count(), Err(Status::PatternTypeMismatch));
+        assert!(solid.get_surface().is_err());
+
+        let mut linear = Pattern::create_linear(1., 2., 3., 4.);
+        linear.add_color_stop_rgba(1., 0., 0., 1., 0.25).unwrap();
+        linear.add_color_stop_rgb(0.5, 1., 0., 0.).unwrap();
+        assert_eq!(linear.get_type(), PatternType::Linear);
+        assert_eq!(linear.get_linear_points(), Ok((1., 2., 3., 4.)));
+        assert_eq!(linear.get_color_stop_count(), Ok(2));
+        assert_eq!(linear.get_color_stop_rgba(0), Ok((0.5, 1., 0., 0., 1.)));
+        assert_eq!(linear.get_color_stop_rgba(1), Ok((1., 0., 0., 1., 0.25)));
+        assert_eq!(linear.get_color_stop_rgba(2), Err(Status::InvalidIndex));
+        assert_eq!(linear.get_rgba(), Err(Status::PatternTypeMismatch));
+
+        let radial = Pattern::create_radial(1., 2., 3., 4., 5., 6.);
+        assert_eq!(radial.get_type(), PatternType::Radial);
+        assert_eq!(radial.get_radial_circles(), Ok((1., 2., 3., 4., 5., 6.)));
+        assert_eq!(radial.get_linear_points(), Err(Status::PatternTypeMismatch));
+
+        let surface = ImageSurface::create(Format::A8, 3, 2);
+        let pattern = Pattern::create_for_surface(&surface);
+        assert_eq!(pattern.get_type(), PatternType::Surface);
+        assert_eq!(pattern.get_surface().unwrap().width, 3);
+        assert_eq!(Pattern::create_mesh().get_type(), PatternType::Mesh);
+    }
+
+    #[test]
+    fn surface_pattern_samples_pixels() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 2, 2);
+        *surface.get_mut(1, 0).unwrap() = Rgba::new(0., 1., 0., 1.);
+        let mut pattern = Pattern::create_for_surface(&surface);
+        pattern.set_filter(Filter::Nearest);
+
+        assert_eq!(pattern.sample(1.5, 0.5), Rgba::new(0., 1., 0., 1.));
+        assert_eq!(pattern.sample(1.99, 0.), Rgba::new(0., 1., 0., 1.));
+        assert_eq!(pattern.sample(0.5, 0.5), Rgba::new(0., 0., 0., 0.));
+        // Outside of the surface
+        assert_eq!(pattern.sample(2.5, 0.5).alpha, 0.);
+        assert_eq!(pattern.sample(-0.5, 0.5).alpha, 0.);
+    }
+
+    #[test]
+    fn surface_pattern_keeps_its_own_copy() {
+        let mut surface = ImageSurface::create(Format::RGBA128F, 1, 1);
+        let pattern = Pattern::create_for_surface(&surface);
+        *surface.get_mut(0, 0).unwrap() = Rgba::new(1., 1., 1., 1.);
+        assert_eq!(pattern.sample(0.5, 0.5).alpha, 0.);
+    }
+
+    #[test]
+    fn pattern_matrix() {
+        let mut pattern = Pattern::create_rgb(0., 0., 0.);
+        assert_eq!(pattern.get_matrix(), Matrix::init_identity());
+        pattern.set_matrix(Matrix::init_translate(-3., -4.)).unwrap();
+        assert_eq!(pattern.get_matrix().transform_point(3., 4.), (0., 0.));
+
+        assert_eq!(pattern.set_matrix(Matrix::init_scale(0., 1.)), Err(Status::InvalidMatrix));
+        assert_eq!(pattern.get_matrix(), Matrix::init_translate(-3., -4.));
+    }
+
+    fn black_to_white(mut pattern: Pattern) -> Pattern {
+        pattern.add_color_stop_rgb(0., 0., 0., 0.).unwrap();
+        pattern.add_color_stop_rgb(1., 1., 1., 1.).unwrap();
+        pattern
+    }
+
+    fn assert_close(actual: Rgba, expected: Rgba) {
+        assert_within(actual, expected, 1e-4);
+    }
+
+    fn assert_within(actual: Rgba, expected: Rgba, tolerance: f32) {
+        assert!((actual.red - expected.red).abs() < tolerance &&
+                (actual.green - expected.green).abs() < tolerance &&
+                (actual.blue - expected.blue).abs() < tolerance &&
+                (actual.alpha - expected.alpha).abs() < tolerance,
+                "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn linear_gradient_projects_onto_its_line() {
+        let pattern = black_to_white(Pattern::create_linear(10., 0., 20., 0.));
+
+        assert_eq!(pattern.sample(10., 0.), Rgba::new(0., 0., 0., 1.));
+        assert_eq!(pattern.sample(15., 100.), Rgba::new(0.5, 0.5, 0.5, 1.));
+        assert_eq!(pattern.sample(20., -3.), Rgba::new(1., 1., 1., 1.));
+        // Beyond the ends the end colors continue
+        assert_eq!(pattern.sample(0., 0.), Rgba::new(0., 0., 0., 1.));
+        assert_eq!(pattern.sample(30., 0.), Rgba::new(1., 1., 1., 1.));
+    }
+
+    #[test]
+    fn concentric_radial_gradient() {
+        let pattern = black_to_white(Pattern::create_radial(0., 0., 2., 0., 0., 6.));
+
+        assert_eq!(pattern.sample(0., 1.), Rgba::new(0., 0., 0., 1.));
+        assert_eq!(pattern.sample(4., 0.), Rgba::new(0.5, 0.5, 0.5, 1.));
+        assert_eq!(pattern.sample(0., -6.), Rgba::new(1., 1., 1., 1.));
+        assert_eq!(pattern.sample(10., 0.), Rgba::new(1., 1., 1., 1.));
+    }
+
+    #[test]
+    fn focal_radial_gradient() {
+        // A point of focus at (2, 0) inside a circle of radius 4 around the origin
+        let pattern = black_to_white(Pattern::create_radial(2., 0., 0., 0., 0., 4.));
+
+        assert_close(pattern.sample(2., 0.), Rgba::new(0., 0., 0., 1.));
+        // The circle halfway has radius 2 around (1, 0)
+        assert_close(pattern.sample(3., 0.), Rgba::new(0.5, 0.5, 0.5, 1.));
+        assert_close(pattern.sample(-1., 0.), Rgba::new(0.5, 0.5, 0.5, 1.));
+        assert_close(pattern.sample(1., 2.), Rgba::new(0.5, 0.5, 0.5, 1.));
+        assert_close(pattern.sample(-4., 0.), Rgba::new(1., 1., 1., 1.));
+        assert_close(pattern.sample(4., 0.), Rgba::new(1., 1., 1., 1.));
+    }
+
+    #[test]
+    fn radial_gradient_outside_its_cone() {
+        // Two circles side by side sweep out a cone, and nothing is painted outside of it
+        let mut pattern = black_to_white(Pattern::create_radial(0., 0., 1., 10., 0., 2.));
+        pattern.set_extend(Extend::None);
+
+        assert_close(pattern.sample(-1., 0.), Rgba::new(0., 0., 0., 1.));
+        // (5, 0) lies on the circles at offsets 4/11 and 2/3, and the later one is on top
+        assert_close(pattern.sample(5., 0.), Rgba::new(2. / 3., 2. / 3., 2. / 3., 1.));
+        assert_close(pattern.sample(10., 2.), Rgba::new(1., 1., 1., 1.));
+        assert_eq!(pattern.sample(5., 10.).alpha, 0.);
+        assert_eq!(pattern.sample(-5., 0.).alpha, 0.);
+        assert_eq!(pattern.sample(15., 0.).alpha, 0.);
+
+        // Padded, the circles continue beyond the end
+        pattern.set_extend(Extend::Pad);
+        assert_close(pattern.sample(15., 0.), Rgba::new(1., 1., 1., 1.));
+    }
+
+    #[test]
+    fn gradient_interpolates_premultiplied_colors() {
+        let mut pattern = Pattern::create_linear(0., 0., 4., 0.);
+        pattern.add_color_stop_rgba(0., 1., 0., 0., 1.).unwrap();
+        pattern.add_color_stop_rgba(0.5, 0., 0., 1., 0.).unwrap();
+        pattern.add_color_stop_rgba(1., 0., 1., 0., 1.).unwrap();
+
+        // Halfway to the transparent stop the color is still pure red, only fainter
+        assert_eq!(pattern.sample(1., 0.), Rgba::new(1., 0., 0., 0.5));
+        assert_eq!(pattern.sample(2., 0.), Rgba::new(0., 0., 0., 0.));
+        assert_eq!(pattern.sample(3., 0.), Rgba::new(0., 1., 0., 0.5));
+    }
+
+    #[test]
+    fn gradient_without_stops_is_transparent() {
+        let pattern = Pattern::create_linear(0., 0., 1., 0.);
+        assert_eq!(pattern.sample(0.5, 0.), Rgba::new(0., 0., 0., 0.));
+    }
+
+    #[test]
+    fn gradient_with_non_finite_offsets_is_transparent() {
+        // The length of this gradient overflows, which used to panic looking for the stops
+        for &extend in [Extend::None, Extend::Repeat, Extend::Reflect, Extend::Pad].iter() {
+            let mut pattern = black_to_white(Pattern::create_linear(-1e30, 0., 1e30, 0.));
+            pattern.set_extend(extend);
+            assert_eq!(pattern.sample(0.5, 0.), Rgba::new(0., 0., 0., 0.));
+
+            let mut pattern = black_to_white(Pattern::create_linear(f32::NAN, 0., 1., 0.));
+            pattern.set_extend(extend);
+            assert_eq!(pattern.sample(0.5, 0.), Rgba::new(0., 0., 0., 0.));
+        }
+    }
+
+    #[test]
+    fn color_stops_are_sorted() {
+        let mut pattern = Pattern::create_linear(0., 0., 10., 0.);
+        pattern.add_color_stop_rgb(1., 0., 0., 1.).unwrap();
+        pattern.add_color_stop_rgb(0., 1., 0., 0.).unwrap();
+        // Two stops at the same offset give a sharp edge, in the order they were added
+        pattern.add_color_stop_rgb(0.5, 1., 0., 0.).unwrap();
+        pattern.add_color_stop_rgb(0.5, 0., 1., 0.).unwrap();
+        // Offsets are clamped
+        pattern.add_color_stop_rgb(7., 1., 1., 1.).unwrap();
+
+        assert_eq!(pattern.sample(4.9, 0.), Rgba::new(1., 0., 0., 1.));
+        assert_eq!(pattern.sample(5., 0.), Rgba::new(0., 1., 0., 1.));
+        assert_close(pattern.sample(7.5, 0.), Rgba::new(0., 0.5, 0.5, 1.));
+        assert_eq!(pattern.sample(10., 0.), Rgba::new(1., 1., 1., 1.));
+    }
+
+    #[test]
+    fn color_stops_need_a_gradient() {
+        let mut pattern = Pattern::create_rgb(0., 0., 0.);
+        assert_eq!(pattern.add_color_stop_rgb(0., 1., 1., 1.), Err(Status::PatternTypeMismatch));
+        let mut pattern = Pattern::create_for_surface(&ImageSurface::create(Format::RGBA128F, 1, 1));
+        assert_eq!(pattern.add_color_stop_rgba(0., 1., 1., 1., 1.),
+                   Err(Status::PatternTypeMismatch));
+    }
+
+    #[test]
+    fn default_extend() {
+        assert_eq!(Pattern::create_linear(0., 0., 1., 1.).get_extend(), Extend::Pad);
+        assert_eq!(Pattern::create_radial(0., 0., 0., 0., 0., 1.).get_extend(), Extend::Pad);
+        assert_eq!(Pattern::create_for_surface(&ImageSurface::create(Format::RGBA128F, 1, 1)).get_extend(),
+                   Extend::None);
+    }
+
+    #[test]
+    fn gradient_extend() {
+        let mut pattern = black_to_white(Pattern::create_linear(0., 0., 4., 0.));
+
+        pattern.set_extend(Extend::None);
+        assert_eq!(pattern.sample(-1., 0.).alpha, 0.);
+        assert_eq!(pattern.sample(5., 0.).alpha, 0.);
+        assert_eq!(pattern.sample(1., 0.), Rgba::new(0.25, 0.25, 0.25, 1.));
+
+        pattern.set_extend(Extend::Repeat);
+        assert_eq!(pattern.sample(5., 0.), Rgba::new(0.25, 0.25, 0.25, 1.));
+        assert_eq!(pattern.sample(-1., 0.), Rgba::new(0.75, 0.75, 0.75, 1.));
+
+        pattern.set_extend(Extend::Reflect);
+        assert_eq!(pattern.sample(5., 0.), Rgba::new(0.75, 0.75, 0.75, 1.));
+        assert_eq!(pattern.sample(-1., 0.), Rgba::new(0.25, 0.25, 0.25, 1.));
+        assert_eq!(pattern.sample(9., 0.), Rgba::new(0.25, 0.25, 0.25, 1.));
+
+        pattern.set_extend(Extend::Pad);
+        assert_eq!(pattern.sample(-1., 0.), Rgba::new(0., 0., 0., 1.));
+        assert_eq!(pattern.sample(5., 0.), Rgba::new(1., 1., 1., 1.));
+    }
+
+    // Returns a mesh pattern with one square patch from (0, 0) to (size, size), with corners
+    // red, green, blue and white going clockwise.
+    fn square_mesh(size: f32) -> Pattern {
+        let mut pattern = Pattern::create_mesh();
+        pattern.begin_patch().unwrap();
+        pattern.move_to(0., 0.).unwrap();
+        pattern.line_to(size, 0.).unwrap();
+        pattern.line_to(size, size).unwrap();
+        pattern.line_to(0., size).unwrap();
+        pattern.set_corner_color_rgb(0, 1., 0., 0.).unwrap();
+        pattern.set_corner_color_rgb(1, 0., 1., 0.).unwrap();
+        pattern.set_corner_color_rgb(2, 0., 0., 1.).unwrap();
+        pattern.set_corner_color_rgb(3, 1., 1., 1.).unwrap();
+        pattern.end_patch().unwrap();
+        pattern
+    }
+
+    #[test]
+    fn mesh_interpolates_corner_colors() {
+        let pattern = square_mesh(10.);
+        assert_eq!(pattern.get_patch_count(), Ok(1));
+
+        // Near the corners.  The mesh is drawn as triangles, which only approximate the
+        // bilinear interpolation between the corners.
+        let pixels = pattern.render(&Matrix::init_identity(), 12, 12);
+        assert_within(pixels[0], Rgba::new(0.95, 0.095, 0.05, 1.), 0.01);
+        assert_within(pixels[9], Rgba::new(0.05, 0.905, 0.05, 1.), 0.01);
+        assert_within(pixels[9 * 12 + 9], Rgba::new(0.05, 0.095, 0.95, 1.), 0.01);
+        assert_within(pixels[9 * 12], Rgba::new(0.95, 0.905, 0.95, 1.), 0.01);
+        // The middle is the average of the corners
+        assert_close(pattern.sample(5., 5.), Rgba::new(0.5, 0.5, 0.5, 1.));
+        // Outside of the patch
+        assert_eq!(pixels[10].alpha, 0.);
+        assert_eq!(pixels[10 * 12].alpha, 0.);
+    }
+
+    #[test]
+    fn sample_pixel_matches_render() {
+        let device_to_pattern = Matrix::init_scale(0.2, 0.3);
+        let mut gradient = black_to_white(Pattern::create_linear(0., 0., 1., 1.));
+        gradient.set_extend(Extend::Reflect);
+        for pattern in [square_mesh(1.), gradient].iter() {
+            let pixels = pattern.render(&device_to_pattern, 6, 5);
+            for y in 0..5 {
+                for x in 0..6 {
+                    // The mesh is drawn shifted to the pixel, which rounds a little differently
+                    assert_within(pattern.sample_pixel(&device_to_pattern, x, y), pixels[y * 6 + x],
+                                  1e-5);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mesh_follows_the_pattern_matrix() {
+        let pattern = square_mesh(1.);
+        // Device pixels are a fifth of a unit of pattern space
+        let pixels = pattern.render(&Matrix::init_scale(0.2, 0.2), 6, 6);
+        assert_within(pixels[2 * 6 + 2], Rgba::new(0.5, 0.5, 0.5, 1.), 0.02);
+        assert_eq!(pixels[5 * 6 + 2].alpha, 0.);
+    }
+
+    #[test]
+    fn mesh_patch_with_curved_sides() {
+        // A patch whose top side bulges upwards
+        let mut pattern = Pattern::create_mesh();
+        pattern.begin_patch().unwrap();
+        pattern.move_to(0., 10.).unwrap();
+        pattern.curve_to(0., 0., 10., 0., 10., 10.).unwrap();
+        pattern.line_to(10., 20.).unwrap();
+        pattern.line_to(0., 20.).unwrap();
+        for corner in 0..4 {
+            pattern.set_corner_color_rgb(corner, 0., 0., 1.).unwrap();
+        }
+        pattern.end_patch().unwrap();
+
+        // The top of the bulge is at y = 2.5
+        assert_close(pattern.sample(5., 3.), Rgba::new(0., 0., 1., 1.));
+        assert_eq!(pattern.sample(5., 2.).alpha, 0.);
+        assert_eq!(pattern.sample(0.5, 5.).alpha, 0.);
+    }
+
+    #[test]
+    fn mesh_patch_closes_itself() {
+        // Three sides, the fourth is a line back to the start, which makes a triangle
+        let mut pattern = Pattern::create_mesh();
+        pattern.begin_patch().unwrap();
+        pattern.line_to(0., 0.).unwrap();
+        pattern.line_to(10., 0.).unwrap();
+        pattern.line_to(0., 10.).unwrap();
+        pattern.set_corner_color_rgb(0, 1., 0., 0.).unwrap();
+        pattern.set_corner_color_rgb(1, 1., 0., 0.).unwrap();
+        pattern.set_corner_color_rgb(2, 1., 0., 0.).unwrap();
+        pattern.set_corner_color_rgb(3, 1., 0., 0.).unwrap();
+        pattern.end_patch().unwrap();
+
+        assert_close(pattern.sample(2., 2.), Rgba::new(1., 0., 0., 1.));
+        assert_eq!(pattern.sample(8., 8.).alpha, 0.);
+    }
+
+    #[test]
+    fn mesh_control_points_move_colors() {
+        let mut pattern = Pattern::create_mesh();
+        pattern.begin_patch().unwrap();
+        pattern.move_to(0., 0.).unwrap();
+        pattern.line_to(9., 0.).unwrap();
+        pattern.line_to(9., 9.).unwrap();
+        pattern.line_to(0., 9.).unwrap();
+        pattern.set_corner_color_rgb(0, 1., 1., 1.).unwrap();
+        // Pulling the interior control points towards corner 2 drags the white along
+        pattern.set_control_point(0, 9., 9.).unwrap();
+        pattern.set_control_point(1, 9., 9.).unwrap();
+        pattern.set_control_point(3, 9., 9.).unwrap();
+        pattern.end_patch().unwrap();
+        let plain = square_mesh(9.);
+
+        assert!(pattern.sample(6., 6.).alpha > 0.);
+        assert_eq!(plain.sample(6., 6.).alpha, 1.);
+        assert!(pattern.sample(6., 6.).red > 0.2);
+    }
+
+    #[test]
+    fn later_patches_are_on_top() {
+        let mut pattern = square_mesh(10.);
+        pattern.begin_patch().unwrap();
+        pattern.move_to(5., 5.).unwrap();
+        pattern.line_to(15., 5.).unwrap();
+        pattern.line_to(15., 15.).unwrap();
+        pattern.line_to(5., 15.).unwrap();
+        for corner in 0..4 {
+            pattern.set_corner_color_rgba(corner, 0., 0., 0., 0.5).unwrap();
+        }
+        pattern.end_patch().unwrap();
+
+        assert_eq!(pattern.get_patch_count(), Ok(2));
+        assert_close(pattern.sample(7., 7.), Rgba::new(0., 0., 0., 0.5));
+        assert_within(pattern.sample(2., 2.), Rgba::new(0.8, 0.32, 0.2, 1.), 0.01);
+    }
+
+    #[test]
+    fn mesh_construction_errors() {
+        let mut pattern = Pattern::create_mesh();
+        assert_eq!(pattern.move_to(0., 0.), Err(Status::InvalidMeshConstruction));
+        assert_eq!(pattern.end_patch(), Err(Status::InvalidMeshConstruction));
+        assert_eq!(pattern.set_corner_color_rgb(0, 1., 1., 1.),
+                   Err(Status::InvalidMeshConstruction));
+
+        pattern.begin_patch().unwrap();
+        assert_eq!(pattern.begin_patch(), Err(Status::InvalidMeshConstruction));
+        assert_eq!(pattern.set_control_point(4, 0., 0.), Err(Status::InvalidIndex));
+        assert_eq!(pattern.set_corner_color_rgb(4, 0., 0., 0.), Err(Status::InvalidIndex));
+        // A patch needs at least a point
+        assert_eq!(pattern.end_patch(), Err(Status::InvalidMeshConstruction));
+
+        pattern.begin_patch().unwrap();
+        pattern.move_to(0., 0.).unwrap();
+        assert_eq!(pattern.move_to(1., 1.), Err(Status::InvalidMeshConstruction));
+        for _ in 0..4 {
+            pattern.line_to(1., 1.).unwrap();
+        }
+        assert_eq!(pattern.line_to(2., 2.), Err(Status::InvalidMeshConstruction));
+        pattern.end_patch().unwrap();
+        assert_eq!(pattern.get_patch_count(), Ok(1));
+
+        let mut gradient = Pattern::create_linear(0., 0., 1., 1.);
+        assert_eq!(gradient.begin_patch(), Err(Status::PatternTypeMismatch));
+        assert_eq!(gradient.get_patch_count(), Err(Status::PatternTypeMismatch));
+    }
+
+    // Returns a surface `width` pixels wide and one high, with a white pixel every `period`.
+    fn stripes(width: usize, period: usize) -> ImageSurface<'static> {
+        let mut surface = ImageSurface::create(Format::RGBA128F, width, 1);
+        for x in (0..width).filter(|x| x % period == 0) {
+            *surface.get_mut(x, 0).unwrap() = Rgba::new(1., 1., 1., 1.);
+        }
+        surface
+    }
+
+    #[test]
+    fn default_filter() {
+        assert_eq!(Pattern::create_for_surface(&stripes(1, 1)).get_filter(), Filter::Good);
+    }
+
+    #[test]
+    fn nearest_and_bilinear_filters() {
+        let mut pattern = Pattern::create_for_surface(&stripes(4, 2));
+
+        pattern.set_filter(Filter::Fast);
+        assert_eq!(pattern.sample(0.9, 0.5), Rgba::new(1., 1., 1., 1.));
+        assert_eq!(pattern.sample(1.1, 0.5), Rgba::new(0., 0., 0., 0.));
+
+        pattern.set_filter(Filter::Bilinear);
+        // At pixel centers the pixels come through unchanged, between them they are mixed
+        assert_eq!(pattern.sample(0.5, 0.5), Rgba::new(1., 1., 1., 1.));
+        assert_eq!(pattern.sample(1., 0.5), Rgba::new(1., 1., 1., 0.5));
+        assert_eq!(pattern.sample(1.25, 0.5), Rgba::new(1., 1., 1., 0.25));
+        // The edges fade into the transparency outside
+        assert_eq!(pattern.sample(0.25, 0.5), Rgba::new(1., 1., 1., 0.75));
+        assert_eq!(pattern.sample(0.5, 0.).alpha, 0.5);
+    }
+
+    #[test]
+    fn good_filter_averages_when_shrinking() {
+        // Every fourth pixel is white, so shrinking four times should give a quarter of white
+        let mut pattern = Pattern::create_for_surface(&stripes(32, 4));
+        let shrink = Matrix::init_scale(4., 1.);
+
+        for pixel in &pattern.render(&shrink, 8, 1) {
+            assert_close(*pixel, Rgba::new(1., 1., 1., 0.25));
+        }
+
+        // Bilinear filtering lands between pixels and skips the white ones
+        pattern.set_filter(Filter::Bilinear);
+        for pixel in &pattern.render(&shrink, 8, 1) {
+            assert_eq!(pixel.alpha, 0.);
+        }
+    }
+
+    #[test]
+    fn good_filter_interpolates_when_enlarging() {
+        let pattern = Pattern::create_for_surface(&stripes(2, 2));
+        // Device pixels a quarter of a surface pixel wide, the first white surface pixel ends
+        // between device pixels 3 and 4
+        let pixels = pattern.render(&Matrix::init_scale(0.25, 1.), 8, 1);
+        assert_close(pixels[2], Rgba::new(1., 1., 1., 0.875));
+        assert_close(pixels[4], Rgba::new(1., 1., 1., 0.375));
+        assert_close(pixels[5], Rgba::new(1., 1., 1., 0.125));
+        assert_eq!(pixels[6].alpha, 0.);
+    }
+
+    #[test]
+    fn gaussian_filter() {
+        let mut pattern = Pattern::create_for_surface(&stripes(9, 9));
+        pattern.set_filter(Filter::Gaussian);
+
+        // The white pixel spreads out, fading with distance
+        let center = pattern.sample(0.5, 0.5);
+        let next = pattern.sample(1.5, 0.5);
+        assert!(center.alpha < 1. && center.alpha > next.alpha && next.alpha > 0.);
+        assert_eq!(pattern.sample(2.5, 0.5).alpha, 0.);
+
+        // Shrinking widens it, still averaging stripes to gray
+        let mut surface = ImageSurface::create(Format::RGBA128F, 64, 8);
+        for y in 0..8 {
+            for x in (0..64).filter(|x| x % 2 == 0) {
+                *surface.get_mut(x, y).unwrap() = Rgba::new(1., 1., 1., 1.);
+            }
+        }
+        let mut pattern = Pattern::create_for_surface(&surface);
+        pattern.set_filter(Filter::Gaussian);
+        let pixels = pattern.render(&Matrix::init_scale(4., 1.), 16, 8);
+        assert_within(pixels[4 * 16 + 8], Rgba::new(1., 1., 1., 0.5), 1e-3);
+    }
+
+    #[test]
+    fn best_filter_is_lanczos() {
+        let mut pattern = Pattern::create_for_surface(&stripes(9, 9));
+        pattern.set_filter(Filter::Best);
+
+        // Unlike Gaussian, the kernel is zero at the other pixel centers, so sampling at a pixel
+        // center gives exactly that pixel
+        assert_eq!(pattern.sample(0.5, 0.5), Rgba::new(1., 1., 1., 1.));
+        assert_eq!(pattern.sample(1.5, 0.5).alpha, 0.);
+        // Between pixel centers its negative lobes reach past the nearest pixels
+        let between = pattern.sample(2., 0.5);
+        assert!(between.alpha == 0. && pattern.sample(1., 0.5).alpha > 0.5);
+        pattern.set_filter(Filter::Gaussian);
+        assert!(pattern.sample(0.5, 0.5).alpha < 1.);
+
+        // Shrinking widens it, still averaging stripes to gray
+        let mut surface = ImageSurface::create(Format::RGBA128F, 64, 8);
+        for y in 0..8 {
+            for x in (0..64).filter(|x| x % 2 == 0) {
+                *surface.get_mut(x, y).unwrap() = Rgba::new(1., 1., 1., 1.);
+            }
+        }
+        let mut pattern = Pattern::create_for_surface(&surface);
+        pattern.set_filter(Filter::Best);
+        let pixels = pattern.render(&Matrix::init_scale(4., 1.), 16, 8);
+        assert_within(pixels[4 * 16 + 8], Rgba::new(1., 1., 1., 0.5), 1e-2);
+    }
+
+    #[test]
+    fn surface_extend() {
+        // Pixels 0, 1 and 2 have alphas 0.25, 0.5 and 1
+        let mut surface = ImageSurface::create(Format::RGBA128F, 3, 1);
+        *surface.get_mut(0, 0).unwrap() = Rgba::new(1., 1., 1., 0.25);
+        *surface.get_mut(1, 0).unwrap() = Rgba::new(1., 1., 1., 0.5);
+        *surface.get_mut(2, 0).unwrap() = Rgba::new(1., 1., 1., 1.);
+        let mut pattern = Pattern::create_for_surface(&surface);
+        pattern.set_filter(Filter::Nearest);
+        let alphas = |pattern: &Pattern| {
+            (-4..7).map(|x| pattern.sample(x as f32 + 0.5, 0.5).alpha).collect::<Vec<_>>()
+        };
+
+        assert_eq!(alphas(&pattern),
+                   vec![0., 0., 0., 0., 0.25, 0.5, 1., 0., 0., 0., 0.]);
+        pattern.set_extend(Extend::Repeat);
+        assert_eq!(alphas(&pattern),
+                   vec![1., 0.25, 0.5, 1., 0.25, 0.5, 1., 0.25, 0.5, 1., 0.25]);
+        pattern.set_extend(Extend::Reflect);
+        assert_eq!(alphas(&pattern),
+                   vec![1., 1., 0.5, 0.25, 0.25, 0.5, 1., 1., 0.5, 0.25, 0.25]);
+        pattern.set_extend(Extend::Pad);
+        assert_eq!(alphas(&pattern),
+                   vec![0.25, 0.25, 0.25, 0.25, 0.25, 0.5, 1., 1., 1., 1., 1.]);
+        // Rows extend the same way
+        assert_eq!(pattern.sample(2.5, -10.).alpha, 1.);
+    }
+
+    #[test]
+    fn repeated_surface_filters_across_the_seam() {
+        let mut pattern = Pattern::create_for_surface(&stripes(2, 2));
+        pattern.set_extend(Extend::Repeat);
+        // Halfway between the last pixel of one copy and the first of the next
+        assert_eq!(pattern.sample(2., 0.5), Rgba::new(1., 1., 1., 0.5));
+        assert_eq!(pattern.sample(-1.5, 7.5), Rgba::new(1., 1., 1., 1.));
+    }
+}
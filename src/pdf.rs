@@ -0,0 +1,1697 @@
+/*
+ * Cairus - a reimplementation of the cairo graphics library in Rust
+ *
+ * Copyright © 2017 CairusOrg
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it either under the terms of the GNU Lesser General Public
+ * License version 2.1 as published by the Free Software Foundation
+ * (the "LGPL") or, at your option, under the terms of the Mozilla
+ * Public License Version 2.0 (the "MPL"). If you do not alter this
+ * notice, a recipient may use your version of this file under either
+ * the MPL or the LGPL.
+ *
+ * You should have received a copy of the LGPL along with this library
+ * in the file LICENSE-LGPL-2_1; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Suite 500, Boston, MA 02110-1335, USA
+ * You should have received a copy of the MPL along with this library
+ * in the file LICENSE-MPL-2_0
+ *
+ * The contents of this file are subject to the Mozilla Public License
+ * Version 2.0 (the "License"); you may not use this file except in
+ * compliance with the License. You may obtain a copy of the License at
+ * http://www.mozilla.org/MPL/
+ *
+ * This software is distributed on an "AS IS" basis, WITHOUT WARRANTY
+ * OF ANY KIND, either express or implied. See the LGPL or the MPL for
+ * the specific language governing rights and limitations.
+ *
+ * The Original Code is the cairus graphics library.
+ *
+ */
+
+//! # Overview
+//! A PdfSurface records what a context draws on it as vector graphics, and writes it out as a
+//! PDF 1.4 document of one or more pages, analogous to cairo's PDF surface.
+//!
+//! Every content stream starts by flipping the page, so that drawing happens in the same device
+//! space as on an image surface: points from the top left corner, with y growing downwards.
+//! Fills and clips are written with the device space points they were drawn with, and strokes in
+//! the user space they were drawn in, so that the line style is measured there.
+//!
+//! Solid colors become PDF colors, gradients become shadings, and surface patterns become tiling
+//! patterns of the surface as an image.  Transparent parts of gradients and images are drawn
+//! through soft masks.  Mesh patterns, and masks that aren't a solid color, are rendered into an
+//! image one pixel per point.  Images and content streams are compressed.
+//!
+//! PDF can blend like the blend mode operators, but of the Porter-Duff operators it only draws
+//! like `Operator::Over`.  `Operator::Dest` doesn't draw anything.  `Operator::Source` and
+//! `Operator::Clear` cut the shape they draw out of the page drawn so far with a soft mask, and
+//! `Operator::Source` then draws the source over it, so that where they draw they replace the
+//! page, with the source or with nothing.  A partly transparent shape drawn with a partly
+//! transparent pattern with `Operator::Source` leaves too little of the page under it.  The
+//! other Porter-Duff operators are rasterized: once one of them is used on a page, the context
+//! rasterizes what was drawn on the page into an image, one pixel per point, and the area every
+//! such operator changes is replaced with that image.  The image is only 72 pixels per inch, so
+//! rasterized drawing looks coarser than the rest of the page.  Surface patterns that extend
+//! with `Extend::Pad` aren't extended.
+
+extern crate deflate;
+
+use std::fs::File;
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path;
+use context::{FillRule, LineCap, LineJoin, Fallback};
+use matrix::Matrix;
+use operators::Operator;
+use path::{Path, PathSegment};
+use patterns::{Pattern, PatternType, Extend, Filter};
+use status::Status;
+use stroker::StrokeStyle;
+use surfaces::{ImageSurface, Format};
+use types::{ChannelOrder, AlphaMode};
+
+// The objects every document has, whose numbers pages refer to before they are written
+const CATALOG: usize = 1;
+const PAGE_TREE: usize = 2;
+
+// The largest width or height of a page in points, which PDF viewers can show
+const MAX_PAGE_SIZE: f32 = 14400.;
+
+/// A surface that records drawing as vector graphics and writes it to a PDF document, one page
+/// at a time.
+///
+/// Each page is written when it is shown, and the rest of the document when the surface is
+/// finished.  A surface that is dropped without being finished is finished then, but any error
+/// doing so is lost.
+pub struct PdfSurface<'w> {
+    /// The width of every page, in points.
+    pub width: f32,
+    /// The height of every page, in points.
+    pub height: f32,
+    writer: Box<dyn Write + 'w>,
+    // How much has been written, which is where the next object starts
+    written: usize,
+    // Where every object starts, by its number less one, once it has been written
+    offsets: Vec<Option<usize>>,
+    // The object numbers of the pages written so far
+    pages: Vec<usize>,
+    // The page being drawn
+    page: Page,
+    // The clip paths drawing can be restricted to
+    clips: Vec<ClipPath>,
+    // The drawing on the page, which rasterized drawing is taken from
+    fallback: Fallback,
+    // The error writing stopped at, which every later write returns
+    error: Option<Status>,
+    finished: bool,
+}
+
+// The drawing and resources of the page being drawn.
+struct Page {
+    // The object number of the resources, which the page and its forms and patterns share
+    resources: usize,
+    // What was drawn since the page was last cut out of
+    content: String,
+    // What was drawn before each cut
+    parts: Vec<String>,
+    // The operators drawing each shape cut out of the page in black, with the opacity it is cut
+    // out by
+    cuts: Vec<String>,
+    ext_g_states: Vec<String>,
+    patterns: Vec<usize>,
+    x_objects: Vec<usize>,
+    shadings: Vec<usize>,
+    // Objects the page refers to, which are written along with it
+    objects: Vec<(usize, Vec<u8>)>,
+}
+
+// An area drawing is restricted to, within the clip path `parent` if there is one.
+struct ClipPath {
+    operators: String,
+    fill_rule: FillRule,
+    parent: Option<usize>,
+}
+
+// What an element draws.
+enum Shape<'s> {
+    // The area inside a path in device space
+    Fill(String, FillRule),
+    // A stroke along a path in user space, with the matrix mapping user space to device space
+    Stroke(String, Matrix, &'s StrokeStyle),
+    // The whole page
+    Page,
+}
+
+// How the source is painted.
+enum Color {
+    // A solid color and its alpha
+    Rgb(f32, f32, f32, f32),
+    // The name of a pattern, and a soft mask form with the alpha of the pattern, if it has one
+    Pattern(String, Option<usize>),
+}
+
+impl<'w> PdfSurface<'w> {
+    /// Returns a surface with pages `width` by `height` points large, which writes to the file at
+    /// `path`.  Like an image surface, a point of user space is a unit of device space until the
+    /// context is transformed.
+    ///
+    /// Returns Status::InvalidSize if either dimension isn't a positive number of at most 14400
+    /// points, Status::FileNotFound if the directory of `path` doesn't exist, and
+    /// Status::WriteError if the file can't be written.
+    pub fn create(path: &path::Path, width: f32, height: f32)
+                  -> Result<PdfSurface<'static>, Status> {
+        check_size(width, height)?;
+        let file = match File::create(path) {
+            Ok(file) => file,
+            Err(ref error) if error.kind() == ErrorKind::NotFound => {
+                return Err(Status::FileNotFound)
+            },
+            Err(_) => return Err(Status::WriteError),
+        };
+        PdfSurface::create_for_stream(BufWriter::new(file), width, height)
+    }
+
+    /// Returns a surface like `create`, which writes to `writer` instead of a file.
+    ///
+    /// Returns Status::InvalidSize if either dimension isn't a positive number of at most 14400
+    /// points, and Status::WriteError if `writer` fails.
+    pub fn create_for_stream<W: Write + 'w>(writer: W, width: f32, height: f32)
+                                            -> Result<PdfSurface<'w>, Status> {
+        check_size(width, height)?;
+        let mut surface = PdfSurface {
+            width: width,
+            height: height,
+            writer: Box::new(writer),
+            written: 0,
+            offsets: vec![None; PAGE_TREE],
+            pages: Vec::new(),
+            page: Page::new(0),
+            clips: Vec::new(),
+            fallback: Fallback::new(width.ceil() as usize, height.ceil() as usize),
+            error: None,
+            finished: false,
+        };
+        surface.page.resources = surface.reserve();
+        // The binary comment tells programs that the file isn't text
+        surface.write(b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n")?;
+        Ok(surface)
+    }
+
+    /// Writes the page drawn so far and starts a new, empty one.  The clip and the rest of the
+    /// drawing state carry over to the new page.
+    ///
+    /// Returns Status::SurfaceFinished if the surface has been finished, and Status::WriteError
+    /// if writing fails.
+    pub fn show_page(&mut self) -> Result<(), Status> {
+        if self.finished {
+            return Err(Status::SurfaceFinished);
+        }
+        self.apply_cuts();
+        let resources = self.reserve();
+        let page = ::std::mem::replace(&mut self.page, Page::new(resources));
+        let (width, height) = self.pixel_size();
+        self.fallback = Fallback::new(width, height);
+        self.write_page(page)
+    }
+
+    /// Writes the page being drawn, if anything was drawn on it or no page was shown before, and
+    /// then the rest of the document.  Drawing on a finished surface does nothing.
+    ///
+    /// Returns Status::WriteError if writing fails.  Finishing a surface again does nothing.
+    pub fn finish(&mut self) -> Result<(), Status> {
+        if self.finished {
+            return self.error.map_or(Ok(()), Err);
+        }
+        self.finished = true;
+        self.apply_cuts();
+        if !self.page.content.is_empty() || self.pages.is_empty() {
+            let page = ::std::mem::replace(&mut self.page, Page::new(0));
+            self.write_page(page)?;
+        }
+
+        let kids = self.pages.iter().map(|page| format!("{} 0 R", page)).collect::<Vec<_>>();
+        let page_tree = format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "),
+                                self.pages.len());
+        self.write_object(PAGE_TREE, page_tree.as_bytes())?;
+        self.write_object(CATALOG, format!("<< /Type /Catalog /Pages {} 0 R >>", PAGE_TREE)
+                          .as_bytes())?;
+
+        // Objects of a page that wasn't written are left empty
+        let unwritten = (1..self.offsets.len() + 1)
+            .filter(|number| self.offsets[number - 1].is_none())
+            .collect::<Vec<_>>();
+        for number in unwritten {
+            self.write_object(number, b"null")?;
+        }
+
+        // Every entry of the cross-reference table is 20 bytes long
+        let start = self.written;
+        let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in self.offsets.iter().flatten() {
+            trailer.push_str(&format!("{:010} 00000 n \n", offset));
+        }
+        trailer.push_str(&format!("trailer\n<< /Size {} /Root {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+                                  self.offsets.len() + 1, CATALOG, start));
+        self.write(trailer.as_bytes())?;
+        let flushed = self.writer.flush().map_err(|_| Status::WriteError);
+        flushed.map_err(|error| *self.error.get_or_insert(error))
+    }
+
+    // The size in pixels of the images that patterns PDF can't express are rendered into.
+    pub(crate) fn pixel_size(&self) -> (usize, usize) {
+        (self.width.ceil() as usize, self.height.ceil() as usize)
+    }
+
+    // The drawing on the page being drawn, which the context keeps up with, or None once the
+    // surface is finished.
+    pub(crate) fn fallback_mut(&mut self) -> Option<&mut Fallback> {
+        if self.finished {
+            None
+        } else {
+            Some(&mut self.fallback)
+        }
+    }
+
+    // Records replacing the drawing in `area`, which is whether each pixel of the page is in it,
+    // with the rasterized page.  This is how operators PDF has nothing like are drawn.
+    pub(crate) fn replace_area(&mut self, area: &[bool]) {
+        let page_image = match self.fallback.image() {
+            Some(image) if !self.finished => image.clone(),
+            _ => return,
+        };
+        let (width, height) = self.pixel_size();
+        let image = self.surface_image(&page_image, false);
+        let samples = area.iter().map(|&inside| if inside { 255 } else { 0 }).collect::<Vec<_>>();
+        let area_image = self.image(width, height, "/DeviceGray", &samples, None, false);
+        let content = format!("q {} cm /X{} Do Q\n", image_matrix(width, height), area_image);
+        let area_mask = self.form(&content, " /CS /DeviceGray");
+        // The area is cut out of the page, and the image drawn in it
+        let state = self.ext_g_state(soft_mask(area_mask));
+        self.cut_out(&format!("q {} gs\n0 g\n0 0 {} {} re f\nQ\n", state, number(self.width),
+                              number(self.height)), 1.);
+        self.page.content.push_str(&format!("q {} gs {} cm /X{} Do Q\n", state,
+                                            image_matrix(width, height), image));
+    }
+
+    // Records filling `path` with `source`, seen through `device_to_source`.
+    pub(crate) fn fill(&mut self, path: &Path, fill_rule: FillRule, source: &Pattern,
+                       device_to_source: &Matrix, operator: Operator, clip: Option<usize>) {
+        if path.segments().is_empty() {
+            return;
+        }
+        let shape = Shape::Fill(path_operators(path, &Matrix::init_identity()), fill_rule);
+        self.draw(&shape, source, device_to_source, 1., None, operator, clip);
+    }
+
+    // Records stroking `path` with `style` in the user space `ctm` maps to device space.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn stroke(&mut self, path: &Path, ctm: &Matrix, style: &StrokeStyle,
+                         source: &Pattern, device_to_source: &Matrix, operator: Operator,
+                         clip: Option<usize>) {
+        if path.segments().is_empty() {
+            return;
+        }
+        let device_to_user = match ctm.invert() {
+            Ok(matrix) => matrix,
+            Err(_) => return,
+        };
+        let shape = Shape::Stroke(path_operators(path, &device_to_user), *ctm, style);
+        self.draw(&shape, source, device_to_source, 1., None, operator, clip);
+    }
+
+    // Records painting the whole page with `source`, faded to `alpha`.
+    pub(crate) fn paint(&mut self, source: &Pattern, device_to_source: &Matrix, alpha: f32,
+                        operator: Operator, clip: Option<usize>) {
+        self.draw(&Shape::Page, source, device_to_source, alpha, None, operator, clip);
+    }
+
+    // Records painting with `source` through the alpha of `mask`, seen through
+    // `device_to_mask`.
+    pub(crate) fn mask(&mut self, source: &Pattern, device_to_source: &Matrix, mask: &Pattern,
+                       device_to_mask: &Matrix, operator: Operator, clip: Option<usize>) {
+        if self.finished {
+            return;
+        }
+        if let Ok((_, _, _, alpha)) = mask.get_rgba() {
+            self.paint(source, device_to_source, alpha, operator, clip);
+            return;
+        }
+
+        // Luminosity soft masks show as much as the mask is bright
+        let (width, height) = self.pixel_size();
+        let alphas = mask.render(device_to_mask, width, height).iter()
+            .map(|rgba| (rgba.alpha.clamp(0., 1.) * 255.).round() as u8)
+            .collect::<Vec<_>>();
+        let image = self.image(width, height, "/DeviceGray", &alphas, None, true);
+        let content = format!("q {} cm /X{} Do Q\n", image_matrix(width, height), image);
+        let form = self.form(&content, " /CS /DeviceGray");
+        self.draw(&Shape::Page, source, device_to_source, 1., Some(form), operator, clip);
+    }
+
+    // Records a clip path of the area inside `path`, within the clip path `clip` if there is
+    // one, and returns its index.
+    pub(crate) fn clip(&mut self, path: &Path, fill_rule: FillRule, clip: Option<usize>)
+                       -> usize {
+        self.clips.push(ClipPath {
+            operators: path_operators(path, &Matrix::init_identity()),
+            fill_rule: fill_rule,
+            parent: clip,
+        });
+        self.clips.len() - 1
+    }
+
+    // Draws `shape` with `source` faded to `alpha`, through the soft mask form `mask` if there
+    // is one.  Operators that are rasterized are drawn by `replace_area` instead.
+    #[allow(clippy::too_many_arguments)]
+    fn draw(&mut self, shape: &Shape, source: &Pattern, device_to_source: &Matrix, alpha: f32,
+            mask: Option<usize>, operator: Operator, clip: Option<usize>) {
+        if self.finished || alpha <= 0. || operator == Operator::Dest || is_rasterized(operator) {
+            return;
+        }
+        let color = match operator {
+            Operator::Clear => None,
+            _ => self.color(source, device_to_source),
+        };
+        // Source replaces the page inside the shape with the source, and Clear with nothing, so
+        // the shape is cut out of the page before the source is drawn over it
+        if operator == Operator::Source || operator == Operator::Clear {
+            self.knock_out(shape, color.as_ref(), alpha, mask, clip);
+        }
+        let color = match color {
+            Some(color) => color,
+            None => return,
+        };
+
+        let (painted, alpha, source_mask) = match color {
+            Color::Rgb(red, green, blue, color_alpha) => {
+                (format!("{} {} {}", number(red), number(green), number(blue)),
+                 alpha * color_alpha, None)
+            },
+            Color::Pattern(name, source_mask) => (name, alpha, source_mask),
+        };
+        let is_pattern = source_mask.is_some() || painted.starts_with('/');
+        let shape = self.shape_operators(shape, &painted, is_pattern);
+
+        let mut state = Vec::new();
+        if alpha < 1. {
+            state.push(format!("/ca {} /CA {}", number(alpha), number(alpha)));
+        }
+        if let Some(blend_mode) = blend_mode(operator) {
+            state.push(format!("/BM /{}", blend_mode));
+        }
+        // A graphics state has one soft mask, so the source is drawn with its own in a group
+        // that the mask is applied to
+        let (mask, shape) = match (mask, source_mask) {
+            (Some(mask), Some(source_mask)) => {
+                let inner = self.ext_g_state(soft_mask(source_mask));
+                let group = self.form(&format!("q {} gs\n{}Q\n", inner, shape), "");
+                (Some(mask), format!("/X{} Do\n", group))
+            },
+            (mask, source_mask) => (mask.or(source_mask), shape),
+        };
+        if let Some(mask) = mask {
+            state.push(soft_mask(mask));
+        }
+
+        let mut content = format!("q\n{}", self.clip_operators(clip));
+        if !state.is_empty() {
+            let name = self.ext_g_state(state.join(" "));
+            content.push_str(&format!("{} gs\n", name));
+        }
+        content.push_str(&shape);
+        content.push_str("Q\n");
+        self.page.content.push_str(&content);
+    }
+
+    // Cuts `shape`, faded to `alpha` and through the soft mask form `mask` if there is one, out
+    // of the page, so that drawing `color` over it replaces the page.
+    //
+    // Drawing the color with coverage c over the page cut out by k leaves (1 - k)(1 - a c) of
+    // the page, where a is the alpha of the color, and replacing the page leaves 1 - c.  That
+    // fixes k for solid colors and opaque patterns.  Under a partly transparent shape the page
+    // is cut out by c for other patterns, which leaves too little of it.
+    fn knock_out(&mut self, shape: &Shape, color: Option<&Color>, alpha: f32, mask: Option<usize>,
+                 clip: Option<usize>) {
+        let opacity = match color {
+            _ if alpha >= 1. || mask.is_some() => alpha,
+            Some(&Color::Rgb(_, _, _, color_alpha)) => {
+                1. - (1. - alpha) / (1. - color_alpha * alpha)
+            },
+            Some(&Color::Pattern(_, None)) => 0.,
+            _ => alpha,
+        };
+        if opacity <= 0. {
+            return;
+        }
+        let mut content = format!("q\n{}", self.clip_operators(clip));
+        if let Some(mask) = mask {
+            let name = self.ext_g_state(soft_mask(mask));
+            content.push_str(&format!("{} gs\n", name));
+        }
+        content.push_str(&self.shape_operators(shape, "0 0 0", false));
+        content.push_str("Q\n");
+        self.cut_out(&content, opacity);
+    }
+
+    // Ends the part of the page drawn since the last cut, and cuts what `content` draws in black,
+    // faded to `opacity`, out of it and every part before it.
+    fn cut_out(&mut self, content: &str, opacity: f32) {
+        let form = self.form(content, "");
+        let cut = if opacity < 1. {
+            let name = self.ext_g_state(format!("/ca {} /CA {}", number(opacity), number(opacity)));
+            format!("q {} gs /X{} Do Q\n", name, form)
+        } else {
+            format!("/X{} Do\n", form)
+        };
+        let part = ::std::mem::take(&mut self.page.content);
+        self.page.parts.push(part);
+        self.page.cuts.push(cut);
+    }
+
+    // Draws every part of the page that was cut out of through a soft mask of the cuts after it.
+    // The mask is white with the cuts drawn over it in black, so that it keeps the product of
+    // what each cut leaves, and the forms are never nested deeper however often the page is cut.
+    fn apply_cuts(&mut self) {
+        let parts = ::std::mem::take(&mut self.page.parts);
+        let cuts = ::std::mem::take(&mut self.page.cuts);
+        let mut content = String::new();
+        for (index, part) in parts.iter().enumerate() {
+            if part.is_empty() {
+                continue;
+            }
+            let mut mask = format!("1 g\n0 0 {} {} re f\n", number(self.width),
+                                   number(self.height));
+            for cut in &cuts[index..] {
+                mask.push_str(cut);
+            }
+            let mask = self.form(&mask, " /CS /DeviceGray");
+            let part = self.form(part, "");
+            let state = self.ext_g_state(soft_mask(mask));
+            content.push_str(&format!("q {} gs /X{} Do Q\n", state, part));
+        }
+        content.push_str(&self.page.content);
+        self.page.content = content;
+    }
+
+    // Returns the operators restricting drawing to the clip path `clip`, if there is one.
+    fn clip_operators(&self, clip: Option<usize>) -> String {
+        let mut operators = String::new();
+        let mut next = clip;
+        while let Some(index) = next {
+            let clip = &self.clips[index];
+            let operator = match clip.fill_rule {
+                FillRule::Winding => "W",
+                FillRule::EvenOdd => "W*",
+            };
+            operators.push_str(&format!("{} {} n\n", clip.operators, operator));
+            next = clip.parent;
+        }
+        operators
+    }
+
+    // Returns the operators painting `shape` with the color or pattern `painted`.
+    fn shape_operators(&self, shape: &Shape, painted: &str, is_pattern: bool) -> String {
+        let (color_space, color) = match (shape, is_pattern) {
+            (&Shape::Stroke(..), true) => ("/Pattern CS ", " SCN"),
+            (&Shape::Stroke(..), false) => ("", " RG"),
+            (_, true) => ("/Pattern cs ", " scn"),
+            (_, false) => ("", " rg"),
+        };
+        let color = format!("{}{}{}\n", color_space, painted, color);
+        match *shape {
+            Shape::Fill(ref operators, fill_rule) => {
+                let fill = match fill_rule {
+                    FillRule::Winding => "f",
+                    FillRule::EvenOdd => "f*",
+                };
+                format!("{}{} {}\n", color, operators, fill)
+            },
+            Shape::Stroke(ref operators, ref ctm, style) => {
+                format!("{}q {} cm\n{}{} S\nQ\n", color, matrix_operands(ctm),
+                        stroke_operators(style), operators)
+            },
+            Shape::Page => {
+                format!("{}0 0 {} {} re f\n", color, number(self.width), number(self.height))
+            },
+        }
+    }
+
+    // Returns how `source` is painted, adding the objects it needs, or None if it would paint
+    // nothing.
+    fn color(&mut self, source: &Pattern, device_to_source: &Matrix) -> Option<Color> {
+        let source_to_device = device_to_source.invert().ok()?;
+        match source.get_type() {
+            PatternType::Solid => {
+                let (red, green, blue, alpha) = source.get_rgba().unwrap();
+                if alpha <= 0. {
+                    return None;
+                }
+                Some(Color::Rgb(red, green, blue, alpha))
+            },
+            PatternType::Linear | PatternType::Radial => {
+                self.gradient(source, device_to_source, &source_to_device)
+            },
+            PatternType::Surface => {
+                let surface = source.get_surface().unwrap();
+                let interpolate = interpolate(source.get_filter());
+                let pattern = match source.get_extend() {
+                    extend @ Extend::Repeat | extend @ Extend::Reflect => {
+                        self.tiling_pattern(surface, &source_to_device, interpolate,
+                                            extend == Extend::Reflect)
+                    },
+                    Extend::None | Extend::Pad => {
+                        self.page_pattern(surface, &source_to_device, interpolate)
+                    },
+                };
+                Some(Color::Pattern(format!("/P{}", pattern), None))
+            },
+            PatternType::Mesh => {
+                let (width, height) = self.pixel_size();
+                let mut image = ImageSurface::create(Format::ARGB32, width, height);
+                let colors = source.render(device_to_source, width, height);
+                image.update_pixels(|idx, pixel| *pixel = colors[idx]);
+                let pattern = self.page_pattern(&image, &Matrix::init_identity(), true);
+                Some(Color::Pattern(format!("/P{}", pattern), None))
+            },
+        }
+    }
+
+    // Returns the shading pattern of the gradient `source`, or None if it has no color stops.
+    fn gradient(&mut self, source: &Pattern, device_to_source: &Matrix,
+                source_to_device: &Matrix) -> Option<Color> {
+        let count = source.get_color_stop_count().unwrap_or(0);
+        if count == 0 {
+            return None;
+        }
+        let stops = (0..count)
+            .map(|index| source.get_color_stop_rgba(index).unwrap())
+            .collect::<Vec<_>>();
+
+        // PDF shadings only pad, so repeating gradients are laid out over the whole page
+        let extend = source.get_extend();
+        let (start, end) = match extend {
+            Extend::Repeat | Extend::Reflect => self.gradient_range(source, device_to_source),
+            Extend::None | Extend::Pad => (0., 1.),
+        };
+        let (shading_type, coords) = match source.get_linear_points() {
+            Ok((x0, y0, x1, y1)) => {
+                let at = |t: f32| format!("{} {}", number(x0 + t * (x1 - x0)),
+                                          number(y0 + t * (y1 - y0)));
+                (2, format!("{} {}", at(start), at(end)))
+            },
+            Err(_) => {
+                let (cx0, cy0, r0, cx1, cy1, r1) = source.get_radial_circles().unwrap();
+                let at = |t: f32| format!("{} {} {}", number(cx0 + t * (cx1 - cx0)),
+                                          number(cy0 + t * (cy1 - cy0)),
+                                          number((r0 + t * (r1 - r0)).max(0.)));
+                (3, format!("{} {}", at(start), at(end)))
+            },
+        };
+        let padded = if extend == Extend::None { "false false" } else { "true true" };
+        let shading = |color_space: &str, function: String| {
+            let function = match extend {
+                Extend::Repeat => periodic_function(&function, start, end, false),
+                Extend::Reflect => periodic_function(&function, start, end, true),
+                Extend::None | Extend::Pad => function,
+            };
+            format!("<< /ShadingType {} /ColorSpace {} /Coords [{}] /Domain [{} {}] \
+                     /Function {} /Extend [{}] >>", shading_type, color_space, coords,
+                    number(start), number(end), function, padded)
+        };
+
+        let colors = stops.iter()
+            .map(|&(offset, red, green, blue, _)| (offset, vec![red, green, blue]))
+            .collect::<Vec<_>>();
+        let color_shading = shading("/DeviceRGB", stops_function(&colors));
+        let pattern = self.reserve();
+        let source_to_page = source_to_device.multiply(&self.device_to_page());
+        self.page.objects.push((pattern, format!(
+            "<< /Type /Pattern /PatternType 2 /Shading {} /Matrix [{}] >>", color_shading,
+            matrix_operands(&source_to_page)).into_bytes()));
+        self.page.patterns.push(pattern);
+
+        if stops.iter().all(|stop| stop.4 >= 1.) {
+            return Some(Color::Pattern(format!("/P{}", pattern), None));
+        }
+        let alphas = stops.iter()
+            .map(|&(offset, _, _, _, alpha)| (offset, vec![alpha]))
+            .collect::<Vec<_>>();
+        let alpha_shading = self.reserve();
+        self.page.objects.push((alpha_shading,
+                                shading("/DeviceGray", stops_function(&alphas)).into_bytes()));
+        self.page.shadings.push(alpha_shading);
+        let content = format!("q {} cm /Sh{} sh Q\n", matrix_operands(source_to_device),
+                              alpha_shading);
+        let mask = self.form(&content, " /CS /DeviceGray");
+        Some(Color::Pattern(format!("/P{}", pattern), Some(mask)))
+    }
+
+    // Returns the range of the gradient `source` that covers the page.
+    fn gradient_range(&self, source: &Pattern, device_to_source: &Matrix) -> (f32, f32) {
+        // No more periods than this are laid out
+        const PERIODS: f32 = 1024.;
+        let corners = [(0., 0.), (self.width, 0.), (0., self.height), (self.width, self.height)]
+            .iter()
+            .map(|&(x, y)| device_to_source.transform_point(x, y))
+            .collect::<Vec<_>>();
+
+        if let Ok((x0, y0, x1, y1)) = source.get_linear_points() {
+            let (dx, dy) = (x1 - x0, y1 - y0);
+            let length = dx * dx + dy * dy;
+            if length <= 0. {
+                return (0., 1.);
+            }
+            let offsets = corners.iter()
+                .map(|&(x, y)| ((x - x0) * dx + (y - y0) * dy) / length)
+                .collect::<Vec<_>>();
+            let start = offsets.iter().cloned().fold(f32::INFINITY, f32::min);
+            let end = offsets.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+            let start = start.max(end - PERIODS);
+            return if end > start { (start, end) } else { (start, start + 1.) };
+        }
+
+        // Radial gradients are laid out from where the radius is 0 to where the circle covers
+        // the page, if it ever does
+        let (cx0, cy0, r0, cx1, cy1, r1) = source.get_radial_circles().unwrap();
+        let dr = r1 - r0;
+        if dr == 0. {
+            return (0., 1.);
+        }
+        let covers = |t: f32| {
+            let (cx, cy, r) = (cx0 + t * (cx1 - cx0), cy0 + t * (cy1 - cy0), r0 + t * dr);
+            corners.iter().all(|&(x, y)| ((x - cx).powi(2) + (y - cy).powi(2)).sqrt() <= r)
+        };
+        let zero = -r0 / dr;
+        let mut periods = 1.;
+        if dr > 0. {
+            while !covers(periods) && periods < PERIODS {
+                periods *= 2.;
+            }
+            (zero.max(periods - PERIODS), periods)
+        } else {
+            while !covers(1. - periods) && periods < PERIODS {
+                periods *= 2.;
+            }
+            (1. - periods, zero.min(1. - periods + PERIODS))
+        }
+    }
+
+    // Returns the number of a pattern showing `surface` once, placed by `surface_to_device`.
+    fn page_pattern(&mut self, surface: &ImageSurface, surface_to_device: &Matrix,
+                    interpolate: bool) -> usize {
+        let image = self.surface_image(surface, interpolate);
+        let placement = image_placement(surface.width, surface.height).multiply(surface_to_device);
+        // The pattern is as large as the page, so no other copy reaches it
+        let content = format!("q {} cm /X{} Do Q\n", matrix_operands(&placement), image);
+        let (width, height) = (number(self.width), number(self.height));
+        let device_to_page = self.device_to_page();
+        self.pattern(&content, &format!("/BBox [0 0 {w} {h}] /XStep {w} /YStep {h}", w = width,
+                                        h = height), &device_to_page)
+    }
+
+    // Returns the number of a pattern repeating `surface`, or reflecting it if `reflect`,
+    // placed by `surface_to_device`.
+    fn tiling_pattern(&mut self, surface: &ImageSurface, surface_to_device: &Matrix,
+                      interpolate: bool, reflect: bool) -> usize {
+        let image = self.surface_image(surface, interpolate);
+        let (width, height) = (surface.width as f32, surface.height as f32);
+        let mut placements = vec![image_placement(surface.width, surface.height)];
+        if reflect {
+            // The surface and its mirror images, left to right and top to bottom
+            placements.push(Matrix::new(-width, 0., 0., -height, 2. * width, height));
+            placements.push(Matrix::new(width, 0., 0., height, 0., height));
+            placements.push(Matrix::new(-width, 0., 0., height, 2. * width, height));
+        }
+        let content = placements.iter()
+            .map(|placement| format!("q {} cm /X{} Do Q\n", matrix_operands(placement), image))
+            .collect::<String>();
+        let scale = if reflect { 2. } else { 1. };
+        let cell = format!("/BBox [0 0 {w} {h}] /XStep {w} /YStep {h}",
+                           w = number(width * scale), h = number(height * scale));
+        let surface_to_page = surface_to_device.multiply(&self.device_to_page());
+        self.pattern(&content, &cell, &surface_to_page)
+    }
+
+    // Returns the number of a colored tiling pattern drawing `content` in every `cell`, placed
+    // by `pattern_to_page`.
+    fn pattern(&mut self, content: &str, cell: &str, pattern_to_page: &Matrix) -> usize {
+        let pattern = self.reserve();
+        let dictionary = format!("/Type /Pattern /PatternType 1 /PaintType 1 /TilingType 1 {} \
+                                  /Matrix [{}] /Resources {} 0 R", cell,
+                                 matrix_operands(pattern_to_page), self.page.resources);
+        self.page.objects.push((pattern, stream(&dictionary, content.as_bytes())));
+        self.page.patterns.push(pattern);
+        pattern
+    }
+
+    // Returns the number of an image of `surface`, with a soft mask of its alpha if the format
+    // has one.
+    fn surface_image(&mut self, surface: &ImageSurface, interpolate: bool) -> usize {
+        let mut colors = Vec::with_capacity(surface.width * surface.height * 3);
+        let mut alphas = Vec::with_capacity(surface.width * surface.height);
+        for pixel in surface.iter() {
+            let bytes = pixel.to_bytes(ChannelOrder::Rgba, AlphaMode::Straight);
+            colors.extend_from_slice(&bytes[..3]);
+            alphas.push(bytes[3]);
+        }
+        let mask = match surface.get_format() {
+            Format::ARGB32 | Format::A8 => {
+                Some(self.image(surface.width, surface.height, "/DeviceGray", &alphas, None,
+                                interpolate))
+            },
+            _ => None,
+        };
+        self.image(surface.width, surface.height, "/DeviceRGB", &colors, mask, interpolate)
+    }
+
+    // Returns the number of an image of 8 bit `samples` in `color_space`.
+    fn image(&mut self, width: usize, height: usize, color_space: &str, samples: &[u8],
+             mask: Option<usize>, interpolate: bool) -> usize {
+        let image = self.reserve();
+        let mut dictionary = format!("/Type /XObject /Subtype /Image /Width {} /Height {} \
+                                      /ColorSpace {} /BitsPerComponent 8 /Interpolate {}", width,
+                                     height, color_space, interpolate);
+        if let Some(mask) = mask {
+            dictionary.push_str(&format!(" /SMask {} 0 R", mask));
+        }
+        self.page.objects.push((image, stream(&dictionary, samples)));
+        self.page.x_objects.push(image);
+        image
+    }
+
+    // Returns the number of a transparency group drawing `content` over the page, with the
+    // `group` attributes.
+    fn form(&mut self, content: &str, group: &str) -> usize {
+        let form = self.reserve();
+        let page = self.device_to_page();
+        let dictionary = format!("/Type /XObject /Subtype /Form /BBox [0 0 {} {}] /Matrix [{}] \
+                                  /Group << /S /Transparency{} >> /Resources {} 0 R",
+                                 number(self.width), number(self.height),
+                                 matrix_operands(&page), group, self.page.resources);
+        let content = format!("{} cm\n{}", matrix_operands(&page), content);
+        self.page.objects.push((form, stream(&dictionary, content.as_bytes())));
+        self.page.x_objects.push(form);
+        form
+    }
+
+    // Returns the name of a graphics state with the `entries`, adding it if the page doesn't
+    // have it yet.
+    fn ext_g_state(&mut self, entries: String) -> String {
+        let index = match self.page.ext_g_states.iter().position(|state| *state == entries) {
+            Some(index) => index,
+            None => {
+                self.page.ext_g_states.push(entries);
+                self.page.ext_g_states.len() - 1
+            },
+        };
+        format!("/GS{}", index)
+    }
+
+    // The matrix mapping device space to the page, which has its origin at the bottom left
+    // corner.  Forms and patterns are placed in this space too.
+    fn device_to_page(&self) -> Matrix {
+        Matrix::new(1., 0., 0., -1., 0., self.height)
+    }
+
+    // Writes `page` with its content, resources and the objects they refer to.
+    fn write_page(&mut self, page: Page) -> Result<(), Status> {
+        let object = self.reserve();
+        let contents = self.reserve();
+        let content = format!("{} cm\n{}", matrix_operands(&self.device_to_page()), page.content);
+        let dictionary = format!("<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}] \
+                                  /Resources {} 0 R /Contents {} 0 R \
+                                  /Group << /S /Transparency /CS /DeviceRGB >> >>", PAGE_TREE,
+                                 number(self.width), number(self.height),
+                                 page.resources, contents);
+        self.write_object(object, dictionary.as_bytes())?;
+        self.write_object(contents, &stream("", content.as_bytes()))?;
+        self.write_object(page.resources, page.resources_dictionary().as_bytes())?;
+        for &(object, ref body) in &page.objects {
+            self.write_object(object, body)?;
+        }
+        self.pages.push(object);
+        Ok(())
+    }
+
+    // Writes the object numbered `number` with `body`.
+    fn write_object(&mut self, number: usize, body: &[u8]) -> Result<(), Status> {
+        self.offsets[number - 1] = Some(self.written);
+        self.write(format!("{} 0 obj\n", number).as_bytes())?;
+        self.write(body)?;
+        self.write(b"\nendobj\n")
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Status> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        if self.writer.write_all(bytes).is_err() {
+            self.error = Some(Status::WriteError);
+            return Err(Status::WriteError);
+        }
+        self.written += bytes.len();
+        Ok(())
+    }
+
+    // Returns the number of a new object, which has to be written before the document ends.
+    fn reserve(&mut self) -> usize {
+        self.offsets.push(None);
+        self.offsets.len()
+    }
+}
+
+impl<'w> Drop for PdfSurface<'w> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+impl Page {
+    fn new(resources: usize) -> Page {
+        Page {
+            resources: resources,
+            content: String::new(),
+            parts: Vec::new(),
+            cuts: Vec::new(),
+            ext_g_states: Vec::new(),
+            patterns: Vec::new(),
+            x_objects: Vec::new(),
+            shadings: Vec::new(),
+            objects: Vec::new(),
+        }
+    }
+
+    fn resources_dictionary(&self) -> String {
+        let mut dictionary = String::from("<<");
+        if !self.ext_g_states.is_empty() {
+            dictionary.push_str(" /ExtGState <<");
+            for (index, state) in self.ext_g_states.iter().enumerate() {
+                dictionary.push_str(&format!(" /GS{} << {} >>", index, state));
+            }
+            dictionary.push_str(" >>");
+        }
+        let objects = [("Pattern", "P", &self.patterns), ("XObject", "X", &self.x_objects),
+                       ("Shading", "Sh", &self.shadings)];
+        for &(kind, prefix, numbers) in objects.iter() {
+            if numbers.is_empty() {
+                continue;
+            }
+            dictionary.push_str(&format!(" /{} <<", kind));
+            for number in numbers {
+                dictionary.push_str(&format!(" /{}{} {} 0 R", prefix, number, number));
+            }
+            dictionary.push_str(" >>");
+        }
+        dictionary.push_str(" >>");
+        dictionary
+    }
+}
+
+fn check_size(width: f32, height: f32) -> Result<(), Status> {
+    if width > 0. && height > 0. && width <= MAX_PAGE_SIZE && height <= MAX_PAGE_SIZE {
+        Ok(())
+    } else {
+        Err(Status::InvalidSize)
+    }
+}
+
+// Returns the body of a stream object of `data` compressed, with the `dictionary` entries.
+fn stream(dictionary: &str, data: &[u8]) -> Vec<u8> {
+    let compressed = deflate::deflate_bytes_zlib(data);
+    let separator = if dictionary.is_empty() { "" } else { " " };
+    let mut body = format!("<< {}{}/Filter /FlateDecode /Length {} >>\nstream\n", dictionary,
+                           separator, compressed.len()).into_bytes();
+    body.extend_from_slice(&compressed);
+    body.extend_from_slice(b"\nendstream");
+    body
+}
+
+// Returns the operators constructing `path`, with its points transformed by `matrix`.
+fn path_operators(path: &Path, matrix: &Matrix) -> String {
+    let point = |point: &::common_geometry::Point| {
+        let (x, y) = matrix.transform_point(point.x, point.y);
+        format!("{} {}", number(x), number(y))
+    };
+    let operators = path.segments().iter().map(|segment| {
+        match *segment {
+            PathSegment::MoveTo(ref to) => format!("{} m", point(to)),
+            PathSegment::LineTo(ref to) => format!("{} l", point(to)),
+            PathSegment::CurveTo(ref control1, ref control2, ref to) => {
+                format!("{} {} {} c", point(control1), point(control2), point(to))
+            },
+            PathSegment::ClosePath => String::from("h"),
+        }
+    });
+    operators.collect::<Vec<_>>().join(" ")
+}
+
+// Returns the operators setting the line style to `style`.
+fn stroke_operators(style: &StrokeStyle) -> String {
+    let cap = match style.line_cap {
+        LineCap::Butt => 0,
+        LineCap::Round => 1,
+        LineCap::Square => 2,
+    };
+    let join = match style.line_join {
+        LineJoin::Miter => 0,
+        LineJoin::Round => 1,
+        LineJoin::Bevel => 2,
+    };
+    let dashes = style.dashes.iter().map(|dash| number(*dash)).collect::<Vec<_>>();
+    // PDF doesn't allow miter limits below 1, which are the same as 1 anyway
+    format!("{} w {} J {} j {} M [{}] {} d\n", number(style.line_width), cap, join,
+            number(style.miter_limit.max(1.)), dashes.join(" "), number(style.dash_offset))
+}
+
+// The blend mode of `operator`, or None for the operators that draw like Over.
+fn blend_mode(operator: Operator) -> Option<&'static str> {
+    match operator {
+        Operator::Multiply => Some("Multiply"),
+        Operator::Screen => Some("Screen"),
+        Operator::Overlay => Some("Overlay"),
+        Operator::Darken => Some("Darken"),
+        Operator::Lighten => Some("Lighten"),
+        Operator::ColorDodge => Some("ColorDodge"),
+        Operator::ColorBurn => Some("ColorBurn"),
+        Operator::HardLight => Some("HardLight"),
+        Operator::SoftLight => Some("SoftLight"),
+        Operator::Difference => Some("Difference"),
+        Operator::Exclusion => Some("Exclusion"),
+        Operator::HSLHue => Some("Hue"),
+        Operator::HSLSaturation => Some("Saturation"),
+        Operator::HSLColor => Some("Color"),
+        Operator::HSLLuminosity => Some("Luminosity"),
+        _ => None,
+    }
+}
+
+// Whether drawing with `operator` is rasterized, because PDF has nothing like it.
+pub(crate) fn is_rasterized(operator: Operator) -> bool {
+    matches!(operator, Operator::In | Operator::Out | Operator::Atop | Operator::DestOver |
+             Operator::DestIn | Operator::DestOut | Operator::DestAtop | Operator::Xor |
+             Operator::Add | Operator::Saturate)
+}
+
+// Returns the graphics state entry applying the soft mask form `form`.
+fn soft_mask(form: usize) -> String {
+    format!("/SMask << /Type /Mask /S /Luminosity /G {} 0 R >>", form)
+}
+
+fn interpolate(filter: Filter) -> bool {
+    !matches!(filter, Filter::Fast | Filter::Nearest)
+}
+
+// Returns the matrix mapping the unit square images are drawn in to a `width` by `height` area,
+// with the first row of the image at the top.
+fn image_placement(width: usize, height: usize) -> Matrix {
+    Matrix::new(width as f32, 0., 0., -(height as f32), 0., height as f32)
+}
+
+// Returns the operands of `cm` drawing an image `width` by `height` pixels large one pixel per
+// unit.
+fn image_matrix(width: usize, height: usize) -> String {
+    matrix_operands(&image_placement(width, height))
+}
+
+// Returns a function of the offset along a gradient interpolating between `stops`, which are
+// offsets and the color components there.
+fn stops_function(stops: &[(f32, Vec<f32>)]) -> String {
+    let mut stops = stops.to_vec();
+    // Functions need the offsets in order, and the colors of the ends to go on to 0 and 1
+    stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    if stops[0].0 > 0. {
+        let first = (0., stops[0].1.clone());
+        stops.insert(0, first);
+    }
+    if stops[stops.len() - 1].0 < 1. {
+        let last = (1., stops[stops.len() - 1].1.clone());
+        stops.push(last);
+    }
+
+    let components = |color: &[f32]| {
+        color.iter().map(|component| number(*component)).collect::<Vec<_>>().join(" ")
+    };
+    let pieces = stops.windows(2)
+        .map(|pair| format!("<< /FunctionType 2 /Domain [0 1] /C0 [{}] /C1 [{}] /N 1 >>",
+                            components(&pair[0].1), components(&pair[1].1)))
+        .collect::<Vec<_>>();
+    if pieces.len() == 1 {
+        return pieces[0].clone();
+    }
+    let bounds = stops[1..stops.len() - 1].iter()
+        .map(|stop| number(stop.0))
+        .collect::<Vec<_>>();
+    let encode = vec!["0 1"; pieces.len()];
+    format!("<< /FunctionType 3 /Domain [0 1] /Functions [{}] /Bounds [{}] /Encode [{}] >>",
+            pieces.join(" "), bounds.join(" "), encode.join(" "))
+}
+
+// Returns a function of the offsets from `start` to `end` that repeats `function` every period
+// between whole offsets, mirroring every other period if `reflect`.
+fn periodic_function(function: &str, start: f32, end: f32, reflect: bool) -> String {
+    let mut bounds = Vec::new();
+    let mut encode = Vec::new();
+    let mut period = start.floor();
+    while period < end {
+        let (from, to) = (start.max(period) - period, end.min(period + 1.) - period);
+        if reflect && period % 2. != 0. {
+            encode.push(format!("{} {}", number(1. - from), number(1. - to)));
+        } else {
+            encode.push(format!("{} {}", number(from), number(to)));
+        }
+        period += 1.;
+        if period < end {
+            bounds.push(number(period));
+        }
+    }
+    let functions = vec![function; encode.len()];
+    format!("<< /FunctionType 3 /Domain [{} {}] /Functions [{}] /Bounds [{}] /Encode [{}] >>",
+            number(start), number(end), functions.join(" "), bounds.join(" "), encode.join(" "))
+}
+
+// Returns the operands of `cm` or a /Matrix entry for `matrix`.
+fn matrix_operands(matrix: &Matrix) -> String {
+    format!("{} {} {} {} {} {}", number(matrix.xx), number(matrix.yx), number(matrix.xy),
+            number(matrix.yy), number(matrix.x0), number(matrix.y0))
+}
+
+// Returns `value` as it is written in the document, which doesn't need the sign of zero.
+fn number(value: f32) -> String {
+    (value + 0.).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate inflate;
+
+    use context::{Context, LineJoin};
+    use matrix::Matrix;
+    use operators::Operator;
+    use patterns::{Pattern, Extend};
+    use status::Status;
+    use surfaces::{ImageSurface, Format};
+    use types::Rgba;
+    use super::{PdfSurface, periodic_function, stops_function};
+
+    use std::fs;
+    use std::io::{self, Write};
+    use std::path::Path;
+
+    // The bodies of the objects of `document` by number less one, found through its
+    // cross-reference table, which this checks.
+    fn objects(document: &[u8]) -> Vec<Option<String>> {
+        // Binary bytes are replaced one for one, so that offsets stay the same
+        let text = document.iter()
+            .map(|&byte| if byte.is_ascii() { byte as char } else { '?' })
+            .collect::<String>();
+        assert!(text.starts_with("%PDF-1.4\n"));
+        assert!(text.ends_with("%%EOF\n"));
+        let startxref = text.rfind("startxref\n").unwrap();
+        let start = text[startxref + 10..].lines().next().unwrap().parse::<usize>().unwrap();
+        let mut lines = text[start..].lines();
+        assert_eq!(lines.next(), Some("xref"));
+        let size = lines.next().unwrap()[2..].parse::<usize>().unwrap();
+        assert_eq!(lines.next(), Some("0000000000 65535 f "));
+        (1..size).map(|number| {
+            let entry = lines.next().unwrap();
+            assert_eq!(entry.len(), 19);
+            if entry.ends_with('f') {
+                return None;
+            }
+            let offset = entry[..10].parse::<usize>().unwrap();
+            let header = format!("{} 0 obj\n", number);
+            assert!(text[offset..].starts_with(&header));
+            let body = &text[offset + header.len()..];
+            Some(body[..body.find("\nendobj\n").unwrap()].to_string())
+        }).collect()
+    }
+
+    // Returns the decompressed data of the stream object `body` from `document`.
+    fn stream_data(document: &[u8], body: &str) -> Vec<u8> {
+        let length = body.split("/Length ").nth(1).unwrap().split(' ').next().unwrap();
+        let length = length.parse::<usize>().unwrap();
+        // The body was read as text, so the data is found again in the bytes of the document
+        let dictionary = &body[..body.find("stream\n").unwrap() + 7];
+        let start = document.windows(dictionary.len())
+            .position(|window| window == dictionary.as_bytes()).unwrap() + dictionary.len();
+        let compressed = &document[start..start + length];
+        assert_eq!(&document[start + length..start + length + 10], b"\nendstream");
+
+        let mut inflater = inflate::InflateStream::from_zlib();
+        let mut data = Vec::new();
+        let mut input = compressed;
+        loop {
+            let (read, output) = inflater.update(input).unwrap();
+            if read == 0 && output.is_empty() {
+                break;
+            }
+            data.extend_from_slice(output);
+            input = &input[read..];
+        }
+        data
+    }
+
+    // Returns the objects referred to as `key` in `body`.
+    fn references(body: &str, key: &str) -> Vec<usize> {
+        let list = body.split(key).nth(1).unwrap();
+        let list = if list.starts_with('[') {
+            &list[1..list.find(']').unwrap()]
+        } else {
+            &list[..list.find(" R").unwrap() + 2]
+        };
+        list.split(" R").filter(|reference| !reference.trim().is_empty())
+            .map(|reference| reference.trim().split(' ').next().unwrap().parse().unwrap())
+            .collect()
+    }
+
+    // Returns the content streams of the pages of `document`.
+    fn page_contents(document: &[u8]) -> Vec<String> {
+        let objects = objects(document);
+        let catalog = objects[0].as_ref().unwrap();
+        assert_eq!(catalog, "<< /Type /Catalog /Pages 2 0 R >>");
+        let pages = references(objects[1].as_ref().unwrap(), "/Kids ");
+        pages.iter().map(|&page| {
+            let page = objects[page - 1].as_ref().unwrap();
+            let contents = references(page, "/Contents ")[0];
+            let data = stream_data(document, objects[contents - 1].as_ref().unwrap());
+            String::from_utf8(data).unwrap()
+        }).collect()
+    }
+
+    // Returns the object numbers of the forms and images `content` draws, in order.
+    fn drawn(content: &str) -> Vec<usize> {
+        content.split("/X").skip(1)
+            .map(|name| name.split(' ').next().unwrap().parse().unwrap())
+            .collect()
+    }
+
+    // Returns the dictionary and the decompressed content of the object numbered `number`.
+    fn object(document: &[u8], number: usize) -> (String, Vec<u8>) {
+        let body = objects(document)[number - 1].clone().unwrap();
+        // Images can have the same dictionary, so the data is looked for after the header
+        let header = format!("\n{} 0 obj\n", number);
+        let start = document.windows(header.len())
+            .position(|window| window == header.as_bytes()).unwrap();
+        let data = stream_data(&document[start..], &body);
+        (body, data)
+    }
+
+    fn square(context: &mut Context, x: f32, y: f32, size: f32) {
+        context.move_to(x, y);
+        context.line_to(x + size, y);
+        context.line_to(x + size, y + size);
+        context.line_to(x, y + size);
+        context.close_path();
+    }
+
+    // Returns the document of a 100 by 50 point surface drawn on by `draw`.
+    fn draw<F>(draw: F) -> Vec<u8> where F: FnOnce(&mut Context) {
+        let mut document = Vec::new();
+        {
+            let mut surface = PdfSurface::create_for_stream(&mut document, 100., 50.).unwrap();
+            {
+                let mut context = Context::create(&mut surface);
+                draw(&mut context);
+            }
+            surface.finish().unwrap();
+        }
+        document
+    }
+
+    #[test]
+    fn test_empty_document() {
+        let document = draw(|_| {});
+        let objects = objects(&document);
+        assert_eq!(objects[1].as_ref().unwrap(), "<< /Type /Pages /Kids [4 0 R] /Count 1 >>");
+        assert!(objects[3].as_ref().unwrap().contains("/MediaBox [0 0 100 50]"));
+        assert_eq!(page_contents(&document), vec!["1 0 0 -1 0 50 cm\n"]);
+    }
+
+    #[test]
+    fn test_invalid_size() {
+        assert_eq!(PdfSurface::create_for_stream(Vec::new(), 0., 10.).err(),
+                   Some(Status::InvalidSize));
+        assert_eq!(PdfSurface::create_for_stream(Vec::new(), 10., f32::NAN).err(),
+                   Some(Status::InvalidSize));
+        assert_eq!(PdfSurface::create_for_stream(Vec::new(), 1e5, 1e5).err(),
+                   Some(Status::InvalidSize));
+        assert!(PdfSurface::create_for_stream(Vec::new(), 14400., 14400.).is_ok());
+    }
+
+    #[test]
+    fn test_fill_and_stroke() {
+        let document = draw(|context| {
+            context.set_source_rgba(1., 0., 0., 0.5);
+            context.translate(10., 20.).unwrap();
+            square(context, 0., 0., 10.);
+            context.fill();
+
+            // Nothing is drawn for an empty path or a transparent source
+            context.fill();
+            square(context, 0., 0., 10.);
+            context.set_source_rgba(0., 0., 0., 0.);
+            context.fill();
+
+            context.set_source_rgba(0., 0., 1., 1.);
+            context.scale(2., 2.).unwrap();
+            context.set_line_width(3.);
+            context.set_line_join(LineJoin::Bevel);
+            context.set_dash(&[1., 2.], 0.5).unwrap();
+            context.move_to(1., 1.);
+            context.line_to(5., 1.);
+            context.stroke();
+        });
+        let content = &page_contents(&document)[0];
+        assert_eq!(content, "1 0 0 -1 0 50 cm\n\
+                             q\n/GS0 gs\n1 0 0 rg\n10 20 m 20 20 l 20 30 l 10 30 l h f\nQ\n\
+                             q\n0 0 1 RG\nq 2 0 0 2 10 20 cm\n3 w 0 J 2 j 10 M [1 2] 0.5 d\n\
+                             1 1 m 5 1 l S\nQ\nQ\n");
+        let objects = objects(&document);
+        assert!(objects.iter().any(|object| {
+            object.as_ref().is_some_and(|object| {
+                object.contains("/ExtGState << /GS0 << /ca 0.5 /CA 0.5 >> >>")
+            })
+        }));
+    }
+
+    #[test]
+    fn test_pages_and_clip() {
+        let mut document = Vec::new();
+        {
+            let mut surface = PdfSurface::create_for_stream(&mut document, 100., 50.).unwrap();
+            {
+                let mut context = Context::create(&mut surface);
+                context.set_source_rgba(0., 1., 0., 1.);
+                square(&mut context, 0., 0., 40.);
+                context.clip();
+                context.paint();
+                context.show_page().unwrap();
+
+                // The clip carries over, and clips nest
+                square(&mut context, 20., 20., 40.);
+                context.clip();
+                context.paint();
+            }
+            surface.finish().unwrap();
+            // Finishing again does nothing, but pages can't be added anymore
+            assert_eq!(surface.finish(), Ok(()));
+            assert_eq!(surface.show_page(), Err(Status::SurfaceFinished));
+        }
+        let contents = page_contents(&document);
+        assert_eq!(contents.len(), 2);
+        assert_eq!(contents[0], "1 0 0 -1 0 50 cm\n\
+                                 q\n0 0 m 40 0 l 40 40 l 0 40 l h W n\n\
+                                 0 1 0 rg\n0 0 100 50 re f\nQ\n");
+        assert_eq!(contents[1], "1 0 0 -1 0 50 cm\n\
+                                 q\n20 20 m 60 20 l 60 60 l 20 60 l h W n\n\
+                                 0 0 m 40 0 l 40 40 l 0 40 l h W n\n\
+                                 0 1 0 rg\n0 0 100 50 re f\nQ\n");
+    }
+
+    #[test]
+    fn test_operators() {
+        let document = draw(|context| {
+            context.set_source_rgba(1., 1., 1., 1.);
+            context.set_operator(Operator::Dest);
+            context.paint();
+            context.set_operator(Operator::Multiply);
+            context.paint_with_alpha(0.25);
+        });
+        assert_eq!(page_contents(&document)[0], "1 0 0 -1 0 50 cm\n\
+                                                 q\n/GS0 gs\n1 1 1 rg\n0 0 100 50 re f\nQ\n");
+        assert!(objects(&document)[2].as_ref().unwrap()
+                .contains("/GS0 << /ca 0.25 /CA 0.25 /BM /Multiply >>"));
+    }
+
+    #[test]
+    fn test_source_knocks_out() {
+        let document = draw(|context| {
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.paint();
+            context.set_operator(Operator::Source);
+            context.set_source_rgba(0., 0., 1., 0.5);
+            square(context, 0., 0., 10.);
+            context.fill();
+        });
+        // The square is cut out of the red, and half transparent blue drawn over it
+        let contents = &page_contents(&document)[0];
+        let part = drawn(contents)[0];
+        assert_eq!(contents, &format!("1 0 0 -1 0 50 cm\nq /GS1 gs /X{} Do Q\n\
+                                       q\n/GS0 gs\n0 0 1 rg\n0 0 m 10 0 l 10 10 l 0 10 l h f\nQ\n",
+                                      part));
+        assert_eq!(object(&document, part).1,
+                   b"1 0 0 -1 0 50 cm\nq\n1 0 0 rg\n0 0 100 50 re f\nQ\n".to_vec());
+        let resources = objects(&document)[2].clone().unwrap();
+        assert!(resources.contains("/GS0 << /ca 0.5 /CA 0.5 >>"));
+        let mask = references(&resources, "/GS1 << /SMask << /Type /Mask /S /Luminosity /G ")[0];
+        let (_, mask) = object(&document, mask);
+        let mask = String::from_utf8(mask).unwrap();
+        let cut = drawn(&mask)[0];
+        assert_eq!(mask, format!("1 0 0 -1 0 50 cm\n1 g\n0 0 100 50 re f\n/X{} Do\n", cut));
+        assert_eq!(object(&document, cut).1,
+                   b"1 0 0 -1 0 50 cm\nq\n0 0 0 rg\n0 0 m 10 0 l 10 10 l 0 10 l h f\nQ\n".to_vec());
+    }
+
+    #[test]
+    fn test_source_knocks_out_partly() {
+        // Returns the opacity the page is cut out by when a shape half covers it with `source`
+        fn cut_opacity(source: Pattern) -> Option<String> {
+            let document = draw(|context| {
+                context.set_source_rgba(1., 0., 0., 1.);
+                context.paint();
+                context.set_operator(Operator::Source);
+                context.set_source(&source);
+                context.paint_with_alpha(0.5);
+            });
+            let resources = objects(&document)[2].clone().unwrap();
+            if !resources.contains("/SMask") {
+                return None;
+            }
+            let mask = references(&resources, "/SMask << /Type /Mask /S /Luminosity /G ")[0];
+            let mask = String::from_utf8(object(&document, mask).1).unwrap();
+            let state = mask.lines().last().unwrap().split(' ').nth(1).unwrap().to_string();
+            let entries = resources.split(&format!("{} << ", state)).nth(1).unwrap();
+            Some(entries[..entries.find(" >>").unwrap()].to_string())
+        }
+
+        // Half of the page is left by cutting 1/3 of it out and drawing a quarter of the color
+        // over the rest
+        assert_eq!(cut_opacity(Pattern::create_rgba(0., 0., 1., 0.5)),
+                   Some(String::from("/ca 0.3333333 /CA 0.3333333")));
+        // A transparent color leaves what the cut does, and an opaque one what it draws
+        assert_eq!(cut_opacity(Pattern::create_rgba(0., 0., 1., 0.)),
+                   Some(String::from("/ca 0.5 /CA 0.5")));
+        assert_eq!(cut_opacity(Pattern::create_rgba(0., 0., 1., 1.)), None);
+    }
+
+    #[test]
+    fn test_clear_knocks_out() {
+        let document = draw(|context| {
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.paint();
+            context.set_operator(Operator::Clear);
+            context.paint_with_alpha(0.5);
+        });
+        // The page is cut out by half, and nothing drawn over it
+        let contents = &page_contents(&document)[0];
+        let part = drawn(contents)[0];
+        assert_eq!(contents, &format!("1 0 0 -1 0 50 cm\nq /GS1 gs /X{} Do Q\n", part));
+        let resources = objects(&document)[2].clone().unwrap();
+        assert!(resources.contains("/GS0 << /ca 0.5 /CA 0.5 >>"));
+        let mask = references(&resources, "/GS1 << /SMask << /Type /Mask /S /Luminosity /G ")[0];
+        let mask = String::from_utf8(object(&document, mask).1).unwrap();
+        let cut = drawn(&mask)[0];
+        assert_eq!(mask, format!("1 0 0 -1 0 50 cm\n1 g\n0 0 100 50 re f\nq /GS0 gs /X{} Do Q\n",
+                                 cut));
+        assert_eq!(object(&document, cut).1,
+                   b"1 0 0 -1 0 50 cm\nq\n0 0 0 rg\n0 0 100 50 re f\nQ\n".to_vec());
+    }
+
+    #[test]
+    fn test_knockouts_are_not_nested() {
+        let document = draw(|context| {
+            context.set_source_rgba(0., 0., 1., 0.5);
+            for index in 0..20 {
+                let operator = if index % 2 == 0 { Operator::Source } else { Operator::Over };
+                context.set_operator(operator);
+                square(context, index as f32, 0., 10.);
+                context.fill();
+            }
+        });
+        // Returns how deeply `content` nests forms
+        fn depth(document: &[u8], content: &str) -> usize {
+            drawn(content).iter().map(|&form| {
+                let content = String::from_utf8(object(document, form).1).unwrap();
+                depth(document, &content) + 1
+            }).max().unwrap_or(0)
+        }
+
+        // Every part of the page and every mask draws its forms directly.  Nothing was drawn
+        // before the first cut, so there is a part before each of the other nine.
+        let contents = &page_contents(&document)[0];
+        assert_eq!(drawn(contents).len(), 9);
+        assert_eq!(depth(&document, contents), 1);
+        let resources = objects(&document)[2].clone().unwrap();
+        let masks = resources.split("/G ").skip(1)
+            .map(|mask| mask.split(' ').next().unwrap().parse().unwrap());
+        for mask in masks {
+            let content = String::from_utf8(object(&document, mask).1).unwrap();
+            assert_eq!(depth(&document, &content), 1);
+        }
+    }
+
+    #[test]
+    fn test_rasterized_operators() {
+        // Returns the area replaced by drawing a blue square with `operator` over red, and the
+        // colors and alphas of the image it is replaced with
+        fn replaced(operator: Operator) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+            let document = draw(|context| {
+                context.set_source_rgba(1., 0., 0., 1.);
+                context.paint();
+                context.set_operator(operator);
+                context.set_source_rgba(0., 0., 1., 1.);
+                square(context, 0., 0., 10.);
+                context.fill();
+            });
+            let contents = &page_contents(&document)[0];
+            let image = drawn(contents)[1];
+            assert_eq!(contents, &format!("1 0 0 -1 0 50 cm\nq /GS1 gs /X{} Do Q\n\
+                                           q /GS0 gs 100 0 0 -50 0 50 cm /X{} Do Q\n",
+                                          drawn(contents)[0], image));
+            let resources = objects(&document)[2].clone().unwrap();
+            let area = references(&resources, "/GS0 << /SMask << /Type /Mask \
+                                               /S /Luminosity /G ")[0];
+            let (_, area) = object(&document, area);
+            let area = object(&document, drawn(&String::from_utf8(area).unwrap())[0]).1;
+            let (dictionary, colors) = object(&document, image);
+            let alphas = object(&document, references(&dictionary, "/SMask ")[0]).1;
+            (area, colors, alphas)
+        }
+
+        // In clears the page outside the square, so all of it is replaced
+        let (area, colors, alphas) = replaced(Operator::In);
+        assert!(area.iter().all(|&inside| inside == 255));
+        let inside = 100 * 5 + 5;
+        assert_eq!(&colors[3 * inside..3 * inside + 3], &[0, 0, 255]);
+        assert_eq!(alphas[inside], 255);
+        assert_eq!(alphas[100 * 20 + 50], 0);
+
+        // Xor only changes the square, where both are opaque and it clears
+        let (area, _, alphas) = replaced(Operator::Xor);
+        assert_eq!((area[inside], area[100 * 20 + 50]), (255, 0));
+        assert_eq!((alphas[inside], alphas[100 * 20 + 50]), (0, 255));
+    }
+
+    #[test]
+    fn test_page_rasterized_lazily() {
+        let mut surface = PdfSurface::create_for_stream(Vec::new(), 100., 50.).unwrap();
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.paint();
+            context.set_operator(Operator::Source);
+            context.paint();
+        }
+        assert!(surface.fallback.image().is_none());
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_operator(Operator::Xor);
+            context.paint();
+        }
+        assert!(surface.fallback.image().is_some());
+        surface.show_page().unwrap();
+        assert!(surface.fallback.image().is_none());
+    }
+
+    #[test]
+    fn test_gradients() {
+        let document = draw(|context| {
+            let mut linear = Pattern::create_linear(0., 0., 10., 0.);
+            linear.add_color_stop_rgba(0., 1., 0., 0., 1.).unwrap();
+            linear.add_color_stop_rgba(1., 0., 0., 1., 1.).unwrap();
+            context.translate(5., 0.).unwrap();
+            context.set_source(&linear);
+            context.paint();
+
+            let mut radial = Pattern::create_radial(0., 0., 5., 0., 0., 10.);
+            radial.add_color_stop_rgba(0.5, 1., 1., 1., 0.5).unwrap();
+            radial.set_extend(Extend::None);
+            context.set_source(&radial);
+            context.paint();
+
+            // A gradient without stops draws nothing
+            context.set_source(&Pattern::create_linear(0., 0., 1., 1.));
+            context.paint();
+        });
+        let objects = objects(&document);
+        let bodies = objects.iter().filter_map(|object| object.as_ref()).collect::<Vec<_>>();
+        assert!(bodies.contains(&&String::from(
+            "<< /Type /Pattern /PatternType 2 /Shading << /ShadingType 2 \
+             /ColorSpace /DeviceRGB /Coords [0 0 10 0] /Domain [0 1] /Function << \
+             /FunctionType 2 /Domain [0 1] /C0 [1 0 0] /C1 [0 0 1] /N 1 >> \
+             /Extend [true true] >> /Matrix [1 0 0 -1 5 50] >>")));
+        // The transparent radial gradient is drawn through a soft mask of its alpha
+        assert!(bodies.iter().any(|body| body.starts_with(
+            "<< /ShadingType 3 /ColorSpace /DeviceGray /Coords [0 0 5 0 0 10] \
+             /Domain [0 1] /Function << /FunctionType 3")));
+        assert!(bodies.iter().any(|body| body.contains(
+            "/S /Transparency /CS /DeviceGray >>")));
+
+        let contents = &page_contents(&document)[0];
+        assert_eq!(contents.matches("/Pattern cs").count(), 2);
+        assert_eq!(contents.matches(" gs\n").count(), 1);
+    }
+
+    #[test]
+    fn test_gradient_functions() {
+        let stops = vec![(0.5, vec![1.]), (0.25, vec![0.])];
+        assert_eq!(stops_function(&stops),
+                   "<< /FunctionType 3 /Domain [0 1] /Functions [\
+                    << /FunctionType 2 /Domain [0 1] /C0 [0] /C1 [0] /N 1 >> \
+                    << /FunctionType 2 /Domain [0 1] /C0 [0] /C1 [1] /N 1 >> \
+                    << /FunctionType 2 /Domain [0 1] /C0 [1] /C1 [1] /N 1 >>] \
+                    /Bounds [0.25 0.5] /Encode [0 1 0 1 0 1] >>");
+        assert_eq!(periodic_function("F", -0.5, 1.5, true),
+                   "<< /FunctionType 3 /Domain [-0.5 1.5] /Functions [F F F] /Bounds [0 1] \
+                    /Encode [0.5 0 0 1 1 0.5] >>");
+        assert_eq!(periodic_function("F", 0., 2., false),
+                   "<< /FunctionType 3 /Domain [0 2] /Functions [F F] /Bounds [1] \
+                    /Encode [0 1 0 1] >>");
+    }
+
+    #[test]
+    fn test_repeating_gradient() {
+        let document = draw(|context| {
+            let mut linear = Pattern::create_linear(0., 0., 40., 0.);
+            linear.add_color_stop_rgba(0., 0., 0., 0., 1.).unwrap();
+            linear.set_extend(Extend::Repeat);
+            context.set_source(&linear);
+            context.paint();
+        });
+        // The gradient is laid out over the width of the page
+        let objects = objects(&document);
+        assert!(objects.iter().any(|object| object.as_ref().is_some_and(|object| {
+            object.contains("/Coords [0 0 100 0] /Domain [0 2.5] /Function << /FunctionType 3 \
+                             /Domain [0 2.5] /Functions [")
+        })));
+    }
+
+    #[test]
+    fn test_surface_pattern() {
+        let mut image = ImageSurface::create(Format::ARGB32, 2, 1);
+        *image.get_mut(0, 0).unwrap() = Rgba::new(1., 0., 0., 1.);
+        *image.get_mut(1, 0).unwrap() = Rgba::new(0., 0., 1., 0.5);
+        let document = draw(|context| {
+            let mut pattern = Pattern::create_for_surface(&image);
+            pattern.set_matrix(Matrix::init_translate(-10., -20.)).unwrap();
+            context.set_source(&pattern);
+            square(context, 0., 0., 50.);
+            context.fill();
+
+            pattern.set_extend(Extend::Reflect);
+            context.set_source(&pattern);
+            context.paint();
+        });
+        let objects = objects(&document);
+        let bodies = objects.iter().filter_map(|object| object.as_ref()).collect::<Vec<_>>();
+
+        // The colors and alpha of the surface are separate images
+        let images = bodies.iter().filter(|body| body.contains("/Subtype /Image"))
+            .collect::<Vec<_>>();
+        assert_eq!(images.len(), 4);
+        let colors = images.iter().find(|body| body.contains("/DeviceRGB")).unwrap();
+        assert!(colors.contains("/Width 2 /Height 1"));
+        assert!(colors.contains("/SMask "));
+        assert_eq!(stream_data(&document, colors), vec![255, 0, 0, 0, 0, 255]);
+        let alpha = images.iter().find(|body| body.contains("/DeviceGray")).unwrap();
+        assert_eq!(stream_data(&document, alpha), vec![255, 128]);
+
+        // Without extending, the surface is shown once in a pattern as large as the page
+        let patterns = bodies.iter().filter(|body| body.contains("/PatternType 1"))
+            .collect::<Vec<_>>();
+        assert_eq!(patterns.len(), 2);
+        assert!(patterns[0].contains("/BBox [0 0 100 50] /XStep 100 /YStep 50 \
+                                      /Matrix [1 0 0 -1 0 50]"));
+        assert!(String::from_utf8(stream_data(&document, patterns[0])).unwrap()
+                .starts_with("q 2 0 0 -1 10 21 cm /X"));
+        // Reflecting repeats the surface and its mirror images
+        assert!(patterns[1].contains("/BBox [0 0 4 2] /XStep 4 /YStep 2 \
+                                      /Matrix [1 0 0 -1 10 30]"));
+        assert_eq!(String::from_utf8(stream_data(&document, patterns[1])).unwrap()
+                   .matches(" Do Q\n").count(), 4);
+    }
+
+    #[test]
+    fn test_mask() {
+        let document = draw(|context| {
+            context.set_source_rgba(0., 0., 0., 1.);
+            context.mask(&Pattern::create_rgba(0., 0., 0., 0.5));
+            let mut mask = Pattern::create_linear(0., 0., 100., 0.);
+            mask.add_color_stop_rgba(0., 0., 0., 0., 0.).unwrap();
+            mask.add_color_stop_rgba(1., 0., 0., 0., 1.).unwrap();
+            context.mask(&mask);
+        });
+        let contents = &page_contents(&document)[0];
+        assert_eq!(contents, "1 0 0 -1 0 50 cm\n\
+                              q\n/GS0 gs\n0 0 0 rg\n0 0 100 50 re f\nQ\n\
+                              q\n/GS1 gs\n0 0 0 rg\n0 0 100 50 re f\nQ\n");
+        let objects = objects(&document);
+        let bodies = objects.iter().filter_map(|object| object.as_ref()).collect::<Vec<_>>();
+        assert!(bodies.iter().any(|body| body.contains("/GS1 << /SMask << /Type /Mask \
+                                                        /S /Luminosity /G ")));
+        // The alpha of the mask is rendered one pixel per point
+        let mask = bodies.iter().find(|body| body.contains("/Subtype /Image")).unwrap();
+        assert!(mask.contains("/Width 100 /Height 50 /ColorSpace /DeviceGray"));
+        let alphas = stream_data(&document, mask);
+        assert_eq!((alphas[0], alphas[99], alphas[100]), (1, 254, 1));
+    }
+
+    #[test]
+    fn test_create() {
+        let path = Path::new("test_pdf_surface.pdf");
+        {
+            let mut surface = PdfSurface::create(path, 10., 10.).unwrap();
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.paint();
+        }
+        // Dropping the surface finished it
+        let written = fs::read(path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(page_contents(&written).len(), 1);
+
+        assert_eq!(PdfSurface::create(Path::new("missing/directory/file.pdf"), 10., 10.).err(),
+                   Some(Status::FileNotFound));
+    }
+
+    // A writer that fails after `remaining` bytes.
+    struct ShortWriter {
+        remaining: usize,
+    }
+
+    impl Write for ShortWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.remaining == 0 {
+                return Err(io::Error::other("full"));
+            }
+            let written = buf.len().min(self.remaining);
+            self.remaining -= written;
+            Ok(written)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_error() {
+        assert_eq!(PdfSurface::create_for_stream(ShortWriter { remaining: 4 }, 10., 10.).err(),
+                   Some(Status::WriteError));
+        let mut surface = PdfSurface::create_for_stream(ShortWriter { remaining: 100 }, 10., 10.)
+            .unwrap();
+        assert_eq!(surface.show_page(), Err(Status::WriteError));
+        assert_eq!(surface.finish(), Err(Status::WriteError));
+    }
+}
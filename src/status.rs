@@ -34,7 +34,12 @@
  *
  */
 
-enum Status{
+//! Defines the error statuses that Cairus operations can report, analogous to cairo_status_t.
+
+/// Indicates the result of a Cairus operation.  Fallible functions return one of the error
+/// variants as the `Err` value of a `Result`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Status{
 
     Success = 0,
     NoMemory,
@@ -50,10 +55,16 @@ enum Status{
     WriteError,
     SurfaceFinished,
     SurfaceTypeMismatch,
+    PatternTypeMismatch,
     InvalidContent,
     InvalidFormat,
     InvalidVisual,
     FileNotFound,
+    InvalidDash,
+    InvalidIndex,
+    InvalidStride,
+    InvalidSize,
+    InvalidMeshConstruction,
     LastStatus
 
-}
\ No newline at end of file
+}
@@ -0,0 +1,706 @@
+/*
+ * Cairus - a reimplementation of the cairo graphics library in Rust
+ *
+ * Copyright © 2017 CairusOrg
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it either under the terms of the GNU Lesser General Public
+ * License version 2.1 as published by the Free Software Foundation
+ * (the "LGPL") or, at your option, under the terms of the Mozilla
+ * Public License Version 2.0 (the "MPL"). If you do not alter this
+ * notice, a recipient may use your version of this file under either
+ * the MPL or the LGPL.
+ *
+ * You should have received a copy of the LGPL along with this library
+ * in the file LICENSE-LGPL-2_1; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Suite 500, Boston, MA 02110-1335, USA
+ * You should have received a copy of the MPL along with this library
+ * in the file LICENSE-MPL-2_0
+ *
+ * The contents of this file are subject to the Mozilla Public License
+ * Version 2.0 (the "License"); you may not use this file except in
+ * compliance with the License. You may obtain a copy of the License at
+ * http://www.mozilla.org/MPL/
+ *
+ * This software is distributed on an "AS IS" basis, WITHOUT WARRANTY
+ * OF ANY KIND, either express or implied. See the LGPL or the MPL for
+ * the specific language governing rights and limitations.
+ *
+ * The Original Code is the cairus graphics library.
+ *
+ */
+
+//! This module converts paths into the outlines that get filled when a context strokes them.
+//!
+//! Every straight segment of a flattened path becomes a rectangle as wide as the line, and every
+//! join and cap adds a small polygon of its own.  None of these polygons are merged; they are all
+//! given the same orientation, so filling them together with the winding rule paints their
+//! union.  This keeps the stroker simple and leaves the overlaps to `bo_trap::sweep`, which has to
+//! handle overlapping edges anyway.
+//!
+//! Dashed strokes are split into their "on" pieces first, and each piece is then stroked as an
+//! open polyline of its own.
+
+use std::f32;
+use common_geometry::{Point, Edge};
+use context::{LineCap, LineJoin};
+use path::Polyline;
+
+// The most segments a round join or cap is approximated with, which keeps within a tolerance of
+// 0.1 for lines up to about 2600 pixels wide.
+const MAX_CIRCLE_SEGMENTS: usize = 256;
+
+/// The parameters of a stroke, analogous to cairo_stroke_style_t.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrokeStyle {
+    pub line_width: f32,
+    pub line_cap: LineCap,
+    pub line_join: LineJoin,
+    pub miter_limit: f32,
+    /// Alternating lengths of the "on" and "off" parts of a dash, or empty for a solid line.
+    pub dashes: Vec<f32>,
+    /// How far into the dash pattern the stroke starts.
+    pub dash_offset: f32,
+}
+
+impl StrokeStyle {
+    /// Returns cairo's default stroke style: a 2 unit wide line with butt caps and miter joins,
+    /// and a miter limit of 10.
+    pub fn new() -> StrokeStyle {
+        StrokeStyle {
+            line_width: 2.,
+            line_cap: LineCap::Butt,
+            line_join: LineJoin::Miter,
+            miter_limit: 10.,
+            dashes: Vec::new(),
+            dash_offset: 0.,
+        }
+    }
+
+    /// Returns the length of the dash pattern, counted twice when it has an odd number of
+    /// lengths, analogous to _cairo_stroke_style_dash_period.
+    pub fn dash_period(&self) -> f32 {
+        let period: f32 = self.dashes.iter().sum();
+        if self.dashes.len() % 2 == 1 {
+            period * 2.
+        } else {
+            period
+        }
+    }
+
+    /// Returns the share of the line the dash pattern covers, up to 1.  Like cairo's
+    /// _cairo_stroke_style_dash_stroked, square and round caps add to every "on" dash as much
+    /// of the following gap as they can cover.
+    pub fn dash_coverage(&self) -> f32 {
+        let cap_scale = match self.line_cap {
+            LineCap::Butt => 0.,
+            // cairo's approximation of the area of the round caps
+            LineCap::Round => 9. * f32::consts::PI / 32.,
+            LineCap::Square => 1.,
+        };
+        let mut stroked = 0.;
+        let count = self.dashes.len() * if self.dashes.len() % 2 == 1 { 2 } else { 1 };
+        for idx in (0..count).step_by(2) {
+            let on = self.dashes[idx % self.dashes.len()];
+            let off = self.dashes[(idx + 1) % self.dashes.len()];
+            stroked += on + cap_scale * off.min(self.line_width);
+        }
+        (stroked / self.dash_period()).min(1.)
+    }
+}
+
+impl Default for StrokeStyle {
+    fn default() -> StrokeStyle {
+        StrokeStyle::new()
+    }
+}
+
+/// Returns the polygons outlining `polylines` stroked with `style`.  The stroke is the union of
+/// the polygons.
+///
+/// `tolerance` is how far round joins and caps may stray from a true circle.
+pub fn stroke_polygons(polylines: &[Polyline], style: &StrokeStyle, tolerance: f32) -> Vec<Vec<Point>> {
+    let mut polygons = Vec::new();
+    if style.line_width <= 0. {
+        return polygons;
+    }
+
+    let half_width = style.line_width / 2.;
+    for polyline in polylines {
+        if style.dashes.is_empty() {
+            stroke_polyline(polyline, style, tolerance, &mut polygons);
+            continue;
+        }
+
+        let (pieces, dots) = dash_polyline(polyline, &style.dashes, style.dash_offset);
+        for piece in pieces.iter() {
+            stroke_polyline(piece, style, tolerance, &mut polygons);
+        }
+        // A dash of zero length is only its two caps, facing along the line
+        for (point, direction) in dots {
+            add_cap(point, direction, style, half_width, tolerance, &mut polygons);
+            add_cap(point, scale(direction, -1.), style, half_width, tolerance, &mut polygons);
+        }
+    }
+    polygons
+}
+
+/// Splits `polyline` into the pieces where `dashes` is "on", starting `offset` into the dash
+/// pattern.  The pattern restarts at the beginning of every polyline.
+///
+/// Returns the pieces with length, and separately the position and direction of every "on" dash
+/// of zero length.  Like in cairo, a pattern with an odd number of lengths is repeated so that
+/// its lengths alternate between "on" and "off" both times through.  `dashes` must not be
+/// negative, and must not all be zero.
+pub fn dash_polyline(polyline: &Polyline, dashes: &[f32], offset: f32) -> (Vec<Polyline>, Vec<(Point, Point)>) {
+    let mut pattern = dashes.to_vec();
+    if pattern.len() % 2 == 1 {
+        pattern.extend_from_slice(dashes);
+    }
+    let pattern_length: f32 = pattern.iter().sum();
+
+    // Find the place in the pattern where the polyline starts
+    let mut offset = offset % pattern_length;
+    if offset < 0. {
+        offset += pattern_length;
+    }
+    let mut index = 0;
+    let mut on = true;
+    while offset > pattern[index] || (offset == pattern[index] && pattern[index] > 0.) {
+        offset -= pattern[index];
+        index = (index + 1) % pattern.len();
+        on = !on;
+    }
+    let mut remaining = pattern[index] - offset;
+
+    let mut points = polyline.points.clone();
+    points.dedup();
+    if polyline.closed && points.len() > 1 && points[0] == points[points.len() - 1] {
+        points.pop();
+    }
+
+    let mut pieces: Vec<Polyline> = Vec::new();
+    let mut dots = Vec::new();
+    if points.len() == 1 {
+        if on {
+            pieces.push(polyline.clone());
+        }
+        return (pieces, dots);
+    }
+
+    let starts_on = on;
+    let mut first_piece_is_whole = false;
+    let mut current: Option<Vec<Point>> = if on { Some(vec![points[0]]) } else { None };
+    let segment_count = if polyline.closed { points.len() } else { points.len() - 1 };
+    for idx in 0..segment_count {
+        let from = points[idx];
+        let to = points[(idx + 1) % points.len()];
+        let delta = to - from;
+        let length = (delta.x * delta.x + delta.y * delta.y).sqrt();
+        let direction = direction(from, to);
+
+        let mut travelled = 0.;
+        while travelled + remaining <= length {
+            travelled += remaining;
+            let point = add(from, scale(direction, travelled));
+            match current.take() {
+                Some(mut piece) => {
+                    push_distinct(&mut piece, point);
+                    if piece.len() == 1 {
+                        dots.push((point, direction));
+                    } else {
+                        first_piece_is_whole = first_piece_is_whole || (starts_on && pieces.is_empty());
+                        pieces.push(Polyline { points: piece, closed: false });
+                    }
+                },
+                None => current = Some(vec![point]),
+            }
+            on = !on;
+            index = (index + 1) % pattern.len();
+            remaining = pattern[index];
+        }
+        remaining -= length - travelled;
+
+        if let Some(ref mut piece) = current {
+            push_distinct(piece, to);
+        }
+    }
+
+    if let Some(mut piece) = current {
+        if polyline.closed && starts_on && pieces.is_empty() && dots.is_empty() {
+            // The dash never turned off, the whole polyline is stroked as it is
+            pieces.push(polyline.clone());
+        } else if polyline.closed && first_piece_is_whole {
+            // The last piece runs through the start of the polyline into the first piece
+            let first = pieces.remove(0);
+            for &point in first.points.iter() {
+                push_distinct(&mut piece, point);
+            }
+            pieces.push(Polyline { points: piece, closed: false });
+        } else if piece.len() > 1 {
+            pieces.push(Polyline { points: piece, closed: false });
+        }
+    }
+
+    (pieces, dots)
+}
+
+// Appends `point` to `points` unless it would repeat the last point.
+fn push_distinct(points: &mut Vec<Point>, point: Point) {
+    if points.last() != Some(&point) {
+        points.push(point);
+    }
+}
+
+/// Returns the edges of `polygons`, each polygon oriented the same way so that overlapping
+/// polygons fill as their union under the winding rule.  Polygons without area are dropped.
+pub fn polygon_edges(polygons: &[Vec<Point>]) -> Vec<Edge> {
+    let mut edges = Vec::new();
+    for polygon in polygons {
+        let area = signed_area(polygon);
+        if area == 0. {
+            continue;
+        }
+
+        for idx in 0..polygon.len() {
+            let (mut from, mut to) = (polygon[idx], polygon[(idx + 1) % polygon.len()]);
+            if area < 0. {
+                std::mem::swap(&mut from, &mut to);
+            }
+            if from != to {
+                edges.push(Edge::from_points(from, to));
+            }
+        }
+    }
+    edges
+}
+
+// Adds the polygons stroking a single polyline to `polygons`.
+fn stroke_polyline(polyline: &Polyline, style: &StrokeStyle, tolerance: f32,
+                   polygons: &mut Vec<Vec<Point>>) {
+    let half_width = style.line_width / 2.;
+    let mut points = polyline.points.clone();
+    points.dedup();
+    if polyline.closed && points.len() > 1 && points[0] == points[points.len() - 1] {
+        points.pop();
+    }
+
+    // A sub-path that never leaves its first point is drawn as a dot by round and square caps,
+    // unless it is nothing but a move_to
+    if points.len() == 1 {
+        if polyline.closed || polyline.points.len() > 1 {
+            add_dot(points[0], style, half_width, tolerance, polygons);
+        }
+        return;
+    }
+
+    let segment_count = if polyline.closed { points.len() } else { points.len() - 1 };
+    for idx in 0..segment_count {
+        let from = points[idx];
+        let to = points[(idx + 1) % points.len()];
+        let normal = scale(normal(direction(from, to)), half_width);
+        polygons.push(vec![add(from, normal), add(to, normal), sub(to, normal), sub(from, normal)]);
+    }
+
+    // Joins sit between every pair of segments, which for a closed polyline includes the last
+    // and first segments
+    let (first_join, last_join) = if polyline.closed { (0, points.len()) } else { (1, points.len() - 1) };
+    for idx in first_join..last_join {
+        let previous = points[(idx + points.len() - 1) % points.len()];
+        let next = points[(idx + 1) % points.len()];
+        add_join(points[idx], direction(previous, points[idx]), direction(points[idx], next),
+                 style, half_width, tolerance, polygons);
+    }
+
+    if !polyline.closed {
+        let last = points.len() - 1;
+        add_cap(points[0], direction(points[1], points[0]), style, half_width, tolerance, polygons);
+        add_cap(points[last], direction(points[last - 1], points[last]), style, half_width,
+                tolerance, polygons);
+    }
+}
+
+// Adds the polygon joining a segment travelling along `incoming` into `vertex` to a segment
+// leaving `vertex` along `outgoing`.
+fn add_join(vertex: Point, incoming: Point, outgoing: Point, style: &StrokeStyle, half_width: f32,
+            tolerance: f32, polygons: &mut Vec<Vec<Point>>) {
+    let cross = incoming.x * outgoing.y - incoming.y * outgoing.x;
+    let dot = incoming.x * outgoing.x + incoming.y * outgoing.y;
+    if cross == 0. && dot > 0. {
+        // The segments carry straight on, there is no gap to fill
+        return;
+    }
+
+    if style.line_join == LineJoin::Round {
+        polygons.push(circle(vertex, half_width, tolerance));
+        return;
+    }
+
+    // The gap between the segments' rectangles opens on the outside of the turn
+    let side = if cross > 0. { -half_width } else { half_width };
+    let outer_in = add(vertex, scale(normal(incoming), side));
+    let outer_out = add(vertex, scale(normal(outgoing), side));
+
+    // The miter length divided by the line width is 1 / sin(theta / 2), where theta is the
+    // angle between the segments.  In terms of the turn between the directions,
+    // sin(theta / 2) ^ 2 = (1 + dot) / 2.
+    let use_miter = style.line_join == LineJoin::Miter && dot > -1. &&
+                    2. / (1. + dot) <= style.miter_limit * style.miter_limit;
+    if use_miter {
+        let offset = add(sub(outer_in, vertex), sub(outer_out, vertex));
+        let tip = add(vertex, scale(offset, 1. / (1. + dot)));
+        polygons.push(vec![vertex, outer_in, tip, outer_out]);
+    } else {
+        polygons.push(vec![vertex, outer_in, outer_out]);
+    }
+}
+
+// Adds the cap at the `end` of a polyline, where `outward` points away from the line.
+fn add_cap(end: Point, outward: Point, style: &StrokeStyle, half_width: f32, tolerance: f32,
+           polygons: &mut Vec<Vec<Point>>) {
+    match style.line_cap {
+        LineCap::Butt => {},
+        LineCap::Round => polygons.push(circle(end, half_width, tolerance)),
+        LineCap::Square => {
+            let normal = scale(normal(outward), half_width);
+            let extension = scale(outward, half_width);
+            polygons.push(vec![add(end, normal), add(add(end, normal), extension),
+                               add(sub(end, normal), extension), sub(end, normal)]);
+        },
+    }
+}
+
+// Adds the dot drawn for a sub-path of zero length.  Square caps make a square lined up with the
+// axes, since a point has no direction of its own.
+fn add_dot(center: Point, style: &StrokeStyle, half_width: f32, tolerance: f32,
+           polygons: &mut Vec<Vec<Point>>) {
+    match style.line_cap {
+        LineCap::Butt => {},
+        LineCap::Round => polygons.push(circle(center, half_width, tolerance)),
+        LineCap::Square => polygons.push(vec![
+            Point::new(center.x - half_width, center.y - half_width),
+            Point::new(center.x + half_width, center.y - half_width),
+            Point::new(center.x + half_width, center.y + half_width),
+            Point::new(center.x - half_width, center.y + half_width),
+        ]),
+    }
+}
+
+// Returns a polygon approximating the circle around `center`, staying within `tolerance` of it.
+// The number of segments is capped at MAX_CIRCLE_SEGMENTS, so a tiny tolerance, or a large scale
+// shrinking the user space one, can't make the polygon huge.
+fn circle(center: Point, radius: f32, tolerance: f32) -> Vec<Point> {
+    let segments = if radius <= tolerance {
+        4
+    } else {
+        let step = 2. * (1. - tolerance / radius).acos();
+        ((2. * f32::consts::PI / step).ceil().min(MAX_CIRCLE_SEGMENTS as f32) as usize).max(4)
+    };
+
+    (0..segments).map(|idx| {
+        let angle = 2. * f32::consts::PI * idx as f32 / segments as f32;
+        Point::new(center.x + radius * angle.cos(), center.y + radius * angle.sin())
+    }).collect()
+}
+
+// Twice the signed area of `polygon`, positive for polygons wound like the quads built for
+// segments.
+fn signed_area(polygon: &[Point]) -> f32 {
+    let mut area = 0.;
+    for idx in 0..polygon.len() {
+        let (from, to) = (polygon[idx], polygon[(idx + 1) % polygon.len()]);
+        area += from.x * to.y - to.x * from.y;
+    }
+    area
+}
+
+// The unit vector pointing from `from` to `to`, which must differ.
+fn direction(from: Point, to: Point) -> Point {
+    let delta = to - from;
+    let length = (delta.x * delta.x + delta.y * delta.y).sqrt();
+    Point::new(delta.x / length, delta.y / length)
+}
+
+// `vector` turned a quarter turn.
+fn normal(vector: Point) -> Point {
+    Point::new(-vector.y, vector.x)
+}
+
+fn add(first: Point, second: Point) -> Point {
+    Point::new(first.x + second.x, first.y + second.y)
+}
+
+fn sub(first: Point, second: Point) -> Point {
+    first - second
+}
+
+fn scale(vector: Point, factor: f32) -> Point {
+    Point::new(vector.x * factor, vector.y * factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_polyline(points: &[(f32, f32)]) -> Polyline {
+        Polyline {
+            points: points.iter().map(|&(x, y)| Point::new(x, y)).collect(),
+            closed: false,
+        }
+    }
+
+    fn style(line_cap: LineCap, line_join: LineJoin) -> StrokeStyle {
+        StrokeStyle {
+            line_width: 2.,
+            line_cap: line_cap,
+            line_join: line_join,
+            miter_limit: 10.,
+            dashes: Vec::new(),
+            dash_offset: 0.,
+        }
+    }
+
+    fn bounds(polygons: &[Vec<Point>]) -> (f32, f32, f32, f32) {
+        let mut bounds = (f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for point in polygons.iter().flat_map(|polygon| polygon.iter()) {
+            bounds.0 = bounds.0.min(point.x);
+            bounds.1 = bounds.1.min(point.y);
+            bounds.2 = bounds.2.max(point.x);
+            bounds.3 = bounds.3.max(point.y);
+        }
+        bounds
+    }
+
+    #[test]
+    fn dash_period_repeats_odd_patterns() {
+        let mut style = style(LineCap::Butt, LineJoin::Miter);
+        style.dashes = vec![1., 2.];
+        assert_eq!(style.dash_period(), 3.);
+        style.dashes = vec![1.];
+        assert_eq!(style.dash_period(), 2.);
+    }
+
+    #[test]
+    fn dash_coverage_counts_caps() {
+        let mut style = style(LineCap::Butt, LineJoin::Miter);
+        style.dashes = vec![1., 3.];
+        assert_eq!(style.dash_coverage(), 0.25);
+        style.line_cap = LineCap::Square;
+        assert_eq!(style.dash_coverage(), 0.75);
+        style.dashes = vec![1., 0.5];
+        assert_eq!(style.dash_coverage(), 1.);
+    }
+
+    #[test]
+    fn single_segment_butt() {
+        let polylines = vec![open_polyline(&[(0., 0.), (10., 0.)])];
+        let polygons = stroke_polygons(&polylines, &style(LineCap::Butt, LineJoin::Miter), 0.1);
+        assert_eq!(polygons.len(), 1);
+        assert_eq!(bounds(&polygons), (0., -1., 10., 1.));
+    }
+
+    #[test]
+    fn single_segment_square_caps() {
+        let polylines = vec![open_polyline(&[(0., 0.), (10., 0.)])];
+        let polygons = stroke_polygons(&polylines, &style(LineCap::Square, LineJoin::Miter), 0.1);
+        assert_eq!(bounds(&polygons), (-1., -1., 11., 1.));
+    }
+
+    #[test]
+    fn single_segment_round_caps() {
+        let polylines = vec![open_polyline(&[(0., 0.), (10., 0.)])];
+        let polygons = stroke_polygons(&polylines, &style(LineCap::Round, LineJoin::Miter), 0.1);
+        let (left, top, right, bottom) = bounds(&polygons);
+        assert_eq!(right, 11.);
+        assert!((-1. ..-0.9).contains(&left));
+        assert!((-1. ..-0.9).contains(&top));
+        assert!(bottom <= 1. && bottom > 0.9);
+    }
+
+    #[test]
+    fn round_cap_segments_are_capped() {
+        // A zero tolerance used to make the number of segments overflow
+        let polylines = vec![open_polyline(&[(0., 0.), (10., 0.)])];
+        let polygons = stroke_polygons(&polylines, &style(LineCap::Round, LineJoin::Miter), 0.);
+        assert!(polygons.iter().all(|polygon| polygon.len() <= MAX_CIRCLE_SEGMENTS));
+        let polygons = stroke_polygons(&polylines, &style(LineCap::Round, LineJoin::Miter), 1e-9);
+        assert!(polygons.iter().all(|polygon| polygon.len() <= MAX_CIRCLE_SEGMENTS));
+    }
+
+    #[test]
+    fn miter_join_reaches_corner() {
+        let polylines = vec![open_polyline(&[(0., 0.), (10., 0.), (10., 10.)])];
+        let polygons = stroke_polygons(&polylines, &style(LineCap::Butt, LineJoin::Miter), 0.1);
+        assert!(polygons.iter().any(|polygon| polygon.contains(&Point::new(11., -1.))));
+    }
+
+    #[test]
+    fn bevel_join_cuts_corner() {
+        let polylines = vec![open_polyline(&[(0., 0.), (10., 0.), (10., 10.)])];
+        let polygons = stroke_polygons(&polylines, &style(LineCap::Butt, LineJoin::Bevel), 0.1);
+        assert!(!polygons.iter().any(|polygon| polygon.contains(&Point::new(11., -1.))));
+        assert_eq!(polygons.len(), 3);
+    }
+
+    #[test]
+    fn miter_limit_falls_back_to_bevel() {
+        // A sharp turn has a long miter, which the limit turns into a bevel
+        let polylines = vec![open_polyline(&[(0., 0.), (10., 0.), (0., 1.)])];
+        let mut sharp = style(LineCap::Butt, LineJoin::Miter);
+        let polygons = stroke_polygons(&polylines, &sharp, 0.1);
+        assert_eq!(polygons[2].len(), 3);
+
+        sharp.miter_limit = 100.;
+        let polygons = stroke_polygons(&polylines, &sharp, 0.1);
+        assert_eq!(polygons[2].len(), 4);
+    }
+
+    #[test]
+    fn closed_polyline_joins_every_corner() {
+        let polylines = vec![Polyline {
+            points: vec![Point::new(0., 0.), Point::new(10., 0.), Point::new(10., 10.),
+                         Point::new(0., 10.)],
+            closed: true,
+        }];
+        let polygons = stroke_polygons(&polylines, &style(LineCap::Square, LineJoin::Miter), 0.1);
+        // Four sides and four joins, and no caps
+        assert_eq!(polygons.len(), 8);
+        assert_eq!(bounds(&polygons), (-1., -1., 11., 11.));
+    }
+
+    #[test]
+    fn degenerate_sub_paths() {
+        let dot = vec![open_polyline(&[(5., 5.), (5., 5.)])];
+        assert!(stroke_polygons(&dot, &style(LineCap::Butt, LineJoin::Miter), 0.1).is_empty());
+        let polygons = stroke_polygons(&dot, &style(LineCap::Square, LineJoin::Miter), 0.1);
+        assert_eq!(bounds(&polygons), (4., 4., 6., 6.));
+
+        // A lone move_to draws nothing, whatever the cap
+        let move_to = vec![open_polyline(&[(5., 5.)])];
+        assert!(stroke_polygons(&move_to, &style(LineCap::Round, LineJoin::Miter), 0.1).is_empty());
+    }
+
+    #[test]
+    fn polygon_edges_share_orientation() {
+        let clockwise = vec![Point::new(0., 0.), Point::new(1., 0.), Point::new(1., 1.)];
+        let counter_clockwise = vec![Point::new(0., 0.), Point::new(1., 1.), Point::new(1., 0.)];
+        let vertical_direction = |edges: Vec<Edge>| {
+            edges.iter().find(|edge| edge.line.point1.x == 1. && edge.line.point2.x == 1.)
+                .unwrap().direction
+        };
+        assert_eq!(vertical_direction(polygon_edges(&[clockwise])),
+                   vertical_direction(polygon_edges(&[counter_clockwise])));
+    }
+
+    fn piece_points(pieces: &[Polyline]) -> Vec<Vec<(f32, f32)>> {
+        pieces.iter()
+            .map(|piece| piece.points.iter().map(|point| (point.x, point.y)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn dash_line() {
+        let polyline = open_polyline(&[(0., 0.), (10., 0.)]);
+        let (pieces, dots) = dash_polyline(&polyline, &[2., 1.], 0.);
+        assert_eq!(piece_points(&pieces), vec![
+            vec![(0., 0.), (2., 0.)],
+            vec![(3., 0.), (5., 0.)],
+            vec![(6., 0.), (8., 0.)],
+            vec![(9., 0.), (10., 0.)],
+        ]);
+        assert!(dots.is_empty());
+    }
+
+    #[test]
+    fn dash_odd_length_pattern_alternates() {
+        // [3] is 3 on, 3 off; [1, 2, 3] is 1 on, 2 off, 3 on, 1 off, 2 on, 3 off
+        let polyline = open_polyline(&[(0., 0.), (12., 0.)]);
+        let (pieces, _) = dash_polyline(&polyline, &[1., 2., 3.], 0.);
+        assert_eq!(piece_points(&pieces), vec![
+            vec![(0., 0.), (1., 0.)],
+            vec![(3., 0.), (6., 0.)],
+            vec![(7., 0.), (9., 0.)],
+        ]);
+    }
+
+    #[test]
+    fn dash_offset_wraps() {
+        let polyline = open_polyline(&[(0., 0.), (6., 0.)]);
+        let (pieces, _) = dash_polyline(&polyline, &[2., 2.], 1.);
+        let (wrapped, _) = dash_polyline(&polyline, &[2., 2.], 9.);
+        let (negative, _) = dash_polyline(&polyline, &[2., 2.], -3.);
+        assert_eq!(piece_points(&pieces), vec![
+            vec![(0., 0.), (1., 0.)],
+            vec![(3., 0.), (5., 0.)],
+        ]);
+        assert_eq!(pieces, wrapped);
+        assert_eq!(pieces, negative);
+    }
+
+    #[test]
+    fn dash_continues_around_corners() {
+        let polyline = open_polyline(&[(0., 0.), (3., 0.), (3., 3.)]);
+        let (pieces, _) = dash_polyline(&polyline, &[4., 1.], 0.);
+        assert_eq!(piece_points(&pieces), vec![
+            vec![(0., 0.), (3., 0.), (3., 1.)],
+            vec![(3., 2.), (3., 3.)],
+        ]);
+    }
+
+    #[test]
+    fn dash_closed_polyline_joins_last_piece_to_first() {
+        let polyline = Polyline {
+            points: vec![Point::new(0., 0.), Point::new(4., 0.), Point::new(4., 4.),
+                         Point::new(0., 4.)],
+            closed: true,
+        };
+        let (pieces, _) = dash_polyline(&polyline, &[3., 2.], 0.);
+        // 16 units around: on 0-3, 5-8, 10-13 and 15-16 running on into 0-3
+        assert_eq!(piece_points(&pieces), vec![
+            vec![(4., 1.), (4., 4.)],
+            vec![(2., 4.), (0., 4.), (0., 3.)],
+            vec![(0., 1.), (0., 0.), (3., 0.)],
+        ]);
+
+        // A dash longer than the polyline keeps it closed
+        let (pieces, _) = dash_polyline(&polyline, &[20., 1.], 0.);
+        assert_eq!(pieces, vec![polyline]);
+    }
+
+    #[test]
+    fn dash_zero_length_dots() {
+        let polylines = vec![open_polyline(&[(0., 0.), (0., 5.)])];
+        let (pieces, dots) = dash_polyline(&polylines[0], &[0., 2.], 0.);
+        assert!(pieces.is_empty());
+        let positions: Vec<f32> = dots.iter().map(|&(point, _)| point.y).collect();
+        assert_eq!(positions, vec![0., 2., 4.]);
+        assert!(dots.iter().all(|&(_, direction)| direction == Point::new(0., 1.)));
+
+        // Butt caps draw nothing for them, round caps draw a dot facing along the line
+        let mut dashed = style(LineCap::Butt, LineJoin::Miter);
+        dashed.dashes = vec![0., 2.];
+        assert!(stroke_polygons(&polylines, &dashed, 0.1).is_empty());
+        dashed.line_cap = LineCap::Square;
+        let polygons = stroke_polygons(&polylines, &dashed, 0.1);
+        assert_eq!(polygons.len(), 6);
+        assert_eq!(bounds(&polygons), (-1., -1., 1., 5.));
+    }
+
+    #[test]
+    fn dash_flattened_curve() {
+        let mut path = ::path::Path::new();
+        path.move_to(Point::new(0., 0.));
+        path.curve_to(Point::new(0., 10.), Point::new(10., 10.), Point::new(10., 0.));
+        let polylines = path.flatten(0.1);
+        let (pieces, _) = dash_polyline(&polylines[0], &[1., 1.], 0.);
+        // The curve is just under 20 units long
+        assert_eq!(pieces.len(), 10);
+        for piece in pieces.iter() {
+            let length: f32 = piece.points.windows(2).map(|pair| {
+                let delta = pair[1] - pair[0];
+                (delta.x * delta.x + delta.y * delta.y).sqrt()
+            }).sum();
+            assert!(length <= 1.001);
+        }
+    }
+}
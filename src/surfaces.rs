@@ -41,9 +41,18 @@
 //! on them by contexts.  They are the 'canvas' of Cairus.
 
 use std::path::Path;
-use std::slice::{IterMut, Iter};
+use std::fs::File;
+use std::io::{BufWriter, ErrorKind, Read, Write};
+use std::ops::{Deref, DerefMut};
 use std::vec::IntoIter;
-use types::Rgba;
+use types::{Rgba, ChannelOrder, AlphaMode};
+use status::Status;
+use operators::{Operator, fetch_operator};
+use netpbm;
+pub use pdf::PdfSurface;
+pub use svg::SvgSurface;
+use bmp;
+use tga;
 extern crate image;
 
 
@@ -51,15 +60,69 @@ extern crate image;
 ///Format enum descriptors for the surface object
 ///These are specifically the format types copied from the C implementation,
 ///analogous to cairo_format_t
+///
+///Pixels are packed into rows of bytes.  The 16 and 32 bit formats store each pixel as one
+///native-endian integer, with the channels in the order of the name from the most significant
+///bits down, and colors pre-multiplied by alpha.  A1 packs eight pixels into each byte, the first
+///in the least significant bit.  The F formats store 32 bit floats and lose no precision.
 #[allow(non_camel_case_types)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Format {
     Invalid,
+    /// 8 bits each of alpha, red, green and blue.
     ARGB32,
+    /// 8 bits each of red, green and blue, with the upper 8 bits unused.  Always opaque.
     RGB24,
+    /// 8 bits of alpha, no color.
     A8,
+    /// 1 bit of alpha, no color.
     A1,
+    /// 5 bits of red, 6 of green and 5 of blue.  Always opaque.
     RGB16_565,
+    /// 10 bits each of red, green and blue, with the upper 2 bits unused.  Always opaque.
     RGB30,
+    /// A float each for red, green and blue.  Always opaque.
+    RGB96F,
+    /// A float each for red, green, blue and alpha, in that order.
+    RGBA128F,
+}
+
+impl Format {
+    // The number of bits each pixel takes up, or None for Format::Invalid.
+    fn bits_per_pixel(&self) -> Option<usize> {
+        match *self {
+            Format::Invalid => None,
+            Format::ARGB32 | Format::RGB24 | Format::RGB30 => Some(32),
+            Format::A8 => Some(8),
+            Format::A1 => Some(1),
+            Format::RGB16_565 => Some(16),
+            Format::RGB96F => Some(96),
+            Format::RGBA128F => Some(128),
+        }
+    }
+
+    // The number of bytes the pixels of a row `width` wide take up, without any padding.
+    fn row_length(&self, width: usize) -> Option<usize> {
+        let bits = self.bits_per_pixel()?.checked_mul(width)?;
+        Some(bits.checked_add(7)? / 8)
+    }
+
+    // The number of bytes between the starts of two rows of pixels `width` wide, which is
+    // rounded up to a multiple of 4 like in cairo.
+    fn stride_for_width(&self, width: usize) -> Option<usize> {
+        Some(self.row_length(width)?.checked_add(3)? / 4 * 4)
+    }
+}
+
+/// Returns the stride to use for rows of pixels of `format` that are `width` wide, or None if
+/// the format is invalid or the row would be too large.  Analogous to
+/// cairo_format_stride_for_width().
+///
+/// The stride is the number of bytes from the start of one row to the start of the next.  This
+/// is the stride `ImageSurface::create` uses, and the one to allocate memory for
+/// `ImageSurface::create_for_data` with when the rows need no other padding.
+pub fn format_stride_for_width(format: Format, width: usize) -> Option<usize> {
+    format.stride_for_width(width)
 }
 
 /// Analogous to cairo_surface_type_t, indicates target drawing type
@@ -95,120 +158,614 @@ pub enum Type {
 /// will be used in rendering to images and calculating clipping, and the pixels will be the things
 /// that actually are operated on by stroke or paint operations.  See the
 /// `test_image_surface_with_operator` test case below for an example of what that might look like.
-pub struct ImageSurface {
-    // base is just a collection of pixels
-    base: Vec<Rgba>,
+///
+/// The pixels are stored packed in the surface's `Format`, and are converted to and from Rgba's
+/// as they are read and written, so a surface only costs as much memory as its format needs.
+///
+/// A surface either owns its pixels, or draws into memory that belongs to someone else for the
+/// lifetime `'d`; see `ImageSurface::create_for_data`.
+pub struct ImageSurface<'d> {
+    // base is the rows of packed pixels, each `stride` bytes long
+    base: Data<'d>,
+    format: Format,
+    stride: usize,
     pub width: usize,
     pub height: usize,
 }
 
-/// ImageSurface provides iter(), into_iter(), and iter_mut() so that when a Cairus context calls
-/// paint, it can simply iterate through the pixels in the image surface and use a image
+/// ImageSurface provides iter(), into_iter(), and update_pixels() so that when a Cairus context
+/// calls paint, it can simply go through the pixels in the image surface and use a image
 /// compositing operator to operate on them.  See `operators.rs` for those operations.
 /// A valid ImageSurface needs tangible height and width dimensions to be successfully created.
-impl ImageSurface {
-    // Analogous to cairo_create(), you pass in a width and height and get in a surface in exchange.
-    pub fn create(width: usize, height: usize) -> ImageSurface {
-        if width <= 0 || height <=0 {
+impl<'d> ImageSurface<'d> {
+    // Analogous to cairo_image_surface_create(), you pass in a format, width and height and get
+    // a transparent surface in exchange.
+    pub fn create(format: Format, width: usize, height: usize) -> ImageSurface<'static> {
+        if width == 0 || height == 0 {
             panic!("error: ImageSurface dimensions are not supported.")
         }
-        else {
-            ImageSurface {
-                base: vec![Rgba::new(0., 0., 0., 0.); width * height],
-                width: width,
-                height: height,
-            }
+        let stride = match format.stride_for_width(width) {
+            Some(stride) => stride,
+            None => panic!("error: ImageSurface format is not supported."),
+        };
+        ImageSurface {
+            base: Data::Owned(vec![0; stride * height]),
+            format: format,
+            stride: stride,
+            width: width,
+            height: height,
         }
     }
 
-    pub fn iter(&self) -> Iter<Rgba> {
-        self.base.iter()
+    /// Returns a surface that draws into `data`, which holds `height` rows of pixels packed in
+    /// `format`, with the rows starting `stride` bytes apart.  Analogous to
+    /// cairo_image_surface_create_for_data().
+    ///
+    /// The pixels already in `data` are kept, and `data` is borrowed for as long as the surface
+    /// lives.  The stride may be anything from the bytes a row of pixels takes up, which allows
+    /// for any padding between the rows, although `format_stride_for_width` is what cairo uses.
+    /// The last row only needs to be as long as its pixels.
+    ///
+    /// Returns Status::InvalidFormat for Format::Invalid, Status::InvalidSize for an empty
+    /// surface or `data` that is too small, and Status::InvalidStride for a stride that is
+    /// shorter than a row.
+    pub fn create_for_data(data: &'d mut [u8], format: Format, width: usize, height: usize,
+                           stride: usize) -> Result<ImageSurface<'d>, Status> {
+        if format == Format::Invalid {
+            return Err(Status::InvalidFormat);
+        }
+        if width == 0 || height == 0 {
+            return Err(Status::InvalidSize);
+        }
+        let row_length = format.row_length(width).ok_or(Status::InvalidSize)?;
+        if stride < row_length {
+            return Err(Status::InvalidStride);
+        }
+        let length = stride.checked_mul(height - 1)
+            .and_then(|length| length.checked_add(row_length));
+        match length {
+            Some(length) if length <= data.len() => {},
+            _ => return Err(Status::InvalidSize),
+        }
+        Ok(ImageSurface {
+            base: Data::Borrowed(data),
+            format: format,
+            stride: stride,
+            width: width,
+            height: height,
+        })
     }
 
-    pub fn iter_mut(&mut self) -> IterMut<Rgba> {
-        self.base.iter_mut()
+    /// Returns a copy of the surface that owns its pixels, whether or not this one does.  The
+    /// copy is packed with the stride `ImageSurface::create` would use.
+    pub fn copy(&self) -> ImageSurface<'static> {
+        let mut copy = ImageSurface::create(self.format, self.width, self.height);
+        let length = self.format.row_length(self.width).unwrap();
+        for y in 0..self.height {
+            let row = &self.base[y * self.stride..y * self.stride + length];
+            copy.base[y * copy.stride..y * copy.stride + length].copy_from_slice(row);
+        }
+        copy
+    }
+
+    /// Returns the memory holding the pixels, laid out as described by `get_format` and
+    /// `get_stride`.  Analogous to cairo_image_surface_get_data().
+    pub fn get_data(&self) -> &[u8] {
+        &self.base
+    }
+
+    /// Returns the memory holding the pixels for changing.  See `get_data`.
+    pub fn get_data_mut(&mut self) -> &mut [u8] {
+        &mut self.base
+    }
+
+    /// Returns the number of bytes from the start of one row of pixels to the start of the next.
+    /// Analogous to cairo_image_surface_get_stride().
+    pub fn get_stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Returns the format the pixels are packed in.  Analogous to
+    /// cairo_image_surface_get_format().
+    pub fn get_format(&self) -> Format {
+        self.format
+    }
+
+    /// Returns an iterator over copies of the pixels, row by row.
+    pub fn iter(&self) -> Pixels<'_> {
+        Pixels {
+            surface: self,
+            idx: 0,
+        }
+    }
+
+    /// Calls `f` with the index and a copy of every pixel, row by row, and stores what it leaves
+    /// in the copy back into the surface.
+    pub fn update_pixels<F>(&mut self, mut f: F) where F: FnMut(usize, &mut Rgba) {
+        for idx in 0..self.width * self.height {
+            let mut pixel = self.read(idx);
+            f(idx, &mut pixel);
+            self.write(idx, &pixel);
+        }
+    }
+
+    /// Reads the PNG file at `path` into a new surface, analogous to
+    /// cairo_image_surface_create_from_png().  See `create_from_png_stream`.
+    ///
+    /// Returns Status::FileNotFound if there is no file at `path`, and Status::ReadError if it
+    /// can't be read or isn't a PNG image.
+    pub fn create_from_png(path: &Path) -> Result<ImageSurface<'static>, Status> {
+        match File::open(path) {
+            Ok(file) => ImageSurface::create_from_png_stream(file),
+            Err(ref error) if error.kind() == ErrorKind::NotFound => Err(Status::FileNotFound),
+            Err(_) => Err(Status::ReadError),
+        }
+    }
+
+    /// Reads a PNG image from `reader` into a new surface, analogous to
+    /// cairo_image_surface_create_from_png_stream().
+    ///
+    /// Grayscale and palette images are converted to color, 16 bit channels are cut down to
+    /// 8 bits, and transparency is pre-multiplied like the rest of Cairus expects.  Images with
+    /// an alpha channel or transparent palette entries give a Format::ARGB32 surface, the others
+    /// a Format::RGB24 one.
+    ///
+    /// Returns Status::ReadError if `reader` fails or doesn't hold a PNG image.
+    pub fn create_from_png_stream<R: Read>(mut reader: R) -> Result<ImageSurface<'static>, Status> {
+        let mut bytes = Vec::new();
+        if reader.read_to_end(&mut bytes).is_err() {
+            return Err(Status::ReadError);
+        }
+        let image = match image::load_from_memory_with_format(&bytes, image::ImageFormat::PNG) {
+            Ok(image) => image,
+            Err(_) => return Err(Status::ReadError),
+        };
+        let format = match image {
+            image::DynamicImage::ImageLumaA8(_) | image::DynamicImage::ImageRgba8(_) => {
+                Format::ARGB32
+            },
+            _ => Format::RGB24,
+        };
+        let image = image.to_rgba();
+        if image.width() == 0 || image.height() == 0 {
+            return Err(Status::ReadError);
+        }
+
+        let mut surface = ImageSurface::create(format, image.width() as usize,
+                                               image.height() as usize);
+        let mut pixels = image.pixels();
+        surface.update_pixels(|_, pixel| {
+            let bytes = pixels.next().unwrap().data;
+            *pixel = Rgba::from_bytes(&bytes, ChannelOrder::Rgba, AlphaMode::Straight);
+        });
+        Ok(surface)
+    }
+
+    /// Reads the PAM, PPM or PGM file at `path` into a new surface.  See
+    /// `create_from_netpbm_stream`.
+    ///
+    /// Returns Status::FileNotFound if there is no file at `path`.
+    pub fn create_from_netpbm(path: &Path) -> Result<ImageSurface<'static>, Status> {
+        match File::open(path) {
+            Ok(file) => ImageSurface::create_from_netpbm_stream(file),
+            Err(ref error) if error.kind() == ErrorKind::NotFound => Err(Status::FileNotFound),
+            Err(_) => Err(Status::ReadError),
+        }
+    }
+
+    /// Reads a binary PAM, PPM or PGM image from `reader` into a new surface.  Gray images
+    /// become Format::A8 surfaces of their gray values, color images Format::RGB24 surfaces, and
+    /// images with alpha Format::ARGB32 surfaces.
+    ///
+    /// Returns Status::ReadError if `reader` fails or the image is malformed, and
+    /// Status::InvalidFormat for plain (text) Netpbm images and unknown PAM tuple types.
+    pub fn create_from_netpbm_stream<R: Read>(reader: R) -> Result<ImageSurface<'static>, Status> {
+        netpbm::read(reader)
     }
 
     fn into_bytes(& self) -> Vec<u8> {
         let mut bytes = Vec::new();
-        for pixel in self.base.iter() {
-            for byte in pixel.into_bytes() {
-              bytes.push(byte);
-            }
+        for pixel in self.iter() {
+            bytes.extend_from_slice(&pixel.to_bytes(ChannelOrder::Rgba, AlphaMode::Straight));
         }
         bytes
     }
+    /// The quality `to_file` writes JPEG files with.
+    pub const DEFAULT_JPEG_QUALITY: u8 = 90;
+
     ///The external Rust Image Crate is a library under development to read,
     /// manipulate and write images. At the moment "image" supports reading and writing
     /// JPG and PNG images. The below functions, to_file(), to_png, and to_jpg use this external
     /// library to write output image files, provided a valid Cairus ImageSurface.
-    pub fn to_file(&self, path: &Path){
-        let path_extension = path.extension().unwrap();
-        let extension = (String::from(path_extension.to_str().unwrap())).to_lowercase();
-       // let () = extension2;
-        if extension == "png"  {
-            self.to_png(path)
+    ///
+    /// JPEG files are written with `write_to_jpeg` at DEFAULT_JPEG_QUALITY over white.  PAM,
+    /// PPM, PGM, BMP and TGA files are written with `write_to_pam`, `write_to_ppm`,
+    /// `write_to_pgm`, `write_to_bmp` and `write_to_tga`.
+    ///
+    /// The type of file is picked by the extension of `path`, ignoring case.  Returns
+    /// Status::InvalidFormat for a missing or unsupported extension, or a PGM file of a surface
+    /// that isn't Format::A8, Status::FileNotFound if the directory of `path` doesn't exist, and
+    /// Status::WriteError if the file can't be written.
+    pub fn to_file(&self, path: &Path) -> Result<(), Status> {
+        let extension = match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) => extension.to_lowercase(),
+            None => return Err(Status::InvalidFormat),
+        };
+        match extension.as_str() {
+            "png" => self.to_png(path),
+            "jpg" | "jpeg" => self.to_jpg(path),
+            "pam" => self.write_file(path, |surface, writer| surface.write_to_pam(writer)),
+            "ppm" => self.write_file(path, |surface, writer| surface.write_to_ppm(writer)),
+            "pgm" if self.format == Format::A8 => {
+                self.write_file(path, |surface, writer| surface.write_to_pgm(writer))
+            },
+            "bmp" => self.write_file(path, |surface, writer| surface.write_to_bmp(writer)),
+            "tga" => self.write_file(path, |surface, writer| surface.write_to_tga(writer)),
+            _ => Err(Status::InvalidFormat),
         }
-        else if extension == "jpg" {
-            self.to_jpg(path);
+    }
+
+    /// Writes the surface to `writer` as a PNG image, analogous to
+    /// cairo_surface_write_to_png_stream().  Returns Status::WriteError if `writer` fails.
+    pub fn write_to_png_stream<W: Write>(&self, writer: W) -> Result<(), Status> {
+        let buffer = self.into_bytes();
+        image::png::PNGEncoder::new(writer)
+            .encode(buffer.as_slice(), self.width as u32, self.height as u32, image::RGBA(8))
+            .map_err(|_| Status::WriteError)
+    }
+
+    /// Writes the surface to `writer` as a PAM image with straight alpha.  Returns
+    /// Status::WriteError if `writer` fails.
+    pub fn write_to_pam<W: Write>(&self, writer: W) -> Result<(), Status> {
+        netpbm::write_pam(self, writer)
+    }
+
+    /// Writes the surface to `writer` as a PPM image, which has no alpha, so the colors are
+    /// written pre-multiplied as if the surface was shown over black.  Returns
+    /// Status::WriteError if `writer` fails.
+    pub fn write_to_ppm<W: Write>(&self, writer: W) -> Result<(), Status> {
+        netpbm::write_ppm(self, writer)
+    }
+
+    /// Writes the alpha of a Format::A8 surface to `writer` as a PGM image.  Returns
+    /// Status::InvalidFormat for surfaces of other formats and Status::WriteError if `writer`
+    /// fails.
+    pub fn write_to_pgm<W: Write>(&self, writer: W) -> Result<(), Status> {
+        netpbm::write_pgm(self, writer)
+    }
+
+    /// Writes the surface to `writer` as a 24 bit BMP image, which has no alpha, so the colors
+    /// are written pre-multiplied as if the surface was shown over black.  Returns
+    /// Status::InvalidSize if the surface is too large for a BMP image and Status::WriteError if
+    /// `writer` fails.
+    pub fn write_to_bmp<W: Write>(&self, writer: W) -> Result<(), Status> {
+        bmp::write_bmp(self, writer)
+    }
+
+    /// Writes the surface to `writer` as an uncompressed 32 bit TGA image with straight alpha.
+    /// Returns Status::InvalidSize if the surface is wider or taller than 65535 pixels and
+    /// Status::WriteError if `writer` fails.
+    pub fn write_to_tga<W: Write>(&self, writer: W) -> Result<(), Status> {
+        tga::write_tga(self, writer)
+    }
+
+    /// Writes the surface to `writer` as a JPEG image of `quality` between 1 and 100, where
+    /// higher is better and larger.  JPEG images have no alpha, so the surface is composited
+    /// over `background` first, and what of that is still transparent is shown over black.  The
+    /// background is usually opaque, e.g. `cairus::Rgba::new(1., 1., 1., 1.)` for white.
+    ///
+    /// Returns Status::InvalidSize if the surface is wider or taller than 65535 pixels and
+    /// Status::WriteError if `writer` fails.
+    pub fn write_to_jpeg<W: Write>(&self, mut writer: W, quality: u8, background: Rgba)
+        -> Result<(), Status> {
+        if self.width > u16::MAX as usize || self.height > u16::MAX as usize {
+            return Err(Status::InvalidSize);
         }
-        else {
-            panic!("error: {:?} output not supported", path_extension);
+        let over = fetch_operator(&Operator::Over);
+        let mut buffer = Vec::with_capacity(self.width * self.height * 3);
+        for pixel in self.iter() {
+            let mut color = background;
+            over(&pixel, &mut color);
+            let bytes = color.to_bytes(ChannelOrder::Rgba, AlphaMode::Premultiplied);
+            buffer.extend_from_slice(&bytes[..3]);
         }
+        image::jpeg::JPEGEncoder::new_with_quality(&mut writer, quality.clamp(1, 100))
+            .encode(buffer.as_slice(), self.width as u32, self.height as u32, image::RGB(8))
+            .map_err(|_| Status::WriteError)
     }
 
-    fn to_png(&self, path: &Path) {
-        let buffer = self.into_bytes();
-        let _ = image::save_buffer(path, buffer.as_slice(), self.width as u32,
-                                           self.height as u32, image::RGBA(8)).unwrap();
+    // Writes the file at `path` with `write`.
+    fn write_file<F>(&self, path: &Path, write: F) -> Result<(), Status>
+        where F: FnOnce(&ImageSurface<'d>, &mut BufWriter<File>) -> Result<(), Status> {
+        let mut writer = create_file(path)?;
+        write(self, &mut writer)?;
+        writer.flush().map_err(|_| Status::WriteError)
     }
 
-    fn to_jpg(&self, path: &Path) {
-        let buffer = self.into_bytes();
-        let _ = image::save_buffer(path, buffer.as_slice(), self.width as u32,
-                                           self.height as u32, image::RGBA(8)).unwrap();
+    fn to_png(&self, path: &Path) -> Result<(), Status> {
+        self.write_file(path, |surface, writer| surface.write_to_png_stream(writer))
+    }
+
+    fn to_jpg(&self, path: &Path) -> Result<(), Status> {
+        let white = Rgba::new(1., 1., 1., 1.);
+        self.write_file(path, |surface, writer| {
+            surface.write_to_jpeg(writer, ImageSurface::DEFAULT_JPEG_QUALITY, white)
+        })
     }
 
-    pub fn get(&self, x: usize, y: usize) -> Option<&Rgba> {
+    /// Returns a copy of the pixel at (x, y), or None if it is outside of the surface.
+    pub fn get(&self, x: usize, y: usize) -> Option<Rgba> {
+        if x >= self.width {
+            return None;
+        }
         let position = ImageSurface::calculate_position(self.width, x, y);
-        self.base.get(position)
+        self.get_with_index(position)
     }
 
-    pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut Rgba> {
+    /// Returns the pixel at (x, y) for changing, or None if it is outside of the surface.  The
+    /// change is stored into the surface when the returned `PixelMut` is dropped.
+    pub fn get_mut(&mut self, x: usize, y: usize) -> Option<PixelMut<'_, 'd>> {
+        if x >= self.width {
+            return None;
+        }
         let position = ImageSurface::calculate_position(self.width, x, y);
-        self.base.get_mut(position)
+        self.get_mut_with_index(position)
     }
 
-    pub fn get_with_index(&self, idx: usize) -> Option<&Rgba> {
-        self.base.get(idx)
+    /// Returns a copy of the pixel at `idx` counting row by row, or None if there is no such
+    /// pixel.
+    pub fn get_with_index(&self, idx: usize) -> Option<Rgba> {
+        if idx >= self.width * self.height {
+            return None;
+        }
+        Some(self.read(idx))
     }
 
-    pub fn get_mut_with_index(&mut self, idx: usize) -> Option<&mut Rgba> {
-        self.base.get_mut(idx)
+    /// Returns the pixel at `idx` counting row by row for changing, or None if there is no such
+    /// pixel.  See `get_mut`.
+    pub fn get_mut_with_index(&mut self, idx: usize) -> Option<PixelMut<'_, 'd>> {
+        if idx >= self.width * self.height {
+            return None;
+        }
+        let rgba = self.read(idx);
+        Some(PixelMut {
+            surface: self,
+            idx: idx,
+            rgba: rgba,
+        })
     }
 
     fn calculate_position(width: usize, x: usize, y: usize) -> usize {
         y.wrapping_mul(width).wrapping_add(x)
     }
+
+    // Unpacks the pixel at `idx`, which must be inside of the surface.
+    fn read(&self, idx: usize) -> Rgba {
+        let (x, y) = (idx % self.width, idx / self.width);
+        let row = &self.base[y * self.stride..];
+        match self.format {
+            Format::Invalid => Rgba::new(0., 0., 0., 0.),
+            Format::ARGB32 => {
+                Rgba::from_bytes(&row[4 * x..], ChannelOrder::Argb32, AlphaMode::Premultiplied)
+            },
+            Format::RGB24 => {
+                let word = read_u32(row, x);
+                Rgba::new(unpack(word >> 16, 8), unpack(word >> 8, 8), unpack(word, 8), 1.)
+            },
+            Format::A8 => Rgba::new(0., 0., 0., unpack(row[x] as u32, 8)),
+            Format::A1 => Rgba::new(0., 0., 0., unpack((row[x / 8] >> (x % 8)) as u32, 1)),
+            Format::RGB16_565 => {
+                let word = u16::from_ne_bytes([row[2 * x], row[2 * x + 1]]) as u32;
+                Rgba::new(unpack(word >> 11, 5), unpack(word >> 5, 6), unpack(word, 5), 1.)
+            },
+            Format::RGB30 => {
+                let word = read_u32(row, x);
+                Rgba::new(unpack(word >> 20, 10), unpack(word >> 10, 10), unpack(word, 10), 1.)
+            },
+            Format::RGB96F => {
+                Rgba::new(read_f32(row, 3 * x), read_f32(row, 3 * x + 1),
+                          read_f32(row, 3 * x + 2), 1.)
+            },
+            Format::RGBA128F => {
+                Rgba {
+                    red: read_f32(row, 4 * x),
+                    green: read_f32(row, 4 * x + 1),
+                    blue: read_f32(row, 4 * x + 2),
+                    alpha: read_f32(row, 4 * x + 3),
+                }
+            },
+        }
+    }
+
+    // Packs `rgba` into the pixel at `idx`, which must be inside of the surface.  Formats without
+    // alpha keep just the (pre-multiplied) color, formats without color keep just the alpha.
+    fn write(&mut self, idx: usize, rgba: &Rgba) {
+        let (x, y) = (idx % self.width, idx / self.width);
+        let row = &mut self.base[y * self.stride..];
+        match self.format {
+            Format::Invalid => {},
+            Format::ARGB32 => {
+                let bytes = rgba.to_bytes(ChannelOrder::Argb32, AlphaMode::Premultiplied);
+                row[4 * x..4 * x + 4].copy_from_slice(&bytes);
+            },
+            Format::RGB24 => {
+                let word = pack(rgba.red, 8) << 16 | pack(rgba.green, 8) << 8 |
+                           pack(rgba.blue, 8);
+                write_u32(row, x, word);
+            },
+            Format::A8 => row[x] = pack(rgba.alpha, 8) as u8,
+            Format::A1 => {
+                let bit = 1 << (x % 8);
+                if pack(rgba.alpha, 1) == 1 {
+                    row[x / 8] |= bit;
+                } else {
+                    row[x / 8] &= !bit;
+                }
+            },
+            Format::RGB16_565 => {
+                let word = pack(rgba.red, 5) << 11 | pack(rgba.green, 6) << 5 |
+                           pack(rgba.blue, 5);
+                row[2 * x..2 * x + 2].copy_from_slice(&(word as u16).to_ne_bytes());
+            },
+            Format::RGB30 => {
+                let word = pack(rgba.red, 10) << 20 | pack(rgba.green, 10) << 10 |
+                           pack(rgba.blue, 10);
+                write_u32(row, x, word);
+            },
+            Format::RGB96F => {
+                write_f32(row, 3 * x, rgba.red);
+                write_f32(row, 3 * x + 1, rgba.green);
+                write_f32(row, 3 * x + 2, rgba.blue);
+            },
+            Format::RGBA128F => {
+                write_f32(row, 4 * x, rgba.red);
+                write_f32(row, 4 * x + 1, rgba.green);
+                write_f32(row, 4 * x + 2, rgba.blue);
+                write_f32(row, 4 * x + 3, rgba.alpha);
+            },
+        }
+    }
+}
+
+// Creates the file at `path` for writing an image to.
+fn create_file(path: &Path) -> Result<BufWriter<File>, Status> {
+    match File::create(path) {
+        Ok(file) => Ok(BufWriter::new(file)),
+        Err(ref error) if error.kind() == ErrorKind::NotFound => Err(Status::FileNotFound),
+        Err(_) => Err(Status::WriteError),
+    }
+}
+
+// Returns the lowest `bits` of `value` as a channel between 0 and 1.
+fn unpack(value: u32, bits: u32) -> f32 {
+    let max = (1 << bits) - 1;
+    (value & max) as f32 / max as f32
+}
+
+// Returns `channel`, clamped between 0 and 1, rounded to the nearest of the values `bits` bits
+// can hold.
+fn pack(channel: f32, bits: u32) -> u32 {
+    let max = ((1 << bits) - 1) as f32;
+    (channel.clamp(0., 1.) * max).round() as u32
+}
+
+// Returns the `idx`th 32 bit word of `bytes`.
+fn read_u32(bytes: &[u8], idx: usize) -> u32 {
+    u32::from_ne_bytes([bytes[4 * idx], bytes[4 * idx + 1], bytes[4 * idx + 2], bytes[4 * idx + 3]])
+}
+
+fn write_u32(bytes: &mut [u8], idx: usize, word: u32) {
+    bytes[4 * idx..4 * idx + 4].copy_from_slice(&word.to_ne_bytes());
+}
+
+// Returns the `idx`th 32 bit float of `bytes`.
+fn read_f32(bytes: &[u8], idx: usize) -> f32 {
+    f32::from_bits(read_u32(bytes, idx))
+}
+
+fn write_f32(bytes: &mut [u8], idx: usize, value: f32) {
+    write_u32(bytes, idx, value.to_bits());
+}
+
+// The memory an ImageSurface keeps its pixels in.
+enum Data<'d> {
+    Owned(Vec<u8>),
+    Borrowed(&'d mut [u8]),
+}
+
+impl<'d> Deref for Data<'d> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match *self {
+            Data::Owned(ref bytes) => bytes,
+            Data::Borrowed(ref bytes) => bytes,
+        }
+    }
+}
+
+impl<'d> DerefMut for Data<'d> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match *self {
+            Data::Owned(ref mut bytes) => bytes,
+            Data::Borrowed(ref mut bytes) => bytes,
+        }
+    }
+}
+
+/// Cloning a surface copies its pixels, so the clone owns them even if the original borrows
+/// them.  See `ImageSurface::copy`.
+impl<'d> Clone for ImageSurface<'d> {
+    fn clone(&self) -> ImageSurface<'d> {
+        self.copy()
+    }
+}
+
+/// A pixel of an ImageSurface that is being changed, returned by `ImageSurface::get_mut`.  It
+/// dereferences to the pixel's Rgba, and stores it back into the surface when it is dropped.
+pub struct PixelMut<'a, 'd: 'a> {
+    surface: &'a mut ImageSurface<'d>,
+    idx: usize,
+    rgba: Rgba,
+}
+
+impl<'a, 'd> Deref for PixelMut<'a, 'd> {
+    type Target = Rgba;
+
+    fn deref(&self) -> &Rgba {
+        &self.rgba
+    }
+}
+
+impl<'a, 'd> DerefMut for PixelMut<'a, 'd> {
+    fn deref_mut(&mut self) -> &mut Rgba {
+        &mut self.rgba
+    }
+}
+
+impl<'a, 'd> Drop for PixelMut<'a, 'd> {
+    fn drop(&mut self) {
+        self.surface.write(self.idx, &self.rgba);
+    }
+}
+
+/// An iterator over copies of the pixels of an ImageSurface, returned by `ImageSurface::iter`.
+pub struct Pixels<'a> {
+    surface: &'a ImageSurface<'a>,
+    idx: usize,
+}
+
+impl<'a> Iterator for Pixels<'a> {
+    type Item = Rgba;
+
+    fn next(&mut self) -> Option<Rgba> {
+        let pixel = self.surface.get_with_index(self.idx);
+        if pixel.is_some() {
+            self.idx += 1;
+        }
+        pixel
+    }
 }
 
-impl IntoIterator for ImageSurface {
+impl<'d> IntoIterator for ImageSurface<'d> {
     type Item = Rgba;
     type IntoIter = IntoIter<Rgba>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.base.into_iter()
+        self.iter().collect::<Vec<_>>().into_iter()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use types::Rgba;
-    use surfaces::ImageSurface;
+    use surfaces::{ImageSurface, Format, format_stride_for_width};
     use operators::{Operator, fetch_operator};
+    use status::Status;
     use surfaces::image::GenericImage;
 
     use std::fs;
+    use std::io::{self, Write};
     use std::path::Path;
     extern crate image;
 
@@ -218,7 +775,7 @@ mod tests {
         // Test that ImageSurface's IntoIterator is functioning correctly by comparing every pixel
         // in the surface to the default (which is transparent).
         let transparent_pixel = Rgba::new(0., 0., 0., 0.);
-        let surface = ImageSurface::create(100, 100);
+        let surface = ImageSurface::create(Format::RGBA128F, 100, 100);
         for pixel in surface {
             assert_eq!(pixel, transparent_pixel);
         }
@@ -228,7 +785,7 @@ mod tests {
     fn test_image_surface_into_iter() {
         // Test that the explicit into_iter() call functions correctly.
         let transparent_pixel = Rgba::new(0., 0., 0., 0.);
-        let surface = ImageSurface::create(100, 100);
+        let surface = ImageSurface::create(Format::RGBA128F, 100, 100);
         for pixel in surface.into_iter() {
             assert_eq!(pixel, transparent_pixel);
         }
@@ -239,11 +796,11 @@ mod tests {
     #[test]
     fn test_image_surface_iter() {
         // Passes if ImageSurface::iter() functions properly
-        let surface = ImageSurface::create(100, 100);
+        let surface = ImageSurface::create(Format::RGBA128F, 100, 100);
 
         // Leave pixel.red to default (0.0), change all other channels to 1.0
         let result = surface.iter()
-            .map(|&pixel| {
+            .map(|pixel| {
                 Rgba {
                     red: pixel.red,
                     green: 1.,
@@ -267,15 +824,15 @@ mod tests {
     }
 
     #[test]
-    fn test_image_surface_iter_mut() {
-        // Passes if ImageSurface::iter_mut() functions properly
-        let mut surface = ImageSurface::create(100, 100);
+    fn test_image_surface_update_pixels() {
+        // Passes if ImageSurface::update_pixels() functions properly
+        let mut surface = ImageSurface::create(Format::RGBA128F, 100, 100);
         let expected = Rgba::new(1., 0., 0., 1.);
 
-        for mut pixel in surface.iter_mut() {
+        surface.update_pixels(|_, pixel| {
             pixel.alpha = expected.alpha;
             pixel.red = expected.red;
-        }
+        });
 
         for pixel in surface {
             assert_eq!(pixel, expected);
@@ -286,19 +843,17 @@ mod tests {
     fn test_image_surface_with_operator() {
         // Demonstrates usage with an operator
         //
-        // Our goal here is to take a surface and paint it red.  We use the the surface's iter_mut
-        // function because operators modify the image's pixels in-place.
+        // Our goal here is to take a surface and paint it red.  We use the the surface's
+        // update_pixels function because operators modify the image's pixels in-place.
 
         // Create our source Rgba, destination, and choose an operator
         let source_rgba = Rgba::new(1., 0., 0., 1.);
-        let mut destination = ImageSurface::create(100, 100);
+        let mut destination = ImageSurface::create(Format::RGBA128F, 100, 100);
         let op = Operator::Over;
 
         // Using fetch_operator and the Operator enum.
         let operator = fetch_operator(&op);
-        for mut pixel in destination.iter_mut() {
-            operator(&source_rgba, pixel);
-        }
+        destination.update_pixels(|_, pixel| operator(&source_rgba, pixel));
 
         // Check that the resulting pixels in destination are red RGBA(1, 0, 0, 1)
         let expected = Rgba::new(1., 0., 0., 1.);
@@ -312,7 +867,7 @@ mod tests {
         // verifies that into bytes returns the correct number of bytes and all bytes are correct
 
         // Setup
-        let surface = ImageSurface::create(100, 100);
+        let surface = ImageSurface::create(Format::RGBA128F, 100, 100);
 
         // Call
         let result = surface.into_bytes();
@@ -329,13 +884,13 @@ mod tests {
         // Writes image surface to file then verifies image in file has correct dimensions.
 
         //setup
-        let surface = ImageSurface::create(100, 100);
+        let surface = ImageSurface::create(Format::RGBA128F, 100, 100);
         let path = Path::new("test2.png");
         let expected_width = surface.width as u32;
         let expected_height = surface.height as u32;
 
         //call
-        surface.to_file(path);
+        surface.to_file(path).unwrap();
         let img = image::open(path).unwrap();
         let (result_width, result_height) = img.dimensions();
 
@@ -344,7 +899,7 @@ mod tests {
         assert_eq!(result_height, expected_height, "Error: width was not as expected");
 
         // Cleanup
-        let _ = fs::remove_file(path).unwrap();
+        fs::remove_file(path).unwrap();
     }
 
     #[test]
@@ -352,12 +907,12 @@ mod tests {
         // Writes image surface to file then verifies file content is as expected
 
         // Setup
-        let surface = ImageSurface::create(100, 100);
+        let surface = ImageSurface::create(Format::RGBA128F, 100, 100);
         let transparent_pixel = Rgba::new(0.,0.,0.,0.);
         let path = Path::new("test3.png");
 
         // Call
-        surface.to_file(path);
+        surface.to_file(path).unwrap();
         let img = image::open(path).unwrap().to_rgba();
 
         // Test
@@ -367,7 +922,7 @@ mod tests {
             assert_eq!(result,transparent_pixel, "Error: Image integrity failed");
         }
         // Cleanup
-        let _ = fs::remove_file(path).unwrap();
+        fs::remove_file(path).unwrap();
     }
 
     #[test]
@@ -375,17 +930,17 @@ mod tests {
         // Writes image surface to file and verifies file was created
 
         // Setup
-        let surface = ImageSurface::create(100, 100);
+        let surface = ImageSurface::create(Format::RGBA128F, 100, 100);
         let path = Path::new("test3.jpg");
 
         // Call
-        surface.to_file(path);
+        surface.to_file(path).unwrap();
 
         // Test
         assert!(Path::new(path).exists(), "Error: JPG file was not created");
 
         // Cleanup
-        let _ = fs::remove_file(path).unwrap();
+        fs::remove_file(path).unwrap();
     }
 
     #[test]
@@ -393,17 +948,17 @@ mod tests {
         // Writes image surface to file and verifies file was created
 
         // Setup
-        let surface = ImageSurface::create(100, 100);
+        let surface = ImageSurface::create(Format::RGBA128F, 100, 100);
         let path = Path::new("test3.JPG");
 
         // Call
-        surface.to_file(path);
+        surface.to_file(path).unwrap();
 
         // Test
         assert!(Path::new(path).exists(), "Error: JPG file was not created");
 
         // Cleanup
-        let _ = fs::remove_file(path).unwrap();
+        fs::remove_file(path).unwrap();
     }
 
     #[test]
@@ -411,17 +966,17 @@ mod tests {
         // Tests private to_png() function
 
         // Setup
-        let surface = ImageSurface::create(100, 100);
+        let surface = ImageSurface::create(Format::RGBA128F, 100, 100);
         let path = Path::new("pngfile.png");
 
         // Call
-        surface.to_file(path);
+        surface.to_file(path).unwrap();
 
         // Test
         assert!(Path::new(path).exists(), "Error: PNG file was not created");
 
         // Cleanup
-        let _ = fs::remove_file(path).unwrap();
+        fs::remove_file(path).unwrap();
     }
 
     #[test]
@@ -429,17 +984,17 @@ mod tests {
         // Tests private to_jpg() function
 
         // Setup
-        let surface = ImageSurface::create(100, 100);
+        let surface = ImageSurface::create(Format::RGBA128F, 100, 100);
         let path = Path::new("jaypeg.jpg");
 
         // Call
-        surface.to_file(path);
+        surface.to_file(path).unwrap();
 
         // Test
         assert!(Path::new(path).exists(), "Error: JPG file was not created");
 
         // Cleanup
-        let _ = fs::remove_file(path).unwrap();
+        fs::remove_file(path).unwrap();
     }
 
     #[test]
@@ -448,7 +1003,7 @@ mod tests {
         // Verifies we cannot create a image with a 0 height value
 
         // Call and panic
-        let _ = ImageSurface::create(100, 0);
+        let _ = ImageSurface::create(Format::RGBA128F, 100, 0);
     }
 
     #[test]
@@ -457,29 +1012,464 @@ mod tests {
         // Verifies we cannot create a image with a 0 width value
 
         // Call and panic
-        let _ = ImageSurface::create(0, 100);
+        let _ = ImageSurface::create(Format::RGBA128F, 0, 100);
     }
 
     #[test]
-    #[should_panic]
     fn test_invalid_file_extension() {
         // Verifies we cannot create a image with a invalid type
 
         // Setup
-        let surface = ImageSurface::create(100, 100);
+        let surface = ImageSurface::create(Format::RGBA128F, 100, 100);
         let path = Path::new("test_extension.uyk");
 
-        // Call and panic
-        let _ = surface.to_file(path);
+        // Call
+        let result = surface.to_file(path);
 
-        // Cleanup
-        let _ = fs::remove_file(path).unwrap();
+        // Test
+        assert_eq!(result, Err(Status::InvalidFormat));
+        assert!(!path.exists(), "Error: file was created");
+    }
+
+    #[test]
+    fn test_to_file_errors() {
+        let surface = ImageSurface::create(Format::ARGB32, 10, 10);
+        assert_eq!(surface.to_file(Path::new("no_extension")), Err(Status::InvalidFormat));
+        assert_eq!(surface.to_file(Path::new("no_such_directory/test.png")),
+                   Err(Status::FileNotFound));
+    }
+
+    #[test]
+    fn test_netpbm_files() {
+        let mut surface = ImageSurface::create(Format::ARGB32, 3, 2);
+        *surface.get_mut(2, 1).unwrap() = Rgba::new(0., 1., 0., 0.4);
+        for name in &["netpbm.pam", "netpbm.PAM"] {
+            let path = Path::new(name);
+            surface.to_file(path).unwrap();
+            let result = ImageSurface::create_from_netpbm(path);
+            fs::remove_file(path).unwrap();
+            assert_eq!(result.unwrap().iter().collect::<Vec<_>>(),
+                       surface.iter().collect::<Vec<_>>());
+        }
+
+        let path = Path::new("netpbm.ppm");
+        surface.to_file(path).unwrap();
+        let result = ImageSurface::create_from_netpbm(path);
+        fs::remove_file(path).unwrap();
+        assert_eq!(result.unwrap().get(2, 1).unwrap(), Rgba::new(0., 0.4, 0., 1.));
+
+        // Only masks can be written as PGM files
+        let path = Path::new("netpbm.pgm");
+        assert_eq!(surface.to_file(path), Err(Status::InvalidFormat));
+        assert!(!path.exists(), "Error: file was created");
+        let mut mask = ImageSurface::create(Format::A8, 2, 2);
+        *mask.get_mut(0, 1).unwrap() = Rgba::new(0., 0., 0., 1.);
+        mask.to_file(path).unwrap();
+        let result = ImageSurface::create_from_netpbm(path);
+        fs::remove_file(path).unwrap();
+        assert_eq!(result.unwrap().get_data(), mask.get_data());
+
+        let result = ImageSurface::create_from_netpbm(Path::new("no_such_file.pam"));
+        assert_eq!(result.err(), Some(Status::FileNotFound));
+    }
+
+    // Returns the JPEG image in `bytes` as rgb bytes.
+    fn decode_jpeg(bytes: &[u8]) -> Vec<u8> {
+        let image = image::load_from_memory_with_format(bytes, image::ImageFormat::JPEG).unwrap();
+        image.to_rgb().into_raw()
+    }
+
+    #[test]
+    fn test_write_to_jpeg_background() {
+        // Left half is transparent, right half half transparent red
+        let mut surface = ImageSurface::create(Format::ARGB32, 16, 16);
+        surface.update_pixels(|idx, pixel| {
+            if idx % 16 >= 8 {
+                *pixel = Rgba::new(1., 0., 0., 0.5);
+            }
+        });
+        let mut bytes = Vec::new();
+        surface.write_to_jpeg(&mut bytes, 100, Rgba::new(0., 0., 1., 1.)).unwrap();
+        let rgb = decode_jpeg(&bytes);
+
+        let close = |pixel: &[u8], expected: [u8; 3]| {
+            pixel.iter().zip(expected.iter()).all(|(&a, &b)| (a as i32 - b as i32).abs() <= 8)
+        };
+        assert!(close(&rgb[3 * (4 * 16 + 2)..], [0, 0, 255]));
+        assert!(close(&rgb[3 * (4 * 16 + 13)..], [128, 0, 128]));
+    }
+
+    #[test]
+    fn test_write_to_jpeg_through_public_api() {
+        // Only what is public at the crate root, as it is used from outside Cairus
+        let mut surface = ::surfaces::ImageSurface::create(::surfaces::Format::ARGB32, 8, 8);
+        {
+            let mut context = ::context::Context::create(&mut surface);
+            context.set_source_rgba(0., 1., 0., 0.5);
+            context.paint();
+        }
+        let mut bytes = Vec::new();
+        surface.write_to_jpeg(&mut bytes, 100, ::Rgba::new(1., 0., 0., 1.)).unwrap();
+        let rgb = decode_jpeg(&bytes);
+        let close = |pixel: &[u8], expected: [u8; 3]| {
+            pixel.iter().zip(expected.iter()).all(|(&a, &b)| (a as i32 - b as i32).abs() <= 8)
+        };
+        assert!(close(&rgb[3 * (4 * 8 + 4)..], [128, 128, 0]));
+    }
+
+    #[test]
+    fn test_write_to_jpeg_quality() {
+        let mut surface = ImageSurface::create(Format::RGB24, 32, 32);
+        surface.update_pixels(|idx, pixel| {
+            let shade = ((idx * 37) % 101) as f32 / 100.;
+            *pixel = Rgba::new(shade, 1. - shade, 0.5, 1.);
+        });
+        let white = Rgba::new(1., 1., 1., 1.);
+        let mut low = Vec::new();
+        surface.write_to_jpeg(&mut low, 10, white).unwrap();
+        let mut high = Vec::new();
+        surface.write_to_jpeg(&mut high, 95, white).unwrap();
+        assert!(low.len() < high.len());
+        assert_eq!(decode_jpeg(&low).len(), 32 * 32 * 3);
+    }
+
+    #[test]
+    fn test_bmp_and_tga_files() {
+        let surface = ImageSurface::create(Format::ARGB32, 5, 3);
+        for name in &["legacy.bmp", "legacy.TGA", "legacy.jpeg"] {
+            let path = Path::new(name);
+            surface.to_file(path).unwrap();
+            let mut expected = Vec::new();
+            match *name {
+                "legacy.bmp" => surface.write_to_bmp(&mut expected).unwrap(),
+                "legacy.TGA" => surface.write_to_tga(&mut expected).unwrap(),
+                _ => {
+                    let white = Rgba::new(1., 1., 1., 1.);
+                    surface.write_to_jpeg(&mut expected, ImageSurface::DEFAULT_JPEG_QUALITY,
+                                          white).unwrap()
+                },
+            }
+            let result = fs::read(path);
+            fs::remove_file(path).unwrap();
+            assert_eq!(result.unwrap(), expected);
+        }
+    }
+
+    // A writer that fails after taking `capacity` bytes.
+    struct ShortWriter {
+        capacity: usize,
+    }
+
+    impl Write for ShortWriter {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            if self.capacity < bytes.len() {
+                return Err(io::Error::other("out of space"));
+            }
+            self.capacity -= bytes.len();
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_to_png_stream() {
+        let mut surface = ImageSurface::create(Format::ARGB32, 4, 3);
+        *surface.get_mut(1, 2).unwrap() = Rgba::new(0., 0., 1., 1.);
+        let mut bytes = Vec::new();
+        surface.write_to_png_stream(&mut bytes).unwrap();
+        let result = ImageSurface::create_from_png_stream(&bytes[..]).unwrap();
+        assert_eq!(result.iter().collect::<Vec<_>>(), surface.iter().collect::<Vec<_>>());
+
+        // Translucent pixels are un-pre-multiplied with rounding, and come back the same
+        *surface.get_mut(3, 0).unwrap() = Rgba::new(1., 0.5, 0.2, 0.4);
+        *surface.get_mut(2, 0).unwrap() = Rgba { red: 0.5, green: 0., blue: 0., alpha: 0. };
+        let mut bytes = Vec::new();
+        surface.write_to_png_stream(&mut bytes).unwrap();
+        let result = ImageSurface::create_from_png_stream(&bytes[..]).unwrap();
+        assert_eq!(result.get(3, 0), surface.get(3, 0));
+        assert_eq!(result.get(2, 0).unwrap(), Rgba::new(0., 0., 0., 0.));
+
+        let result = surface.write_to_png_stream(ShortWriter { capacity: 20 });
+        assert_eq!(result, Err(Status::WriteError));
     }
 
     #[test]
     fn image_surface_index() {
-        let destination = ImageSurface::create(100, 100);
+        let destination = ImageSurface::create(Format::RGBA128F, 100, 100);
         let transparent_pixel = Rgba::new(0., 0., 0., 0.);
-        assert_eq!(*destination.get(0, 0).unwrap(), transparent_pixel);
+        assert_eq!(destination.get(0, 0).unwrap(), transparent_pixel);
+    }
+
+    #[test]
+    fn image_surface_index_out_of_bounds() {
+        let mut surface = ImageSurface::create(Format::ARGB32, 3, 2);
+        assert!(surface.get(3, 0).is_none());
+        assert!(surface.get(0, 2).is_none());
+        assert!(surface.get_mut(3, 1).is_none());
+        assert!(surface.get_with_index(6).is_none());
+        assert_eq!(surface.iter().count(), 6);
+    }
+
+    #[test]
+    fn format_strides() {
+        // Rows are padded to whole 32 bit words
+        assert_eq!(Format::ARGB32.stride_for_width(3), Some(12));
+        assert_eq!(Format::RGB24.stride_for_width(3), Some(12));
+        assert_eq!(Format::A8.stride_for_width(3), Some(4));
+        assert_eq!(Format::A8.stride_for_width(8), Some(8));
+        assert_eq!(Format::A1.stride_for_width(33), Some(8));
+        assert_eq!(Format::RGB16_565.stride_for_width(3), Some(8));
+        assert_eq!(Format::RGB30.stride_for_width(3), Some(12));
+        assert_eq!(Format::RGB96F.stride_for_width(3), Some(36));
+        assert_eq!(Format::RGBA128F.stride_for_width(3), Some(48));
+        assert_eq!(Format::Invalid.stride_for_width(3), None);
+        assert_eq!(Format::ARGB32.stride_for_width(usize::MAX), None);
+    }
+
+    #[test]
+    fn packed_surface_memory() {
+        // A 4K A8 mask takes a byte per pixel
+        let surface = ImageSurface::create(Format::A8, 3840, 2160);
+        assert_eq!(surface.base.len(), 3840 * 2160);
+        let surface = ImageSurface::create(Format::A1, 64, 2);
+        assert_eq!(surface.base.len(), 16);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_invalid_image_surface_format() {
+        let _ = ImageSurface::create(Format::Invalid, 10, 10);
+    }
+
+    // Writes `rgba` to the first pixel of a surface of `format` and returns what is read back.
+    fn round_trip(format: Format, rgba: Rgba) -> Rgba {
+        let mut surface = ImageSurface::create(format, 3, 3);
+        *surface.get_mut(1, 2).unwrap() = rgba;
+        surface.get(1, 2).unwrap()
+    }
+
+    #[test]
+    fn packed_formats_round_trip() {
+        let color = Rgba::new(1., 0.5, 0.25, 0.5);
+
+        let argb32 = round_trip(Format::ARGB32, color);
+        assert_eq!(argb32, Rgba::new(128. / 128., 64. / 128., 32. / 128., 128. / 255.));
+
+        // Formats without alpha keep the pre-multiplied color and are opaque
+        let rgb24 = round_trip(Format::RGB24, color);
+        assert_eq!(rgb24, Rgba::new(128. / 255., 64. / 255., 32. / 255., 1.));
+        let rgb16 = round_trip(Format::RGB16_565, color);
+        assert_eq!(rgb16, Rgba::new(16. / 31., 16. / 63., 4. / 31., 1.));
+        let rgb30 = round_trip(Format::RGB30, color);
+        assert_eq!(rgb30, Rgba::new(512. / 1023., 256. / 1023., 128. / 1023., 1.));
+        let rgb96 = round_trip(Format::RGB96F, color);
+        assert_eq!(rgb96, Rgba::new(0.5, 0.25, 0.125, 1.));
+
+        // Formats without color keep just the alpha
+        assert_eq!(round_trip(Format::A8, color), Rgba::new(0., 0., 0., 128. / 255.));
+        assert_eq!(round_trip(Format::A1, color), Rgba::new(0., 0., 0., 1.));
+        assert_eq!(round_trip(Format::A1, Rgba::new(1., 1., 1., 0.4)).alpha, 0.);
+
+        assert_eq!(round_trip(Format::RGBA128F, color), color);
+
+        // Channels out of range are clamped
+        let bright = Rgba { red: 2., green: -1., blue: 0.5, alpha: 1. };
+        assert_eq!(round_trip(Format::ARGB32, bright), Rgba::new(1., 0., 128. / 255., 1.));
+    }
+
+    #[test]
+    fn a1_pixels_share_bytes() {
+        let mut surface = ImageSurface::create(Format::A1, 10, 2);
+        for x in (0..10).filter(|x| x % 3 == 0) {
+            *surface.get_mut(x, 1).unwrap() = Rgba::new(0., 0., 0., 1.);
+        }
+        surface.get_mut(3, 1).unwrap().alpha = 0.;
+
+        let alphas = (0..10).map(|x| surface.get(x, 1).unwrap().alpha).collect::<Vec<_>>();
+        assert_eq!(alphas, vec![1., 0., 0., 0., 0., 0., 1., 0., 0., 1.]);
+        assert!(surface.iter().take(10).all(|pixel| pixel.alpha == 0.));
+        // The first pixel is in the lowest bit
+        assert_eq!(surface.base[surface.stride], 0b0100_0001);
+    }
+
+    #[test]
+    fn argb32_layout() {
+        let mut surface = ImageSurface::create(Format::ARGB32, 1, 1);
+        *surface.get_mut(0, 0).unwrap() = Rgba::new(1., 0., 0., 1.);
+        let word = u32::from_ne_bytes([surface.base[0], surface.base[1], surface.base[2],
+                                       surface.base[3]]);
+        assert_eq!(word, 0xffff0000);
+    }
+
+    #[test]
+    fn test_format_stride_for_width() {
+        assert_eq!(format_stride_for_width(Format::ARGB32, 5), Some(20));
+        assert_eq!(format_stride_for_width(Format::A8, 5), Some(8));
+        assert_eq!(format_stride_for_width(Format::Invalid, 5), None);
+        let surface = ImageSurface::create(Format::RGB16_565, 5, 2);
+        assert_eq!(Some(surface.get_stride()), format_stride_for_width(Format::RGB16_565, 5));
+        assert_eq!(surface.get_format(), Format::RGB16_565);
+        assert_eq!(surface.get_data().len(), 24);
+    }
+
+    #[test]
+    fn create_for_data_with_padded_rows() {
+        // Two rows of three ARGB32 pixels, 16 bytes apart, with the padding left alone
+        let mut data = vec![0xaa; 28];
+        {
+            let mut surface = ImageSurface::create_for_data(&mut data, Format::ARGB32, 3, 2, 16)
+                .unwrap();
+            assert_eq!(surface.get_stride(), 16);
+            assert_eq!(surface.get_format(), Format::ARGB32);
+            *surface.get_mut(0, 1).unwrap() = Rgba::new(1., 0., 0., 1.);
+            surface.get_mut(2, 0).unwrap().alpha = 0.;
+            // Existing pixels are kept
+            let gray = 0xaa as f32 / 255.;
+            assert_eq!(surface.get(1, 0).unwrap(),
+                       Rgba { red: gray, green: gray, blue: gray, alpha: gray });
+        }
+        assert_eq!(&data[12..16], &[0xaa; 4]);
+        assert_eq!(&data[16..20], &0xffff0000u32.to_ne_bytes());
+        assert_eq!(&data[8..12], &0x00aaaaaau32.to_ne_bytes());
+    }
+
+    #[test]
+    fn create_for_data_get_data() {
+        let mut data = vec![0; 8];
+        let mut surface = ImageSurface::create_for_data(&mut data, Format::A8, 2, 2, 4).unwrap();
+        surface.get_data_mut()[5] = 255;
+        assert_eq!(surface.get(1, 1).unwrap().alpha, 1.);
+        assert_eq!(surface.get_data(), &[0, 0, 0, 0, 0, 255, 0, 0]);
+    }
+
+    #[test]
+    fn create_for_data_copy_owns_pixels() {
+        let mut data = vec![0; 10];
+        let copy = {
+            let mut surface = ImageSurface::create_for_data(&mut data, Format::A8, 2, 2, 8)
+                .unwrap();
+            *surface.get_mut(1, 1).unwrap() = Rgba::new(0., 0., 0., 1.);
+            surface.clone()
+        };
+        assert_eq!(copy.get_stride(), 4);
+        assert_eq!(copy.get_data(), &[0, 0, 0, 0, 0, 255, 0, 0]);
+        assert_eq!(data[9], 255);
+    }
+
+    #[test]
+    fn create_for_data_errors() {
+        let mut data = vec![0; 32];
+        let result = ImageSurface::create_for_data(&mut data, Format::Invalid, 2, 2, 8);
+        assert_eq!(result.err(), Some(Status::InvalidFormat));
+        let result = ImageSurface::create_for_data(&mut data, Format::ARGB32, 0, 2, 8);
+        assert_eq!(result.err(), Some(Status::InvalidSize));
+        let result = ImageSurface::create_for_data(&mut data, Format::ARGB32, 3, 2, 8);
+        assert_eq!(result.err(), Some(Status::InvalidStride));
+        let result = ImageSurface::create_for_data(&mut data, Format::ARGB32, 3, 2, 24);
+        assert_eq!(result.err(), Some(Status::InvalidSize));
+        let result = ImageSurface::create_for_data(&mut data, Format::ARGB32, 2, 2, usize::MAX);
+        assert_eq!(result.err(), Some(Status::InvalidSize));
+
+        // The last row doesn't need padding, and strides needn't be aligned
+        assert!(ImageSurface::create_for_data(&mut data, Format::ARGB32, 3, 2, 20).is_ok());
+        assert!(ImageSurface::create_for_data(&mut data, Format::RGB16_565, 3, 2, 7).is_ok());
+    }
+
+    // A 2x2 palette PNG with opaque red, half transparent blue, transparent green and white
+    const PALETTE_PNG: [u8; 110] = [
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48,
+        0x44, 0x52, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x02, 0x08, 0x03, 0x00, 0x00,
+        0x00, 0x45, 0x68, 0xfd, 0x16, 0x00, 0x00, 0x00, 0x0c, 0x50, 0x4c, 0x54, 0x45, 0xff,
+        0x00, 0x00, 0x00, 0x00, 0xff, 0x00, 0xff, 0x00, 0xff, 0xff, 0xff, 0x5b, 0x1c, 0xed,
+        0x5d, 0x00, 0x00, 0x00, 0x03, 0x74, 0x52, 0x4e, 0x53, 0xff, 0x80, 0x00, 0x7f, 0x6d,
+        0x68, 0x78, 0x00, 0x00, 0x00, 0x0e, 0x49, 0x44, 0x41, 0x54, 0x78, 0xda, 0x63, 0x60,
+        0x60, 0x64, 0x60, 0x62, 0x06, 0x00, 0x00, 0x11, 0x00, 0x07, 0x83, 0xca, 0x64, 0x64,
+        0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    // A 2x1 16 bit RGBA PNG with opaque red and yellow at alpha 0x8080
+    const RGBA16_PNG: [u8; 77] = [
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48,
+        0x44, 0x52, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01, 0x10, 0x06, 0x00, 0x00,
+        0x00, 0xa4, 0xb2, 0xa3, 0xc9, 0x00, 0x00, 0x00, 0x14, 0x49, 0x44, 0x41, 0x54, 0x78,
+        0xda, 0x63, 0xf8, 0xff, 0x9f, 0x01, 0x08, 0xfe, 0x83, 0x01, 0x03, 0x43, 0x43, 0x03,
+        0x00, 0x4d, 0x45, 0x08, 0xf9, 0x3b, 0x19, 0x42, 0x40, 0x00, 0x00, 0x00, 0x00, 0x49,
+        0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    // A 3x1 1 bit grayscale PNG, black, white and black
+    const GRAY1_PNG: [u8; 67] = [
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48,
+        0x44, 0x52, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00,
+        0x00, 0x33, 0x9b, 0x29, 0x19, 0x00, 0x00, 0x00, 0x0a, 0x49, 0x44, 0x41, 0x54, 0x78,
+        0xda, 0x63, 0x70, 0x00, 0x00, 0x00, 0x42, 0x00, 0x41, 0x84, 0xbf, 0x8e, 0x62, 0x00,
+        0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    #[test]
+    fn create_from_png_palette() {
+        let surface = ImageSurface::create_from_png_stream(&PALETTE_PNG[..]).unwrap();
+        assert_eq!(surface.get_format(), Format::ARGB32);
+        assert_eq!((surface.width, surface.height), (2, 2));
+        assert_eq!(surface.get(0, 0).unwrap(), Rgba::new(1., 0., 0., 1.));
+        assert_eq!(surface.get(1, 0).unwrap(), Rgba::new(0., 0., 1., 128. / 255.));
+        assert_eq!(surface.get(0, 1).unwrap(), Rgba::new(0., 0., 0., 0.));
+        assert_eq!(surface.get(1, 1).unwrap(), Rgba::new(1., 1., 1., 1.));
+    }
+
+    #[test]
+    fn create_from_png_16_bit() {
+        let surface = ImageSurface::create_from_png_stream(&RGBA16_PNG[..]).unwrap();
+        assert_eq!(surface.get_format(), Format::ARGB32);
+        assert_eq!(surface.get(0, 0).unwrap(), Rgba::new(1., 0., 0., 1.));
+        assert_eq!(surface.get(1, 0).unwrap(), Rgba::new(1., 1., 0., 128. / 255.));
+    }
+
+    #[test]
+    fn create_from_png_grayscale() {
+        let surface = ImageSurface::create_from_png_stream(&GRAY1_PNG[..]).unwrap();
+        assert_eq!(surface.get_format(), Format::RGB24);
+        let pixels = surface.iter().collect::<Vec<_>>();
+        assert_eq!(pixels, vec![Rgba::new(0., 0., 0., 1.), Rgba::new(1., 1., 1., 1.),
+                                Rgba::new(0., 0., 0., 1.)]);
+
+        // 8 bit gray with alpha
+        let mut bytes = Vec::new();
+        image::png::PNGEncoder::new(&mut bytes)
+            .encode(&[255, 255, 51, 51], 2, 1, image::ColorType::GrayA(8)).unwrap();
+        let surface = ImageSurface::create_from_png_stream(&bytes[..]).unwrap();
+        assert_eq!(surface.get_format(), Format::ARGB32);
+        assert_eq!(surface.get(0, 0).unwrap(), Rgba::new(1., 1., 1., 1.));
+        // Pre-multiplied and rounded to 8 bits
+        let gray = 10. / 255.;
+        assert_eq!(surface.get(1, 0).unwrap(),
+                   Rgba { red: gray, green: gray, blue: gray, alpha: 0.2 });
+    }
+
+    #[test]
+    fn create_from_png_file_round_trip() {
+        let mut surface = ImageSurface::create(Format::ARGB32, 3, 2);
+        *surface.get_mut(0, 0).unwrap() = Rgba::new(1., 0., 0., 1.);
+        *surface.get_mut(2, 1).unwrap() = Rgba::new(0., 1., 1., 1.);
+        let path = Path::new("png_round_trip.png");
+        surface.to_file(path).unwrap();
+
+        let result = ImageSurface::create_from_png(path);
+        fs::remove_file(path).unwrap();
+        let result = result.unwrap();
+        assert_eq!(result.iter().collect::<Vec<_>>(), surface.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn create_from_png_errors() {
+        let result = ImageSurface::create_from_png(Path::new("no_such_file.png"));
+        assert_eq!(result.err(), Some(Status::FileNotFound));
+        let result = ImageSurface::create_from_png_stream(&PALETTE_PNG[..40]);
+        assert_eq!(result.err(), Some(Status::ReadError));
+        let result = ImageSurface::create_from_png_stream(&b"not a png"[..]);
+        assert_eq!(result.err(), Some(Status::ReadError));
     }
 }
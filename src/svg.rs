@@ -0,0 +1,1015 @@
+/*
+ * Cairus - a reimplementation of the cairo graphics library in Rust
+ *
+ * Copyright © 2017 CairusOrg
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it either under the terms of the GNU Lesser General Public
+ * License version 2.1 as published by the Free Software Foundation
+ * (the "LGPL") or, at your option, under the terms of the Mozilla
+ * Public License Version 2.0 (the "MPL"). If you do not alter this
+ * notice, a recipient may use your version of this file under either
+ * the MPL or the LGPL.
+ *
+ * You should have received a copy of the LGPL along with this library
+ * in the file LICENSE-LGPL-2_1; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Suite 500, Boston, MA 02110-1335, USA
+ * You should have received a copy of the MPL along with this library
+ * in the file LICENSE-MPL-2_0
+ *
+ * The contents of this file are subject to the Mozilla Public License
+ * Version 2.0 (the "License"); you may not use this file except in
+ * compliance with the License. You may obtain a copy of the License at
+ * http://www.mozilla.org/MPL/
+ *
+ * This software is distributed on an "AS IS" basis, WITHOUT WARRANTY
+ * OF ANY KIND, either express or implied. See the LGPL or the MPL for
+ * the specific language governing rights and limitations.
+ *
+ * The Original Code is the cairus graphics library.
+ *
+ */
+
+//! # Overview
+//! An SvgSurface records what a context draws on it as vector graphics, and writes it out as an
+//! SVG 1.1 document, analogous to cairo's SVG surface.
+//!
+//! A context keeps its path in device space, which is the space of the document, so fills and
+//! clips are written with the points they were drawn with.  Strokes are written in the user space
+//! they were drawn in, with the transformation matrix as their `transform`, so that the line
+//! style is measured there like on an image surface.
+//!
+//! Solid colors, gradients and repeated surface patterns become SVG paint.  SVG 1.1 has nothing
+//! like the other patterns, so they are drawn as an image through a mask in the shape being
+//! drawn: surface patterns show their surface without extending it, and mesh patterns and masks
+//! that aren't a solid color are rendered into an image one pixel per unit.  Images are embedded
+//! as PNG data URIs.
+//!
+//! SVG 1.1 has no compositing operators, so it only draws like `Operator::Over`.
+//! `Operator::Dest` doesn't draw anything.  `Operator::Source` and `Operator::Clear` cut the
+//! shape they draw out of the document drawn so far with a mask, and `Operator::Source` then
+//! draws the source over it, so that where they draw they replace the document, with the source
+//! or with nothing.  A partly transparent shape drawn with a pattern with `Operator::Source`
+//! leaves too little of the document under it.  The other operators are rasterized like on a
+//! PDF surface: once one of them is used, the context rasterizes what was drawn into an image,
+//! one pixel per unit, and the area every such operator changes is replaced with that image.
+
+use std::fs::File;
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path;
+use context::{FillRule, LineCap, LineJoin, Fallback};
+use matrix::Matrix;
+use operators::Operator;
+use path::{Path, PathSegment};
+use patterns::{Pattern, PatternType, Extend, Filter};
+use status::Status;
+use stroker::StrokeStyle;
+use surfaces::{ImageSurface, Format};
+use types::{Rgba, ChannelOrder, AlphaMode};
+
+/// A surface that records drawing as vector graphics and writes it as an SVG document.
+pub struct SvgSurface {
+    /// The width of the document, in points.
+    pub width: f32,
+    /// The height of the document, in points.
+    pub height: f32,
+    // The gradients, patterns, clip paths and masks that elements refer to by id
+    definitions: Vec<String>,
+    // What has been drawn since the document was last cut out of, in order
+    elements: Vec<String>,
+    // What was drawn before each cut
+    parts: Vec<Vec<String>>,
+    // The ids of the shapes cut out of the document, drawn in black with the opacity they are
+    // cut out by
+    cuts: Vec<String>,
+    // The drawing, which rasterized drawing is taken from
+    fallback: Fallback,
+    // Tells apart the ids of definitions
+    next_id: usize,
+}
+
+// How the source is written for an element.
+enum Paint {
+    // Nothing would be drawn
+    Nothing,
+    // A fill or stroke value, and its opacity
+    Server(String, f32),
+    // An element that shows the source in device space, to be masked by the shape
+    Content(String),
+}
+
+// What an element draws.
+enum Shape<'s> {
+    // The area inside a path in device space
+    Fill(String, FillRule),
+    // A stroke along a path in user space, with the matrix mapping user space to device space
+    Stroke(String, Matrix, &'s StrokeStyle),
+    // The whole document
+    Page,
+}
+
+impl SvgSurface {
+    /// Returns an empty surface `width` by `height` points large.  Like an image surface, a
+    /// point of user space is a unit of device space until the context is transformed.
+    ///
+    /// Panics if either dimension isn't a positive number.
+    pub fn create(width: f32, height: f32) -> SvgSurface {
+        if !(width > 0. && height > 0. && width.is_finite() && height.is_finite()) {
+            panic!("error: SvgSurface dimensions are not supported.")
+        }
+        SvgSurface {
+            width: width,
+            height: height,
+            definitions: Vec::new(),
+            elements: Vec::new(),
+            parts: Vec::new(),
+            cuts: Vec::new(),
+            fallback: Fallback::new(width.ceil() as usize, height.ceil() as usize),
+            next_id: 0,
+        }
+    }
+
+    /// Writes the document to the file at `path`.  Returns Status::FileNotFound if the
+    /// directory of `path` doesn't exist, and Status::WriteError if the file can't be written.
+    pub fn write_to_file(&self, path: &path::Path) -> Result<(), Status> {
+        let mut writer = match File::create(path) {
+            Ok(file) => BufWriter::new(file),
+            Err(ref error) if error.kind() == ErrorKind::NotFound => {
+                return Err(Status::FileNotFound)
+            },
+            Err(_) => return Err(Status::WriteError),
+        };
+        self.write_to_stream(&mut writer)?;
+        writer.flush().map_err(|_| Status::WriteError)
+    }
+
+    /// Writes the document to `writer`.  Returns Status::WriteError if `writer` fails.
+    pub fn write_to_stream<W: Write>(&self, mut writer: W) -> Result<(), Status> {
+        let mut document = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        document.push_str(&format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" \
+             xmlns:xlink=\"http://www.w3.org/1999/xlink\" width=\"{w}pt\" height=\"{h}pt\" \
+             viewBox=\"0 0 {w} {h}\" version=\"1.1\">\n", w = self.width, h = self.height));
+        // Every part of the document that was cut out of is drawn through a mask of the cuts
+        // after it.  The mask is white with the cuts drawn over it in black, so that it keeps
+        // the product of what each cut leaves, and the parts are never nested.
+        let mut definitions = self.definitions.clone();
+        let mut elements = Vec::new();
+        for (index, part) in self.parts.iter().enumerate() {
+            if part.is_empty() {
+                continue;
+            }
+            let id = format!("part{}", index);
+            let mut content = format!("<rect x=\"0\" y=\"0\" width=\"{}\" height=\"{}\" \
+                                       fill=\"white\"/>", self.width, self.height);
+            for cut in &self.cuts[index..] {
+                content.push_str(&format!("<use xlink:href=\"#{}\"/>", cut));
+            }
+            definitions.push(self.mask_definition(&id, &content));
+            elements.push(format!("<g mask=\"url(#{})\">\n{}\n</g>", id, part.join("\n")));
+        }
+        elements.extend(self.elements.iter().cloned());
+
+        if !definitions.is_empty() {
+            document.push_str("<defs>\n");
+            for definition in &definitions {
+                document.push_str(definition);
+                document.push('\n');
+            }
+            document.push_str("</defs>\n");
+        }
+        for element in &elements {
+            document.push_str(element);
+            document.push('\n');
+        }
+        document.push_str("</svg>\n");
+        writer.write_all(document.as_bytes()).map_err(|_| Status::WriteError)
+    }
+
+    // The size in pixels of the images that patterns SVG can't express are rendered into.
+    pub(crate) fn pixel_size(&self) -> (usize, usize) {
+        (self.width.ceil() as usize, self.height.ceil() as usize)
+    }
+
+    // The drawing, which the context keeps up with.
+    pub(crate) fn fallback_mut(&mut self) -> &mut Fallback {
+        &mut self.fallback
+    }
+
+    // Records replacing the drawing in `area`, which is whether each pixel of the document is
+    // in it, with the rasterized drawing.  This is how operators SVG has nothing like are drawn.
+    pub(crate) fn replace_area(&mut self, area: &[bool]) {
+        let image = match self.fallback.image() {
+            Some(image) => image_element(image, None, None),
+            None => return,
+        };
+        let (width, height) = self.pixel_size();
+        let mut area_image = ImageSurface::create(Format::ARGB32, width, height);
+        area_image.update_pixels(|idx, pixel| if area[idx] { *pixel = Rgba::new(1., 1., 1., 1.) });
+        let mask = self.define_mask(&image_element(&area_image, None, None));
+        // The area is cut out of the document, and the image drawn in it
+        let black = self.shape_element(&Shape::Page, "fill=\"black\"");
+        self.cut_out(format!("<g mask=\"url(#{})\">{}</g>", mask, black));
+        self.elements.push(format!("<g mask=\"url(#{})\">{}</g>", mask, image));
+    }
+
+    // Records filling `path` with `source`, seen through `device_to_source`.
+    pub(crate) fn fill(&mut self, path: &Path, fill_rule: FillRule, source: &Pattern,
+                       device_to_source: &Matrix, operator: Operator, clip: Option<&str>) {
+        if path.segments().is_empty() {
+            return;
+        }
+        let shape = Shape::Fill(path_data(path, &Matrix::init_identity()), fill_rule);
+        self.composite(&shape, source, device_to_source, 1., None, operator, clip);
+    }
+
+    // Records stroking `path` with `style` in the user space `ctm` maps to device space.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn stroke(&mut self, path: &Path, ctm: &Matrix, style: &StrokeStyle,
+                         source: &Pattern, device_to_source: &Matrix, operator: Operator,
+                         clip: Option<&str>) {
+        if path.segments().is_empty() {
+            return;
+        }
+        let device_to_user = match ctm.invert() {
+            Ok(matrix) => matrix,
+            Err(_) => return,
+        };
+        let shape = Shape::Stroke(path_data(path, &device_to_user), *ctm, style);
+        self.composite(&shape, source, device_to_source, 1., None, operator, clip);
+    }
+
+    // Records painting the whole document with `source`, faded to `alpha`.
+    pub(crate) fn paint(&mut self, source: &Pattern, device_to_source: &Matrix, alpha: f32,
+                        operator: Operator, clip: Option<&str>) {
+        self.composite(&Shape::Page, source, device_to_source, alpha, None, operator, clip);
+    }
+
+    // Records painting with `source` through the alpha of `mask`, seen through
+    // `device_to_mask`.
+    pub(crate) fn mask(&mut self, source: &Pattern, device_to_source: &Matrix, mask: &Pattern,
+                       device_to_mask: &Matrix, operator: Operator, clip: Option<&str>) {
+        if let Ok((_, _, _, alpha)) = mask.get_rgba() {
+            self.paint(source, device_to_source, alpha, operator, clip);
+            return;
+        }
+        if operator == Operator::Dest || is_rasterized(operator) {
+            return;
+        }
+
+        // Luminance masks show as much as the mask is bright, which for white is its alpha
+        let (width, height) = self.pixel_size();
+        let alphas = mask.render(device_to_mask, width, height);
+        let mut image = ImageSurface::create(Format::ARGB32, width, height);
+        image.update_pixels(|idx, pixel| *pixel = Rgba::new(1., 1., 1., alphas[idx].alpha));
+        let id = self.define_mask(&image_element(&image, None, None));
+        self.composite(&Shape::Page, source, device_to_source, 1., Some(&id), operator, clip);
+    }
+
+    // Records a clip path of the area inside `path`, within the clip path `clip` if there is
+    // one, and returns its id.
+    pub(crate) fn clip(&mut self, path: &Path, fill_rule: FillRule, clip: Option<&str>)
+                       -> String {
+        let id = self.new_id("clip");
+        let parent = match clip {
+            Some(clip) => format!(" clip-path=\"url(#{})\"", clip),
+            None => String::new(),
+        };
+        self.definitions.push(format!(
+            "<clipPath id=\"{}\"{}><path d=\"{}\" clip-rule=\"{}\"/></clipPath>", id, parent,
+            path_data(path, &Matrix::init_identity()), fill_rule_name(fill_rule)));
+        id
+    }
+
+    // Draws `shape` with `source` faded to `alpha`, through the mask `mask` if there is one.
+    // Operators that are rasterized are drawn by `replace_area` instead.
+    #[allow(clippy::too_many_arguments)]
+    fn composite(&mut self, shape: &Shape, source: &Pattern, device_to_source: &Matrix,
+                 alpha: f32, mask: Option<&str>, operator: Operator, clip: Option<&str>) {
+        if alpha <= 0. || operator == Operator::Dest || is_rasterized(operator) {
+            return;
+        }
+        // Source replaces the document inside the shape with the source, and Clear with
+        // nothing, so the shape is cut out of the document before the source is drawn over it
+        if operator == Operator::Clear {
+            self.knock_out(shape, None, alpha, mask, clip);
+            return;
+        }
+        if operator == Operator::Source {
+            self.knock_out(shape, Some(source), alpha, mask, clip);
+        }
+        let element = match self.draw(shape, source, device_to_source) {
+            Some(element) => element,
+            None => return,
+        };
+        let element = match mask {
+            Some(mask) => format!("<g mask=\"url(#{})\">{}</g>", mask, element),
+            None => element,
+        };
+        self.push(element, alpha, clip);
+    }
+
+    // Cuts `shape`, faded to `alpha` and through the mask `mask` if there is one, out of the
+    // document, so that drawing `source` over it replaces the document.
+    //
+    // Drawing a color with coverage c over the document cut out by k leaves (1 - k)(1 - a c) of
+    // the document, where a is the alpha of the color, and replacing the document leaves 1 - c.
+    // That fixes k for solid colors.  Under a partly transparent shape the document is cut out
+    // by c for patterns, which leaves too little of it.
+    fn knock_out(&mut self, shape: &Shape, source: Option<&Pattern>, alpha: f32,
+                 mask: Option<&str>, clip: Option<&str>) {
+        let opacity = match source.map(Pattern::get_rgba) {
+            _ if alpha >= 1. || mask.is_some() => alpha,
+            Some(Ok((_, _, _, color_alpha))) => 1. - (1. - alpha) / (1. - color_alpha * alpha),
+            _ => alpha,
+        };
+        if opacity <= 0. {
+            return;
+        }
+        let property = match *shape {
+            Shape::Stroke(..) => "stroke",
+            _ => "fill",
+        };
+        let mut element = self.shape_element(shape, &format!("{}=\"black\"", property));
+        if let Some(mask) = mask {
+            element = format!("<g mask=\"url(#{})\">{}</g>", mask, element);
+        }
+        self.cut_out(group(element, opacity, clip));
+    }
+
+    // Ends the part of the document drawn since the last cut, and cuts what `element` draws in
+    // black out of it and every part before it.
+    fn cut_out(&mut self, element: String) {
+        let id = self.new_id("cut");
+        self.definitions.push(format!("<g id=\"{}\">{}</g>", id, element));
+        let part = ::std::mem::take(&mut self.elements);
+        self.parts.push(part);
+        self.cuts.push(id);
+    }
+
+    // Returns the element drawing `shape` with `source`, or None if it would draw nothing.
+    fn draw(&mut self, shape: &Shape, source: &Pattern, device_to_source: &Matrix)
+            -> Option<String> {
+        let element_to_device = match *shape {
+            Shape::Stroke(_, ctm, _) => ctm,
+            _ => Matrix::init_identity(),
+        };
+        let property = match *shape {
+            Shape::Stroke(..) => "stroke",
+            _ => "fill",
+        };
+        match self.paint_server(source, device_to_source, &element_to_device) {
+            Paint::Nothing => None,
+            Paint::Server(value, opacity) => {
+                let mut attributes = format!("{}=\"{}\"", property, value);
+                if opacity < 1. {
+                    attributes.push_str(&format!(" {}-opacity=\"{}\"", property, opacity));
+                }
+                Some(self.shape_element(shape, &attributes))
+            },
+            Paint::Content(content) => {
+                let white = format!("{}=\"white\"", property);
+                let id = self.define_mask(&self.shape_element(shape, &white));
+                Some(format!("<g mask=\"url(#{})\">{}</g>", id, content))
+            },
+        }
+    }
+
+    // Returns the element of `shape` with the paint `attributes`.
+    fn shape_element(&self, shape: &Shape, attributes: &str) -> String {
+        match *shape {
+            Shape::Fill(ref data, fill_rule) => {
+                format!("<path d=\"{}\" fill-rule=\"{}\" {}/>", data, fill_rule_name(fill_rule),
+                        attributes)
+            },
+            Shape::Stroke(ref data, ref ctm, style) => {
+                format!("<path d=\"{}\" transform=\"{}\" fill=\"none\"{} {}/>", data,
+                        matrix_value(ctm), stroke_attributes(style), attributes)
+            },
+            Shape::Page => {
+                format!("<rect x=\"0\" y=\"0\" width=\"{}\" height=\"{}\" {}/>", self.width,
+                        self.height, attributes)
+            },
+        }
+    }
+
+    // Returns how `source` is written for an element whose coordinates `element_to_device`
+    // maps to device space, adding the definitions it needs.
+    fn paint_server(&mut self, source: &Pattern, device_to_source: &Matrix,
+                    element_to_device: &Matrix) -> Paint {
+        let source_to_device = match device_to_source.invert() {
+            Ok(matrix) => matrix,
+            Err(_) => return Paint::Nothing,
+        };
+        let device_to_element = match element_to_device.invert() {
+            Ok(matrix) => matrix,
+            Err(_) => return Paint::Nothing,
+        };
+        let source_to_element = source_to_device.multiply(&device_to_element);
+
+        match source.get_type() {
+            PatternType::Solid => {
+                let (red, green, blue, alpha) = source.get_rgba().unwrap();
+                if alpha <= 0. {
+                    return Paint::Nothing;
+                }
+                Paint::Server(color_value(red, green, blue), alpha)
+            },
+            PatternType::Linear => {
+                let (x1, y1, x2, y2) = source.get_linear_points().unwrap();
+                let stops = color_stops(source, |offset| offset);
+                if stops.is_empty() {
+                    return Paint::Nothing;
+                }
+                let id = self.new_id("gradient");
+                self.definitions.push(format!(
+                    "<linearGradient id=\"{}\" gradientUnits=\"userSpaceOnUse\" x1=\"{}\" \
+                     y1=\"{}\" x2=\"{}\" y2=\"{}\" spreadMethod=\"{}\" gradientTransform=\"{}\">\
+                     {}</linearGradient>", id, x1, y1, x2, y2, spread_method(source.get_extend()),
+                    matrix_value(&source_to_element), stops));
+                Paint::Server(format!("url(#{})", id), 1.)
+            },
+            PatternType::Radial => {
+                let (mut fx, mut fy, mut r0, mut cx, mut cy, mut r1) =
+                    source.get_radial_circles().unwrap();
+                // SVG 1.1 gradients start from a point, so a start circle is made by moving the
+                // stops outwards, and the larger circle has to be the end
+                let reversed = r0 > r1;
+                if reversed {
+                    ::std::mem::swap(&mut fx, &mut cx);
+                    ::std::mem::swap(&mut fy, &mut cy);
+                    ::std::mem::swap(&mut r0, &mut r1);
+                }
+                if r1 <= 0. {
+                    return Paint::Nothing;
+                }
+                let stops = color_stops(source, |offset| {
+                    let offset = if reversed { 1. - offset } else { offset };
+                    (r0 + offset * (r1 - r0)) / r1
+                });
+                if stops.is_empty() {
+                    return Paint::Nothing;
+                }
+                let id = self.new_id("gradient");
+                self.definitions.push(format!(
+                    "<radialGradient id=\"{}\" gradientUnits=\"userSpaceOnUse\" cx=\"{}\" \
+                     cy=\"{}\" r=\"{}\" fx=\"{}\" fy=\"{}\" spreadMethod=\"{}\" \
+                     gradientTransform=\"{}\">{}</radialGradient>", id, cx, cy, r1, fx, fy,
+                    spread_method(source.get_extend()), matrix_value(&source_to_element), stops));
+                Paint::Server(format!("url(#{})", id), 1.)
+            },
+            PatternType::Surface => {
+                let surface = source.get_surface().unwrap();
+                let rendering = image_rendering(source.get_filter());
+                if source.get_extend() != Extend::Repeat {
+                    return Paint::Content(image_element(surface, Some(&source_to_device),
+                                                        rendering));
+                }
+                let id = self.new_id("pattern");
+                self.definitions.push(format!(
+                    "<pattern id=\"{}\" patternUnits=\"userSpaceOnUse\" width=\"{}\" \
+                     height=\"{}\" patternTransform=\"{}\">{}</pattern>", id, surface.width,
+                    surface.height, matrix_value(&source_to_element),
+                    image_element(surface, None, rendering)));
+                Paint::Server(format!("url(#{})", id), 1.)
+            },
+            PatternType::Mesh => {
+                let (width, height) = self.pixel_size();
+                let colors = source.render(device_to_source, width, height);
+                let mut image = ImageSurface::create(Format::ARGB32, width, height);
+                image.update_pixels(|idx, pixel| *pixel = colors[idx]);
+                Paint::Content(image_element(&image, None, None))
+            },
+        }
+    }
+
+    // Adds `element`, faded to `alpha` and clipped by the clip path `clip`.
+    fn push(&mut self, element: String, alpha: f32, clip: Option<&str>) {
+        if alpha <= 0. {
+            return;
+        }
+        self.elements.push(group(element, alpha, clip));
+    }
+
+    // Adds a mask over the whole document showing `content`, and returns its id.
+    fn define_mask(&mut self, content: &str) -> String {
+        let id = self.new_id("mask");
+        let definition = self.mask_definition(&id, content);
+        self.definitions.push(definition);
+        id
+    }
+
+    // Returns the definition of a mask over the whole document with the `id`, showing `content`.
+    fn mask_definition(&self, id: &str, content: &str) -> String {
+        format!("<mask id=\"{}\" maskUnits=\"userSpaceOnUse\" x=\"0\" y=\"0\" width=\"{}\" \
+                 height=\"{}\">{}</mask>", id, self.width, self.height, content)
+    }
+
+    // Returns a new id starting with `prefix`.
+    fn new_id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{}{}", prefix, self.next_id)
+    }
+}
+
+// Whether drawing with `operator` is rasterized, because SVG 1.1 has nothing like it.
+pub(crate) fn is_rasterized(operator: Operator) -> bool {
+    !matches!(operator, Operator::Over | Operator::Source | Operator::Clear | Operator::Dest)
+}
+
+// Returns `element` faded to `alpha` and clipped by the clip path `clip`.
+fn group(mut element: String, alpha: f32, clip: Option<&str>) -> String {
+    if alpha < 1. {
+        element = format!("<g opacity=\"{}\">{}</g>", alpha, element);
+    }
+    if let Some(clip) = clip {
+        element = format!("<g clip-path=\"url(#{})\">{}</g>", clip, element);
+    }
+    element
+}
+
+// Returns the SVG path data of `path`, with its points transformed by `matrix`.
+fn path_data(path: &Path, matrix: &Matrix) -> String {
+    let point = |point: &::common_geometry::Point| {
+        let (x, y) = matrix.transform_point(point.x, point.y);
+        format!("{} {}", number(x), number(y))
+    };
+    let segments = path.segments().iter().map(|segment| {
+        match *segment {
+            PathSegment::MoveTo(ref to) => format!("M {}", point(to)),
+            PathSegment::LineTo(ref to) => format!("L {}", point(to)),
+            PathSegment::CurveTo(ref control1, ref control2, ref to) => {
+                format!("C {} {} {}", point(control1), point(control2), point(to))
+            },
+            PathSegment::ClosePath => String::from("Z"),
+        }
+    });
+    segments.collect::<Vec<_>>().join(" ")
+}
+
+fn fill_rule_name(fill_rule: FillRule) -> &'static str {
+    match fill_rule {
+        FillRule::Winding => "nonzero",
+        FillRule::EvenOdd => "evenodd",
+    }
+}
+
+// Returns the stroke attributes of `style`, each with a leading space.
+fn stroke_attributes(style: &StrokeStyle) -> String {
+    let cap = match style.line_cap {
+        LineCap::Butt => "butt",
+        LineCap::Round => "round",
+        LineCap::Square => "square",
+    };
+    let join = match style.line_join {
+        LineJoin::Miter => "miter",
+        LineJoin::Round => "round",
+        LineJoin::Bevel => "bevel",
+    };
+    // SVG doesn't allow miter limits below 1, which are the same as 1 anyway
+    let mut attributes = format!(" stroke-width=\"{}\" stroke-linecap=\"{}\" \
+                                  stroke-linejoin=\"{}\" stroke-miterlimit=\"{}\"",
+                                 style.line_width, cap, join, style.miter_limit.max(1.));
+    if !style.dashes.is_empty() {
+        let dashes = style.dashes.iter().map(|dash| dash.to_string()).collect::<Vec<_>>();
+        attributes.push_str(&format!(" stroke-dasharray=\"{}\" stroke-dashoffset=\"{}\"",
+                                     dashes.join(","), style.dash_offset));
+    }
+    attributes
+}
+
+// Returns the SVG transform of `matrix`.
+fn matrix_value(matrix: &Matrix) -> String {
+    format!("matrix({} {} {} {} {} {})", number(matrix.xx), number(matrix.yx), number(matrix.xy),
+            number(matrix.yy), number(matrix.x0), number(matrix.y0))
+}
+
+// Returns `value` as it is written in the document, which doesn't need the sign of zero.
+fn number(value: f32) -> String {
+    (value + 0.).to_string()
+}
+
+// Returns the SVG color of the channels, which are between 0 and 1.
+fn color_value(red: f32, green: f32, blue: f32) -> String {
+    let bytes = Rgba::new(red, green, blue, 1.).to_bytes(ChannelOrder::Rgba, AlphaMode::Straight);
+    format!("rgb({},{},{})", bytes[0], bytes[1], bytes[2])
+}
+
+// Returns the stop elements of the gradient `pattern`, with their offsets moved by `offset`.
+fn color_stops<F>(pattern: &Pattern, offset: F) -> String where F: Fn(f32) -> f32 {
+    let count = pattern.get_color_stop_count().unwrap_or(0);
+    let mut stops = (0..count)
+        .map(|index| pattern.get_color_stop_rgba(index).unwrap())
+        .map(|(stop, red, green, blue, alpha)| (offset(stop), red, green, blue, alpha))
+        .collect::<Vec<_>>();
+    // SVG needs the offsets in order
+    stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    stops.iter()
+        .map(|&(offset, red, green, blue, alpha)| {
+            format!("<stop offset=\"{}\" stop-color=\"{}\" stop-opacity=\"{}\"/>", offset,
+                    color_value(red, green, blue), alpha)
+        })
+        .collect()
+}
+
+// SVG has nothing for Extend::None, so gradients pad instead.
+fn spread_method(extend: Extend) -> &'static str {
+    match extend {
+        Extend::None | Extend::Pad => "pad",
+        Extend::Repeat => "repeat",
+        Extend::Reflect => "reflect",
+    }
+}
+
+fn image_rendering(filter: Filter) -> Option<&'static str> {
+    match filter {
+        Filter::Fast | Filter::Nearest => Some("optimizeSpeed"),
+        _ => None,
+    }
+}
+
+// Returns an image element showing `surface` as an embedded PNG image, transformed by
+// `transform` and with the `rendering` hint if there are any.
+fn image_element(surface: &ImageSurface, transform: Option<&Matrix>,
+                 rendering: Option<&str>) -> String {
+    let mut png = Vec::new();
+    surface.write_to_png_stream(&mut png).expect("writing to memory doesn't fail");
+    let mut element = format!("<image width=\"{}\" height=\"{}\"", surface.width,
+                              surface.height);
+    if let Some(transform) = transform {
+        element.push_str(&format!(" transform=\"{}\"", matrix_value(transform)));
+    }
+    if let Some(rendering) = rendering {
+        element.push_str(&format!(" image-rendering=\"{}\"", rendering));
+    }
+    element.push_str(&format!(" xlink:href=\"data:image/png;base64,{}\"/>", base64(&png)));
+    element
+}
+
+// Returns `bytes` in base64, with padding.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let word = chunk.iter().enumerate()
+            .fold(0u32, |word, (i, &byte)| word | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(word >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use context::{Context, LineCap};
+    use matrix::Matrix;
+    use operators::Operator;
+    use patterns::{Pattern, Extend};
+    use status::Status;
+    use surfaces::{ImageSurface, Format};
+    use types::Rgba;
+    use super::{SvgSurface, base64};
+
+    use std::fs;
+    use std::path::Path;
+
+    fn document(surface: &SvgSurface) -> String {
+        let mut bytes = Vec::new();
+        surface.write_to_stream(&mut bytes).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    fn square(context: &mut Context, x: f32, y: f32, size: f32) {
+        context.move_to(x, y);
+        context.line_to(x + size, y);
+        context.line_to(x + size, y + size);
+        context.line_to(x, y + size);
+        context.close_path();
+    }
+
+    #[test]
+    fn test_empty_document() {
+        let surface = SvgSurface::create(200., 100.5);
+        assert_eq!(document(&surface),
+                   "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                    <svg xmlns=\"http://www.w3.org/2000/svg\" \
+                    xmlns:xlink=\"http://www.w3.org/1999/xlink\" width=\"200pt\" \
+                    height=\"100.5pt\" viewBox=\"0 0 200 100.5\" version=\"1.1\">\n</svg>\n");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_empty_size() {
+        SvgSurface::create(0., 100.);
+    }
+
+    #[test]
+    fn test_fill() {
+        let mut surface = SvgSurface::create(100., 100.);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 0., 0., 0.5);
+            context.translate(10., 20.).unwrap();
+            square(&mut context, 0., 0., 10.);
+            context.fill();
+
+            // Nothing is recorded for an empty path or a transparent source
+            context.fill();
+            square(&mut context, 0., 0., 10.);
+            context.set_source_rgba(0., 0., 0., 0.);
+            context.fill();
+        }
+        let document = document(&surface);
+        assert!(document.contains("<path d=\"M 10 20 L 20 20 L 20 30 L 10 30 Z\" \
+                                   fill-rule=\"nonzero\" fill=\"rgb(255,0,0)\" \
+                                   fill-opacity=\"0.5\"/>"));
+        assert_eq!(document.matches("<path").count(), 1);
+        assert!(!document.contains("<defs>"));
+    }
+
+    #[test]
+    fn test_stroke() {
+        let mut surface = SvgSurface::create(100., 100.);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(0., 0., 1., 1.);
+            context.scale(2., 2.).unwrap();
+            context.set_line_width(3.);
+            context.set_line_cap(LineCap::Round);
+            context.set_dash(&[1., 2.], 0.5).unwrap();
+            context.move_to(1., 1.);
+            context.line_to(5., 1.);
+            context.stroke();
+        }
+        // The path is in user space, so the line style is scaled with it
+        assert!(document(&surface).contains(
+            "<path d=\"M 1 1 L 5 1\" transform=\"matrix(2 0 0 2 0 0)\" fill=\"none\" \
+             stroke-width=\"3\" stroke-linecap=\"round\" stroke-linejoin=\"miter\" \
+             stroke-miterlimit=\"10\" stroke-dasharray=\"1,2\" stroke-dashoffset=\"0.5\" \
+             stroke=\"rgb(0,0,255)\"/>"));
+    }
+
+    #[test]
+    fn test_gradients() {
+        let mut surface = SvgSurface::create(100., 100.);
+        {
+            let mut context = Context::create(&mut surface);
+            let mut linear = Pattern::create_linear(0., 0., 10., 0.);
+            linear.add_color_stop_rgba(1., 0., 0., 1., 1.).unwrap();
+            linear.add_color_stop_rgba(0., 1., 0., 0., 0.5).unwrap();
+            linear.set_extend(Extend::Reflect);
+            context.translate(5., 0.).unwrap();
+            context.set_source(&linear);
+            context.paint();
+
+            let mut radial = Pattern::create_radial(0., 0., 5., 0., 0., 10.);
+            radial.add_color_stop_rgba(0., 1., 1., 1., 1.).unwrap();
+            radial.add_color_stop_rgba(1., 0., 0., 0., 1.).unwrap();
+            context.set_source(&radial);
+            context.paint_with_alpha(0.25);
+
+            // A gradient without stops draws nothing
+            context.set_source(&Pattern::create_linear(0., 0., 1., 1.));
+            context.paint();
+        }
+        let document = document(&surface);
+        assert!(document.contains(
+            "<linearGradient id=\"gradient1\" gradientUnits=\"userSpaceOnUse\" x1=\"0\" y1=\"0\" \
+             x2=\"10\" y2=\"0\" spreadMethod=\"reflect\" gradientTransform=\"matrix(1 0 0 1 5 0)\">\
+             <stop offset=\"0\" stop-color=\"rgb(255,0,0)\" stop-opacity=\"0.5\"/>\
+             <stop offset=\"1\" stop-color=\"rgb(0,0,255)\" stop-opacity=\"1\"/>\
+             </linearGradient>"));
+        assert!(document.contains(
+            "<rect x=\"0\" y=\"0\" width=\"100\" height=\"100\" fill=\"url(#gradient1)\"/>"));
+        // The stops start at the start circle
+        assert!(document.contains(
+            "cx=\"0\" cy=\"0\" r=\"10\" fx=\"0\" fy=\"0\" spreadMethod=\"pad\" \
+             gradientTransform=\"matrix(1 0 0 1 5 0)\">\
+             <stop offset=\"0.5\" stop-color=\"rgb(255,255,255)\" stop-opacity=\"1\"/>\
+             <stop offset=\"1\" stop-color=\"rgb(0,0,0)\" stop-opacity=\"1\"/>"));
+        assert!(document.contains("<g opacity=\"0.25\"><rect x=\"0\" y=\"0\" width=\"100\" \
+                                   height=\"100\" fill=\"url(#gradient2)\"/></g>"));
+        assert_eq!(document.matches("<rect").count(), 2);
+    }
+
+    #[test]
+    fn test_clip() {
+        let mut surface = SvgSurface::create(100., 100.);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(0., 1., 0., 1.);
+            square(&mut context, 0., 0., 50.);
+            context.clip();
+            context.save();
+            square(&mut context, 25., 25., 50.);
+            context.clip();
+            context.paint();
+            context.restore().unwrap();
+            context.paint();
+            context.reset_clip();
+            context.paint();
+        }
+        let document = document(&surface);
+        assert!(document.contains("<clipPath id=\"clip1\"><path d=\"M 0 0 L 50 0 L 50 50 \
+                                   L 0 50 Z\" clip-rule=\"nonzero\"/></clipPath>"));
+        // Clips nest inside the clip they were made in
+        assert!(document.contains("<clipPath id=\"clip2\" clip-path=\"url(#clip1)\">"));
+        let rect = "<rect x=\"0\" y=\"0\" width=\"100\" height=\"100\" fill=\"rgb(0,255,0)\"/>";
+        assert!(document.contains(&format!("<g clip-path=\"url(#clip2)\">{}</g>\n\
+                                            <g clip-path=\"url(#clip1)\">{}</g>\n{}\n",
+                                           rect, rect, rect)));
+    }
+
+    #[test]
+    fn test_surface_pattern() {
+        let mut image = ImageSurface::create(Format::RGB24, 2, 1);
+        *image.get_mut(0, 0).unwrap() = Rgba::new(1., 0., 0., 1.);
+        let mut png = Vec::new();
+        image.write_to_png_stream(&mut png).unwrap();
+        let data_uri = format!("xlink:href=\"data:image/png;base64,{}\"/>", base64(&png));
+
+        let mut surface = SvgSurface::create(100., 100.);
+        {
+            let mut context = Context::create(&mut surface);
+            let mut pattern = Pattern::create_for_surface(&image);
+            pattern.set_matrix(Matrix::init_translate(-10., -20.)).unwrap();
+            context.set_source(&pattern);
+            square(&mut context, 0., 0., 50.);
+            context.fill();
+
+            pattern.set_extend(Extend::Repeat);
+            context.set_source(&pattern);
+            context.paint();
+        }
+        let document = document(&surface);
+        // Without repeating, the image is shown through a mask of the shape
+        assert!(document.contains(
+            "<mask id=\"mask1\" maskUnits=\"userSpaceOnUse\" x=\"0\" y=\"0\" width=\"100\" \
+             height=\"100\"><path d=\"M 0 0 L 50 0 L 50 50 L 0 50 Z\" fill-rule=\"nonzero\" \
+             fill=\"white\"/></mask>"));
+        assert!(document.contains(&format!(
+            "<g mask=\"url(#mask1)\"><image width=\"2\" height=\"1\" \
+             transform=\"matrix(1 0 0 1 10 20)\" {}</g>", data_uri)));
+        assert!(document.contains(&format!(
+            "<pattern id=\"pattern2\" patternUnits=\"userSpaceOnUse\" width=\"2\" height=\"1\" \
+             patternTransform=\"matrix(1 0 0 1 10 20)\"><image width=\"2\" height=\"1\" {}\
+             </pattern>", data_uri)));
+        assert!(document.contains("fill=\"url(#pattern2)\"/>"));
+    }
+
+    #[test]
+    fn test_mask() {
+        let mut surface = SvgSurface::create(4., 4.);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(0., 0., 0., 1.);
+            context.mask(&Pattern::create_rgba(0., 0., 0., 0.5));
+            let mut mask = Pattern::create_linear(0., 0., 4., 0.);
+            mask.add_color_stop_rgba(0., 0., 0., 0., 0.).unwrap();
+            mask.add_color_stop_rgba(1., 0., 0., 0., 1.).unwrap();
+            context.mask(&mask);
+        }
+        let document = document(&surface);
+        assert!(document.contains("<g opacity=\"0.5\"><rect x=\"0\" y=\"0\" width=\"4\" \
+                                   height=\"4\" fill=\"rgb(0,0,0)\"/></g>"));
+        assert!(document.contains("<mask id=\"mask1\" maskUnits=\"userSpaceOnUse\" x=\"0\" \
+                                   y=\"0\" width=\"4\" height=\"4\"><image width=\"4\" \
+                                   height=\"4\" xlink:href=\"data:image/png;base64,"));
+        assert!(document.contains("<g mask=\"url(#mask1)\"><rect x=\"0\" y=\"0\" width=\"4\" \
+                                   height=\"4\" fill=\"rgb(0,0,0)\"/></g>"));
+    }
+
+    #[test]
+    fn test_clear_cuts_out() {
+        let mut surface = SvgSurface::create(100., 100.);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.paint();
+            context.set_operator(Operator::Clear);
+            square(&mut context, 0., 0., 10.);
+            context.fill();
+        }
+        // The red is drawn through a mask with the square cut out of it, and nothing else
+        let document = document(&surface);
+        assert!(document.contains("<g id=\"cut1\"><path d=\"M 0 0 L 10 0 L 10 10 L 0 10 Z\" \
+                                   fill-rule=\"nonzero\" fill=\"black\"/></g>"));
+        assert!(document.contains("<mask id=\"part0\" maskUnits=\"userSpaceOnUse\" x=\"0\" \
+                                   y=\"0\" width=\"100\" height=\"100\"><rect x=\"0\" y=\"0\" \
+                                   width=\"100\" height=\"100\" fill=\"white\"/>\
+                                   <use xlink:href=\"#cut1\"/></mask>"));
+        assert!(document.ends_with("<g mask=\"url(#part0)\">\n<rect x=\"0\" y=\"0\" \
+                                    width=\"100\" height=\"100\" fill=\"rgb(255,0,0)\"/>\n\
+                                    </g>\n</svg>\n"));
+    }
+
+    #[test]
+    fn test_source_cuts_out() {
+        let mut surface = SvgSurface::create(100., 100.);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.paint();
+            context.set_operator(Operator::Source);
+            context.set_source_rgba(0., 0., 0., 0.);
+            square(&mut context, 0., 0., 10.);
+            context.fill();
+            context.set_source_rgba(0., 0., 1., 0.5);
+            square(&mut context, 20., 0., 10.);
+            context.fill();
+            // Half of the page is left by cutting 1/3 of it out and drawing a quarter of the
+            // color over the rest
+            context.paint_with_alpha(0.5);
+        }
+        let document = document(&surface);
+        // A transparent source only cuts, and a color is drawn over its cut
+        assert!(document.contains("<g id=\"cut1\"><path d=\"M 0 0 L 10 0"));
+        assert!(document.contains("<g id=\"cut2\"><path d=\"M 20 0 L 30 0"));
+        assert!(document.contains("<g id=\"cut3\"><g opacity=\"0.3333333\"><rect x=\"0\" \
+                                   y=\"0\" width=\"100\" height=\"100\" fill=\"black\"/></g></g>"));
+        assert!(document.contains("<g mask=\"url(#part2)\">\n<path d=\"M 20 0 L 30 0 L 30 10 \
+                                   L 20 10 Z\" fill-rule=\"nonzero\" fill=\"rgb(0,0,255)\" \
+                                   fill-opacity=\"0.5\"/>\n</g>"));
+        assert!(document.ends_with("<g opacity=\"0.5\"><rect x=\"0\" y=\"0\" width=\"100\" \
+                                    height=\"100\" fill=\"rgb(0,0,255)\" fill-opacity=\"0.5\"/>\
+                                    </g>\n</svg>\n"));
+        assert_eq!(document.matches("fill=\"rgb(").count(), 3);
+    }
+
+    #[test]
+    fn test_cuts_are_not_nested() {
+        let mut surface = SvgSurface::create(100., 100.);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(0., 0., 1., 0.5);
+            for index in 0..20 {
+                let operator = if index % 2 == 0 { Operator::Source } else { Operator::Over };
+                context.set_operator(operator);
+                square(&mut context, index as f32, 0., 10.);
+                context.fill();
+            }
+        }
+        // Nothing was drawn before the first cut, so there is a part before each of the other
+        // nine, and every part is drawn through its own mask of the cuts after it
+        let document = document(&surface);
+        let parts = document.split("<g mask=\"url(#part").skip(1).collect::<Vec<_>>();
+        assert_eq!(parts.len(), 9);
+        for part in parts {
+            assert!(!part[..part.find("</g>").unwrap()].contains("<g"));
+        }
+        assert!(document.contains("<use xlink:href=\"#cut10\"/></mask>"));
+    }
+
+    #[test]
+    fn test_operators() {
+        let mut surface = SvgSurface::create(4., 4.);
+        {
+            let mut context = Context::create(&mut surface);
+            context.set_source_rgba(1., 0., 0., 1.);
+            context.paint();
+            context.set_operator(Operator::Dest);
+            context.paint();
+            context.set_operator(Operator::Xor);
+            square(&mut context, 0., 0., 2.);
+            context.fill();
+        }
+        // Dest draws nothing, and Xor replaces the square with the rasterized drawing
+        let document = document(&surface);
+        assert!(document.contains("<g id=\"cut2\"><g mask=\"url(#mask1)\"><rect x=\"0\" \
+                                   y=\"0\" width=\"4\" height=\"4\" fill=\"black\"/></g></g>"));
+        assert!(document.contains("<mask id=\"mask1\" maskUnits=\"userSpaceOnUse\" x=\"0\" \
+                                   y=\"0\" width=\"4\" height=\"4\"><image width=\"4\" \
+                                   height=\"4\" xlink:href=\"data:image/png;base64,"));
+        assert!(document.contains("</g>\n<g mask=\"url(#mask1)\"><image width=\"4\" \
+                                   height=\"4\" xlink:href=\"data:image/png;base64,"));
+        assert_eq!(document.matches("fill=\"rgb(").count(), 1);
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"M"), "TQ==");
+        assert_eq!(base64(b"Ma"), "TWE=");
+        assert_eq!(base64(b"Man"), "TWFu");
+        assert_eq!(base64(&[0xfb, 0xff, 0xfe]), "+//+");
+    }
+
+    #[test]
+    fn test_write_to_file() {
+        let path = Path::new("test_svg_surface.svg");
+        let surface = SvgSurface::create(10., 10.);
+        surface.write_to_file(path).unwrap();
+        let written = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(written, document(&surface));
+
+        assert_eq!(surface.write_to_file(Path::new("missing/directory/file.svg")),
+                   Err(Status::FileNotFound));
+    }
+}
@@ -0,0 +1,93 @@
+/*
+ * Cairus - a reimplementation of the cairo graphics library in Rust
+ *
+ * Copyright © 2017 CairusOrg
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it either under the terms of the GNU Lesser General Public
+ * License version 2.1 as published by the Free Software Foundation
+ * (the "LGPL") or, at your option, under the terms of the Mozilla
+ * Public License Version 2.0 (the "MPL"). If you do not alter this
+ * notice, a recipient may use your version of this file under either
+ * the MPL or the LGPL.
+ *
+ * You should have received a copy of the LGPL along with this library
+ * in the file LICENSE-LGPL-2_1; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Suite 500, Boston, MA 02110-1335, USA
+ * You should have received a copy of the MPL along with this library
+ * in the file LICENSE-MPL-2_0
+ *
+ * The contents of this file are subject to the Mozilla Public License
+ * Version 2.0 (the "License"); you may not use this file except in
+ * compliance with the License. You may obtain a copy of the License at
+ * http://www.mozilla.org/MPL/
+ *
+ * This software is distributed on an "AS IS" basis, WITHOUT WARRANTY
+ * OF ANY KIND, either express or implied. See the LGPL or the MPL for
+ * the specific language governing rights and limitations.
+ *
+ * The Original Code is the cairus graphics library.
+ *
+ */
+
+//! Writes ImageSurfaces as TGA (Truevision TARGA) images.
+//!
+//! Images are written uncompressed with 32 bits a pixel: blue, green, red and straight alpha.
+//! The rows are stored top to bottom, and the file ends with the TGA 2.0 footer so that readers
+//! can tell it apart from older TGA files.
+
+use std::io::Write;
+use status::Status;
+use surfaces::ImageSurface;
+use types::{ChannelOrder, AlphaMode};
+
+// Image type of uncompressed true color images
+const TRUE_COLOR: u8 = 2;
+
+// Image descriptor with 8 alpha bits a pixel, and the first row at the top
+const DESCRIPTOR: u8 = 0x28;
+
+// The footer, with no extension or developer areas
+const FOOTER: &[u8; 26] = b"\0\0\0\0\0\0\0\0TRUEVISION-XFILE.\0";
+
+/// Writes `surface` to `writer` as a 32 bit TGA image.  Returns Status::InvalidSize for a
+/// surface wider or taller than 65535 pixels, and Status::WriteError if `writer` fails.
+pub fn write_tga<W: Write>(surface: &ImageSurface, mut writer: W) -> Result<(), Status> {
+    if surface.width > u16::MAX as usize || surface.height > u16::MAX as usize {
+        return Err(Status::InvalidSize);
+    }
+
+    let mut bytes = Vec::with_capacity(18 + surface.width * surface.height * 4 + FOOTER.len());
+    // No image ID or color map
+    bytes.extend_from_slice(&[0, 0, TRUE_COLOR, 0, 0, 0, 0, 0]);
+    // The origin, then the size
+    bytes.extend_from_slice(&[0; 4]);
+    bytes.extend_from_slice(&(surface.width as u16).to_le_bytes());
+    bytes.extend_from_slice(&(surface.height as u16).to_le_bytes());
+    bytes.extend_from_slice(&[32, DESCRIPTOR]);
+
+    for pixel in surface.iter() {
+        bytes.extend_from_slice(&pixel.to_bytes(ChannelOrder::Bgra, AlphaMode::Straight));
+    }
+    bytes.extend_from_slice(FOOTER);
+    writer.write_all(&bytes).map_err(|_| Status::WriteError)
+}
+
+#[cfg(test)]
+mod tests {
+    use tga::write_tga;
+    use surfaces::{ImageSurface, Format};
+    use types::Rgba;
+
+    #[test]
+    fn tga_layout() {
+        let mut surface = ImageSurface::create(Format::ARGB32, 2, 1);
+        *surface.get_mut(1, 0).unwrap() = Rgba::new(1., 0.5, 0., 0.6);
+        let mut bytes = Vec::new();
+        write_tga(&surface, &mut bytes).unwrap();
+
+        assert_eq!(&bytes[..18], &[0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 1, 0, 32, 0x28]);
+        assert_eq!(&bytes[18..26], &[0, 0, 0, 0, 0, 128, 255, 153]);
+        assert_eq!(&bytes[26..], &b"\0\0\0\0\0\0\0\0TRUEVISION-XFILE.\0"[..]);
+    }
+}
@@ -55,7 +55,7 @@
 //! parts are 'subpixel' or 'sampling points'.  The more subpixel points that are covered by the
 //! trapezoid, the more opaque that pixel will be.  This is called point-sampling anti-aliasing.
 //!
-//!   The way we divide a pixel is into a 17x15 uniform grid.  For example, a single pixel goes
+//!   The way we divide a pixel is into a 16x16 uniform grid.  For example, a single pixel goes
 //! from image on the left, to that on the right.
 //!
 //!
@@ -75,10 +75,11 @@
 //! |                          |                   |                          |
 //! +--------------------------+                   X--X -X--X---X---X---X--X--X
 
-//! Cairus iterates through each X in the Subpixel grid above, and checks if that X point is
-//! inside the trapezoid.  If it is, the opacity of the original pixel will increase.
+//! Cairus samples the middle of each cell in the subpixel grid above, and checks if that point
+//! is inside the trapezoid.  If it is, the opacity of the original pixel will increase.
 //!
-//!  See the `fn Pixel::sample_points()` function for the implementation.
+//!  See `fn mask_from_trapezoids` for the implementation, which finds where each row of
+//!  sample points enters and leaves the trapezoid rather than testing every point alone.
 //!
 //!  Checking If A Point Is In A Trapezoid
 //!
@@ -107,15 +108,14 @@
 //!
 //!  As Cairus iterates through a pixel's subpixel points, it uses this ray intersection
 //!  technique to deterimine whether the subpixel is inside or outside of the trapezoid.  For every
-//!  subpixel point that is inside the opacity of that pixel increases by 1/255.  Because it is
-//!  a 17x15 subpixel grid, and 17 * 15 = 255, for a trapezoid to make a pixel fully opaque, it
+//!  subpixel point that is inside the opacity of that pixel increases by 1/256.  Because it is
+//!  a 16x16 subpixel grid, and 16 * 16 = 256, for a trapezoid to make a pixel fully opaque, it
 //!  must cover every single subpixel point inside that pixel.  If it doesn't cover any subpixel,
 //!  the pixel is left transparent.
 //!
 //!  See `fn ray_from_point_crosses_line` for ray intersection algorithm, and
 //!  `fn Trapezoid::contains_point` for how it is used to determine if a point is in a trapezoid.
 
-use surfaces::ImageSurface;
 use common_geometry::{Point, LineSegment};
 use std::f32;
 use std::collections::HashMap;
@@ -184,7 +184,7 @@ impl IntoPixels for Trapezoid {
         }
 
         // Order by y-value, for scanline from bottom
-        outline_pixels.sort_by(|&ref a, &ref b| a.y.cmp(&b.y));
+        outline_pixels.sort_by_key(|a| a.y);
         let mut minmap = HashMap::new();
         let mut maxmap = HashMap::new();
         for pixel in outline_pixels.iter() {
@@ -251,7 +251,7 @@ impl TrapezoidBasePair {
 ///
 /// A Trapezoid's base line segments are the parallel lines that form the Trapezoid.
 fn bases_from_points(a: Point, b: Point, c: Point, d: Point) -> Vec<TrapezoidBasePair> {
-    let mut points = vec![a, b, c, d];
+    let mut points = [a, b, c, d];
     points.sort_by(|&a, &b| { a.x.partial_cmp(&b.x).unwrap() });
 
     let mut possible_lines = Vec::new();
@@ -315,37 +315,80 @@ fn ray_from_point_crosses_line(point: &Point, line: &LineSegment) -> bool {
     }
 }
 
-/// Returns an ImageSurface mask from a Vec of Trapezoids.
-///
-/// The Rgba values will only have alpha values, as it is expected that this mask will only be
-/// used with the `operator_in` operator.
-pub fn mask_from_trapezoids(trapezoids: &Vec<Trapezoid>, width: usize, height: usize) -> ImageSurface {
-    let mut mask = ImageSurface::create(width, height);
+// The subpixel grid is 16 samples across and 16 samples down, 16 * 16 = 256 samples per pixel.
+// An even number of samples each way keeps every sample off the middle of the pixel, so an edge
+// through the middle covers exactly half of the samples on either side of it.
+const SAMPLE_COLUMNS: usize = 16;
+const SAMPLE_ROWS: usize = 16;
+const SAMPLES: u32 = (SAMPLE_COLUMNS * SAMPLE_ROWS) as u32;
 
+/// Returns a mask from a Vec of Trapezoids: how much of each pixel of a `width` by `height`
+/// grid the trapezoids cover, between 0 and 1, row by row.
+///
+/// Each sample point sits in the middle of its cell of the subpixel grid.  Trapezoid edges are
+/// half-open (a sample exactly on a trapezoid's top or left edge is inside, one on its bottom or
+/// right edge is not), so trapezoids sharing an edge never count the same sample twice.
+pub fn mask_from_trapezoids(trapezoids: &Vec<Trapezoid>, width: usize, height: usize) -> Vec<f32> {
+    let mut coverage = vec![0; width * height];
     for trapezoid in trapezoids {
-        for pixel in trapezoid.into_pixels() {
-            let mut successes = 0;
-            if pixel.is_edge() {
-                for sample_point in pixel.sample_points() {
-                    if trapezoid.contains_point(&sample_point) {
-                        successes += 1;
-                    }
+        add_trapezoid_coverage(trapezoid, width, height, &mut coverage);
+    }
+
+    coverage.into_iter().map(|samples: u32| samples.min(SAMPLES) as f32 / SAMPLES as f32).collect()
+}
+
+// Adds the number of samples `trapezoid` covers in each pixel to `coverage`, a row-major
+// `width` by `height` grid.
+fn add_trapezoid_coverage(trapezoid: &Trapezoid, width: usize, height: usize, coverage: &mut [u32]) {
+    let lines = trapezoid.lines();
+    let top = lines.iter().fold(f32::INFINITY, |top, line| top.min(line.point1.y).min(line.point2.y));
+    let bottom = lines.iter().fold(f32::NEG_INFINITY, |bottom, line| bottom.max(line.point1.y).max(line.point2.y));
+    if top >= bottom || top.is_nan() {
+        return;
+    }
+
+    let first_row = top.floor().max(0.) as usize;
+    let last_row = (bottom.ceil().max(0.) as usize).min(height);
+    for row in first_row..last_row {
+        for sample_row in 0..SAMPLE_ROWS {
+            let y = row as f32 + (sample_row as f32 + 0.5) / SAMPLE_ROWS as f32;
+
+            // The span of the trapezoid along this row of samples
+            let mut left = f32::INFINITY;
+            let mut right = f32::NEG_INFINITY;
+            for line in lines.iter() {
+                let min_y = line.point1.y.min(line.point2.y);
+                let max_y = line.point1.y.max(line.point2.y);
+                if min_y == max_y || y < min_y || y >= max_y {
+                    continue;
                 }
-            } else {
-                successes = 255;
+                let x = line.current_x_for_y(y);
+                left = left.min(x);
+                right = right.max(x);
             }
-            let (x, y) = (pixel.x as usize, pixel.y as usize);
-            match mask.get_mut(x, y) {
-                Some(mut rgba) => {
-                    rgba.alpha += successes as f32 / 255.;
-                    rgba.alpha.max(1.);
-                },
-                None => {},
+
+            if left < right {
+                add_span_coverage(left, right, &mut coverage[row * width..(row + 1) * width]);
             }
-         }
-     }
+        }
+    }
+}
 
-     mask
+// Adds the number of samples in one row of the subpixel grid that lie in [left, right) to each
+// pixel of `row`.
+fn add_span_coverage(left: f32, right: f32, row: &mut [u32]) {
+    let columns = SAMPLE_COLUMNS as f32;
+    let first = left.floor().max(0.) as usize;
+    let last = (right.ceil().max(0.) as usize).min(row.len());
+    for (column, samples) in row.iter_mut().enumerate().take(last).skip(first) {
+        let x = column as f32;
+        // Sample i of this pixel sits at x + (i + 0.5) / columns
+        let first_sample = ((left - x) * columns - 0.5).ceil().max(0.);
+        let last_sample = ((right - x) * columns - 0.5).ceil().min(columns);
+        if last_sample > first_sample {
+            *samples += (last_sample - first_sample) as u32;
+        }
+    }
 }
 
 #[cfg(test)]
@@ -406,7 +449,7 @@ mod tests {
         let pair = TrapezoidBasePair(base1, base2);
         let bases = bases_from_points(a, b, c, d);
         assert!(bases[0] == pair);
-        assert!(bases.len() >= 1);
+        assert!(!bases.is_empty());
     }
 
     // Test that the ray_from_point_crosses_line function performs the 'crossings_test'
@@ -547,15 +590,13 @@ mod tests {
         // filled_pixels is the coordinates for pixels that should be filled (or somewhat opaque)
         let filled_pixels = vec![(2, 1), (8, 1), (5, 8), (7, 0)];
         for (x, y) in filled_pixels {
-            let rgba = mask.get(x, y).unwrap();
-            assert!(rgba.alpha > 0.);
+            assert!(mask[y * 10 + x] > 0.);
         }
 
         // transparent_pixels is the coordinates for pixels that should be transparent
         let transparent_pixels = vec![(1, 9), (10, 2), (0, 2), (3, 9), (9, 9)];
         for (x, y) in transparent_pixels {
-            let rgba = mask.get(x, y).unwrap();
-            assert_eq!(rgba.alpha, 0.);
+            assert_eq!(mask[y * 10 + x], 0.);
         }
     }
 
@@ -577,10 +618,48 @@ mod tests {
         let trapezoids = vec![trap1, trap2];
         let mask = mask_from_trapezoids(&trapezoids, 9, 9);
 
-        let rgba = mask.get(2, 3).unwrap();
-        assert_eq!(rgba.alpha, 1.);
-        let rgba = mask.get(3, 3).unwrap();
-        assert!(rgba.alpha > 0.9);
+        assert_eq!(mask[3 * 9 + 2], 1.);
+        assert!(mask[3 * 9 + 3] > 0.9);
+    }
+
+    // Overlapping trapezoids add their coverage, but a pixel can't be more than fully covered.
+    #[test]
+    fn overlapping_trapezoids_are_at_most_opaque() {
+        let a = Point{x: 0., y: 0.};
+        let b = Point{x: 5., y: 0.};
+        let c = Point{x: 5., y: 5.};
+        let d = Point{x: 0., y: 5.};
+        let trapezoids = vec![Trapezoid::from_points(a, b, c, d),
+                              Trapezoid::from_points(a, b, c, d)];
+        let mask = mask_from_trapezoids(&trapezoids, 9, 9);
+        for alpha in mask.iter() {
+            assert!(*alpha <= 1.);
+        }
+        assert_eq!(mask[2 * 9 + 2], 1.);
+    }
+
+    // Edges through the middle of a pixel cover exactly half of it, from either side.
+    #[test]
+    fn edges_at_half_pixels_cover_half() {
+        let left = Trapezoid::from_points(Point{x: 0., y: 0.}, Point{x: 2.5, y: 0.},
+                                          Point{x: 2.5, y: 5.}, Point{x: 0., y: 5.});
+        let mask = mask_from_trapezoids(&vec![left], 5, 5);
+        assert_eq!(mask[2], 0.5);
+
+        let right = Trapezoid::from_points(Point{x: 2.5, y: 0.}, Point{x: 5., y: 0.},
+                                           Point{x: 5., y: 5.}, Point{x: 2.5, y: 5.});
+        let mask = mask_from_trapezoids(&vec![right], 5, 5);
+        assert_eq!(mask[2], 0.5);
+
+        let top = Trapezoid::from_points(Point{x: 0., y: 0.}, Point{x: 5., y: 0.},
+                                         Point{x: 5., y: 2.5}, Point{x: 0., y: 2.5});
+        let mask = mask_from_trapezoids(&vec![top], 5, 5);
+        assert_eq!(mask[2 * 5], 0.5);
+
+        let bottom = Trapezoid::from_points(Point{x: 0., y: 2.5}, Point{x: 5., y: 2.5},
+                                            Point{x: 5., y: 5.}, Point{x: 0., y: 5.});
+        let mask = mask_from_trapezoids(&vec![bottom], 5, 5);
+        assert_eq!(mask[2 * 5], 0.5);
     }
 
     // Passes if a degenerate trapezoid (a triangle) functions correctly
@@ -38,6 +38,27 @@
 
 use common_geometry::Point;
 
+/// The order that the channels of a pixel are stored in as bytes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChannelOrder {
+    /// A byte each of red, green, blue and alpha, in that order.
+    Rgba,
+    /// A byte each of blue, green, red and alpha, in that order.
+    Bgra,
+    /// A native-endian 32 bit integer with alpha in the most significant byte, then red, green
+    /// and blue, like cairo's ARGB32.  On little-endian machines this is the same as Bgra.
+    Argb32,
+}
+
+/// Whether the colors of a pixel stored as bytes are pre-multiplied by its alpha.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// The colors are pre-multiplied, like Rgba and cairo's image formats.
+    Premultiplied,
+    /// The colors are independent of the alpha, like in PNG files.
+    Straight,
+}
+
 /// Represents color with red, green, blue, and alpha channels.
 #[derive(Debug, Clone, Copy)]
 pub struct Rgba {
@@ -70,12 +91,59 @@ impl Rgba {
     ///
     /// Each channel gets converted from a float to a byte (which can represent numbers up to 255).
     /// They are divided by the alpha value to 'factor out' colors being pre-multiplied (see method
-    /// Rgba::new() on pre-multiplied alpha).
+    /// Rgba::new() on pre-multiplied alpha).  This is `to_bytes` with ChannelOrder::Rgba and
+    /// AlphaMode::Straight.
     pub fn into_bytes(&self) -> Vec<u8> {
-        vec![
-             (self.red * 255. / self.alpha) as u8,  (self.green * 255. / self.alpha) as u8,
-             (self.blue * 255. / self.alpha) as u8, (self.alpha * 255.) as u8
-            ]
+        self.to_bytes(ChannelOrder::Rgba, AlphaMode::Straight).to_vec()
+    }
+
+    /// Returns the color of a pixel stored as the first 4 of `bytes`, with its channels in
+    /// `order` and its colors pre-multiplied or not as `mode` says.
+    ///
+    /// Panics if `bytes` is shorter than 4.
+    pub fn from_bytes(bytes: &[u8], order: ChannelOrder, mode: AlphaMode) -> Rgba {
+        let [red, green, blue, alpha] = match order {
+            ChannelOrder::Rgba => [bytes[0], bytes[1], bytes[2], bytes[3]],
+            ChannelOrder::Bgra => [bytes[2], bytes[1], bytes[0], bytes[3]],
+            ChannelOrder::Argb32 => {
+                let [alpha, red, green, blue] =
+                    u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).to_be_bytes();
+                [red, green, blue, alpha]
+            },
+        };
+        let color = Rgba {
+            red: red as f32 / 255.,
+            green: green as f32 / 255.,
+            blue: blue as f32 / 255.,
+            alpha: alpha as f32 / 255.,
+        };
+        match mode {
+            AlphaMode::Premultiplied => color,
+            AlphaMode::Straight => Rgba::new(color.red, color.green, color.blue, color.alpha),
+        }
+    }
+
+    /// Returns the 4 bytes of a pixel of this color, with its channels in `order` and its colors
+    /// pre-multiplied or not as `mode` says.
+    ///
+    /// Channels are clamped between 0 and 1 and rounded to the nearest byte.  Straight colors
+    /// are divided by the alpha first, and a transparent color is all zeros.
+    pub fn to_bytes(&self, order: ChannelOrder, mode: AlphaMode) -> [u8; 4] {
+        let alpha = self.alpha.clamp(0., 1.);
+        let (red, green, blue) = match mode {
+            AlphaMode::Premultiplied => (self.red, self.green, self.blue),
+            AlphaMode::Straight if alpha > 0. => {
+                (self.red / alpha, self.green / alpha, self.blue / alpha)
+            },
+            AlphaMode::Straight => (0., 0., 0.),
+        };
+        let [red, green, blue, alpha] = [to_byte(red), to_byte(green), to_byte(blue),
+                                         to_byte(alpha)];
+        match order {
+            ChannelOrder::Rgba => [red, green, blue, alpha],
+            ChannelOrder::Bgra => [blue, green, red, alpha],
+            ChannelOrder::Argb32 => u32::from_be_bytes([alpha, red, green, blue]).to_ne_bytes(),
+        }
     }
 
     /// Modifies all RGBA values to be between 1.0 and 0.0.
@@ -92,14 +160,19 @@ impl Rgba {
             self.alpha = 0.;
         } else {
             // Bound every channel between 0 and 1
-            self.red = self.red.min(1.).max(0.);
-            self.green = self.green.min(1.).max(0.);
-            self.blue = self.blue.min(1.).max(0.);
-            self.alpha = self.alpha.min(1.).max(0.);
+            self.red = self.red.clamp(0., 1.);
+            self.green = self.green.clamp(0., 1.);
+            self.blue = self.blue.clamp(0., 1.);
+            self.alpha = self.alpha.clamp(0., 1.);
         }
     }
 }
 
+// Returns `channel`, clamped between 0 and 1, as the nearest byte.
+fn to_byte(channel: f32) -> u8 {
+    (channel.clamp(0., 1.) * 255.).round() as u8
+}
+
 impl PartialEq for Rgba {
     fn eq(&self, other: &Rgba) -> bool {
         self.red == other.red && self.green == other.green &&
@@ -148,7 +221,7 @@ pub trait IntoPixels {
 
 #[cfg(test)]
 mod tests {
-    use super::Rgba;
+    use super::{Rgba, ChannelOrder, AlphaMode};
 
     #[test]
     fn test_rgba_into_bytes_all_ones() {
@@ -166,11 +239,80 @@ mod tests {
 
     #[test]
     fn test_rgba_into_bytes_all_half() {
+        // 127.5 rounds up
         let color = Rgba::new(0.5, 0.5, 0.5, 0.5);
-        let expected = vec![127, 127, 127, 127];
+        let expected = vec![128, 128, 128, 128];
         assert_eq!(color.into_bytes(), expected);
     }
 
+    #[test]
+    fn test_rgba_into_bytes_transparent_color() {
+        // Colors can't be recovered from a transparent pixel
+        let color = Rgba { red: 0.5, green: 0.25, blue: 1., alpha: 0. };
+        assert_eq!(color.into_bytes(), vec![0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_rgba_to_bytes_orders() {
+        let color = Rgba::new(1., 0.5, 0.2, 0.4);
+        let straight = [255, 128, 51, 102];
+        let premultiplied = [102, 51, 20, 102];
+        assert_eq!(color.to_bytes(ChannelOrder::Rgba, AlphaMode::Straight), straight);
+        assert_eq!(color.to_bytes(ChannelOrder::Rgba, AlphaMode::Premultiplied), premultiplied);
+        assert_eq!(color.to_bytes(ChannelOrder::Bgra, AlphaMode::Straight), [51, 128, 255, 102]);
+        assert_eq!(color.to_bytes(ChannelOrder::Argb32, AlphaMode::Premultiplied),
+                   0x66663314u32.to_ne_bytes());
+    }
+
+    #[test]
+    fn test_rgba_to_bytes_clamps() {
+        let color = Rgba { red: 0.6, green: -1., blue: 0.2, alpha: 0.5 };
+        assert_eq!(color.to_bytes(ChannelOrder::Rgba, AlphaMode::Straight), [255, 0, 102, 128]);
+        let color = Rgba { red: 2., green: 0., blue: 0., alpha: 2. };
+        assert_eq!(color.to_bytes(ChannelOrder::Rgba, AlphaMode::Premultiplied), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_rgba_from_bytes() {
+        let color = Rgba::from_bytes(&[255, 0, 51, 51], ChannelOrder::Rgba, AlphaMode::Straight);
+        assert_eq!(color, Rgba::new(1., 0., 0.2, 0.2));
+        let color = Rgba::from_bytes(&[51, 0, 10, 51], ChannelOrder::Bgra,
+                                     AlphaMode::Premultiplied);
+        assert_eq!(color, Rgba { red: 10. / 255., green: 0., blue: 0.2, alpha: 0.2 });
+        let bytes = 0x80402010u32.to_ne_bytes();
+        let color = Rgba::from_bytes(&bytes, ChannelOrder::Argb32, AlphaMode::Premultiplied);
+        assert_eq!(color.to_bytes(ChannelOrder::Rgba, AlphaMode::Premultiplied),
+                   [0x40, 0x20, 0x10, 0x80]);
+    }
+
+    #[test]
+    fn test_rgba_bytes_round_trip() {
+        // Every straight byte survives a round trip through an opaque pixel, and every
+        // pre-multiplied byte through any pixel
+        for byte in 0..256 {
+            let byte = byte as u8;
+            let bytes = [byte, 255 - byte, byte / 2, 255];
+            for order in &[ChannelOrder::Rgba, ChannelOrder::Bgra, ChannelOrder::Argb32] {
+                let color = Rgba::from_bytes(&bytes, *order, AlphaMode::Straight);
+                assert_eq!(color.to_bytes(*order, AlphaMode::Straight), bytes);
+            }
+            let bytes = [byte / 3, byte / 2, byte, byte];
+            let color = Rgba::from_bytes(&bytes, ChannelOrder::Rgba, AlphaMode::Premultiplied);
+            assert_eq!(color.to_bytes(ChannelOrder::Rgba, AlphaMode::Premultiplied), bytes);
+        }
+    }
+
+    #[test]
+    fn test_rgba_bytes_through_crate_root() {
+        // The conversions are public API, re-exported at the crate root
+        let color = ::Rgba::from_bytes(&[255, 128, 0, 128], ::ChannelOrder::Rgba,
+                                       ::AlphaMode::Straight);
+        assert_eq!(color.to_bytes(::ChannelOrder::Rgba, ::AlphaMode::Premultiplied),
+                   [128, 64, 0, 128]);
+        let transparent = ::Rgba::new(0., 0., 0., 0.);
+        assert_eq!(transparent.to_bytes(::ChannelOrder::Bgra, ::AlphaMode::Straight), [0, 0, 0, 0]);
+    }
+
     #[test]
     fn test_rgba_corrects_large_values() {
         let mut color = Rgba::new(3., 3., 3., 3.);